@@ -34,27 +34,94 @@ export type SubmissionError =
     | ({
         type: 'field',
     } & FieldError)
+    | {
+        type: 'fields',
+        errors: Array<FieldError>
+    }
     | {
         type: 'no_pending_user'
     }
 
 export type FlowComponent =
     | {
-        component: 'access_denied' | 'error',
+        component: 'access_denied' | 'error' | 'expired',
         message: string,
     }
     | ({
         component: 'identification',
         user_fields: Array<UserField>,
         password: PasswordComponentData | null,
+        // Options of a passwordless login, passed to navigator.credentials.get
+        webauthn: any | null,
     } & Sources)
     | ({
         component: 'password',
     } & PasswordComponentData)
+    | {
+        component: 'prompt',
+        prompts: Array<Prompt>
+    }
+    | ({
+        component: 'consent',
+        application: string,
+        scopes: Array<string>
+    } & ConsentMode)
     | {
         component: 'redirect',
         to: string
     }
+    | {
+        component: 'authenticator_totp',
+        setup: TotpSetupData | null
+    }
+    | {
+        component: 'authenticator_webauthn',
+        mode: 'register' | 'authenticate',
+        // Passed to navigator.credentials.create or navigator.credentials.get
+        options: any
+    }
+    | {
+        component: 'authenticator_static',
+        // Only set while setting up the codes
+        codes: Array<string> | null
+    }
+    | {
+        component: 'email',
+        sent_to: string | null
+    }
+    | {
+        component: 'password_change'
+    }
+    | {
+        component: 'confirm'
+    }
+
+export interface TotpSetupData {
+    config_url: string,
+    secret: string
+}
+
+export type PromptKind = "username" | "email" | "password" | "text" | "text_read_only" | "signed_number" | "unsigned_number" | "checkbox" | "switch" | "date" | "date_time" | "seperator" | "static" | "locale"
+
+export interface Prompt {
+    uid: number,
+    slug: string,
+    field_key: string,
+    label: string,
+    kind: PromptKind,
+    placeholder: string | null,
+    required: boolean,
+    help_text: string | null
+}
+
+export type ConsentMode =
+    | {
+        mode: 'always' | 'once'
+    }
+    | {
+        mode: 'until',
+        duration: number
+    }
 
 export interface PasswordComponentData {
     recovery_url: string | null
@@ -79,7 +146,13 @@ export interface PartialUser {
     uid: string,
     name: string,
     avatar_url: string | null,
-    is_admin: boolean
+    is_admin: boolean,
+    groups: Array<PartialGroup>
+}
+
+export interface PartialGroup {
+    uid: string,
+    name: string
 }
 
 export interface CheckAuthResponse {
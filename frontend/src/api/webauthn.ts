@@ -0,0 +1,55 @@
+// The server encodes binary values of the options as base64url, the browser expects buffers
+
+function decode(value: string): ArrayBuffer {
+    const base64 = value.replace(/-/g, '+').replace(/_/g, '/');
+    const binary = atob(base64.padEnd(Math.ceil(base64.length / 4) * 4, '='));
+    return Uint8Array.from(binary, (c) => c.charCodeAt(0)).buffer;
+}
+
+function encode(value: ArrayBuffer): string {
+    const binary = String.fromCharCode(...new Uint8Array(value));
+    return btoa(binary).replace(/\+/g, '-').replace(/\//g, '_').replace(/=+$/, '');
+}
+
+function decode_descriptors(descriptors: Array<any>): Array<PublicKeyCredentialDescriptor> {
+    return descriptors.map((descriptor) => ({ ...descriptor, id: decode(descriptor.id) }));
+}
+
+export async function create_credential(options: any): Promise<string> {
+    const credential = await navigator.credentials.create({
+        publicKey: {
+            ...options,
+            challenge: decode(options.challenge),
+            user: { ...options.user, id: decode(options.user.id) },
+            excludeCredentials: decode_descriptors(options.excludeCredentials),
+        }
+    }) as PublicKeyCredential;
+    const response = credential.response as AuthenticatorAttestationResponse;
+    return JSON.stringify({
+        id: credential.id,
+        response: {
+            clientDataJSON: encode(response.clientDataJSON),
+            attestationObject: encode(response.attestationObject),
+        }
+    });
+}
+
+export async function get_credential(options: any): Promise<string> {
+    const credential = await navigator.credentials.get({
+        publicKey: {
+            ...options,
+            challenge: decode(options.challenge),
+            allowCredentials: decode_descriptors(options.allowCredentials),
+        }
+    }) as PublicKeyCredential;
+    const response = credential.response as AuthenticatorAssertionResponse;
+    return JSON.stringify({
+        id: credential.id,
+        response: {
+            clientDataJSON: encode(response.clientDataJSON),
+            authenticatorData: encode(response.authenticatorData),
+            signature: encode(response.signature),
+            userHandle: response.userHandle ? encode(response.userHandle) : null,
+        }
+    });
+}
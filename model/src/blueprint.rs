@@ -0,0 +1,190 @@
+use serde::{Deserialize, Serialize};
+use uuid::Uuid;
+
+use crate::{
+    AuthenticationRequirement, ConsentMode, FlowDesignation, PasswordBackend, PolicyKind,
+    PromptKind, StaticMode, TotpMode, UserField, UserVerification, WebAuthnMode,
+};
+
+/// The configuration as a document which can be kept in git.
+/// Objects reference each other by their slug, tenants are identified by their host.
+/// Expressions of policies are plain text instead of base64.
+#[derive(Debug, Clone, Default, PartialEq, Serialize, Deserialize)]
+#[serde(default)]
+pub struct Blueprint {
+    pub policies: Vec<PolicyBlueprint>,
+    pub prompts: Vec<PromptBlueprint>,
+    pub stages: Vec<StageBlueprint>,
+    pub flows: Vec<FlowBlueprint>,
+    pub tenants: Vec<TenantBlueprint>,
+}
+
+#[derive(Debug, Clone, PartialEq, Serialize, Deserialize)]
+pub struct PolicyBlueprint {
+    pub slug: String,
+    #[serde(flatten)]
+    pub kind: PolicyKind,
+}
+
+#[derive(Debug, Clone, PartialEq, Serialize, Deserialize)]
+pub struct PromptBlueprint {
+    pub slug: String,
+    pub field_key: String,
+    pub label: String,
+    pub kind: PromptKind,
+    #[serde(default)]
+    pub placeholder: Option<String>,
+    #[serde(default)]
+    pub required: bool,
+    #[serde(default)]
+    pub help_text: Option<String>,
+}
+
+#[derive(Debug, Clone, PartialEq, Serialize, Deserialize)]
+pub struct StageBlueprint {
+    pub slug: String,
+    #[serde(default)]
+    pub timeout: i32,
+    #[serde(flatten)]
+    pub kind: StageBlueprintKind,
+}
+
+#[derive(Debug, Clone, PartialEq, Serialize, Deserialize)]
+#[serde(tag = "kind", rename_all = "snake_case")]
+pub enum StageBlueprintKind {
+    Deny,
+    Prompt {
+        bindings: Vec<PromptBindingBlueprint>,
+    },
+    Identification {
+        #[serde(default)]
+        password: Option<String>,
+        user_fields: Vec<UserField>,
+        #[serde(default)]
+        passwordless: bool,
+    },
+    UserLogin,
+    UserLogout {
+        #[serde(default)]
+        terminate_all: bool,
+    },
+    UserWrite,
+    Password {
+        backends: Vec<PasswordBackend>,
+    },
+    Consent {
+        mode: ConsentMode,
+    },
+    AuthenticatorTotp {
+        mode: TotpMode,
+        issuer: String,
+        #[serde(default = "drift_default")]
+        drift: i16,
+    },
+    AuthenticatorWebAuthn {
+        mode: WebAuthnMode,
+        user_verification: UserVerification,
+    },
+    AuthenticatorStatic {
+        mode: StaticMode,
+        #[serde(default = "count_default")]
+        count: i16,
+    },
+    Email {
+        subject: String,
+        #[serde(default = "template_default")]
+        template: String,
+        #[serde(default = "token_expiry_default")]
+        token_expiry: i32,
+    },
+    PasswordChange,
+}
+
+fn drift_default() -> i16 {
+    1
+}
+
+fn count_default() -> i16 {
+    10
+}
+
+fn template_default() -> String {
+    "email_verification".to_owned()
+}
+
+fn token_expiry_default() -> i32 {
+    30
+}
+
+#[derive(Debug, Clone, PartialEq, Serialize, Deserialize)]
+pub struct PromptBindingBlueprint {
+    pub prompt: String,
+    pub order: i16,
+}
+
+#[derive(Debug, Clone, PartialEq, Serialize, Deserialize)]
+pub struct FlowBlueprint {
+    pub slug: String,
+    pub title: String,
+    pub designation: FlowDesignation,
+    pub authentication: AuthenticationRequirement,
+    #[serde(default)]
+    pub bindings: Vec<BindingBlueprint>,
+    #[serde(default)]
+    pub entries: Vec<EntryBlueprint>,
+}
+
+#[derive(Debug, Clone, PartialEq, Serialize, Deserialize)]
+pub struct EntryBlueprint {
+    pub stage: String,
+    pub ordering: i16,
+    #[serde(default)]
+    pub bindings: Vec<BindingBlueprint>,
+}
+
+#[derive(Debug, Clone, PartialEq, Serialize, Deserialize)]
+pub struct BindingBlueprint {
+    #[serde(flatten)]
+    pub kind: BindingBlueprintKind,
+    #[serde(default = "enabled_default")]
+    pub enabled: bool,
+    #[serde(default)]
+    pub negate: bool,
+    pub order: i16,
+}
+
+fn enabled_default() -> bool {
+    true
+}
+
+#[derive(Debug, Clone, PartialEq, Serialize, Deserialize)]
+#[serde(rename_all = "snake_case")]
+pub enum BindingBlueprintKind {
+    Policy(String),
+    Group(Uuid),
+    User(Uuid),
+}
+
+#[derive(Debug, Clone, PartialEq, Serialize, Deserialize)]
+pub struct TenantBlueprint {
+    pub host: String,
+    #[serde(default)]
+    pub default: bool,
+    pub title: String,
+    pub logo: String,
+    pub favicon: String,
+    #[serde(default)]
+    pub invalidation_flow: Option<String>,
+    #[serde(default)]
+    pub authentication_flow: Option<String>,
+    #[serde(default)]
+    pub authorization_flow: Option<String>,
+    #[serde(default)]
+    pub enrollment_flow: Option<String>,
+    #[serde(default)]
+    pub recovery_flow: Option<String>,
+    #[serde(default)]
+    pub unenrollment_flow: Option<String>,
+    #[serde(default)]
+    pub configuration_flow: Option<String>,
+}
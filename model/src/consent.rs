@@ -0,0 +1,16 @@
+use serde::Serialize;
+use time::OffsetDateTime;
+use uuid::Uuid;
+
+#[derive(Debug, Clone, Serialize)]
+pub struct Consent {
+    pub uid: Uuid,
+    pub user: Uuid,
+    pub application: Option<i32>,
+    pub flow: Option<i32>,
+    pub scopes: Vec<String>,
+    #[serde(with = "time::serde::rfc3339")]
+    pub created: OffsetDateTime,
+    #[serde(with = "time::serde::rfc3339::option")]
+    pub expires: Option<OffsetDateTime>,
+}
@@ -1,13 +1,15 @@
-use serde::Serialize;
+use serde::{Deserialize, Serialize, Serializer};
+use serde_json::Value;
 use uuid::Uuid;
 
-use crate::{error::SubmissionError, UserField};
+use crate::{error::SubmissionError, ConsentMode, Prompt, UserField, WebAuthnMode};
 
 #[derive(Serialize)]
 pub struct FlowData {
     pub flow: FlowInfo,
     #[serde(rename = "response_error")]
     pub error: Option<SubmissionError>,
+    #[serde(serialize_with = "serialize_public_user")]
     pub pending_user: Option<PendingUser>,
     #[serde(flatten)]
     pub component: FlowComponent,
@@ -24,22 +26,67 @@ pub enum FlowComponent {
         #[serde(flatten)]
         sources: Sources,
         password: Option<PasswordComponentData>,
+        /// Options of a passwordless login, passed to `navigator.credentials.get`
+        webauthn: Option<Value>,
     },
     Password {
         #[serde(flatten)]
         data: PasswordComponentData,
     },
+    Prompt {
+        prompts: Vec<Prompt>,
+    },
+    Consent {
+        application: String,
+        scopes: Vec<String>,
+        #[serde(flatten)]
+        mode: ConsentMode,
+    },
     Redirect {
         to: String,
     },
+    AuthenticatorTotp {
+        /// Set while a new device is set up
+        setup: Option<TotpSetupData>,
+    },
+    AuthenticatorWebAuthn {
+        mode: WebAuthnMode,
+        /// Passed to `navigator.credentials.create` or `navigator.credentials.get`,
+        /// binary values are base64url encoded
+        options: Value,
+    },
+    AuthenticatorStatic {
+        /// The new codes, they are only shown once during setup
+        codes: Option<Vec<String>>,
+    },
+    Email {
+        /// Masked address the link has been sent to
+        sent_to: Option<String>,
+    },
+    PasswordChange,
+    /// A server side stage is next, it only runs once the user submits the flow
+    Confirm,
+    /// The current stage timed out, the flow starts over with the next request
+    Expired {
+        message: String,
+    },
     Error {
         message: String,
     },
 }
 
+#[derive(Serialize)]
+pub struct TotpSetupData {
+    /// `otpauth://` uri, which is also the payload of the QR code
+    pub config_url: String,
+    /// Base32 encoded secret, for apps which cannot scan the QR code
+    pub secret: String,
+}
+
 #[derive(Serialize)]
 pub struct PasswordComponentData {
-    pub recovery_url: String,
+    /// Link to the recovery flow of the tenant, if it has one
+    pub recovery_url: Option<String>,
 }
 
 #[derive(Serialize)]
@@ -48,18 +95,32 @@ pub struct Sources {
     pub show_source_labels: bool,
 }
 
-#[derive(Debug, Clone, Serialize)]
+#[derive(Debug, Clone, Serialize, Deserialize)]
 pub struct PendingUser {
-    #[serde(skip)]
     pub uid: Uuid,
     pub name: String,
     pub avatar_url: Option<String>,
-    #[serde(skip)]
     pub authenticated: bool,
-    #[serde(skip)]
     pub is_admin: bool,
 }
 
+/// Only the name and avatar of the pending user are shown to the client.
+fn serialize_public_user<S: Serializer>(
+    user: &Option<PendingUser>,
+    serializer: S,
+) -> Result<S::Ok, S::Error> {
+    #[derive(Serialize)]
+    struct PublicUser<'a> {
+        name: &'a str,
+        avatar_url: &'a Option<String>,
+    }
+    let user = user.as_ref().map(|user| PublicUser {
+        name: &user.name,
+        avatar_url: &user.avatar_url,
+    });
+    user.serialize(serializer)
+}
+
 #[derive(Serialize)]
 pub struct Source {
     pub name: String,
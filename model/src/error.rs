@@ -32,6 +32,22 @@ pub enum SubmissionError {
     NoPendingUser,
     #[from]
     Field(#[error(source)] FieldError),
+    #[display("{} invalid fields", errors.len())]
+    Fields {
+        #[error(not(source))]
+        errors: Vec<FieldError>,
+    },
+}
+
+impl SubmissionError {
+    /// Collapses the errors of several fields into a single submission error.
+    pub fn from_fields(mut errors: Vec<FieldError>) -> Option<Self> {
+        match errors.len() {
+            0 => None,
+            1 => errors.pop().map(Self::Field),
+            _ => Some(Self::Fields { errors }),
+        }
+    }
 }
 
 #[derive(Debug, Clone, Error, Display, Serialize)]
@@ -5,7 +5,7 @@ use uuid::Uuid;
 
 use super::{Policy, Stage};
 
-#[derive(Debug, Clone, Serialize, Deserialize, ToSql, FromSql)]
+#[derive(Debug, Clone, PartialEq, Serialize, Deserialize, ToSql, FromSql)]
 #[postgres(name = "authentication_requirement")]
 #[serde(rename_all = "snake_case")]
 pub enum AuthenticationRequirement {
@@ -19,7 +19,7 @@ pub enum AuthenticationRequirement {
     Ignored,
 }
 
-#[derive(Debug, Clone, Serialize, Deserialize, ToSql, FromSql)]
+#[derive(Debug, Clone, PartialEq, Serialize, Deserialize, ToSql, FromSql)]
 #[postgres(name = "flow_designation")]
 #[serde(rename_all = "snake_case")]
 pub enum FlowDesignation {
@@ -56,6 +56,7 @@ pub struct Flow {
 #[derive(Debug, Clone, Serialize, Deserialize)]
 #[cfg_attr(feature = "datacache", derive(datacache::DataMarker))]
 pub struct FlowBinding {
+    pub uid: i32,
     pub enabled: bool,
     pub negate: bool,
     pub order: i16,
@@ -72,6 +73,7 @@ pub enum FlowBindingKind {
 
 #[derive(Debug, Clone, Serialize, Deserialize)]
 pub struct FlowEntry {
+    pub uid: i32,
     pub ordering: i16,
     pub bindings: Vec<FlowBinding>,
     pub stage: DataRef<Stage>,
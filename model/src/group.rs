@@ -0,0 +1,17 @@
+use serde::{Deserialize, Serialize};
+use serde_json::Value;
+use uuid::Uuid;
+
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct Group {
+    pub uid: Uuid,
+    pub name: String,
+    pub parent: Option<Uuid>,
+    pub attributes: Value,
+}
+
+#[derive(Debug, Clone, PartialEq, Eq, Serialize, Deserialize)]
+pub struct PartialGroup {
+    pub uid: Uuid,
+    pub name: String,
+}
@@ -0,0 +1,50 @@
+use postgres_types::{FromSql, ToSql};
+use serde::{Deserialize, Serialize};
+use serde_json::Value;
+use time::OffsetDateTime;
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Serialize, Deserialize, ToSql, FromSql)]
+#[postgres(name = "signing_algorithm")]
+pub enum SigningAlgorithm {
+    #[postgres(name = "rs256")]
+    #[serde(rename = "RS256", alias = "rs256")]
+    Rs256,
+    #[postgres(name = "es256")]
+    #[serde(rename = "ES256", alias = "es256")]
+    Es256,
+    #[postgres(name = "eddsa")]
+    #[serde(rename = "EdDSA", alias = "eddsa")]
+    EdDsa,
+}
+
+/// A key used to sign session cookies and tokens.
+/// Keys are used for signing between `activates` and `retires`
+/// and are accepted for verification until they expire.
+#[derive(Debug, Clone, Serialize)]
+pub struct SigningKey {
+    pub kid: String,
+    pub algorithm: SigningAlgorithm,
+    /// PKCS#8 PEM encoded private key
+    #[serde(skip)]
+    pub private_key: String,
+    /// The public key as JWK
+    pub jwk: Value,
+    #[serde(with = "time::serde::rfc3339")]
+    pub created: OffsetDateTime,
+    #[serde(with = "time::serde::rfc3339")]
+    pub activates: OffsetDateTime,
+    #[serde(with = "time::serde::rfc3339::option")]
+    pub retires: Option<OffsetDateTime>,
+    #[serde(with = "time::serde::rfc3339::option")]
+    pub expires: Option<OffsetDateTime>,
+}
+
+impl SigningKey {
+    pub fn signs_at(&self, time: OffsetDateTime) -> bool {
+        self.activates <= time && self.retires.map_or(true, |retires| retires > time)
+    }
+
+    pub fn verifies_at(&self, time: OffsetDateTime) -> bool {
+        self.expires.map_or(true, |expires| expires > time)
+    }
+}
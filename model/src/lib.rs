@@ -1,15 +1,26 @@
+pub mod blueprint;
+mod consent;
 mod data;
 pub mod error;
 mod flow;
+mod group;
+mod key;
+mod oauth2;
 mod policy;
 mod prompt;
+mod session;
 mod stage;
 mod tenant;
 pub mod user;
 
+pub use consent::*;
 pub use data::*;
 pub use flow::*;
+pub use group::*;
+pub use key::*;
+pub use oauth2::*;
 pub use policy::*;
 pub use prompt::*;
+pub use session::*;
 pub use stage::*;
 pub use tenant::*;
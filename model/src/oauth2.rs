@@ -0,0 +1,39 @@
+use postgres_types::{FromSql, ToSql};
+use serde::{Deserialize, Serialize};
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Serialize, Deserialize, ToSql, FromSql)]
+#[postgres(name = "oauth2_client_type")]
+#[serde(rename_all = "snake_case")]
+pub enum OAuth2ClientType {
+    #[postgres(name = "confidential")]
+    Confidential,
+    #[postgres(name = "public")]
+    Public,
+}
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Serialize, Deserialize, ToSql, FromSql)]
+#[postgres(name = "oauth2_token_kind")]
+#[serde(rename_all = "snake_case")]
+pub enum OAuth2TokenKind {
+    #[postgres(name = "access")]
+    Access,
+    #[postgres(name = "refresh")]
+    Refresh,
+}
+
+/// The oauth2 settings of a provider together with the application using it.
+#[derive(Debug, Clone, Serialize)]
+pub struct OAuth2Client {
+    pub provider: i32,
+    pub application: i32,
+    pub application_name: String,
+    pub client_id: String,
+    #[serde(skip)]
+    pub client_secret: Option<String>,
+    pub client_type: OAuth2ClientType,
+    pub redirect_uris: Vec<String>,
+    pub scopes: Vec<String>,
+    pub authorization_flow: Option<i32>,
+    pub access_token_validity: i32,
+    pub refresh_token_validity: i32,
+}
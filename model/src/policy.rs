@@ -18,12 +18,56 @@ pub struct Policy {
     pub kind: PolicyKind,
 }
 
-#[derive(Debug, Clone, Serialize, Deserialize)]
+#[derive(Debug, Clone, PartialEq, Serialize, Deserialize)]
 #[serde(tag = "kind", rename_all = "snake_case")]
 pub enum PolicyKind {
-    PasswordExpiry { max_age: i32 },
-    PasswordStrength,
-    Expression(String),
+    PasswordExpiry {
+        max_age: i32,
+    },
+    PasswordStrength(PasswordRules),
+    /// Rhai expression, encoded as base64
+    Expression {
+        expression: String,
+    },
+    /// Rejects passwords contained in the breached password filter
+    BreachedPassword {
+        field_key: String,
+    },
+}
+
+/// Rules of a password strength policy, a rule with a value of `0` is disabled.
+#[derive(Debug, Clone, PartialEq, Serialize, Deserialize)]
+#[serde(default)]
+pub struct PasswordRules {
+    /// Field the password has been collected in
+    pub field_key: String,
+    pub min_length: i16,
+    pub min_uppercase: i16,
+    pub min_lowercase: i16,
+    pub min_digits: i16,
+    pub min_symbols: i16,
+    /// Maximum number of times the same character may be repeated in a row
+    pub max_repeated: Option<i16>,
+    /// Disallows passwords containing the username or email address of the user
+    pub check_user_attributes: bool,
+    /// Minimum zxcvbn score from 0 (too guessable) to 4 (very unguessable)
+    pub min_score: i16,
+}
+
+impl Default for PasswordRules {
+    fn default() -> Self {
+        Self {
+            field_key: "password".to_owned(),
+            min_length: 8,
+            min_uppercase: 0,
+            min_lowercase: 0,
+            min_digits: 0,
+            min_symbols: 0,
+            max_repeated: None,
+            check_user_attributes: true,
+            min_score: 0,
+        }
+    }
 }
 
 #[derive(Debug, Clone, Serialize, Deserialize, ToSql, FromSql)]
@@ -36,14 +80,17 @@ pub enum PolicyKindSimple {
     PasswordStrength,
     #[postgres(name = "expression")]
     Expression,
+    #[postgres(name = "breached_password")]
+    BreachedPassword,
 }
 
 impl<'a> From<&'a PolicyKind> for PolicyKindSimple {
     fn from(value: &'a PolicyKind) -> Self {
         match value {
             PolicyKind::PasswordExpiry { max_age: _ } => Self::PasswordExpiry,
-            PolicyKind::PasswordStrength => Self::PasswordStrength,
-            PolicyKind::Expression(_) => Self::Expression,
+            PolicyKind::PasswordStrength(_) => Self::PasswordStrength,
+            PolicyKind::Expression { .. } => Self::Expression,
+            PolicyKind::BreachedPassword { .. } => Self::BreachedPassword,
         }
     }
 }
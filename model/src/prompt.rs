@@ -13,6 +13,7 @@ pub struct PromptBinding {
 pub struct Prompt {
     #[cfg_attr(feature = "datacache", datacache(queryable))]
     pub uid: i32,
+    pub slug: String,
     pub field_key: String,
     pub label: String,
     pub kind: PromptKind,
@@ -21,7 +22,7 @@ pub struct Prompt {
     pub help_text: Option<String>,
 }
 
-#[derive(Debug, Clone, Serialize, Deserialize, FromSql, ToSql)]
+#[derive(Debug, Clone, PartialEq, Serialize, Deserialize, FromSql, ToSql)]
 #[serde(rename_all = "snake_case")]
 #[postgres(name = "prompt_kind")]
 pub enum PromptKind {
@@ -12,6 +12,7 @@ pub struct Stage {
     #[cfg_attr(feature = "datacache", datacache(queryable))]
     pub slug: String,
     pub kind: StageKind,
+    /// Minutes the stage may be current before the execution expires, 0 never expires
     pub timeout: i32,
 }
 
@@ -47,9 +48,13 @@ pub enum StageKind {
     Identification {
         password: Option<DataRef<Stage>>,
         user_fields: Vec<UserField>,
+        /// Users may identify themselves with a discoverable webauthn credential
+        passwordless: bool,
     },
     UserLogin,
-    UserLogout,
+    UserLogout {
+        terminate_all: bool,
+    },
     UserWrite,
     Password {
         backends: Vec<PasswordBackend>,
@@ -57,6 +62,32 @@ pub enum StageKind {
     Consent {
         mode: ConsentMode,
     },
+    AuthenticatorTotp {
+        mode: TotpMode,
+        issuer: String,
+        /// Time steps before and after the current one which are accepted
+        drift: i16,
+    },
+    AuthenticatorWebAuthn {
+        mode: WebAuthnMode,
+        user_verification: UserVerification,
+    },
+    /// One-time recovery codes, for users who lost their other authenticators
+    AuthenticatorStatic {
+        mode: StaticMode,
+        /// Codes generated during setup
+        count: i16,
+    },
+    /// Sends a link to the email address of the user, the flow continues once it has been opened
+    Email {
+        subject: String,
+        /// Name of the template the message is rendered with
+        template: String,
+        /// Minutes the link is valid for
+        token_expiry: i32,
+    },
+    /// Replaces the password of the user, after the current one has been confirmed
+    PasswordChange,
 }
 
 impl StageKind {
@@ -65,13 +96,67 @@ impl StageKind {
             StageKind::Deny => true,
             StageKind::Prompt { .. } => true,
             StageKind::Identification { .. } => true,
-            StageKind::UserLogin | StageKind::UserLogout | StageKind::UserWrite => false,
+            StageKind::UserLogin | StageKind::UserLogout { .. } | StageKind::UserWrite => false,
             StageKind::Password { .. } => true,
             StageKind::Consent { .. } => true,
+            StageKind::AuthenticatorTotp { .. } => true,
+            StageKind::AuthenticatorWebAuthn { .. } => true,
+            StageKind::AuthenticatorStatic { .. } => true,
+            StageKind::Email { .. } => true,
+            StageKind::PasswordChange => true,
         }
     }
 }
 
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Serialize, Deserialize, FromSql, ToSql)]
+#[serde(rename_all = "snake_case")]
+#[postgres(name = "totp_mode")]
+pub enum TotpMode {
+    /// Adds a new device for the user
+    #[postgres(name = "setup")]
+    Setup,
+    /// Checks a code of one of the devices of the user
+    #[postgres(name = "validate")]
+    Validate,
+}
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Serialize, Deserialize, FromSql, ToSql)]
+#[serde(rename_all = "snake_case")]
+#[postgres(name = "webauthn_mode")]
+pub enum WebAuthnMode {
+    /// Registers a new credential for the user
+    #[postgres(name = "register")]
+    Register,
+    /// Asserts one of the credentials of the user
+    #[postgres(name = "authenticate")]
+    Authenticate,
+}
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Serialize, Deserialize, FromSql, ToSql)]
+#[serde(rename_all = "snake_case")]
+#[postgres(name = "static_mode")]
+pub enum StaticMode {
+    /// Replaces the codes of the user with new ones
+    #[postgres(name = "setup")]
+    Setup,
+    /// Accepts one of the codes of the user, which is used up
+    #[postgres(name = "validate")]
+    Validate,
+}
+
+/// Whether the authenticator has to verify the user, e.g. with a pin or biometrics.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Serialize, Deserialize, FromSql, ToSql)]
+#[serde(rename_all = "snake_case")]
+#[postgres(name = "user_verification")]
+pub enum UserVerification {
+    #[postgres(name = "required")]
+    Required,
+    #[postgres(name = "preferred")]
+    Preferred,
+    #[postgres(name = "discouraged")]
+    Discouraged,
+}
+
 #[derive(Debug, Clone, PartialEq, FromSql, ToSql)]
 #[postgres(name = "consent_mode")]
 pub enum PgConsentMode {
@@ -82,7 +167,7 @@ pub enum PgConsentMode {
     #[postgres(name = "until")]
     Until,
 }
-#[derive(Debug, Clone, Serialize, Deserialize)]
+#[derive(Debug, Clone, PartialEq, Serialize, Deserialize)]
 #[serde(tag = "mode", rename_all = "snake_case")]
 pub enum ConsentMode {
     Always,
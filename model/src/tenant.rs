@@ -36,7 +36,7 @@ impl Tenant {
             FlowDesignation::Authentication => self.authentication_flow.clone(),
             FlowDesignation::Authorization => self.authorization_flow.clone(),
             FlowDesignation::Enrollment => self.enrollment_flow.clone(),
-            FlowDesignation::Recovery => self.enrollment_flow.clone(),
+            FlowDesignation::Recovery => self.recovery_flow.clone(),
             FlowDesignation::Unenrollment => self.unenrollment_flow.clone(),
             FlowDesignation::Configuration => self.configuration_flow.clone(),
         }
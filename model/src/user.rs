@@ -1,6 +1,8 @@
 use serde::Serialize;
 use uuid::Uuid;
 
+use crate::PartialGroup;
+
 #[derive(Debug, Clone, Serialize)]
 #[cfg_attr(feature = "datacache", derive(datacache::DataMarker))]
 pub struct PartialUser {
@@ -10,4 +12,6 @@ pub struct PartialUser {
     pub is_admin: bool,
     #[serde(skip)]
     pub password_change_date: time::OffsetDateTime,
+    /// Groups of the user including inherited parent groups
+    pub groups: Vec<PartialGroup>,
 }
@@ -75,6 +75,16 @@ pub fn compile(expression: &str, scope: &Scope) -> Result<AST, ExpressionCompila
     Ok(ast)
 }
 
+/// Compiles a plain text expression against placeholders of the variables passed to policies,
+/// so expressions can be checked without a request to evaluate them with.
+pub fn check(expression: &str) -> Result<(), ParseError> {
+    let mut scope = Scope::new();
+    scope.push_constant("request", ());
+    scope.push_constant("context", ());
+    DEFAULT_ENGINE.compile_with_scope(&scope, expression)?;
+    Ok(())
+}
+
 fn register_packages(engine: &mut Engine) {
     ContextPackage::new().register_into_engine(engine);
 }
@@ -193,7 +203,7 @@ pub(crate) fn simple_eval(expr: &str) -> crate::ExecutionResult {
 
 #[cfg(test)]
 mod tests {
-    use crate::{simple_eval, LogEntry};
+    use crate::{check, simple_eval, LogEntry};
 
     pub mod preload {
         pub(crate) use crate::{eval_test, register_package};
@@ -211,4 +221,11 @@ mod tests {
         assert_eq!(Some(true), res.result.ok());
         assert_eq!(vec![LogEntry::Text("1".into())], res.output);
     }
+
+    #[test]
+    fn check_variables() {
+        assert!(check("request.uri.path == \"/\"").is_ok());
+        assert!(check("context.pending_user == ()").is_ok());
+        assert!(check("unknown == 1").is_err());
+    }
 }
@@ -53,4 +53,40 @@ mod user_module {
     pub fn get_is_admin_partial(obj: &mut PartialUser) -> bool {
         obj.is_admin.clone()
     }
+
+    /// Checks the membership by group name or uid, parent groups are included
+    #[rhai_fn(global, pure, name = "in_group")]
+    pub fn in_group_partial(obj: &mut PartialUser, group: ImmutableString) -> bool {
+        obj.groups
+            .iter()
+            .any(|g| g.name == group.as_str() || g.uid.to_string() == group.as_str())
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use authust_model::{user::PartialUser, PartialGroup};
+    use time::OffsetDateTime;
+    use uuid::Uuid;
+
+    use super::UserPackage;
+    use crate::tests::preload::*;
+
+    fn user() -> PartialUser {
+        PartialUser {
+            uid: Uuid::nil(),
+            name: "user".into(),
+            avatar_url: None,
+            is_admin: false,
+            password_change_date: OffsetDateTime::UNIX_EPOCH,
+            groups: vec![PartialGroup {
+                uid: Uuid::nil(),
+                name: "admins".into(),
+            }],
+        }
+    }
+
+    eval_test!(test_in_group_name("user": user()) -> bool | (true): r#"user.in_group("admins")"#, UserPackage);
+    eval_test!(test_in_group_uid("user": user()) -> bool | (true): r#"user.in_group("00000000-0000-0000-0000-000000000000")"#, UserPackage);
+    eval_test!(test_not_in_group("user": user()) -> bool | (false): r#"user.in_group("users")"#, UserPackage);
 }
@@ -0,0 +1,10 @@
+alter table sessions
+    add column id         uuid                     default gen_random_uuid()        not null unique,
+    add column created    timestamp with time zone default now()                    not null,
+    add column last_seen  timestamp with time zone default now()                    not null,
+    add column expires    timestamp with time zone default now() + interval '1 day' not null,
+    add column ip         inet,
+    add column user_agent varchar(512);
+
+create index sessions_user_id on sessions (user_id);
+create index sessions_expires on sessions (expires);
@@ -0,0 +1,10 @@
+alter table password_strength_policies
+    add column field_key             varchar(64) default 'password' not null,
+    add column min_length            int2        default 8          not null check ( min_length >= 0 ),
+    add column min_uppercase         int2        default 0          not null check ( min_uppercase >= 0 ),
+    add column min_lowercase         int2        default 0          not null check ( min_lowercase >= 0 ),
+    add column min_digits            int2        default 0          not null check ( min_digits >= 0 ),
+    add column min_symbols           int2        default 0          not null check ( min_symbols >= 0 ),
+    add column max_repeated          int2 check ( max_repeated > 0 ),
+    add column check_user_attributes boolean     default true       not null,
+    add column min_score             int2        default 0          not null check ( min_score between 0 and 4 );
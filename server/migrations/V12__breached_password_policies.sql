@@ -0,0 +1,10 @@
+alter type policy_kind add value 'breached_password';
+
+create table breached_password_policies
+(
+    uid       serial primary key,
+    field_key varchar(64) default 'password' not null
+);
+
+alter table policies
+    add column breached_password int4 references breached_password_policies;
@@ -0,0 +1,19 @@
+alter table flow_bindings
+    add column uid serial primary key;
+
+alter table flow_bindings
+    rename column negate_result to negate;
+
+alter table flow_bindings
+    add constraint flow_bindings_target check ( num_nonnulls(flow, entry) = 1 );
+
+-- Entries and bindings are part of their flow and are deleted together with it
+alter table flow_entries
+    drop constraint flow_entries_flow_fkey,
+    add constraint flow_entries_flow_fkey foreign key (flow) references flows on delete cascade;
+
+alter table flow_bindings
+    drop constraint flow_bindings_flow_fkey,
+    add constraint flow_bindings_flow_fkey foreign key (flow) references flows on delete cascade,
+    drop constraint flow_bindings_entry_fkey,
+    add constraint flow_bindings_entry_fkey foreign key (entry) references flow_entries on delete cascade;
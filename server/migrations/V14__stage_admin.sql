@@ -0,0 +1,17 @@
+-- The identification stage column referenced the wrong table
+alter table stages
+    drop constraint stages_identification_stage_fkey,
+    add constraint stages_identification_stage_fkey foreign key (identification_stage) references identification_stages;
+
+create type password_backend as enum ('internal', 'ldap');
+
+alter table stages
+    add column password_backends password_backend[] default '{internal}' not null;
+
+-- Prompt bindings are part of their stage and are deleted together with it
+alter table stage_prompt_bindings
+    drop constraint stage_prompt_bindings_stage_fkey,
+    add constraint stage_prompt_bindings_stage_fkey foreign key (stage) references stages on delete cascade;
+
+create index stage_prompt_bindings_prompt on stage_prompt_bindings (prompt);
+create index flow_entries_stage on flow_entries (stage);
@@ -0,0 +1,149 @@
+-- Every server caches the configuration and listens on this channel to drop changed entries.
+-- The payload is {"type": <cached type>, "uid": <uid of the cached row>}.
+create function notify_invalidation(kind text, uid int4) returns void as
+$$
+begin
+    if uid is not null then
+        perform pg_notify('authust_invalidation', json_build_object('type', kind, 'uid', uid)::text);
+    end if;
+end;
+$$ language plpgsql;
+
+-- Arguments: cached type, column of the row holding the uid of the cached row
+create function notify_row_invalidation() returns trigger as
+$$
+begin
+    if tg_op <> 'INSERT' then
+        perform notify_invalidation(tg_argv[0], (to_jsonb(old) ->> tg_argv[1])::int4);
+    end if;
+    if tg_op <> 'DELETE' then
+        perform notify_invalidation(tg_argv[0], (to_jsonb(new) ->> tg_argv[1])::int4);
+    end if;
+    return null;
+end;
+$$ language plpgsql;
+
+-- For rows referenced by the cached rows.
+-- Arguments: cached type, table of the cached rows, column referencing this row
+create function notify_referenced_invalidation() returns trigger as
+$$
+declare
+    cached int4;
+begin
+    for cached in execute format('select uid from %I where %I = $1', tg_argv[1], tg_argv[2]) using new.uid
+        loop
+            perform notify_invalidation(tg_argv[0], cached);
+        end loop;
+    return null;
+end;
+$$ language plpgsql;
+
+-- Bindings belong to their flow directly or through one of its entries
+create function notify_flow_binding_invalidation() returns trigger as
+$$
+declare
+    binding record;
+begin
+    if tg_op = 'DELETE' then
+        binding := old;
+    else
+        binding := new;
+    end if;
+    perform notify_invalidation('flow',
+                                coalesce(binding.flow, (select flow from flow_entries where uid = binding.entry)));
+    return null;
+end;
+$$ language plpgsql;
+
+create trigger flows_invalidation
+    after insert or update or delete
+    on flows
+    for each row
+execute function notify_row_invalidation('flow', 'uid');
+
+create trigger flow_entries_invalidation
+    after insert or update or delete
+    on flow_entries
+    for each row
+execute function notify_row_invalidation('flow', 'flow');
+
+create trigger flow_bindings_invalidation
+    after insert or update or delete
+    on flow_bindings
+    for each row
+execute function notify_flow_binding_invalidation();
+
+create trigger stages_invalidation
+    after insert or update or delete
+    on stages
+    for each row
+execute function notify_row_invalidation('stage', 'uid');
+
+create trigger stage_prompt_bindings_invalidation
+    after insert or update or delete
+    on stage_prompt_bindings
+    for each row
+execute function notify_row_invalidation('stage', 'stage');
+
+-- Rows of the kind tables are linked after being inserted and unlinked before being deleted,
+-- which already notifies, so only updates have to be tracked
+create trigger identification_stages_invalidation
+    after update
+    on identification_stages
+    for each row
+execute function notify_referenced_invalidation('stage', 'stages', 'identification_stage');
+
+create trigger consent_stages_invalidation
+    after update
+    on consent_stages
+    for each row
+execute function notify_referenced_invalidation('stage', 'stages', 'consent_stage');
+
+create trigger user_logout_stages_invalidation
+    after update
+    on user_logout_stages
+    for each row
+execute function notify_referenced_invalidation('stage', 'stages', 'user_logout_stage');
+
+create trigger prompts_invalidation
+    after insert or update or delete
+    on prompts
+    for each row
+execute function notify_row_invalidation('prompt', 'uid');
+
+create trigger policies_invalidation
+    after insert or update or delete
+    on policies
+    for each row
+execute function notify_row_invalidation('policy', 'uid');
+
+-- Same as the kind tables of stages
+create trigger password_expiration_policies_invalidation
+    after update
+    on password_expiration_policies
+    for each row
+execute function notify_referenced_invalidation('policy', 'policies', 'password_expiration');
+
+create trigger password_strength_policies_invalidation
+    after update
+    on password_strength_policies
+    for each row
+execute function notify_referenced_invalidation('policy', 'policies', 'password_strength');
+
+create trigger expression_policies_invalidation
+    after update
+    on expression_policies
+    for each row
+execute function notify_referenced_invalidation('policy', 'policies', 'expression');
+
+create trigger breached_password_policies_invalidation
+    after update
+    on breached_password_policies
+    for each row
+execute function notify_referenced_invalidation('policy', 'policies', 'breached_password');
+
+create trigger tenants_invalidation
+    after insert or update or delete
+    on tenants
+    for each row
+execute function notify_row_invalidation('tenant', 'uid');
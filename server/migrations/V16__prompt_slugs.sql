@@ -0,0 +1,11 @@
+-- Blueprints reference prompts by their slug like every other object
+alter table prompts
+    add column slug varchar(128);
+
+update prompts
+set slug = 'prompt-' || uid;
+
+alter table prompts
+    alter column slug set not null,
+    add constraint prompts_slug_key unique (slug),
+    add constraint prompts_slug_check check ( slug = lower(slug) );
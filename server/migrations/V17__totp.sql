@@ -0,0 +1,34 @@
+alter type stage_kind add value 'authenticator_totp';
+
+create type totp_mode as enum ('setup', 'validate');
+
+create table totp_stages
+(
+    uid    serial primary key,
+    mode   totp_mode   not null,
+    -- Shown by authenticator apps next to the account name
+    issuer varchar(64) not null,
+    -- Time steps before and after the current one which are accepted
+    drift  int2        not null check ( drift >= 0 )
+);
+
+alter table stages
+    add column totp_stage int4 references totp_stages;
+
+create trigger totp_stages_invalidation
+    after update
+    on totp_stages
+    for each row
+execute function notify_referenced_invalidation('stage', 'stages', 'totp_stage');
+
+create table totp_devices
+(
+    uid       serial primary key,
+    user_id   uuid        not null references users on delete cascade,
+    secret    bytea       not null,
+    -- Time step of the last accepted code, codes of this or an earlier step are rejected
+    last_step int8,
+    created   timestamptz not null default now()
+);
+
+create index totp_devices_user on totp_devices (user_id);
@@ -0,0 +1,39 @@
+alter type stage_kind add value 'authenticator_webauthn';
+
+create type webauthn_mode as enum ('register', 'authenticate');
+
+create type user_verification as enum ('required', 'preferred', 'discouraged');
+
+create table webauthn_stages
+(
+    uid               serial primary key,
+    mode              webauthn_mode     not null,
+    user_verification user_verification not null
+);
+
+alter table stages
+    add column webauthn_stage int4 references webauthn_stages;
+
+create trigger webauthn_stages_invalidation
+    after update
+    on webauthn_stages
+    for each row
+execute function notify_referenced_invalidation('stage', 'stages', 'webauthn_stage');
+
+alter table identification_stages
+    -- Users may identify themselves with a discoverable credential instead
+    add column passwordless bool not null default false;
+
+create table webauthn_credentials
+(
+    uid           serial primary key,
+    user_id       uuid        not null references users on delete cascade,
+    credential_id bytea       not null unique,
+    -- COSE encoded public key
+    public_key    bytea       not null,
+    -- Counters of authenticators which do not implement one stay at 0
+    sign_count    int8        not null,
+    created       timestamptz not null default now()
+);
+
+create index webauthn_credentials_user on webauthn_credentials (user_id);
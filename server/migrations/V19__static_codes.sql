@@ -0,0 +1,31 @@
+alter type stage_kind add value 'authenticator_static';
+
+create type static_mode as enum ('setup', 'validate');
+
+create table static_stages
+(
+    uid   serial primary key,
+    mode  static_mode not null,
+    -- Codes generated during setup
+    count int2        not null check ( count > 0 )
+);
+
+alter table stages
+    add column static_stage int4 references static_stages;
+
+create trigger static_stages_invalidation
+    after update
+    on static_stages
+    for each row
+execute function notify_referenced_invalidation('stage', 'stages', 'static_stage');
+
+create table static_codes
+(
+    uid     serial primary key,
+    user_id uuid        not null references users on delete cascade,
+    -- Argon2 hash of the code, codes are deleted once they have been used
+    code    text        not null,
+    created timestamptz not null default now()
+);
+
+create index static_codes_user on static_codes (user_id);
@@ -0,0 +1,24 @@
+alter type stage_kind add value 'email';
+
+create table email_stages
+(
+    uid          serial primary key,
+    subject      text not null,
+    -- Name of the handlebars template the message is rendered with
+    template     text not null,
+    -- Minutes the link sent to the user is valid for
+    token_expiry int4 not null check ( token_expiry > 0 )
+);
+
+alter table stages
+    add column email_stage int4 references email_stages;
+
+create trigger email_stages_invalidation
+    after update
+    on email_stages
+    for each row
+execute function notify_referenced_invalidation('stage', 'stages', 'email_stage');
+
+alter table users
+    -- Reset whenever the email changes
+    add column email_verified bool not null default false;
@@ -0,0 +1,70 @@
+alter type stage_kind add value 'password_change';
+
+-- Recovery flow which works out of the box, the user identifies themselves,
+-- opens the link sent to their email address and chooses a new password.
+-- It is the recovery flow of the default tenant, so that fresh installs link to it
+with identification as (
+    insert into identification_stages (fields)
+        values (array ['email', 'name']::userid_field[])
+        returning uid)
+insert
+into stages(slug, kind, timeout, identification_stage)
+select 'default-recovery-identification', 'identification', 30, uid
+from identification;
+
+with email as (
+    insert into email_stages (subject, template, token_expiry)
+        values ('Reset your password', 'password_recovery', 30)
+        returning uid)
+insert
+into stages(slug, kind, timeout, email_stage)
+select 'default-recovery-email', 'email', 30, uid
+from email;
+
+insert into prompts(slug, field_key, label, kind, required)
+values ('default-recovery-password', 'password', 'New password', 'password', true);
+insert into stages(slug, kind, timeout)
+values ('default-recovery-password', 'prompt', 30);
+insert into stage_prompt_bindings(prompt, stage, ordering)
+select p.uid, s.uid, 0
+from prompts p,
+     stages s
+where p.slug = 'default-recovery-password'
+  and s.slug = 'default-recovery-password';
+
+insert into stages(slug, kind, timeout)
+values ('default-recovery-write', 'user_write', 30);
+
+with rules as (
+    insert into password_strength_policies default values
+        returning uid)
+insert
+into policies(slug, kind, password_strength)
+select 'default-password-strength', 'password_strength', uid
+from rules;
+
+insert into flows(slug, title, designation, authentication)
+values ('default-recovery', 'Password Recovery', 'recovery', 'none');
+insert into flow_entries(flow, stage, ordering)
+select f.uid, s.uid, e.ordering
+from flows f,
+     (values ('default-recovery-identification', 30),
+             ('default-recovery-email', 60),
+             ('default-recovery-password', 90),
+             ('default-recovery-write', 120)) e(stage, ordering)
+         join stages s on s.slug = e.stage
+where f.slug = 'default-recovery';
+
+-- The new password has to pass the default rules
+insert into flow_bindings(policy, entry, ordering, enabled, negate)
+select p.uid, e.uid, 0, true, false
+from policies p,
+     flow_entries e
+         join stages s on s.uid = e.stage
+where p.slug = 'default-password-strength'
+  and s.slug = 'default-recovery-password';
+
+update tenants
+set recovery_flow = (select uid from flows where slug = 'default-recovery')
+where is_default
+  and recovery_flow is null;
@@ -0,0 +1,11 @@
+create table flow_executions
+(
+    session   char(96)     not null references sessions on delete cascade,
+    flow      varchar(128) not null,
+    state     jsonb        not null,
+    created   timestamp with time zone default now() not null,
+    last_used timestamp with time zone default now() not null,
+    primary key (session, flow)
+);
+
+create index flow_executions_last_used on flow_executions (last_used);
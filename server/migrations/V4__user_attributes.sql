@@ -0,0 +1,2 @@
+alter table users
+    add column attributes jsonb default '{}'::jsonb not null;
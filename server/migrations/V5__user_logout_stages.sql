@@ -0,0 +1,8 @@
+create table user_logout_stages
+(
+    uid           serial primary key,
+    terminate_all bool default false not null
+);
+
+alter table stages
+    add column user_logout_stage int4 references user_logout_stages;
@@ -0,0 +1,13 @@
+create table consents
+(
+    uid         uuid                     default gen_random_uuid() primary key not null,
+    user_id     uuid                                                        not null references users on delete cascade,
+    application int4 references applications on delete cascade,
+    flow        int4 references flows on delete cascade,
+    scopes      text[]                                                      not null,
+    created     timestamp with time zone default now()                      not null,
+    expires     timestamp with time zone,
+    check ( application is not null or flow is not null )
+);
+
+create index consents_user on consents (user_id);
@@ -0,0 +1,23 @@
+create table groups
+(
+    uid        uuid  default gen_random_uuid() primary key not null,
+    name       varchar(64)                                  not null unique,
+    parent     uuid references groups on delete set null,
+    attributes jsonb default '{}'::jsonb                    not null,
+    check ( parent <> uid )
+);
+
+create table group_memberships
+(
+    group_id uuid not null references groups on delete cascade,
+    user_id  uuid not null references users on delete cascade,
+    primary key (group_id, user_id)
+);
+
+create index group_memberships_user on group_memberships (user_id);
+
+-- Group bindings have no policy, every binding has exactly one kind
+alter table flow_bindings
+    alter column policy drop not null,
+    add constraint flow_bindings_group_binding_fkey foreign key (group_binding) references groups on delete cascade,
+    add constraint flow_bindings_kind check ( num_nonnulls(policy, group_binding, user_binding) = 1 );
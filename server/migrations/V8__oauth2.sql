@@ -0,0 +1,68 @@
+create type oauth2_client_type as enum ('confidential', 'public');
+
+-- A provider serves a single application, so that a client id resolves to exactly one of them
+create unique index application_provider on applications (provider);
+
+create table oauth2_providers
+(
+    uid                    int4 primary key references providers on delete cascade,
+    client_id              varchar(128)                                   not null unique,
+    -- argon2 hash of the secret, public clients have none
+    client_secret          varchar(255),
+    client_type            oauth2_client_type                             not null,
+    redirect_uris          text[]                                         not null,
+    scopes                 text[] default '{openid,profile,email}'::text[] not null,
+    authorization_flow     int4 references flows on delete set null,
+    -- seconds
+    access_token_validity  int4   default 600                             not null,
+    refresh_token_validity int4   default 2592000                         not null,
+    check ( client_type = 'public' or client_secret is not null )
+);
+
+create table oauth2_authorization_requests
+(
+    uid                   uuid                     default gen_random_uuid() primary key not null,
+    session               char(96)                                                    not null references sessions on delete cascade,
+    application           int4                                                        not null references applications on delete cascade,
+    redirect_uri          text                                                        not null,
+    scopes                text[]                                                      not null,
+    state                 varchar(1024),
+    nonce                 varchar(1024),
+    code_challenge        varchar(128),
+    code_challenge_method varchar(8),
+    -- set once the authorization flow has been completed
+    user_id               uuid references users on delete cascade,
+    auth_time             timestamp with time zone,
+    expires               timestamp with time zone                                    not null
+);
+
+create table oauth2_authorization_codes
+(
+    code_hash             char(64) primary key,
+    application           int4                     not null references applications on delete cascade,
+    user_id               uuid                     not null references users on delete cascade,
+    redirect_uri          text                     not null,
+    scopes                text[]                   not null,
+    nonce                 varchar(1024),
+    code_challenge        varchar(128),
+    code_challenge_method varchar(8),
+    auth_time             timestamp with time zone not null,
+    expires               timestamp with time zone not null
+);
+
+create type oauth2_token_kind as enum ('access', 'refresh');
+
+create table oauth2_tokens
+(
+    token_hash  char(64) primary key,
+    kind        oauth2_token_kind                      not null,
+    application int4                                   not null references applications on delete cascade,
+    -- tokens issued through the client credentials grant have no user
+    user_id     uuid references users on delete cascade,
+    scopes      text[]                                 not null,
+    auth_time   timestamp with time zone,
+    created     timestamp with time zone default now() not null,
+    expires     timestamp with time zone               not null
+);
+
+create index oauth2_tokens_expires on oauth2_tokens (expires);
@@ -0,0 +1,16 @@
+create type signing_algorithm as enum ('rs256', 'es256', 'eddsa');
+
+create table signing_keys
+(
+    kid         varchar(64) primary key,
+    algorithm   signing_algorithm                      not null,
+    -- PKCS#8 PEM encoded, encrypted with the configured secret
+    private_key text                                   not null,
+    jwk         jsonb                                  not null,
+    created     timestamp with time zone default now() not null,
+    activates   timestamp with time zone default now() not null,
+    retires     timestamp with time zone,
+    expires     timestamp with time zone,
+    check ( retires is null or retires >= activates ),
+    check ( expires is null or (retires is not null and expires >= retires) )
+);
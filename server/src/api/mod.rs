@@ -13,12 +13,21 @@ use deadpool_postgres::PoolError;
 use derive_more::{Display, Error, From};
 use http::{header::HeaderName, request::Parts, StatusCode};
 
-use jsonwebtoken::{errors::ErrorKind, DecodingKey, EncodingKey};
+use jsonwebtoken::errors::ErrorKind;
 use model::{error::SubmissionError, Flow, FlowParam, FlowQuery};
 use serde::Deserialize;
-use storage::datacache::{DataMarker, DataRef};
+use storage::{
+    datacache::{DataMarker, DataRef},
+    StorageError,
+};
+use tokio_postgres::error::SqlState;
 use tracing_error::SpanTrace;
-pub use v1::setup_api_v1;
+
+use crate::service::{email::EmailError, keys::KeyService};
+pub use v1::{
+    application::oauth2::{jwks, openid_configuration},
+    setup_api_v1,
+};
 
 pub mod csrf;
 mod v1;
@@ -79,11 +88,15 @@ pub enum ApiErrorKind {
     PoolError(#[error(source)] PoolError),
     #[from(ignore)]
     PostgresError(#[error(source)] tokio_postgres::Error),
+    #[from(ignore)]
+    Storage(#[error(source)] StorageError),
 
     #[from]
     SubmissionError(#[error(source)] SubmissionError),
     #[from(ignore)]
     JsonWebToken(#[error(source)] jsonwebtoken::errors::Error),
+    #[from]
+    Email(#[error(source)] EmailError),
 }
 
 impl From<jsonwebtoken::errors::Error> for ApiErrorKind {
@@ -108,11 +121,25 @@ impl From<tokio_postgres::Error> for ApiErrorKind {
             if db_error.routine() == Some("_bt_check_unique") {
                 return Self::Conflict;
             }
+            // Referenced rows that don't exist or rows that are still referenced
+            if db_error.code() == &SqlState::FOREIGN_KEY_VIOLATION {
+                return Self::Conflict;
+            }
         }
         Self::PostgresError(value)
     }
 }
 
+impl From<StorageError> for ApiErrorKind {
+    fn from(value: StorageError) -> Self {
+        match value {
+            StorageError::Pool(err) => Self::PoolError(err),
+            StorageError::Database(err) => err.into(),
+            err @ (StorageError::Key(_) | StorageError::Blueprint(_)) => Self::Storage(err),
+        }
+    }
+}
+
 impl ApiErrorKind {
     pub fn is_internal(&self) -> bool {
         match self {
@@ -130,7 +157,9 @@ impl ApiErrorKind {
             ApiErrorKind::Conflict => false,
             ApiErrorKind::PoolError(_) => true,
             ApiErrorKind::PostgresError(_) => true,
+            ApiErrorKind::Storage(_) => true,
             ApiErrorKind::Axum(_) => true,
+            ApiErrorKind::Email(_) => true,
         }
     }
 
@@ -163,9 +192,11 @@ impl IntoResponse for ApiError {
             | ApiErrorKind::PwHash(_)
             | ApiErrorKind::PoolError(_)
             | ApiErrorKind::PostgresError(_)
+            | ApiErrorKind::Storage(_)
             | ApiErrorKind::MiscInternal(_)
             | ApiErrorKind::MissingMiddleware(_)
-            | ApiErrorKind::JsonWebToken(_) => StatusCode::INTERNAL_SERVER_ERROR.into_response(),
+            | ApiErrorKind::JsonWebToken(_)
+            | ApiErrorKind::Email(_) => StatusCode::INTERNAL_SERVER_ERROR.into_response(),
             ApiErrorKind::InvalidLoginData => StatusCode::UNAUTHORIZED.into_response(),
             ApiErrorKind::NotFound => StatusCode::NOT_FOUND.into_response(),
             ApiErrorKind::SubmissionError(err) => {
@@ -186,8 +217,7 @@ impl IntoResponse for ApiError {
 }
 #[derive(Clone)]
 pub struct AuthServiceData {
-    pub encoding_key: EncodingKey,
-    pub decoding_key: DecodingKey,
+    pub keys: KeyService,
 }
 impl Debug for AuthServiceData {
     fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
@@ -198,6 +228,8 @@ impl Debug for AuthServiceData {
 #[derive(Debug, Deserialize, Default)]
 pub struct ExecutorQuery {
     pub next: Option<String>,
+    /// Token of a link sent by an email stage
+    pub email_token: Option<String>,
 }
 
 #[derive(Debug, Deserialize)]
@@ -1 +1,11 @@
+use axum::Router;
+
+use crate::SharedState;
+
+use self::oauth2::setup_oauth2_router;
+
 pub mod oauth2;
+
+pub fn setup_application_router() -> Router<SharedState> {
+    Router::new().nest("/oauth2", setup_oauth2_router())
+}
@@ -1 +1,891 @@
+use axum::{
+    extract::{OriginalUri, Query, State},
+    response::{IntoResponse, Redirect, Response},
+    routing::{get, post},
+    Form, Json, Router,
+};
+use base64::{
+    engine::general_purpose::{STANDARD, URL_SAFE_NO_PAD},
+    Engine,
+};
+use deadpool_postgres::GenericClient;
+use http::{
+    header::{AUTHORIZATION, WWW_AUTHENTICATE},
+    HeaderMap, StatusCode,
+};
+use model::{FlowDesignation, FlowQuery, OAuth2Client, OAuth2ClientType, OAuth2TokenKind, Tenant};
+use serde::{Deserialize, Serialize};
+use serde_json::{json, Value};
+use sha2::{Digest, Sha256};
+use storage::datacache::{Data, DataRef, LookupRef};
+use time::OffsetDateTime;
+use tracing::instrument;
+use uuid::Uuid;
 
+use crate::{
+    api::{ApiError, ApiErrorKind},
+    auth::Session,
+    executor::fields::{APPLICATION, APPLICATION_NAME, OAUTH2_REQUEST, SCOPES},
+    interface::flow::INTERFACE_BASE_URI,
+    service::oauth2::{AuthorizationCode, AuthorizationRequest, TokenGrant},
+    SharedState,
+};
+
+const OAUTH2_PATH: &str = "/api/v1/application/oauth2";
+const SUPPORTED_SCOPES: [&str; 3] = ["openid", "profile", "email"];
+
+pub fn setup_oauth2_router() -> Router<SharedState> {
+    Router::new()
+        .route("/authorize", get(authorize))
+        .route("/authorize/complete", get(complete_authorization))
+        .route("/token", post(token))
+        .route("/userinfo", get(userinfo).post(userinfo))
+        .route("/introspect", post(introspect))
+        .route("/revoke", post(revoke))
+        .route("/jwks", get(jwks))
+}
+
+#[derive(Serialize)]
+struct OAuth2Error {
+    error: &'static str,
+}
+
+fn oauth2_error(error: &'static str) -> Response {
+    let status = match error {
+        "invalid_client" => StatusCode::UNAUTHORIZED,
+        _ => StatusCode::BAD_REQUEST,
+    };
+    (status, Json(OAuth2Error { error })).into_response()
+}
+
+fn invalid_token() -> Response {
+    (
+        StatusCode::UNAUTHORIZED,
+        [(WWW_AUTHENTICATE, "Bearer error=\"invalid_token\"")],
+    )
+        .into_response()
+}
+
+/// The issuer has to be configured, the host header is controlled by the client
+fn issuer(state: &SharedState) -> Result<String, ApiError> {
+    let issuer = state.issuer().ok_or(ApiErrorKind::MiscInternal(
+        "OAuth2 requires the issuer to be configured",
+    ))?;
+    Ok(issuer.trim_end_matches('/').to_owned())
+}
+
+fn split_scopes(scope: Option<&str>) -> Vec<String> {
+    let mut scopes: Vec<String> = scope
+        .unwrap_or_default()
+        .split_whitespace()
+        .map(str::to_owned)
+        .collect();
+    scopes.sort();
+    scopes.dedup();
+    scopes
+}
+
+fn redirect_with(uri: &str, params: &[(&str, &str)]) -> Response {
+    let query = serde_urlencoded::to_string(params).unwrap_or_default();
+    let separator = if uri.contains('?') { '&' } else { '?' };
+    Redirect::to(&format!("{uri}{separator}{query}")).into_response()
+}
+
+fn flow_redirect(slug: &str, next: &str) -> Response {
+    let query = serde_urlencoded::to_string([("next", next)]).unwrap_or_default();
+    Redirect::to(&format!("{}/flow/{slug}?{query}", *INTERFACE_BASE_URI)).into_response()
+}
+
+async fn flow_slug(state: &SharedState, flow: Option<DataRef<model::Flow>>) -> Option<String> {
+    let flow = state.storage().lookup(&flow?).await?;
+    Some(flow.slug.clone())
+}
+
+#[derive(Debug, Deserialize)]
+struct AuthorizeQuery {
+    response_type: String,
+    client_id: String,
+    redirect_uri: Option<String>,
+    scope: Option<String>,
+    state: Option<String>,
+    nonce: Option<String>,
+    code_challenge: Option<String>,
+    code_challenge_method: Option<String>,
+}
+
+/// Starts the authorization flow of the client for the current session.
+/// Unauthenticated users are sent through the authentication flow of the tenant first.
+#[instrument(skip(session, tenant, state))]
+async fn authorize(
+    session: Session,
+    tenant: Data<Tenant>,
+    State(state): State<SharedState>,
+    OriginalUri(uri): OriginalUri,
+    Query(query): Query<AuthorizeQuery>,
+) -> Result<Response, ApiError> {
+    let connection = state.defaults().connection().await?;
+    let oauth2 = state.oauth2();
+    let Some(client) = oauth2.find_client(&connection, &query.client_id).await? else {
+        return Ok((StatusCode::BAD_REQUEST, "Unknown client").into_response()) };
+    // Errors are only sent back to the client once the redirect uri has been verified
+    let redirect_uri = match &query.redirect_uri {
+        Some(uri) if client.redirect_uris.contains(uri) => uri.clone(),
+        None if client.redirect_uris.len() == 1 => client.redirect_uris[0].clone(),
+        _ => return Ok((StatusCode::BAD_REQUEST, "Invalid redirect uri").into_response()),
+    };
+    let error = |error: &'static str| {
+        let mut params = vec![("error", error)];
+        if let Some(state) = &query.state {
+            params.push(("state", state.as_str()));
+        }
+        redirect_with(&redirect_uri, &params)
+    };
+    if query.response_type != "code" {
+        return Ok(error("unsupported_response_type"));
+    }
+    let scopes = split_scopes(query.scope.as_deref());
+    if scopes.iter().any(|scope| !client.scopes.contains(scope)) {
+        return Ok(error("invalid_scope"));
+    }
+    match (
+        &query.code_challenge,
+        query.code_challenge_method.as_deref(),
+    ) {
+        (Some(_), None | Some("plain") | Some("S256")) => {}
+        (Some(_), Some(_)) => return Ok(error("invalid_request")),
+        (None, _) if client.client_type == OAuth2ClientType::Public => {
+            return Ok(error("invalid_request"));
+        }
+        (None, _) => {}
+    }
+
+    if session.user_id.is_none() {
+        let authentication = tenant.get_flow(&FlowDesignation::Authentication);
+        let Some(slug) = flow_slug(&state, authentication).await else {
+            return Err(ApiErrorKind::NotFound.into_api()) };
+        return Ok(flow_redirect(&slug, &uri.to_string()));
+    }
+    let authorization = client
+        .authorization_flow
+        .map(|uid| DataRef::new(FlowQuery::uid(uid)))
+        .or_else(|| tenant.get_flow(&FlowDesignation::Authorization));
+    let Some(slug) = flow_slug(&state, authorization).await else {
+        return Err(ApiErrorKind::NotFound.into_api()) };
+
+    let request = AuthorizationRequest {
+        application: client.application,
+        redirect_uri,
+        scopes,
+        state: query.state,
+        nonce: query.nonce,
+        code_challenge: query.code_challenge,
+        code_challenge_method: query.code_challenge_method,
+        user: None,
+        auth_time: None,
+    };
+    let request_uid = oauth2
+        .create_request(&connection, &session.session_id, &request)
+        .await?;
+
+    let executor = state.executor();
+    let key = executor
+        .get_key(&session, DataRef::new(FlowQuery::slug(slug.clone())))
+        .ok_or(ApiErrorKind::MiscInternal("Key has slug instead of id").into_api())?;
+    executor.invalidate_flow(&key).await;
+    let execution = executor
+        .get_execution(&key, true)
+        .await
+        .ok_or(ApiErrorKind::NotFound.into_api())?;
+    execution.use_mut_context(|ctx| {
+        ctx.fields
+            .insert_value(APPLICATION.name, client.application.into());
+        ctx.fields
+            .insert_value(APPLICATION_NAME.name, client.application_name.into());
+        ctx.fields.insert_value(SCOPES.name, request.scopes.into());
+        ctx.fields
+            .insert_value(OAUTH2_REQUEST.name, request_uid.to_string().into());
+    });
+    executor.persist(&execution).await?;
+    Ok(flow_redirect(
+        &slug,
+        &format!("{OAUTH2_PATH}/authorize/complete?request={request_uid}"),
+    ))
+}
+
+#[derive(Debug, Deserialize)]
+struct CompleteQuery {
+    request: Uuid,
+}
+
+/// Target of the authorization flow, redirects back to the client with a new code.
+#[instrument(skip(session, state))]
+async fn complete_authorization(
+    session: Session,
+    State(state): State<SharedState>,
+    Query(query): Query<CompleteQuery>,
+) -> Result<Response, ApiError> {
+    let mut connection = state.defaults().connection().await?;
+    let connection = connection.transaction().await?;
+    let oauth2 = state.oauth2();
+    let Some(request) = oauth2
+        .take_approved_request(&connection, query.request, &session.session_id)
+        .await? else { return Err(ApiErrorKind::NotFound.into_api()) };
+    let code = oauth2.create_code(&connection, &request).await?;
+    connection.commit().await?;
+    let mut params = vec![("code", code.as_str())];
+    if let Some(state) = &request.state {
+        params.push(("state", state.as_str()));
+    }
+    Ok(redirect_with(&request.redirect_uri, &params))
+}
+
+/// Authenticates the client through basic authentication or the request body.
+async fn authenticate_client(
+    client: &impl GenericClient,
+    state: &SharedState,
+    headers: &HeaderMap,
+    client_id: Option<&String>,
+    client_secret: Option<&String>,
+) -> Result<Option<OAuth2Client>, ApiError> {
+    let basic = headers
+        .get(AUTHORIZATION)
+        .and_then(|value| value.to_str().ok())
+        .and_then(|value| value.strip_prefix("Basic "))
+        .and_then(|value| STANDARD.decode(value).ok())
+        .and_then(|value| String::from_utf8(value).ok());
+    let (client_id, client_secret) = match &basic {
+        Some(basic) => match basic.split_once(':') {
+            Some((id, secret)) => (id, Some(secret)),
+            None => return Ok(None),
+        },
+        None => match client_id {
+            Some(id) => (id.as_str(), client_secret.map(String::as_str)),
+            None => return Ok(None),
+        },
+    };
+    let oauth2 = state.oauth2();
+    let Some(found) = oauth2.find_client(client, client_id).await? else { return Ok(None) };
+    if !oauth2.verify_secret(&found, client_secret) {
+        return Ok(None);
+    }
+    Ok(Some(found))
+}
+
+#[derive(Deserialize)]
+struct TokenRequest {
+    grant_type: String,
+    code: Option<String>,
+    redirect_uri: Option<String>,
+    code_verifier: Option<String>,
+    refresh_token: Option<String>,
+    scope: Option<String>,
+    client_id: Option<String>,
+    client_secret: Option<String>,
+}
+
+#[derive(Serialize)]
+struct TokenResponse {
+    access_token: String,
+    token_type: &'static str,
+    expires_in: i64,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    refresh_token: Option<String>,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    id_token: Option<String>,
+    scope: String,
+}
+
+/// Either the issued tokens or the oauth2 error code.
+type GrantResult = Result<TokenResponse, &'static str>;
+
+#[instrument(skip(state, headers, form))]
+async fn token(
+    State(state): State<SharedState>,
+    headers: HeaderMap,
+    Form(form): Form<TokenRequest>,
+) -> Result<Response, ApiError> {
+    let mut connection = state.defaults().connection().await?;
+    let connection = connection.transaction().await?;
+    let Some(client) = authenticate_client(
+        &connection,
+        &state,
+        &headers,
+        form.client_id.as_ref(),
+        form.client_secret.as_ref(),
+    )
+    .await? else { return Ok(oauth2_error("invalid_client")) };
+    let issuer = issuer(&state)?;
+    let result = match form.grant_type.as_str() {
+        "authorization_code" => {
+            authorization_code_grant(&connection, &state, &issuer, &client, &form).await?
+        }
+        "refresh_token" => {
+            refresh_token_grant(&connection, &state, &issuer, &client, &form).await?
+        }
+        "client_credentials" => {
+            client_credentials_grant(&connection, &state, &client, &form).await?
+        }
+        _ => Err("unsupported_grant_type"),
+    };
+    match result {
+        Ok(response) => {
+            connection.commit().await?;
+            Ok(Json(response).into_response())
+        }
+        Err(error) => Ok(oauth2_error(error)),
+    }
+}
+
+fn verify_pkce(challenge: Option<&str>, method: Option<&str>, verifier: Option<&str>) -> bool {
+    match (challenge, verifier) {
+        (None, None) => true,
+        (Some(challenge), Some(verifier)) => match method {
+            Some("S256") => {
+                URL_SAFE_NO_PAD.encode(Sha256::digest(verifier.as_bytes())) == challenge
+            }
+            _ => verifier == challenge,
+        },
+        _ => false,
+    }
+}
+
+/// Checks that the code is redeemed with the redirect uri it has been issued for
+/// and with the verifier matching its challenge.
+fn check_code(code: &AuthorizationCode, form: &TokenRequest) -> Result<(), &'static str> {
+    // The redirect uri is always part of the code, either sent or defaulted
+    if form.redirect_uri.as_deref() != Some(code.redirect_uri.as_str()) {
+        return Err("invalid_grant");
+    }
+    if !verify_pkce(
+        code.code_challenge.as_deref(),
+        code.code_challenge_method.as_deref(),
+        form.code_verifier.as_deref(),
+    ) {
+        return Err("invalid_grant");
+    }
+    Ok(())
+}
+
+/// The scopes of a refreshed grant, they can only be narrowed down.
+fn refresh_scopes(
+    requested: Option<&str>,
+    granted: &[String],
+) -> Result<Vec<String>, &'static str> {
+    let scopes = match requested {
+        Some(scope) => split_scopes(Some(scope)),
+        None => granted.to_vec(),
+    };
+    if scopes.iter().any(|scope| !granted.contains(scope)) {
+        return Err("invalid_scope");
+    }
+    Ok(scopes)
+}
+
+/// The scopes a client may request for itself.
+fn client_credentials_scopes(
+    client: &OAuth2Client,
+    requested: Option<&str>,
+) -> Result<Vec<String>, &'static str> {
+    if client.client_type != OAuth2ClientType::Confidential {
+        return Err("unauthorized_client");
+    }
+    let scopes = split_scopes(requested);
+    if scopes.iter().any(|scope| !client.scopes.contains(scope)) {
+        return Err("invalid_scope");
+    }
+    Ok(scopes)
+}
+
+async fn authorization_code_grant(
+    connection: &impl GenericClient,
+    state: &SharedState,
+    issuer: &str,
+    client: &OAuth2Client,
+    form: &TokenRequest,
+) -> Result<GrantResult, ApiError> {
+    let Some(code) = &form.code else { return Ok(Err("invalid_request")) };
+    let Some(code) = state
+        .oauth2()
+        .take_code(connection, code, client.application)
+        .await? else { return Ok(Err("invalid_grant")) };
+    if let Err(error) = check_code(&code, form) {
+        return Ok(Err(error));
+    }
+    let grant = TokenGrant {
+        application: client.application,
+        user: Some(code.user),
+        scopes: code.scopes,
+        auth_time: Some(code.auth_time),
+    };
+    issue_tokens(connection, state, issuer, client, grant, code.nonce).await
+}
+
+async fn refresh_token_grant(
+    connection: &impl GenericClient,
+    state: &SharedState,
+    issuer: &str,
+    client: &OAuth2Client,
+    form: &TokenRequest,
+) -> Result<GrantResult, ApiError> {
+    let Some(token) = &form.refresh_token else { return Ok(Err("invalid_request")) };
+    let Some(token) = state
+        .oauth2()
+        .take_refresh_token(connection, token, client.application)
+        .await? else { return Ok(Err("invalid_grant")) };
+    let scopes = match refresh_scopes(form.scope.as_deref(), &token.scopes) {
+        Ok(scopes) => scopes,
+        Err(error) => return Ok(Err(error)),
+    };
+    let grant = TokenGrant {
+        application: client.application,
+        user: token.user,
+        scopes,
+        auth_time: token.auth_time,
+    };
+    issue_tokens(connection, state, issuer, client, grant, None).await
+}
+
+async fn client_credentials_grant(
+    connection: &impl GenericClient,
+    state: &SharedState,
+    client: &OAuth2Client,
+    form: &TokenRequest,
+) -> Result<GrantResult, ApiError> {
+    let scopes = match client_credentials_scopes(client, form.scope.as_deref()) {
+        Ok(scopes) => scopes,
+        Err(error) => return Ok(Err(error)),
+    };
+    let grant = TokenGrant {
+        application: client.application,
+        user: None,
+        scopes,
+        auth_time: None,
+    };
+    let (access_token, expires) = state
+        .oauth2()
+        .issue_token(
+            connection,
+            OAuth2TokenKind::Access,
+            &grant,
+            client.access_token_validity,
+        )
+        .await?;
+    Ok(Ok(TokenResponse {
+        access_token,
+        token_type: "Bearer",
+        expires_in: (expires - OffsetDateTime::now_utc()).whole_seconds(),
+        refresh_token: None,
+        id_token: None,
+        scope: grant.scopes.join(" "),
+    }))
+}
+
+#[derive(Serialize)]
+struct IdTokenClaims<'a> {
+    iss: &'a str,
+    aud: &'a str,
+    exp: i64,
+    iat: i64,
+    auth_time: i64,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    nonce: Option<String>,
+    #[serde(flatten)]
+    user: UserInfo,
+}
+
+/// Issues an access and a refresh token for the user of the grant,
+/// together with an id token if the `openid` scope has been granted.
+async fn issue_tokens(
+    connection: &impl GenericClient,
+    state: &SharedState,
+    issuer: &str,
+    client: &OAuth2Client,
+    grant: TokenGrant,
+    nonce: Option<String>,
+) -> Result<GrantResult, ApiError> {
+    let (Some(user), Some(auth_time)) = (grant.user, grant.auth_time) else { return Ok(Err("invalid_grant")) };
+    let oauth2 = state.oauth2();
+    let (access_token, expires) = oauth2
+        .issue_token(
+            connection,
+            OAuth2TokenKind::Access,
+            &grant,
+            client.access_token_validity,
+        )
+        .await?;
+    let (refresh_token, _) = oauth2
+        .issue_token(
+            connection,
+            OAuth2TokenKind::Refresh,
+            &grant,
+            client.refresh_token_validity,
+        )
+        .await?;
+    let now = OffsetDateTime::now_utc();
+    let id_token = if grant.scopes.iter().any(|scope| scope == "openid") {
+        let Some(info) = user_info(connection, state, user, &grant.scopes).await? else { return Ok(Err("invalid_grant")) };
+        let claims = IdTokenClaims {
+            iss: issuer,
+            aud: &client.client_id,
+            exp: expires.unix_timestamp(),
+            iat: now.unix_timestamp(),
+            auth_time: auth_time.unix_timestamp(),
+            nonce,
+            user: info,
+        };
+        Some(state.keys().sign(&claims)?)
+    } else {
+        None
+    };
+    Ok(Ok(TokenResponse {
+        access_token,
+        token_type: "Bearer",
+        expires_in: (expires - now).whole_seconds(),
+        refresh_token: Some(refresh_token),
+        id_token,
+        scope: grant.scopes.join(" "),
+    }))
+}
+
+#[derive(Serialize)]
+struct UserInfo {
+    sub: Uuid,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    name: Option<String>,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    preferred_username: Option<String>,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    groups: Option<Vec<String>>,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    email: Option<String>,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    email_verified: Option<bool>,
+}
+
+/// Collects the claims of the user which are covered by the scopes.
+async fn user_info(
+    connection: &impl GenericClient,
+    state: &SharedState,
+    user: Uuid,
+    scopes: &[String],
+) -> Result<Option<UserInfo>, ApiError> {
+    let Some(claims) = state.oauth2().user_claims(connection, user).await? else { return Ok(None) };
+    let has_scope = |name: &str| scopes.iter().any(|scope| scope == name);
+    let mut info = UserInfo {
+        sub: user,
+        name: None,
+        preferred_username: None,
+        groups: None,
+        email: None,
+        email_verified: None,
+    };
+    if has_scope("profile") {
+        let groups = state.users().user_groups(connection, user).await?;
+        info.name = Some(claims.display_name.unwrap_or_else(|| claims.name.clone()));
+        info.preferred_username = Some(claims.name);
+        info.groups = Some(groups.into_iter().map(|group| group.name).collect());
+    }
+    if has_scope("email") {
+        info.email_verified = claims.email.as_ref().map(|_| claims.email_verified);
+        info.email = claims.email;
+    }
+    Ok(Some(info))
+}
+
+fn bearer_token(headers: &HeaderMap) -> Option<&str> {
+    headers
+        .get(AUTHORIZATION)?
+        .to_str()
+        .ok()?
+        .strip_prefix("Bearer ")
+}
+
+#[instrument(skip(state, headers))]
+async fn userinfo(
+    State(state): State<SharedState>,
+    headers: HeaderMap,
+) -> Result<Response, ApiError> {
+    let Some(token) = bearer_token(&headers) else { return Ok(invalid_token()) };
+    let connection = state.defaults().connection().await?;
+    let Some(token) = state.oauth2().find_token(&connection, token).await? else { return Ok(invalid_token()) };
+    let (OAuth2TokenKind::Access, Some(user)) = (token.kind, token.user) else { return Ok(invalid_token()) };
+    let Some(info) = user_info(&connection, &state, user, &token.scopes).await? else { return Ok(invalid_token()) };
+    Ok(Json(info).into_response())
+}
+
+#[derive(Deserialize)]
+struct TokenForm {
+    token: String,
+    client_id: Option<String>,
+    client_secret: Option<String>,
+}
+
+#[derive(Serialize)]
+struct IntrospectionResponse {
+    active: bool,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    scope: Option<String>,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    client_id: Option<String>,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    sub: Option<Uuid>,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    exp: Option<i64>,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    iat: Option<i64>,
+}
+
+/// Token introspection as of RFC 7662, clients can only introspect their own tokens.
+#[instrument(skip(state, headers, form))]
+async fn introspect(
+    State(state): State<SharedState>,
+    headers: HeaderMap,
+    Form(form): Form<TokenForm>,
+) -> Result<Response, ApiError> {
+    let connection = state.defaults().connection().await?;
+    let Some(client) = authenticate_client(
+        &connection,
+        &state,
+        &headers,
+        form.client_id.as_ref(),
+        form.client_secret.as_ref(),
+    )
+    .await? else { return Ok(oauth2_error("invalid_client")) };
+    let token = state.oauth2().find_token(&connection, &form.token).await?;
+    let response = match token {
+        Some(token) if token.application == client.application => IntrospectionResponse {
+            active: true,
+            scope: Some(token.scopes.join(" ")),
+            client_id: Some(client.client_id),
+            sub: token.user,
+            exp: Some(token.expires.unix_timestamp()),
+            iat: Some(token.created.unix_timestamp()),
+        },
+        _ => IntrospectionResponse {
+            active: false,
+            scope: None,
+            client_id: None,
+            sub: None,
+            exp: None,
+            iat: None,
+        },
+    };
+    Ok(Json(response).into_response())
+}
+
+/// Token revocation as of RFC 7009, unknown tokens are not reported.
+#[instrument(skip(state, headers, form))]
+async fn revoke(
+    State(state): State<SharedState>,
+    headers: HeaderMap,
+    Form(form): Form<TokenForm>,
+) -> Result<Response, ApiError> {
+    let connection = state.defaults().connection().await?;
+    let Some(client) = authenticate_client(
+        &connection,
+        &state,
+        &headers,
+        form.client_id.as_ref(),
+        form.client_secret.as_ref(),
+    )
+    .await? else { return Ok(oauth2_error("invalid_client")) };
+    state
+        .oauth2()
+        .revoke_token(&connection, &form.token, client.application)
+        .await?;
+    Ok(StatusCode::OK.into_response())
+}
+
+pub async fn jwks(State(state): State<SharedState>) -> Json<Value> {
+    Json(state.keys().jwks())
+}
+
+pub async fn openid_configuration(
+    State(state): State<SharedState>,
+) -> Result<Json<Value>, ApiError> {
+    let issuer = issuer(&state)?;
+    let base = format!("{issuer}{OAUTH2_PATH}");
+    Ok(Json(json!({
+        "issuer": issuer,
+        "authorization_endpoint": format!("{base}/authorize"),
+        "token_endpoint": format!("{base}/token"),
+        "userinfo_endpoint": format!("{base}/userinfo"),
+        "jwks_uri": format!("{issuer}/.well-known/jwks.json"),
+        "introspection_endpoint": format!("{base}/introspect"),
+        "revocation_endpoint": format!("{base}/revoke"),
+        "response_types_supported": ["code"],
+        "grant_types_supported": ["authorization_code", "refresh_token", "client_credentials"],
+        "subject_types_supported": ["public"],
+        "id_token_signing_alg_values_supported": state.keys().signing_algorithms(),
+        "scopes_supported": SUPPORTED_SCOPES,
+        "token_endpoint_auth_methods_supported": ["client_secret_basic", "client_secret_post", "none"],
+        "code_challenge_methods_supported": ["plain", "S256"],
+        "claims_supported": ["sub", "iss", "aud", "exp", "iat", "auth_time", "nonce", "name", "preferred_username", "groups", "email", "email_verified"],
+    })))
+}
+
+#[cfg(test)]
+mod tests {
+    use base64::{engine::general_purpose::URL_SAFE_NO_PAD, Engine};
+    use model::{OAuth2Client, OAuth2ClientType};
+    use sha2::{Digest, Sha256};
+    use time::OffsetDateTime;
+    use uuid::Uuid;
+
+    use super::{
+        check_code, client_credentials_scopes, refresh_scopes, split_scopes, verify_pkce,
+        TokenRequest,
+    };
+    use crate::service::oauth2::AuthorizationCode;
+
+    const VERIFIER: &str = "dBjftJeZ4CVP-mB92K27uhbUJU1p1r_wW1gFWFOEjXk";
+
+    fn s256(verifier: &str) -> String {
+        URL_SAFE_NO_PAD.encode(Sha256::digest(verifier.as_bytes()))
+    }
+
+    fn code(challenge: Option<&str>, method: Option<&str>) -> AuthorizationCode {
+        AuthorizationCode {
+            user: Uuid::nil(),
+            redirect_uri: "https://app.example.com/callback".to_owned(),
+            scopes: vec!["openid".to_owned()],
+            nonce: None,
+            code_challenge: challenge.map(str::to_owned),
+            code_challenge_method: method.map(str::to_owned),
+            auth_time: OffsetDateTime::now_utc(),
+        }
+    }
+
+    fn form(redirect_uri: Option<&str>, verifier: Option<&str>) -> TokenRequest {
+        TokenRequest {
+            grant_type: "authorization_code".to_owned(),
+            code: Some("code".to_owned()),
+            redirect_uri: redirect_uri.map(str::to_owned),
+            code_verifier: verifier.map(str::to_owned),
+            refresh_token: None,
+            scope: None,
+            client_id: None,
+            client_secret: None,
+        }
+    }
+
+    fn client(client_type: OAuth2ClientType) -> OAuth2Client {
+        OAuth2Client {
+            provider: 1,
+            application: 1,
+            application_name: "App".to_owned(),
+            client_id: "client".to_owned(),
+            client_secret: None,
+            client_type,
+            redirect_uris: vec!["https://app.example.com/callback".to_owned()],
+            scopes: vec!["email".to_owned(), "openid".to_owned()],
+            authorization_flow: None,
+            access_token_validity: 600,
+            refresh_token_validity: 2592000,
+        }
+    }
+
+    fn scopes(scopes: &[&str]) -> Vec<String> {
+        scopes.iter().map(|scope| scope.to_string()).collect()
+    }
+
+    #[test]
+    fn pkce() {
+        assert!(verify_pkce(None, None, None));
+        assert!(verify_pkce(
+            Some(&s256(VERIFIER)),
+            Some("S256"),
+            Some(VERIFIER)
+        ));
+        assert!(!verify_pkce(
+            Some(&s256(VERIFIER)),
+            Some("S256"),
+            Some("other")
+        ));
+        assert!(!verify_pkce(Some(VERIFIER), Some("S256"), Some(VERIFIER)));
+        assert!(verify_pkce(Some(VERIFIER), Some("plain"), Some(VERIFIER)));
+        assert!(verify_pkce(Some(VERIFIER), None, Some(VERIFIER)));
+        assert!(!verify_pkce(Some(VERIFIER), None, Some("other")));
+        assert!(!verify_pkce(Some(VERIFIER), None, None));
+        assert!(!verify_pkce(None, None, Some(VERIFIER)));
+    }
+
+    #[test]
+    fn split() {
+        assert_eq!(split_scopes(None), Vec::<String>::new());
+        assert_eq!(split_scopes(Some("  ")), Vec::<String>::new());
+        assert_eq!(
+            split_scopes(Some("profile openid\temail openid")),
+            scopes(&["email", "openid", "profile"])
+        );
+    }
+
+    #[test]
+    fn authorization_code() {
+        let uri = Some("https://app.example.com/callback");
+        assert_eq!(check_code(&code(None, None), &form(uri, None)), Ok(()));
+        assert_eq!(
+            check_code(
+                &code(Some(&s256(VERIFIER)), Some("S256")),
+                &form(uri, Some(VERIFIER))
+            ),
+            Ok(())
+        );
+        assert_eq!(
+            check_code(&code(Some(&s256(VERIFIER)), Some("S256")), &form(uri, None)),
+            Err("invalid_grant")
+        );
+    }
+
+    #[test]
+    fn redirect_uri_mismatch() {
+        let code = code(None, None);
+        assert_eq!(
+            check_code(&code, &form(Some("https://evil.example/callback"), None)),
+            Err("invalid_grant")
+        );
+        assert_eq!(
+            check_code(
+                &code,
+                &form(Some("https://app.example.com/callback/"), None)
+            ),
+            Err("invalid_grant")
+        );
+        assert_eq!(check_code(&code, &form(None, None)), Err("invalid_grant"));
+    }
+
+    #[test]
+    fn refresh_token() {
+        let granted = scopes(&["email", "openid"]);
+        assert_eq!(refresh_scopes(None, &granted), Ok(granted.clone()));
+        assert_eq!(
+            refresh_scopes(Some("openid"), &granted),
+            Ok(scopes(&["openid"]))
+        );
+        assert_eq!(
+            refresh_scopes(Some("openid profile"), &granted),
+            Err("invalid_scope")
+        );
+    }
+
+    #[test]
+    fn client_credentials() {
+        let confidential = client(OAuth2ClientType::Confidential);
+        assert_eq!(
+            client_credentials_scopes(&confidential, None),
+            Ok(Vec::new())
+        );
+        assert_eq!(
+            client_credentials_scopes(&confidential, Some("email")),
+            Ok(scopes(&["email"]))
+        );
+        assert_eq!(
+            client_credentials_scopes(&confidential, Some("email profile")),
+            Err("invalid_scope")
+        );
+        assert_eq!(
+            client_credentials_scopes(&client(OAuth2ClientType::Public), Some("email")),
+            Err("unauthorized_client")
+        );
+    }
+}
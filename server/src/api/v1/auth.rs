@@ -1,8 +1,8 @@
-use std::fmt::Debug;
+use std::{fmt::Debug, net::SocketAddr};
 
 use async_trait::async_trait;
 use axum::{
-    extract::{FromRequestParts, State},
+    extract::{ConnectInfo, FromRequestParts, State},
     response::{IntoResponse, Response},
     routing::get,
     Json, Router,
@@ -10,14 +10,10 @@ use axum::{
 use deadpool_postgres::GenericClient;
 use futures::future::BoxFuture;
 
-use http::{request::Parts, Request};
-use jsonwebtoken::{Algorithm, DecodingKey, EncodingKey, Header, TokenData, Validation};
+use http::{header::USER_AGENT, request::Parts, Request};
+use jsonwebtoken::{TokenData, Validation};
 use model::user::PartialUser;
 use once_cell::sync::Lazy;
-use rand::{
-    distributions::{Alphanumeric, DistString},
-    rngs::OsRng,
-};
 use serde::{de::DeserializeOwned, Deserialize, Serialize};
 
 use tower::{Layer, Service};
@@ -28,6 +24,7 @@ use uuid::Uuid;
 use crate::{
     api::{ApiError, ApiErrorKind, AuthServiceData},
     auth::Session,
+    service::keys::KeyService,
     SharedState,
 };
 
@@ -102,7 +99,7 @@ where
         let mut inner = self.inner.clone();
         let data = self.data.clone();
         Box::pin(async move {
-            if let Err(err) = handle_auth(&mut req, &data) {
+            if let Err(err) = handle_auth(&mut req, &data).await {
                 return Ok(err);
             }
             Ok(inner.call(req).await.map(IntoResponse::into_response)?)
@@ -121,27 +118,6 @@ pub struct AuthExtensionData {
     pub claims: Claims,
 }
 
-#[derive(Debug, Serialize, Deserialize)]
-pub struct Oauth2Claims {
-    iss: String,
-    sub: String,
-    aud: String,
-    exp: u64,
-    iat: u64,
-    auth_time: u64,
-    acr: String,
-
-    email: String,
-    email_verified: bool,
-
-    name: String,
-    given_name: String,
-    family_name: String,
-    middle_name: String,
-    nickname: String,
-    preferred_username: String,
-}
-
 #[derive(Debug, Clone, Serialize, Deserialize)]
 pub struct Claims {
     pub sid: String,
@@ -149,33 +125,35 @@ pub struct Claims {
     pub sub: Option<Uuid>,
     pub authenticated: bool,
     pub is_admin: bool,
+    /// Latest point in time the session may be used, the session itself may expire earlier when idle
+    pub exp: i64,
 }
 
 const VALIDATION: Lazy<Validation> = Lazy::new(|| {
-    let mut validation = Validation::new(Algorithm::HS256);
-    validation.set_required_spec_claims(&["iss"]);
+    // The algorithm is taken from the key the token has been signed with
+    let mut validation = Validation::default();
+    validation.set_required_spec_claims(&["iss", "exp"]);
     validation.set_issuer(&["authust"]);
     validation
 });
 
-pub fn decode_token<T: DeserializeOwned>(
-    key: &DecodingKey,
+pub async fn decode_token<T: DeserializeOwned>(
+    keys: &KeyService,
     token: &str,
 ) -> Result<TokenData<T>, ApiError> {
-    Ok(jsonwebtoken::decode(token, key, &VALIDATION)?)
+    keys.verify(token, &VALIDATION).await
 }
 
-pub fn encode_token(key: &EncodingKey, claims: &Claims) -> Result<String, ApiError> {
-    let header = Header::new(Algorithm::HS256);
-    Ok(jsonwebtoken::encode(&header, claims, key)?)
+pub fn encode_token(keys: &KeyService, claims: &Claims) -> Result<String, ApiError> {
+    keys.sign(claims)
 }
 
 pub fn set_session_cookie(
-    key: &EncodingKey,
+    keys: &KeyService,
     cookies: &Cookies,
     claims: &Claims,
 ) -> Result<(), ApiError> {
-    let token = encode_token(key, claims)?;
+    let token = encode_token(keys, claims)?;
     let mut cookie = Cookie::new(SESSION_COOKIE_NAME, token);
     cookie.set_path("/");
     cookie.set_same_site(SameSite::Strict);
@@ -184,13 +162,21 @@ pub fn set_session_cookie(
     Ok(())
 }
 
-fn handle_auth<B>(req: &mut Request<B>, data: &AuthServiceData) -> Result<(), Response> {
+async fn handle_auth<B>(req: &mut Request<B>, data: &AuthServiceData) -> Result<(), Response> {
     let cookies: &Cookies = req.extensions().get().expect("Missing cookie layer");
     let Some(cookie) = cookies.get(SESSION_COOKIE_NAME) else {
         req.extensions_mut().insert(AuthExtension::MissingCookie);
         return Ok(()) };
     let value = cookie.value();
-    let token = decode_token(&data.decoding_key, value).map_err(|err| err.into_response())?;
+    // Expired or otherwise invalid cookies are replaced with a new session
+    let token = match decode_token::<Claims>(&data.keys, value).await {
+        Ok(token) => token,
+        Err(err) => {
+            tracing::debug!("Ignoring invalid session cookie {}", err.kind);
+            req.extensions_mut().insert(AuthExtension::MissingCookie);
+            return Ok(());
+        }
+    };
     req.extensions_mut()
         .insert(AuthExtension::Valid(AuthExtensionData {
             header: token.header,
@@ -215,24 +201,31 @@ fn get_auth_extension_data(req: &mut Parts) -> Result<AuthExtensionData, ApiErro
 async fn make_new_session<C: GenericClient>(
     client: &C,
     parts: &mut Parts,
-    data: &AuthServiceData,
+    state: &SharedState,
 ) -> Result<Session, ApiError> {
-    let session_key = Alphanumeric.sample_string(&mut OsRng, 96);
-    let statement = client
-        .prepare_cached("insert into sessions(uid) values($1)")
-        .await?;
-    client.execute(&statement, &[&session_key]).await?;
+    let ip = parts
+        .extensions
+        .get::<ConnectInfo<SocketAddr>>()
+        .map(|ConnectInfo(addr)| addr.ip());
+    let user_agent = parts
+        .headers
+        .get(USER_AGENT)
+        .and_then(|value| value.to_str().ok());
+    let sessions = state.sessions();
+    let session = sessions.create(client, ip, user_agent).await?;
     let claims = Claims {
-        sid: session_key.clone(),
+        sid: session.session_id.clone(),
         iss: "authust".to_owned(),
         sub: None,
         authenticated: false,
         is_admin: false,
+        exp: sessions.cookie_expiry(&session).unix_timestamp(),
     };
     let cookies: &Cookies = parts.extensions.get().expect("Cookie layer is missing");
-    set_session_cookie(&data.encoding_key, &cookies, &claims)?;
+    set_session_cookie(state.keys(), &cookies, &claims)?;
     Ok(Session {
-        session_id: session_key,
+        session_id: session.session_id,
+        id: session.id,
         user_id: None,
         is_admin: false,
     })
@@ -252,27 +245,22 @@ impl FromRequestParts<SharedState> for Session {
             Ok(v) => Ok(v),
             Err(err) => match &err.kind {
                 ApiErrorKind::SessionCookieMissing => {
-                    return make_new_session(&connection, parts, state.auth_data()).await;
+                    return make_new_session(&connection, parts, state).await;
                 }
                 _ => Err(err),
             },
         }?;
         let claims = data.claims;
-        let statement = connection
-            .prepare_cached("select user_id from sessions where uid = $1")
-            .await?;
-        let res = connection
-            .query_opt(&statement, &[&claims.sid])
-            .await?
-            .map(|v| v.get::<_, Option<Uuid>>(0));
-        if let Some(user_id) = res {
+        let session = state.sessions().lookup(&connection, &claims.sid).await?;
+        if let Some(session) = session {
             Ok(Session {
-                session_id: claims.sid,
-                user_id,
+                session_id: session.session_id,
+                id: session.id,
+                user_id: session.user_id,
                 is_admin: claims.is_admin,
             })
         } else {
-            make_new_session(&connection, parts, state.auth_data()).await
+            make_new_session(&connection, parts, state).await
         }
     }
 }
@@ -0,0 +1,38 @@
+use axum::{extract::State, routing::get, Json, Router};
+use model::blueprint::Blueprint;
+use storage::{
+    blueprint::BlueprintStore,
+    lint::{lint as lint_blueprint, Diagnostic},
+};
+use tracing::instrument;
+
+use crate::{api::ApiError, SharedState};
+
+use super::auth::AdminSession;
+
+/// Blueprints are applied with the `tools blueprint` command,
+/// the api only exports and lints the current configuration.
+pub fn setup_blueprint_router() -> Router<SharedState> {
+    Router::new()
+        .route("/", get(export))
+        .route("/lint", get(lint))
+}
+
+#[instrument(skip(state))]
+async fn export(
+    _: AdminSession,
+    State(state): State<SharedState>,
+) -> Result<Json<Blueprint>, ApiError> {
+    let store = BlueprintStore::new(state.defaults().pool());
+    Ok(Json(store.export().await?))
+}
+
+#[instrument(skip(state))]
+async fn lint(
+    _: AdminSession,
+    State(state): State<SharedState>,
+) -> Result<Json<Vec<Diagnostic>>, ApiError> {
+    let store = BlueprintStore::new(state.defaults().pool());
+    let blueprint = store.export().await?;
+    Ok(Json(lint_blueprint(&blueprint)))
+}
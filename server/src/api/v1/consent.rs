@@ -0,0 +1,76 @@
+use axum::{
+    extract::{Path, State},
+    response::{IntoResponse, Response},
+    routing::{delete, get},
+    Json, Router,
+};
+use http::StatusCode;
+use model::Consent;
+use tracing::instrument;
+use uuid::Uuid;
+
+use crate::{
+    api::{ApiError, ApiErrorKind},
+    auth::Session,
+    SharedState,
+};
+
+use super::auth::AdminSession;
+
+pub fn setup_consent_router() -> Router<SharedState> {
+    Router::new()
+        .route("/", get(list))
+        .route("/:uid", delete(revoke))
+        .route("/users/:user", get(list_for_user))
+        .route("/users/:user/:uid", delete(revoke_for_user))
+}
+
+#[instrument(skip(state, session))]
+async fn list(
+    session: Session,
+    State(state): State<SharedState>,
+) -> Result<Json<Vec<Consent>>, ApiError> {
+    let user = session.user_id.ok_or(ApiErrorKind::Unauthorized)?;
+    let connection = state.defaults().connection().await?;
+    let consents = state.consents().list_for_user(&connection, user).await?;
+    Ok(Json(consents))
+}
+
+#[instrument(skip(state, session))]
+async fn revoke(
+    session: Session,
+    Path(uid): Path<Uuid>,
+    State(state): State<SharedState>,
+) -> Result<Response, ApiError> {
+    let user = session.user_id.ok_or(ApiErrorKind::Unauthorized)?;
+    revoke_consent(&state, user, uid).await
+}
+
+#[instrument(skip(state))]
+async fn list_for_user(
+    _: AdminSession,
+    Path(user): Path<Uuid>,
+    State(state): State<SharedState>,
+) -> Result<Json<Vec<Consent>>, ApiError> {
+    let connection = state.defaults().connection().await?;
+    let consents = state.consents().list_for_user(&connection, user).await?;
+    Ok(Json(consents))
+}
+
+#[instrument(skip(state))]
+async fn revoke_for_user(
+    _: AdminSession,
+    Path((user, uid)): Path<(Uuid, Uuid)>,
+    State(state): State<SharedState>,
+) -> Result<Response, ApiError> {
+    revoke_consent(&state, user, uid).await
+}
+
+async fn revoke_consent(state: &SharedState, user: Uuid, uid: Uuid) -> Result<Response, ApiError> {
+    let connection = state.defaults().connection().await?;
+    if state.consents().revoke(&connection, user, uid).await? {
+        Ok(StatusCode::NO_CONTENT.into_response())
+    } else {
+        Err(ApiErrorKind::NotFound.into_api())
+    }
+}
@@ -1,31 +1,55 @@
 use argon2::{password_hash::Encoding, PasswordHash};
+use async_trait::async_trait;
 use axum::{
     extract::{Host, OriginalUri, State},
     response::{IntoResponse, Redirect, Response},
     routing::{get, MethodRouter},
     Form, Json, Router,
 };
+use base64::{engine::general_purpose::URL_SAFE_NO_PAD, Engine};
 
 use deadpool_postgres::GenericClient;
 use policy_engine::uri::Scheme;
+use serde::{de::DeserializeOwned, Serialize};
 use serde_json::Value;
 use storage::datacache::Data;
+use time::OffsetDateTime;
 use tower_cookies::Cookies;
 use tracing::instrument;
+use uuid::Uuid;
 
 use crate::{
-    api::{ApiError, ApiErrorKind, AuthServiceData, ExecutorQuery, RefWrapper},
+    api::{ApiError, ApiErrorKind, ExecutorQuery, RefWrapper},
     auth::Session,
     executor::{
+        fields::{
+            APPLICATION, ATTRIBUTE_PREFIX, DISPLAY_NAME, EMAIL, EMAIL_NONCE, EMAIL_SENT_TO,
+            OAUTH2_REQUEST, PASSWORD, PASSWORD_VERIFIED, SCOPES, STATIC_CODES, TOTP_FAILURES,
+            TOTP_SECRET, USERNAME, WEBAUTHN_CHALLENGE, WEBAUTHN_CREDENTIALS,
+        },
         flow::{CheckContextRequest, FlowExecution},
-        ExecutionError, FlowExecutor,
+        password,
+        prompt::validate_prompt,
+        totp,
+        webauthn::{self, AssertionResponse, Challenge, RegistrationResponse},
+        ExecutionError, FieldKey, FieldStorage,
+    },
+    interface::flow::INTERFACE_BASE_URI,
+    service::{
+        consent::{ConsentService, ConsentTarget},
+        email::{self, link_base, mask_address, EmailClaims},
+        policy::PolicyService,
+        static_codes::StaticCodeService,
+        totp::TotpService,
+        user::{hash_password, UserWrite},
+        webauthn::WebAuthnService,
     },
-    service::user::UserService,
     SharedState,
 };
 use model::{
     error::{FieldError, FieldErrorKind, FieldType, SubmissionError},
-    Flow, FlowData, PasswordBackend, PendingUser, Stage, StageKind, UserField,
+    ConsentMode, Flow, FlowBindingKind, FlowData, PasswordBackend, PendingUser, PolicyKind,
+    PromptBinding, Stage, StageKind, StaticMode, TotpMode, UserField, WebAuthnMode,
 };
 
 use super::{
@@ -33,6 +57,8 @@ use super::{
     ping_handler,
 };
 
+pub mod simulate;
+
 pub fn setup_executor_router() -> Router<SharedState> {
     Router::new().route("/ping", get(ping_handler)).route(
         "/:flow_slug",
@@ -40,12 +66,13 @@ pub fn setup_executor_router() -> Router<SharedState> {
     )
 }
 
-#[instrument(skip(state, session))]
+#[instrument(skip(state, session, cookies))]
 async fn get_flow(
     session: Session,
     RefWrapper(flow): RefWrapper<Flow>,
     State(state): State<SharedState>,
     query: Option<ExecutorQuery>,
+    cookies: Cookies,
     OriginalUri(uri): OriginalUri,
     Host(host): Host,
 ) -> Result<Json<FlowData>, ApiError> {
@@ -57,7 +84,12 @@ async fn get_flow(
         .get_execution(&key, true)
         .await
         .ok_or(ApiErrorKind::NotFound.into_api())?;
-    let connection = state.defaults().connection().await?;
+    if execution.is_expired().await {
+        executor.invalidate_flow(&key).await;
+        return Ok(Json(execution.expired_data()));
+    }
+    let mut connection = state.defaults().connection().await?;
+    let connection = connection.transaction().await?;
     let context = CheckContextRequest {
         uri,
         host,
@@ -66,6 +98,41 @@ async fn get_flow(
         user: session.get_user(&connection, &state).await?,
     };
     let context = execution.get_check_context(context);
+    if let Some(token) = &context.request.query.email_token {
+        match open_email_link(&state, &session, &execution, token).await {
+            Ok(EmailLink::Opened) => executor.persist(&execution).await?,
+            Ok(EmailLink::Used) => {}
+            Err(err) => match &err.kind {
+                ApiErrorKind::SubmissionError(err) => {
+                    return Ok(Json(execution.data(Some(err.clone()), &context).await));
+                }
+                _ => return Err(err),
+            },
+        }
+    }
+    if let Ok(None) = execution.check(&context).await {
+        // Skippable stages are passed, server side stages wait for a submission
+        let entry_idx = execution.entry_index();
+        let was_completed = execution.is_completed();
+        if let Some(err) = complete_or_rewind(
+            &connection,
+            &execution,
+            &state,
+            &cookies,
+            session,
+            entry_idx,
+            false,
+        )
+        .await?
+        {
+            executor.persist(&execution).await?;
+            return Ok(Json(execution.data(Some(err), &context).await));
+        }
+        connection.commit().await?;
+        if execution.entry_index() != entry_idx || execution.is_completed() != was_completed {
+            executor.persist(&execution).await?;
+        }
+    }
     let data = execution.data(None, &context).await;
     Ok(Json(data))
 }
@@ -89,6 +156,10 @@ async fn post_flow(
         .get_execution(&key, false)
         .await
         .ok_or(ApiErrorKind::NotFound.into_api())?;
+    if execution.is_expired().await {
+        executor.invalidate_flow(&key).await;
+        return Ok(Json(execution.expired_data()).into_response());
+    }
     let mut connection = state.defaults().connection().await?;
     let connection = connection.transaction().await?;
     let context = CheckContextRequest {
@@ -102,36 +173,47 @@ async fn post_flow(
     if let Ok(Some(_)) = execution.check(&context).await {
         return Ok(Json(execution.data(None, &context).await).into_response());
     }
-    if let Err(err) =
-        handle_submission(&connection, form, executor, &state.users(), &execution).await
-    {
+    if let Err(err) = handle_submission(&connection, form, &state, &session, &execution).await {
         match &err.kind {
             ApiErrorKind::SubmissionError(err) => {
+                executor.persist(&execution).await?;
                 Ok(Json(execution.data(Some(err.clone()), &context).await).into_response())
             }
             _ => Err(err),
         }
     } else {
-        execution.complete_current();
-        complete(
+        let entry_idx = execution.entry_index();
+        // A submitted server side stage has only been confirmed, it runs while completing
+        let stage = execution.lookup_stage(&execution.get_entry().stage).await;
+        if stage.kind.requires_input() {
+            execution.complete_current();
+        }
+        if let Some(err) = complete_or_rewind(
             &connection,
             &execution,
-            &state.auth_data(),
+            &state,
             &cookies,
             session,
+            entry_idx,
+            true,
         )
-        .await?;
+        .await?
+        {
+            executor.persist(&execution).await?;
+            return Ok(Json(execution.data(Some(err), &context).await).into_response());
+        }
         connection.commit().await?;
+        executor.persist(&execution).await?;
         Ok(Redirect::to(uri.to_string().as_str()).into_response())
     }
 }
 
-#[instrument(skip(form, client, executor, users, execution))]
+#[instrument(skip(form, client, state, session, execution))]
 async fn handle_submission(
     client: &impl GenericClient,
     form: Value,
-    executor: &FlowExecutor,
-    users: &UserService,
+    state: &SharedState,
+    session: &Session,
     execution: &FlowExecution,
 ) -> Result<(), ApiError> {
     if execution.is_completed() {
@@ -139,25 +221,36 @@ async fn handle_submission(
     }
     let entry = execution.get_entry();
     let stage = execution.lookup_stage(&entry.stage).await;
-    handle_stage(form, client, executor, users, execution, stage).await
+    handle_stage(form, client, state, session, execution, stage).await
 }
 
-#[instrument(skip(form, client, _executor, users, execution))]
+#[instrument(skip(form, client, state, session, execution))]
 async fn handle_stage(
     form: Value,
     client: &impl GenericClient,
-    _executor: &FlowExecutor,
-    users: &UserService,
+    state: &SharedState,
+    session: &Session,
     execution: &FlowExecution,
     stage: Data<Stage>,
 ) -> Result<(), ApiError> {
     match &stage.kind {
         StageKind::Deny => return Ok(()),
-        StageKind::Prompt { bindings: _ } => return Ok(()),
+        StageKind::Prompt { bindings } => {
+            return handle_prompt_stage(&form, state.policies(), execution, bindings).await;
+        }
         StageKind::Identification {
             password,
             user_fields,
+            passwordless,
         } => {
+            // A challenge is only valid for a single attempt
+            let challenge = take_webauthn_challenge(execution);
+            if let (true, Some(_)) = (*passwordless, form.get("credential")) {
+                return handle_passwordless_identification(
+                    &form, client, state, execution, challenge,
+                )
+                .await;
+            }
             let uid = str_from_field(
                 "uid",
                 form.get("uid")
@@ -166,7 +259,8 @@ async fn handle_stage(
                         FieldErrorKind::Missing,
                     )))?,
             )?;
-            let user = users
+            let user = state
+                .users()
                 .lookup_user(
                     client,
                     uid,
@@ -205,15 +299,772 @@ async fn handle_stage(
             return Ok(());
         }
         StageKind::UserLogin => return Ok(()),
-        StageKind::UserLogout => return Ok(()),
+        StageKind::UserLogout { .. } => return Ok(()),
         StageKind::UserWrite => return Ok(()),
         StageKind::Password { backends } => {
             return handle_password_stage(&form, client, execution, backends).await;
         }
-        StageKind::Consent { mode: _ } => return Ok(()),
+        StageKind::Consent { mode } => {
+            return handle_consent_stage(client, state.consents(), session, execution, mode).await;
+        }
+        StageKind::AuthenticatorTotp {
+            mode: TotpMode::Setup,
+            drift,
+            ..
+        } => {
+            return handle_totp_setup(&form, client, state.totp(), session, execution, *drift)
+                .await;
+        }
+        StageKind::AuthenticatorTotp {
+            mode: TotpMode::Validate,
+            drift,
+            ..
+        } => {
+            let static_codes = state.static_codes();
+            return handle_totp_validate(
+                &form,
+                client,
+                state.totp(),
+                static_codes,
+                session,
+                execution,
+                *drift,
+            )
+            .await;
+        }
+        StageKind::AuthenticatorWebAuthn {
+            mode: WebAuthnMode::Register,
+            ..
+        } => {
+            return handle_webauthn_register(&form, client, state.webauthn(), session, execution)
+                .await;
+        }
+        StageKind::AuthenticatorWebAuthn {
+            mode: WebAuthnMode::Authenticate,
+            ..
+        } => {
+            return handle_webauthn_authenticate(
+                &form,
+                client,
+                state.webauthn(),
+                state.static_codes(),
+                session,
+                execution,
+            )
+            .await;
+        }
+        StageKind::AuthenticatorStatic {
+            mode: StaticMode::Setup,
+            ..
+        } => {
+            return handle_static_setup(client, state.static_codes(), session, execution).await;
+        }
+        StageKind::AuthenticatorStatic {
+            mode: StaticMode::Validate,
+            ..
+        } => {
+            return handle_static_validate(&form, client, state.static_codes(), session, execution)
+                .await;
+        }
+        // The stage is completed by opening the link which has been sent
+        StageKind::Email { .. } => {
+            let missing = FieldError::new("email_token", FieldErrorKind::Missing);
+            return Err(SubmissionError::from(missing).into());
+        }
+        StageKind::PasswordChange => {
+            return handle_password_change(&form, client, state, session, execution).await;
+        }
     };
 }
 
+#[instrument(skip(form, policies, execution))]
+async fn handle_prompt_stage(
+    form: &Value,
+    policies: &PolicyService,
+    execution: &FlowExecution,
+    bindings: &Vec<PromptBinding>,
+) -> Result<(), ApiError> {
+    let mut values = Vec::with_capacity(bindings.len());
+    let mut errors = Vec::new();
+    for binding in bindings {
+        let prompt = execution.lookup_prompt(&binding.prompt).await;
+        match validate_prompt(&prompt, form.get(&prompt.field_key)) {
+            Ok(Some(value)) => values.push((prompt.field_key.clone(), value)),
+            Ok(None) => {}
+            Err(err) => errors.push(err),
+        }
+    }
+    if errors.is_empty() {
+        errors = check_password_policies(policies, execution, &values).await;
+    }
+    if let Some(err) = SubmissionError::from_fields(errors) {
+        return Err(err.into());
+    }
+    execution.use_mut_context(|ctx| {
+        for (key, value) in values {
+            ctx.fields.insert_value(&key, value);
+        }
+    });
+    Ok(())
+}
+
+/// Checks the submitted values against the password policies bound to the current entry.
+async fn check_password_policies(
+    policies: &PolicyService,
+    execution: &FlowExecution,
+    values: &[(String, Value)],
+) -> Vec<FieldError> {
+    let mut errors = Vec::new();
+    let bindings = execution.get_entry().bindings.iter();
+    for binding in bindings.filter(|binding| binding.enabled && !binding.negate) {
+        let FlowBindingKind::Policy(policy) = &binding.kind else { continue };
+        let policy = execution.lookup_policy(policy).await;
+        let (mut fields, names) = {
+            let context = execution.get_context();
+            let names: Vec<String> = context
+                .user
+                .iter()
+                .map(|user| user.name.clone())
+                .chain(context.pending.iter().map(|user| user.name.clone()))
+                .collect();
+            (context.fields.clone(), names)
+        };
+        for (key, value) in values {
+            fields.insert_value(key, value.clone());
+        }
+        let names: Vec<&str> = names.iter().map(String::as_str).collect();
+        match &policy.kind {
+            PolicyKind::PasswordStrength(rules) => {
+                match password::check_collected(rules, &fields, &names) {
+                    Some(messages) if !messages.is_empty() => errors.push(FieldError::new(
+                        &rules.field_key,
+                        FieldErrorKind::invalid(messages.join(" ")),
+                    )),
+                    _ => {}
+                }
+            }
+            PolicyKind::BreachedPassword { field_key } => {
+                let Some(filter) = policies.breached_passwords() else { continue };
+                if password::is_breached(filter, field_key, &fields) == Some(true) {
+                    errors.push(FieldError::new(
+                        field_key,
+                        FieldErrorKind::invalid(
+                            "This password has appeared in a data breach, please choose a different one.",
+                        ),
+                    ));
+                }
+            }
+            _ => {}
+        }
+    }
+    errors
+}
+
+#[instrument(skip(client, consents, session, execution))]
+async fn handle_consent_stage(
+    client: &impl GenericClient,
+    consents: &ConsentService,
+    session: &Session,
+    execution: &FlowExecution,
+    mode: &ConsentMode,
+) -> Result<(), ApiError> {
+    let user = acting_user(execution, session.user_id).ok_or(SubmissionError::NoPendingUser)?;
+    let expires = match mode {
+        ConsentMode::Always => return Ok(()),
+        ConsentMode::Once => None,
+        ConsentMode::Until { duration } => {
+            Some(OffsetDateTime::now_utc() + time::Duration::seconds(*duration as i64))
+        }
+    };
+    let (target, scopes) = consent_request(execution);
+    consents
+        .grant(client, user, &target, &scopes, expires)
+        .await
+}
+
+/// The user the execution acts for, which is the pending user if there is one.
+fn acting_user(execution: &FlowExecution, session_user: Option<Uuid>) -> Option<Uuid> {
+    match &execution.get_context().pending {
+        Some(pending) if pending.authenticated => Some(pending.uid),
+        Some(_) => None,
+        None => session_user,
+    }
+}
+
+fn consent_request(execution: &FlowExecution) -> (ConsentTarget, Vec<String>) {
+    let context = execution.get_context();
+    let target = match context.fields.get_typed(APPLICATION) {
+        Ok(Some(application)) => ConsentTarget::Application(application),
+        _ => ConsentTarget::Flow(execution.flow_uid()),
+    };
+    let scopes = context
+        .fields
+        .get_typed(SCOPES)
+        .ok()
+        .flatten()
+        .unwrap_or_default();
+    (target, scopes)
+}
+
+async fn has_valid_consent(
+    client: &impl GenericClient,
+    consents: &ConsentService,
+    session: &Session,
+    execution: &FlowExecution,
+    mode: &ConsentMode,
+) -> Result<bool, ApiError> {
+    if let ConsentMode::Always = mode {
+        return Ok(false);
+    }
+    let Some(user) = acting_user(execution, session.user_id) else { return Ok(false) };
+    let (target, scopes) = consent_request(execution);
+    consents.has_consent(client, user, &target, &scopes).await
+}
+
+/// Confirms the device which is being set up with its first code.
+#[instrument(skip(form, client, totp, session, execution))]
+async fn handle_totp_setup(
+    form: &Value,
+    client: &impl GenericClient,
+    totp: &TotpService,
+    session: &Session,
+    execution: &FlowExecution,
+    drift: i16,
+) -> Result<(), ApiError> {
+    let user = acting_user(execution, session.user_id).ok_or(SubmissionError::NoPendingUser)?;
+    let code = submitted_code(form)?;
+    let secret = execution
+        .get_context()
+        .fields
+        .get_typed(TOTP_SECRET)
+        .ok()
+        .flatten()
+        .and_then(|secret| totp::decode_base32(&secret))
+        // The secret is generated once the stage is shown
+        .ok_or_else(invalid_code)?;
+    let now = OffsetDateTime::now_utc().unix_timestamp();
+    let Some(step) = totp::verify(&secret, code, now, drift, None) else { return Err(invalid_code()) };
+    totp.add_device(client, user, &secret, step).await?;
+    execution.use_mut_context(|ctx| {
+        ctx.fields.remove(TOTP_SECRET.name);
+    });
+    Ok(())
+}
+
+/// Recovery codes are accepted instead of a code of the device, in case it has been lost.
+#[instrument(skip(form, client, totp, static_codes, session, execution))]
+async fn handle_totp_validate(
+    form: &Value,
+    client: &impl GenericClient,
+    totp: &TotpService,
+    static_codes: &StaticCodeService,
+    session: &Session,
+    execution: &FlowExecution,
+    drift: i16,
+) -> Result<(), ApiError> {
+    let user = factor_user(execution, session).ok_or(SubmissionError::NoPendingUser)?;
+    let code = submitted_code(form)?;
+    if !totp.verify(client, user, code, drift).await?
+        && !static_codes.verify(client, user, code).await?
+    {
+        let mut exhausted = false;
+        execution.use_mut_context(|ctx| {
+            exhausted = totp::record_failure(&mut ctx.fields);
+            if exhausted {
+                ctx.pending = None;
+                ctx.fields.remove(PASSWORD_VERIFIED.name);
+            }
+        });
+        if exhausted {
+            // The user has to identify themselves again before guessing further codes
+            execution.rewind(0);
+            return Err(SubmissionError::Field(FieldError::new(
+                "code",
+                FieldErrorKind::invalid("Too many invalid codes"),
+            ))
+            .into());
+        }
+        return Err(invalid_code());
+    }
+    execution.use_mut_context(|ctx| {
+        ctx.fields.remove(TOTP_FAILURES.name);
+        if let Some(pending) = ctx.pending.as_mut() {
+            pending.authenticated = true;
+        }
+    });
+    Ok(())
+}
+
+/// The user whose second factor is validated, pending users do not have to be authenticated yet.
+fn factor_user(execution: &FlowExecution, session: &Session) -> Option<Uuid> {
+    match &execution.get_context().pending {
+        Some(pending) => Some(pending.uid),
+        None => session.user_id,
+    }
+}
+
+fn submitted_code(form: &Value) -> Result<&String, SubmissionError> {
+    str_from_field(
+        "code",
+        form.get("code")
+            .ok_or(SubmissionError::Field(FieldError::new(
+                "code",
+                FieldErrorKind::Missing,
+            )))?,
+    )
+}
+
+fn invalid_code() -> ApiError {
+    SubmissionError::Field(FieldError::new(
+        "code",
+        FieldErrorKind::invalid("Invalid code"),
+    ))
+    .into()
+}
+
+/// Validation stages are skipped for users without a device.
+async fn skips_totp_validation(
+    client: &impl GenericClient,
+    totp: &TotpService,
+    session: &Session,
+    execution: &FlowExecution,
+) -> Result<bool, ApiError> {
+    let Some(user) = factor_user(execution, session) else { return Ok(true) };
+    Ok(!totp.has_device(client, user).await?)
+}
+
+/// Validation stages are skipped for users without recovery codes
+/// and for pending users who have already passed another factor, the codes are only a fallback.
+async fn skips_static_validation(
+    client: &impl GenericClient,
+    static_codes: &StaticCodeService,
+    session: &Session,
+    execution: &FlowExecution,
+) -> Result<bool, ApiError> {
+    let authenticated = match &execution.get_context().pending {
+        Some(pending) => pending.authenticated,
+        None => false,
+    };
+    if authenticated {
+        return Ok(true);
+    }
+    let Some(user) = factor_user(execution, session) else { return Ok(true) };
+    Ok(!static_codes.has_codes(client, user).await?)
+}
+
+/// Stores the codes shown to the acting user, once they have confirmed saving them.
+/// Previous codes of the user are replaced.
+#[instrument(skip(client, static_codes, session, execution))]
+async fn handle_static_setup(
+    client: &impl GenericClient,
+    static_codes: &StaticCodeService,
+    session: &Session,
+    execution: &FlowExecution,
+) -> Result<(), ApiError> {
+    let user = acting_user(execution, session.user_id).ok_or(SubmissionError::NoPendingUser)?;
+    let codes = execution
+        .get_context()
+        .fields
+        .get_typed(STATIC_CODES)
+        .ok()
+        .flatten()
+        // The codes are generated once the stage is shown
+        .ok_or(ApiErrorKind::MiscInternal("Missing static codes"))?;
+    static_codes.replace(client, user, &codes).await?;
+    execution.use_mut_context(|ctx| {
+        ctx.fields.remove(STATIC_CODES.name);
+    });
+    Ok(())
+}
+
+#[instrument(skip(form, client, static_codes, session, execution))]
+async fn handle_static_validate(
+    form: &Value,
+    client: &impl GenericClient,
+    static_codes: &StaticCodeService,
+    session: &Session,
+    execution: &FlowExecution,
+) -> Result<(), ApiError> {
+    let user = factor_user(execution, session).ok_or(SubmissionError::NoPendingUser)?;
+    let code = submitted_code(form)?;
+    if !static_codes.verify(client, user, code).await? {
+        return Err(invalid_code());
+    }
+    execution.use_mut_context(|ctx| {
+        if let Some(pending) = ctx.pending.as_mut() {
+            pending.authenticated = true;
+        }
+    });
+    Ok(())
+}
+
+fn is_factor_validation(kind: &StageKind) -> bool {
+    matches!(
+        kind,
+        StageKind::AuthenticatorTotp {
+            mode: TotpMode::Validate,
+            ..
+        } | StageKind::AuthenticatorWebAuthn {
+            mode: WebAuthnMode::Authenticate,
+            ..
+        } | StageKind::AuthenticatorStatic {
+            mode: StaticMode::Validate,
+            ..
+        }
+    )
+}
+
+/// Whether a later entry validates a second factor.
+/// Whether the user has to pass it is only known once the entry is reached.
+async fn validates_factor_later(execution: &FlowExecution) -> bool {
+    for entry in execution.upcoming_entries() {
+        let stage = execution.lookup_stage(&entry.stage).await;
+        if is_factor_validation(&stage.kind) {
+            return true;
+        }
+    }
+    false
+}
+
+/// Called when a validation stage is skipped because the pending user has not set up the factor.
+/// If no later stage validates another factor, the verified password is all the user has to pass.
+async fn skip_factor_validation(execution: &FlowExecution) {
+    if validates_factor_later(execution).await {
+        return;
+    }
+    let verified = execution
+        .get_context()
+        .fields
+        .get_typed(PASSWORD_VERIFIED)
+        .ok()
+        .flatten();
+    execution.use_mut_context(|ctx| {
+        if let Some(pending) = ctx.pending.as_mut() {
+            pending.authenticated |= verified == Some(pending.uid);
+        }
+    });
+}
+
+/// Registers the credential created by the authenticator of the acting user.
+#[instrument(skip(form, client, webauthn, session, execution))]
+async fn handle_webauthn_register(
+    form: &Value,
+    client: &impl GenericClient,
+    webauthn: &WebAuthnService,
+    session: &Session,
+    execution: &FlowExecution,
+) -> Result<(), ApiError> {
+    let challenge = take_webauthn_challenge(execution);
+    let user = acting_user(execution, session.user_id).ok_or(SubmissionError::NoPendingUser)?;
+    let response: RegistrationResponse = webauthn_credential(form)?;
+    // The challenge is generated once the stage is shown
+    let challenge = challenge.ok_or_else(|| invalid_credential("Challenge has expired"))?;
+    let credential =
+        webauthn::verify_registration(&challenge, &response).map_err(invalid_credential)?;
+    let added = webauthn
+        .add_credential(
+            client,
+            user,
+            &credential.id,
+            &credential.public_key,
+            credential.sign_count,
+        )
+        .await?;
+    if !added {
+        return Err(invalid_credential("Credential is already registered"));
+    }
+    Ok(())
+}
+
+#[instrument(skip(form, client, webauthn, static_codes, session, execution))]
+async fn handle_webauthn_authenticate(
+    form: &Value,
+    client: &impl GenericClient,
+    webauthn: &WebAuthnService,
+    static_codes: &StaticCodeService,
+    session: &Session,
+    execution: &FlowExecution,
+) -> Result<(), ApiError> {
+    let challenge = take_webauthn_challenge(execution);
+    let user = factor_user(execution, session).ok_or(SubmissionError::NoPendingUser)?;
+    // A recovery code replaces the credential, in case the authenticator has been lost
+    let code = form
+        .get("code")
+        .and_then(Value::as_str)
+        .filter(|code| !code.is_empty());
+    if let Some(code) = code {
+        if !static_codes.verify(client, user, code).await? {
+            return Err(invalid_code());
+        }
+    } else {
+        let response: AssertionResponse = webauthn_credential(form)?;
+        let challenge = challenge.ok_or_else(|| invalid_credential("Challenge has expired"))?;
+        if verify_webauthn_assertion(client, webauthn, &challenge, &response).await? != user {
+            return Err(invalid_credential("Unknown credential"));
+        }
+    }
+    execution.use_mut_context(|ctx| {
+        if let Some(pending) = ctx.pending.as_mut() {
+            pending.authenticated = true;
+        }
+    });
+    Ok(())
+}
+
+/// Identifies and authenticates the user with a discoverable credential, which replaces the password.
+#[instrument(skip(form, client, state, execution, challenge))]
+async fn handle_passwordless_identification(
+    form: &Value,
+    client: &impl GenericClient,
+    state: &SharedState,
+    execution: &FlowExecution,
+    challenge: Option<Challenge>,
+) -> Result<(), ApiError> {
+    let response: AssertionResponse = webauthn_credential(form)?;
+    let challenge = challenge.ok_or_else(|| invalid_credential("Challenge has expired"))?;
+    // Only discoverable credentials return the user they belong to
+    if response
+        .user_handle()
+        .map_err(invalid_credential)?
+        .is_none()
+    {
+        return Err(invalid_credential("Credential is not discoverable"));
+    }
+    let user = verify_webauthn_assertion(client, state.webauthn(), &challenge, &response).await?;
+    let user = state
+        .users()
+        .lookup_user_uid(client, user)
+        .await?
+        .ok_or_else(|| invalid_credential("Unknown credential"))?;
+    execution.use_mut_context(move |ctx| {
+        ctx.pending = Some(PendingUser {
+            uid: user.uid,
+            name: user.name,
+            avatar_url: None,
+            authenticated: true,
+            is_admin: user.is_admin,
+        });
+    });
+    Ok(())
+}
+
+/// Verifies the assertion with the stored credential and returns the user it belongs to.
+/// The credential stays locked until the transaction ends, so its counter cannot be raced.
+async fn verify_webauthn_assertion(
+    client: &impl GenericClient,
+    webauthn: &WebAuthnService,
+    challenge: &Challenge,
+    response: &AssertionResponse,
+) -> Result<Uuid, ApiError> {
+    let credential_id = response.credential_id().map_err(invalid_credential)?;
+    let Some(stored) = webauthn.find(client, &credential_id).await? else { return Err(invalid_credential("Unknown credential")) };
+    if let Some(handle) = response.user_handle().map_err(invalid_credential)? {
+        if handle != stored.user {
+            return Err(invalid_credential("Unknown credential"));
+        }
+    }
+    let sign_count =
+        webauthn::verify_assertion(challenge, response, &stored.public_key, stored.sign_count)
+            .map_err(invalid_credential)?;
+    webauthn
+        .update_sign_count(client, stored.uid, sign_count)
+        .await?;
+    Ok(stored.user)
+}
+
+fn take_webauthn_challenge(execution: &FlowExecution) -> Option<Challenge> {
+    let challenge = execution
+        .get_context()
+        .fields
+        .get_typed(WEBAUTHN_CHALLENGE)
+        .ok()
+        .flatten();
+    execution.use_mut_context(|ctx| {
+        ctx.fields.remove(WEBAUTHN_CHALLENGE.name);
+    });
+    challenge
+}
+
+/// The `PublicKeyCredential` returned by the browser, serialized as json.
+fn webauthn_credential<T: DeserializeOwned>(form: &Value) -> Result<T, ApiError> {
+    let credential = str_from_field(
+        "credential",
+        form.get("credential")
+            .ok_or(SubmissionError::Field(FieldError::new(
+                "credential",
+                FieldErrorKind::Missing,
+            )))?,
+    )?;
+    serde_json::from_str(credential).map_err(|_| invalid_credential("Malformed credential"))
+}
+
+fn invalid_credential(message: impl ToString) -> ApiError {
+    SubmissionError::Field(FieldError::new(
+        "credential",
+        FieldErrorKind::invalid(message.to_string()),
+    ))
+    .into()
+}
+
+/// Stores the credentials of the user, so the authenticator can offer them.
+/// Authentication stages are skipped for users without a credential.
+async fn load_webauthn_credentials(
+    client: &impl GenericClient,
+    webauthn: &WebAuthnService,
+    session: &Session,
+    execution: &FlowExecution,
+    mode: WebAuthnMode,
+) -> Result<bool, ApiError> {
+    let credentials = match factor_user(execution, session) {
+        Some(user) => webauthn.credential_ids(client, user).await?,
+        None => Vec::new(),
+    };
+    let skip = mode == WebAuthnMode::Authenticate && credentials.is_empty();
+    let credentials: Vec<String> = credentials
+        .iter()
+        .map(|id| URL_SAFE_NO_PAD.encode(id))
+        .collect();
+    execution.use_mut_context(|ctx| {
+        let _ = ctx.fields.insert_typed(WEBAUTHN_CREDENTIALS, credentials);
+    });
+    Ok(skip)
+}
+
+/// Variables of the templates used by the email stage.
+#[derive(Serialize)]
+struct EmailMessage<'a> {
+    subject: &'a str,
+    flow: String,
+    link: String,
+    /// Minutes the link is valid for
+    expiry: i32,
+}
+
+/// Sends the link of the email stage, unless it has already been sent.
+#[instrument(skip(client, state, session, execution))]
+async fn send_email_link(
+    client: &impl GenericClient,
+    state: &SharedState,
+    session: &Session,
+    execution: &FlowExecution,
+    subject: &str,
+    template: &str,
+    token_expiry: i32,
+) -> Result<(), ApiError> {
+    let sent = execution
+        .get_context()
+        .fields
+        .get_value(EMAIL_NONCE.name)
+        .is_some();
+    if sent {
+        return Ok(());
+    }
+    // Opening the link authenticates the pending user, e.g. during recovery
+    let user = factor_user(execution, session).ok_or(SubmissionError::NoPendingUser)?;
+    let Some(address) = state.users().lookup_email(client, user).await? else { return Err(unknown_address()) };
+    let base = link_base(*INTERFACE_BASE_URI, state.issuer()).ok_or(ApiErrorKind::MiscInternal(
+        "Links sent by email require the issuer to be configured",
+    ))?;
+    let expires = OffsetDateTime::now_utc() + time::Duration::minutes(token_expiry as i64);
+    let slug = execution.flow_slug();
+    let claims = EmailClaims::new(
+        session.id,
+        slug.clone(),
+        user,
+        address.clone(),
+        expires.unix_timestamp(),
+    );
+    let token = email::encode_token(state.keys(), &claims)?;
+    let message = EmailMessage {
+        subject,
+        flow: execution.flow_title(),
+        link: format!("{base}/flow/{slug}?email_token={token}"),
+        expiry: token_expiry,
+    };
+    state
+        .email()
+        .send(&address, subject, template, &message)
+        .await?;
+    execution.use_mut_context(|ctx| {
+        let _ = ctx.fields.insert_typed(EMAIL_NONCE, claims.nonce);
+        let _ = ctx
+            .fields
+            .insert_typed(EMAIL_SENT_TO, mask_address(&address));
+    });
+    Ok(())
+}
+
+fn unknown_address() -> ApiError {
+    SubmissionError::Field(FieldError::new(
+        EMAIL.name,
+        FieldErrorKind::invalid("No email address is known for this user"),
+    ))
+    .into()
+}
+
+enum EmailLink {
+    /// The email stage of the current execution has been completed
+    Opened,
+    /// The link has been opened before
+    Used,
+}
+
+/// Completes the email stage of the execution the link has been sent for, which also authenticates the user.
+/// The link only works in the session it has been sent from, otherwise opening it would authenticate
+/// whoever started the flow, e.g. someone recovering the account of the owner of the address.
+#[instrument(skip(state, session, execution, token))]
+async fn open_email_link(
+    state: &SharedState,
+    session: &Session,
+    execution: &FlowExecution,
+    token: &str,
+) -> Result<EmailLink, ApiError> {
+    let claims = email::decode_token(state.keys(), token)
+        .await
+        .map_err(|_| invalid_email_link())?;
+    if claims.flow != execution.flow_slug() {
+        return Err(invalid_email_link());
+    }
+    if claims.session != session.id {
+        return Err(SubmissionError::from(FieldError::new(
+            "email_token",
+            FieldErrorKind::invalid("Open the link in the browser you requested it from"),
+        ))
+        .into());
+    }
+    let stage = execution.lookup_stage(&execution.get_entry().stage).await;
+    let waiting = !execution.is_completed() && matches!(stage.kind, StageKind::Email { .. });
+    let nonce = execution.get_context().fields.get_typed(EMAIL_NONCE);
+    if !waiting || nonce.ok().flatten().as_ref() != Some(&claims.nonce) {
+        return Ok(EmailLink::Used);
+    }
+    let connection = state.defaults().connection().await?;
+    let users = state.users();
+    if !users
+        .verify_email(&connection, claims.sub, &claims.email)
+        .await?
+    {
+        return Err(invalid_email_link());
+    }
+    execution.use_mut_context(|ctx| {
+        ctx.fields.remove(EMAIL_NONCE.name);
+        ctx.fields.remove(EMAIL_SENT_TO.name);
+        if let Some(pending) = ctx.pending.as_mut() {
+            pending.authenticated |= pending.uid == claims.sub;
+        }
+    });
+    execution.complete_current();
+    Ok(EmailLink::Opened)
+}
+
+fn invalid_email_link() -> ApiError {
+    SubmissionError::Field(FieldError::new(
+        "email_token",
+        FieldErrorKind::invalid("This link is invalid or has expired"),
+    ))
+    .into()
+}
+
 #[instrument(skip(form, client, execution))]
 async fn handle_password_stage(
     form: &Value,
@@ -234,32 +1085,19 @@ async fn handle_password_stage(
             )))?,
     )?;
     if backends.contains(&PasswordBackend::Internal) {
-        let statement = client
-            .prepare_cached("select password from users where uid = $1")
-            .await?;
-        let res: Option<String> = client
-            .query_opt(&statement, &[&pending.uid])
-            .await?
-            .map(|v| v.get(0));
-        match res {
-            Some(res) => {
-                let hash = PasswordHash::parse(&res, Encoding::B64)?;
-                let is_valid = match hash.verify_password(&[&argon2::Argon2::default()], password) {
-                    Ok(_) => true,
-                    Err(err) => match err {
-                        argon2::password_hash::Error::Password => false,
-                        err => return Err(err.into()),
-                    },
-                };
-                if is_valid {
-                    execution.use_mut_context(|ctx| {
-                        ctx.pending
-                            .as_mut()
-                            .map(|pending| pending.authenticated = true);
-                    });
-                    return Ok(());
-                }
+        match verify_internal_password(client, pending.uid, password).await? {
+            Some(true) => {
+                // Otherwise the validation stages complete the authentication once they are reached
+                let authenticated = !validates_factor_later(execution).await;
+                execution.use_mut_context(|ctx| {
+                    let _ = ctx.fields.insert_typed(PASSWORD_VERIFIED, pending.uid);
+                    ctx.pending
+                        .as_mut()
+                        .map(|pending| pending.authenticated = authenticated);
+                });
+                return Ok(());
             }
+            Some(false) => {}
             None => return Err(SubmissionError::NoPendingUser.into()),
         }
     }
@@ -270,6 +1108,132 @@ async fn handle_password_stage(
     .into());
 }
 
+/// Checks `password` against the hash stored for the user, `None` if the user does not exist.
+async fn verify_internal_password(
+    client: &impl GenericClient,
+    uid: Uuid,
+    password: &str,
+) -> Result<Option<bool>, ApiError> {
+    let statement = client
+        .prepare_cached("select password from users where uid = $1")
+        .await?;
+    let res: Option<String> = client
+        .query_opt(&statement, &[&uid])
+        .await?
+        .map(|v| v.get(0));
+    let Some(res) = res else { return Ok(None) };
+    let hash = PasswordHash::parse(&res, Encoding::B64)?;
+    match hash.verify_password(&[&argon2::Argon2::default()], password) {
+        Ok(_) => Ok(Some(true)),
+        Err(argon2::password_hash::Error::Password) => Ok(Some(false)),
+        Err(err) => Err(err.into()),
+    }
+}
+
+/// Replaces the password of the acting user, once the current one has been confirmed.
+/// The new password is checked against the password policies bound to the entry.
+#[instrument(skip(form, client, state, session, execution))]
+async fn handle_password_change(
+    form: &Value,
+    client: &impl GenericClient,
+    state: &SharedState,
+    session: &Session,
+    execution: &FlowExecution,
+) -> Result<(), ApiError> {
+    let user = acting_user(execution, session.user_id).ok_or(SubmissionError::NoPendingUser)?;
+    let current = required_str(form, "current_password")?;
+    let password = required_str(form, PASSWORD.name)?;
+    if verify_internal_password(client, user, current).await? != Some(true) {
+        return Err(SubmissionError::from(FieldError::new(
+            "current_password",
+            FieldErrorKind::invalid("Invalid Password"),
+        ))
+        .into());
+    }
+    if password == current {
+        return Err(SubmissionError::from(FieldError::new(
+            PASSWORD.name,
+            FieldErrorKind::invalid("The new password must differ from the current one"),
+        ))
+        .into());
+    }
+    let values = [(PASSWORD.name.to_owned(), Value::String(password.clone()))];
+    let errors = check_password_policies(state.policies(), execution, &values).await;
+    if let Some(err) = SubmissionError::from_fields(errors) {
+        return Err(err.into());
+    }
+    let write = UserWrite {
+        password_hash: Some(hash_password(password)?),
+        ..Default::default()
+    };
+    state.users().write_user(client, Some(user), write).await?;
+    Ok(())
+}
+
+fn user_write_from_fields(fields: &FieldStorage) -> Result<UserWrite, ApiError> {
+    let name = typed_field(fields, USERNAME)?.map(|name| name.trim().to_lowercase());
+    let email = typed_field(fields, EMAIL)?;
+    let display_name = typed_field(fields, DISPLAY_NAME)?;
+    check_length(USERNAME.name, &name, 32)?;
+    check_length(EMAIL.name, &email, 64)?;
+    check_length(DISPLAY_NAME.name, &display_name, 32)?;
+    let password_hash = match typed_field(fields, PASSWORD)? {
+        Some(password) => Some(hash_password(&password)?),
+        None => None,
+    };
+    let attributes = fields
+        .iter()
+        .filter_map(|(key, value)| {
+            key.strip_prefix(ATTRIBUTE_PREFIX)
+                .map(|key| (key.to_owned(), value.clone()))
+        })
+        .collect();
+    Ok(UserWrite {
+        name,
+        email,
+        display_name,
+        password_hash,
+        attributes,
+    })
+}
+
+fn typed_field<T: DeserializeOwned>(
+    fields: &FieldStorage,
+    key: FieldKey<T>,
+) -> Result<Option<T>, SubmissionError> {
+    let name = key.name;
+    fields.get_typed(key).map_err(|_| {
+        SubmissionError::Field(FieldError::new(
+            name,
+            FieldErrorKind::invalid("Invalid value"),
+        ))
+    })
+}
+
+fn check_length(
+    name: &'static str,
+    value: &Option<String>,
+    max: usize,
+) -> Result<(), SubmissionError> {
+    match value {
+        Some(value) if value.chars().count() > max => Err(SubmissionError::Field(FieldError::new(
+            name,
+            FieldErrorKind::invalid(format!("At most {max} characters")),
+        ))),
+        _ => Ok(()),
+    }
+}
+
+fn required_str<'a>(form: &'a Value, name: &'static str) -> Result<&'a String, SubmissionError> {
+    let value = form
+        .get(name)
+        .ok_or(SubmissionError::Field(FieldError::new(
+            name,
+            FieldErrorKind::Missing,
+        )))?;
+    str_from_field(name, value)
+}
+
 fn str_from_field<'a>(name: &'static str, value: &'a Value) -> Result<&'a String, SubmissionError> {
     match value {
         Value::String(value) => Ok(value),
@@ -280,22 +1244,249 @@ fn str_from_field<'a>(name: &'static str, value: &'a Value) -> Result<&'a String
     }
 }
 
-#[instrument(skip(client, execution, keys, cookies, session))]
-async fn complete(
-    client: &impl GenericClient,
+/// Runs the server side stages of the execution.
+/// If one of them rejects the collected data the execution is moved back to `entry_idx`
+/// and the error is returned so it can be displayed.
+async fn complete_or_rewind(
+    client: &(impl GenericClient + Sync),
     execution: &FlowExecution,
-    keys: &AuthServiceData,
+    state: &SharedState,
     cookies: &Cookies,
     session: Session,
+    entry_idx: usize,
+    confirmed: bool,
+) -> Result<Option<SubmissionError>, ApiError> {
+    let session_id = session.session_id.clone();
+    let session_user = session.user_id;
+    let mut effects = SessionEffects {
+        state,
+        cookies,
+        session,
+    };
+    let result = complete(client, execution, state, &mut effects, confirmed).await;
+    match result {
+        Ok(()) => {
+            if execution.is_completed() {
+                approve_authorization(client, state, execution, &session_id, session_user).await?;
+            }
+            Ok(None)
+        }
+        Err(err) => match err.kind {
+            ApiErrorKind::SubmissionError(err) => {
+                execution.rewind(entry_idx);
+                Ok(Some(err))
+            }
+            _ => Err(err),
+        },
+    }
+}
+
+/// Approves the oauth2 authorization request the execution has been started for.
+async fn approve_authorization(
+    client: &impl GenericClient,
+    state: &SharedState,
+    execution: &FlowExecution,
+    session_id: &str,
+    session_user: Option<Uuid>,
+) -> Result<(), ApiError> {
+    let request = execution
+        .get_context()
+        .fields
+        .get_typed(OAUTH2_REQUEST)
+        .ok()
+        .flatten();
+    let (Some(request), Some(user)) = (request, acting_user(execution, session_user)) else { return Ok(()) };
+    state
+        .oauth2()
+        .approve_request(client, request, session_id, user)
+        .await?;
+    Ok(())
+}
+
+/// Side effects of the stages passed while completing an execution.
+/// Real executions change the session and send mails, simulations only record them.
+#[async_trait]
+trait StageEffects: Send {
+    /// The session the execution runs in.
+    fn session(&self) -> Session;
+
+    /// Logs the session in as the authenticated pending user.
+    async fn login<C: GenericClient + Sync>(
+        &mut self,
+        client: &C,
+        user: &PendingUser,
+    ) -> Result<(), ApiError>;
+
+    /// Logs the session out, `terminate_all` also ends the other sessions of the user.
+    async fn logout<C: GenericClient + Sync>(
+        &mut self,
+        client: &C,
+        execution: &FlowExecution,
+        terminate_all: bool,
+    ) -> Result<(), ApiError>;
+
+    /// Sends the link of the email stage.
+    async fn send_email_link<C: GenericClient + Sync>(
+        &mut self,
+        client: &C,
+        execution: &FlowExecution,
+        subject: &str,
+        template: &str,
+        token_expiry: i32,
+    ) -> Result<(), ApiError>;
+
+    /// Called for every stage the execution passes without input.
+    async fn passed(&mut self, _execution: &FlowExecution) {}
+}
+
+/// Effects of a real execution on the session of the request.
+struct SessionEffects<'a> {
+    state: &'a SharedState,
+    cookies: &'a Cookies,
+    session: Session,
+}
+
+#[async_trait]
+impl StageEffects for SessionEffects<'_> {
+    fn session(&self) -> Session {
+        self.session.clone()
+    }
+
+    async fn login<C: GenericClient + Sync>(
+        &mut self,
+        client: &C,
+        user: &PendingUser,
+    ) -> Result<(), ApiError> {
+        let keys = self.state.keys();
+        let cookie = self
+            .cookies
+            .get(SESSION_COOKIE_NAME)
+            .ok_or(ApiErrorKind::InvalidSessionCookie)?;
+        let mut claims: Claims = decode_token(keys, cookie.value()).await?.claims;
+        claims.sub = Some(user.uid);
+        claims.authenticated = true;
+        claims.is_admin = user.is_admin;
+        set_session_cookie(keys, self.cookies, &claims)?;
+        let statement = client
+            .prepare_cached("update sessions set user_id = $1 where uid = $2")
+            .await?;
+        client
+            .execute(&statement, &[&user.uid, &self.session.session_id])
+            .await?;
+        Ok(())
+    }
+
+    async fn logout<C: GenericClient + Sync>(
+        &mut self,
+        client: &C,
+        execution: &FlowExecution,
+        terminate_all: bool,
+    ) -> Result<(), ApiError> {
+        let keys = self.state.keys();
+        let cookie = self
+            .cookies
+            .get(SESSION_COOKIE_NAME)
+            .ok_or(ApiErrorKind::InvalidSessionCookie)?;
+        let mut claims: Claims = decode_token(keys, cookie.value()).await?.claims;
+        claims.sub = None;
+        claims.authenticated = false;
+        claims.is_admin = false;
+        set_session_cookie(keys, self.cookies, &claims)?;
+        if let (true, Some(user_id)) = (terminate_all, self.session.user_id) {
+            let statement = client
+                .prepare_cached("delete from sessions where user_id = $1 and uid <> $2")
+                .await?;
+            client
+                .execute(&statement, &[&user_id, &self.session.session_id])
+                .await?;
+        }
+        let statement = client
+            .prepare_cached("update sessions set user_id = null where uid = $1")
+            .await?;
+        client
+            .execute(&statement, &[&self.session.session_id])
+            .await?;
+        execution.invalidate_other_executions().await;
+        Ok(())
+    }
+
+    async fn send_email_link<C: GenericClient + Sync>(
+        &mut self,
+        client: &C,
+        execution: &FlowExecution,
+        subject: &str,
+        template: &str,
+        token_expiry: i32,
+    ) -> Result<(), ApiError> {
+        let session = &self.session;
+        send_email_link(
+            client,
+            self.state,
+            session,
+            execution,
+            subject,
+            template,
+            token_expiry,
+        )
+        .await
+    }
+}
+
+/// Advances the execution past stages which need no input, shared by real and simulated executions.
+/// Server side stages change the session or the user, they only run once `confirmed`
+/// by a submission, so that following a link cannot log the user in or out.
+#[instrument(skip(client, execution, state, effects))]
+async fn complete<C: GenericClient + Sync, E: StageEffects>(
+    client: &C,
+    execution: &FlowExecution,
+    state: &SharedState,
+    effects: &mut E,
+    confirmed: bool,
 ) -> Result<(), ApiError> {
     let mut iterations = 0;
     loop {
         if execution.is_completed() {
             break;
         }
+        let session = effects.session();
         let entry = execution.get_entry();
         let stage = execution.lookup_stage(&entry.stage).await;
-        if stage.kind.requires_input() {
+        let skip = match &stage.kind {
+            StageKind::Consent { mode } => {
+                let consents = state.consents();
+                has_valid_consent(client, consents, &session, execution, mode).await?
+            }
+            StageKind::AuthenticatorTotp {
+                mode: TotpMode::Validate,
+                ..
+            } => skips_totp_validation(client, state.totp(), &session, execution).await?,
+            StageKind::AuthenticatorWebAuthn { mode, .. } => {
+                let webauthn = state.webauthn();
+                load_webauthn_credentials(client, webauthn, &session, execution, *mode).await?
+            }
+            StageKind::AuthenticatorStatic {
+                mode: StaticMode::Validate,
+                ..
+            } => skips_static_validation(client, state.static_codes(), &session, execution).await?,
+            StageKind::Email {
+                subject,
+                template,
+                token_expiry,
+            } => {
+                effects
+                    .send_email_link(client, execution, subject, template, *token_expiry)
+                    .await?;
+                false
+            }
+            _ => false,
+        };
+        if skip && is_factor_validation(&stage.kind) {
+            skip_factor_validation(execution).await;
+        }
+        if stage.kind.requires_input() && !skip {
+            break;
+        }
+        if !stage.kind.requires_input() && !confirmed {
             break;
         }
         if iterations > 40 {
@@ -319,27 +1510,53 @@ async fn complete(
                         stage: Some(entry.stage.clone()),
                         message: "Authentication of pending user failed".into(),
                     });
+                    effects.passed(execution).await;
                     break;
                 }
-                let cookie = cookies
-                    .get(SESSION_COOKIE_NAME)
-                    .ok_or(ApiErrorKind::InvalidSessionCookie)?;
-                let mut claims: Claims = decode_token(&keys.decoding_key, cookie.value())?.claims;
-                claims.sub = Some(user.uid);
-                claims.authenticated = true;
-                claims.is_admin = user.is_admin;
-                set_session_cookie(&keys.encoding_key, cookies, &claims)?;
-                let statement = client
-                    .prepare_cached("update sessions set user_id = $1 where uid = $2")
-                    .await?;
-                client
-                    .execute(&statement, &[&user.uid, &session.session_id])
-                    .await?;
+                effects.login(client, &user).await?;
+            }
+            StageKind::UserLogout { terminate_all } => {
+                effects.logout(client, execution, terminate_all).await?;
+                execution.use_mut_context(|ctx| {
+                    ctx.pending = None;
+                    ctx.user = None;
+                });
+            }
+            StageKind::UserWrite => {
+                let pending = execution.get_context().pending.clone();
+                let uid = match pending {
+                    Some(pending) if !pending.authenticated => {
+                        execution.set_error(ExecutionError {
+                            stage: Some(entry.stage.clone()),
+                            message: "Pending user must be authenticated before it can be changed"
+                                .into(),
+                        });
+                        effects.passed(execution).await;
+                        break;
+                    }
+                    Some(pending) => Some(pending.uid),
+                    None => session.user_id,
+                };
+                let write = user_write_from_fields(&execution.get_context().fields)?;
+                let user = state.users().write_user(client, uid, write).await?;
+                execution.use_mut_context(move |ctx| {
+                    ctx.fields.remove(PASSWORD.name);
+                    ctx.pending = Some(PendingUser {
+                        uid: user.uid,
+                        name: user.name,
+                        avatar_url: None,
+                        authenticated: true,
+                        is_admin: user.is_admin,
+                    });
+                });
             }
-            StageKind::UserLogout => todo!(),
-            StageKind::UserWrite => todo!(),
+            StageKind::Consent { .. }
+            | StageKind::AuthenticatorTotp { .. }
+            | StageKind::AuthenticatorWebAuthn { .. }
+            | StageKind::AuthenticatorStatic { .. } => {}
             _ => unreachable!("Encountered client side stage"),
         }
+        effects.passed(execution).await;
         execution.complete_current();
     }
     Ok(())
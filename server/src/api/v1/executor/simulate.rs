@@ -0,0 +1,329 @@
+use std::collections::{HashMap, VecDeque};
+
+use async_trait::async_trait;
+use axum::{
+    extract::{Path, State},
+    response::{IntoResponse, Response},
+    Json,
+};
+use deadpool_postgres::GenericClient;
+use http::StatusCode;
+use model::{error::SubmissionError, user::PartialUser, FlowQuery, PendingUser};
+use serde::{Deserialize, Serialize};
+use serde_json::Value;
+use storage::datacache::DataRef;
+use time::OffsetDateTime;
+use tracing::instrument;
+use uuid::Uuid;
+
+use crate::{
+    api::{v1::auth::AdminSession, ApiError, ApiErrorKind, ExecutorQuery},
+    auth::Session,
+    executor::{
+        flow::{CheckContextRequest, CheckDecision, FlowExecution},
+        SIMULATION_SESSION,
+    },
+    service::policy::evaluate::TestContext,
+    SharedState,
+};
+
+use super::{complete, handle_stage, StageEffects};
+
+const MAX_STEPS: usize = 100;
+
+/// A synthetic request to run a flow with.
+#[derive(Debug, Deserialize)]
+pub struct SimulationRequest {
+    #[serde(flatten)]
+    pub context: TestContext,
+    /// Redirect the flow completes with
+    #[serde(default)]
+    pub next: Option<String>,
+    /// Forms submitted to stages requiring input by the slug of the stage.
+    /// A stage rejecting a form is shown again and receives the next form.
+    #[serde(default)]
+    pub submissions: HashMap<String, VecDeque<Value>>,
+}
+
+#[derive(Debug, Serialize)]
+pub struct Simulation {
+    pub steps: Vec<SimulationStep>,
+    pub outcome: SimulationOutcome,
+}
+
+/// An entry the execution reached.
+#[derive(Debug, Serialize)]
+pub struct SimulationStep {
+    pub entry: usize,
+    pub stage: String,
+    /// Whether the stage has been shown to the user, server side stages are run right away
+    pub shown: bool,
+    pub decisions: Vec<CheckDecision>,
+    pub form: Option<Value>,
+    pub error: Option<SubmissionError>,
+}
+
+#[derive(Debug, Serialize)]
+#[serde(tag = "outcome", rename_all = "snake_case")]
+pub enum SimulationOutcome {
+    Completed {
+        redirect: Option<String>,
+        user: Option<Uuid>,
+    },
+    Denied {
+        message: String,
+    },
+    Error {
+        message: String,
+    },
+    /// No form is left for the stage the execution stopped at
+    AwaitingInput {
+        stage: String,
+    },
+    /// The submitted forms never complete the flow
+    StepLimit,
+}
+
+/// Runs the flow without a session.
+/// Writes of server side stages are rolled back, cookies and sessions are never changed.
+#[instrument(skip(state, request))]
+pub async fn simulate(
+    _: AdminSession,
+    Path(slug): Path<String>,
+    State(state): State<SharedState>,
+    Json(request): Json<SimulationRequest>,
+) -> Result<Response, ApiError> {
+    let check_context = match request.context.check_context() {
+        Ok(check_context) => check_context,
+        Err(message) => return Ok((StatusCode::BAD_REQUEST, message).into_response()),
+    };
+    let execution = state
+        .executor()
+        .simulate(DataRef::new(FlowQuery::slug(slug)))
+        .await
+        .ok_or(ApiErrorKind::NotFound.into_api())?;
+    let mut connection = state.defaults().connection().await?;
+    // Never committed
+    let connection = connection.transaction().await?;
+    execution.use_mut_context(|ctx| ctx.pending = check_context.pending_user);
+
+    let mut base = check_context.request;
+    let mut simulator = Simulator {
+        state: &state,
+        execution: &execution,
+        user: base.user.take(),
+        base,
+        next: request.next,
+        decisions: Vec::new(),
+        steps: Vec::new(),
+    };
+    let outcome = simulator.run(&connection, request.submissions).await?;
+    Ok(Json(Simulation {
+        steps: simulator.steps,
+        outcome,
+    })
+    .into_response())
+}
+
+struct Simulator<'a> {
+    state: &'a SharedState,
+    execution: &'a FlowExecution,
+    base: CheckContextRequest,
+    next: Option<String>,
+    /// The user of the simulated session
+    user: Option<PartialUser>,
+    /// Decisions of the last check, they are added to the next step
+    decisions: Vec<CheckDecision>,
+    steps: Vec<SimulationStep>,
+}
+
+impl<'a> Simulator<'a> {
+    async fn run(
+        &mut self,
+        client: &(impl GenericClient + Sync),
+        mut submissions: HashMap<String, VecDeque<Value>>,
+    ) -> Result<SimulationOutcome, ApiError> {
+        while self.steps.len() < MAX_STEPS {
+            let context = self.execution.get_check_context(self.request());
+            let mut decisions = Vec::new();
+            let check = self.execution.check_traced(&context, &mut decisions).await;
+            self.decisions = decisions;
+            if let Ok(Some(message)) = check {
+                self.push_step(true, None, None).await;
+                return Ok(SimulationOutcome::Denied { message });
+            }
+            // Flows may start with server side stages
+            let entry_idx = self.execution.entry_index();
+            if let Some(outcome) = self.complete_or_rewind(client, entry_idx).await? {
+                return Ok(outcome);
+            }
+            if self.execution.entry_index() != entry_idx {
+                // The entry reached is checked before it is shown
+                continue;
+            }
+            let entry = self.execution.get_entry();
+            let stage = self.execution.lookup_stage(&entry.stage).await;
+            let queued = submissions
+                .get_mut(&stage.slug)
+                .and_then(VecDeque::pop_front);
+            let form = match queued {
+                Some(form) => form,
+                None => {
+                    self.push_step(true, None, None).await;
+                    return Ok(SimulationOutcome::AwaitingInput {
+                        stage: stage.slug.clone(),
+                    });
+                }
+            };
+            let session = self.session();
+            let result = handle_stage(
+                form.clone(),
+                client,
+                self.state,
+                &session,
+                self.execution,
+                stage,
+            )
+            .await;
+            match result {
+                Ok(()) => {
+                    self.push_step(true, Some(form), None).await;
+                    self.execution.complete_current();
+                    if let Some(outcome) = self.complete_or_rewind(client, entry_idx).await? {
+                        return Ok(outcome);
+                    }
+                }
+                Err(err) => match err.kind {
+                    ApiErrorKind::SubmissionError(err) => {
+                        self.push_step(true, Some(form), Some(err)).await;
+                    }
+                    _ => return Err(err),
+                },
+            }
+        }
+        Ok(SimulationOutcome::StepLimit)
+    }
+
+    fn request(&self) -> CheckContextRequest {
+        CheckContextRequest {
+            uri: self.base.uri.clone(),
+            host: self.base.host.clone(),
+            scheme: self.base.scheme.clone(),
+            query: ExecutorQuery {
+                next: self.next.clone(),
+                email_token: None,
+            },
+            user: self.user.clone(),
+        }
+    }
+
+    async fn push_step(
+        &mut self,
+        shown: bool,
+        form: Option<Value>,
+        error: Option<SubmissionError>,
+    ) {
+        let entry = self.execution.get_entry();
+        let stage = self.execution.lookup_stage(&entry.stage).await;
+        self.steps.push(SimulationStep {
+            entry: self.execution.entry_index(),
+            stage: stage.slug.clone(),
+            shown,
+            decisions: std::mem::take(&mut self.decisions),
+            form,
+            error,
+        });
+    }
+
+    /// Same as `complete_or_rewind`, returns the outcome if the execution has ended.
+    async fn complete_or_rewind(
+        &mut self,
+        client: &(impl GenericClient + Sync),
+        entry_idx: usize,
+    ) -> Result<Option<SimulationOutcome>, ApiError> {
+        let (execution, state) = (self.execution, self.state);
+        // The simulated user confirms server side stages
+        if let Err(err) = complete(client, execution, state, self, true).await {
+            match err.kind {
+                ApiErrorKind::SubmissionError(err) => {
+                    self.execution.rewind(entry_idx);
+                    self.push_step(true, None, Some(err)).await;
+                }
+                _ => return Err(err),
+            }
+        }
+        if let Some(err) = &self.execution.get_context().error {
+            return Ok(Some(SimulationOutcome::Error {
+                message: err.message.clone(),
+            }));
+        }
+        if self.execution.is_completed() {
+            return Ok(Some(SimulationOutcome::Completed {
+                redirect: self.next.clone(),
+                user: self.user.as_ref().map(|user| user.uid),
+            }));
+        }
+        Ok(None)
+    }
+}
+
+/// The session is only changed in the simulation.
+#[async_trait]
+impl StageEffects for Simulator<'_> {
+    fn session(&self) -> Session {
+        Session {
+            session_id: SIMULATION_SESSION.to_owned(),
+            id: Uuid::nil(),
+            user_id: self.user.as_ref().map(|user| user.uid),
+            is_admin: self.user.as_ref().map_or(false, |user| user.is_admin),
+        }
+    }
+
+    /// Pending users given by the request might not exist, they are logged in without groups.
+    async fn login<C: GenericClient + Sync>(
+        &mut self,
+        client: &C,
+        pending: &PendingUser,
+    ) -> Result<(), ApiError> {
+        let user = self
+            .state
+            .users()
+            .lookup_user_uid(client, pending.uid)
+            .await?;
+        self.user = Some(user.unwrap_or_else(|| PartialUser {
+            uid: pending.uid,
+            name: pending.name.clone(),
+            avatar_url: pending.avatar_url.clone(),
+            is_admin: pending.is_admin,
+            password_change_date: OffsetDateTime::now_utc(),
+            groups: Vec::new(),
+        }));
+        Ok(())
+    }
+
+    async fn logout<C: GenericClient + Sync>(
+        &mut self,
+        _client: &C,
+        _execution: &FlowExecution,
+        _terminate_all: bool,
+    ) -> Result<(), ApiError> {
+        self.user = None;
+        Ok(())
+    }
+
+    /// No mail is sent, the link cannot be opened in a simulation.
+    async fn send_email_link<C: GenericClient + Sync>(
+        &mut self,
+        _client: &C,
+        _execution: &FlowExecution,
+        _subject: &str,
+        _template: &str,
+        _token_expiry: i32,
+    ) -> Result<(), ApiError> {
+        Ok(())
+    }
+
+    async fn passed(&mut self, _execution: &FlowExecution) {
+        self.push_step(false, None, None).await;
+    }
+}
@@ -1,8 +1,26 @@
-use axum::{routing::get, Router};
+use axum::{
+    extract::{Path, State},
+    response::{IntoResponse, Response},
+    routing::{delete, get, post, put},
+    Json, Router,
+};
+use deadpool_postgres::GenericClient;
+use http::StatusCode;
+use model::{Flow, FlowQuery};
+use storage::datacache::{DataRef, DataStorage, LookupRef};
+use tracing::instrument;
 
-use crate::SharedState;
+use crate::{
+    api::{ApiError, ApiErrorKind},
+    service::flow::{BindingOwner, BindingWrite, EntryWrite, FlowWrite},
+    SharedState,
+};
 
-use super::{executor::setup_executor_router, ping_handler};
+use super::{
+    auth::AdminSession,
+    executor::{setup_executor_router, simulate::simulate},
+    ping_handler,
+};
 
 pub fn setup_flow_router() -> Router<SharedState> {
     Router::new()
@@ -10,3 +28,296 @@ pub fn setup_flow_router() -> Router<SharedState> {
         // .at("/executor", get(ping_handler))
         .nest("/executor", setup_executor_router())
 }
+
+/// Admin api to manage flows, their entries and bindings.
+pub fn setup_flow_admin_router() -> Router<SharedState> {
+    Router::new()
+        .route("/", get(list).post(create))
+        .route("/:slug", get(get_flow).put(update).delete(delete_flow))
+        .route("/:slug/entries", post(create_entry))
+        .route("/:slug/entries/order", put(reorder_entries))
+        .route(
+            "/:slug/entries/:entry",
+            put(update_entry).delete(delete_entry),
+        )
+        .route("/:slug/entries/:entry/bindings", post(create_entry_binding))
+        .route(
+            "/:slug/entries/:entry/bindings/:binding",
+            delete(delete_entry_binding),
+        )
+        .route("/:slug/bindings", post(create_flow_binding))
+        .route("/:slug/bindings/:binding", delete(delete_flow_binding))
+        .route("/:slug/simulate", post(simulate))
+}
+
+#[instrument(skip(state))]
+async fn list(
+    _: AdminSession,
+    State(state): State<SharedState>,
+) -> Result<Json<Vec<Flow>>, ApiError> {
+    let connection = state.defaults().connection().await?;
+    let mut flows = Vec::new();
+    for uid in state.flows().list_uids(&connection).await? {
+        if let Some(flow) = state.storage().lookup(&flow_ref(uid)).await {
+            flows.push(flow.as_ref().clone());
+        }
+    }
+    Ok(Json(flows))
+}
+
+#[instrument(skip(state))]
+async fn get_flow(
+    _: AdminSession,
+    Path(slug): Path<String>,
+    State(state): State<SharedState>,
+) -> Result<Json<Flow>, ApiError> {
+    let connection = state.defaults().connection().await?;
+    let uid = find_flow(&state, &connection, &slug).await?;
+    load_flow(&state, uid).await.map(Json)
+}
+
+#[instrument(skip(state))]
+async fn create(
+    _: AdminSession,
+    State(state): State<SharedState>,
+    Json(write): Json<FlowWrite>,
+) -> Result<Response, ApiError> {
+    if let Some(response) = check_flow(&write) {
+        return Ok(response);
+    }
+    let connection = state.defaults().connection().await?;
+    let uid = state.flows().create(&connection, &write).await?;
+    let flow = load_flow(&state, uid).await?;
+    Ok((StatusCode::CREATED, Json(flow)).into_response())
+}
+
+#[instrument(skip(state))]
+async fn update(
+    _: AdminSession,
+    Path(slug): Path<String>,
+    State(state): State<SharedState>,
+    Json(write): Json<FlowWrite>,
+) -> Result<Response, ApiError> {
+    if let Some(response) = check_flow(&write) {
+        return Ok(response);
+    }
+    let connection = state.defaults().connection().await?;
+    let uid = find_flow(&state, &connection, &slug).await?;
+    if !state.flows().update(&connection, uid, &write).await? {
+        return Err(ApiErrorKind::NotFound.into_api());
+    }
+    invalidate(&state, uid).await;
+    Ok(Json(load_flow(&state, uid).await?).into_response())
+}
+
+#[instrument(skip(state))]
+async fn delete_flow(
+    _: AdminSession,
+    Path(slug): Path<String>,
+    State(state): State<SharedState>,
+) -> Result<StatusCode, ApiError> {
+    let connection = state.defaults().connection().await?;
+    let uid = find_flow(&state, &connection, &slug).await?;
+    if !state.flows().delete(&connection, uid).await? {
+        return Err(ApiErrorKind::NotFound.into_api());
+    }
+    invalidate(&state, uid).await;
+    Ok(StatusCode::NO_CONTENT)
+}
+
+#[instrument(skip(state))]
+async fn create_entry(
+    _: AdminSession,
+    Path(slug): Path<String>,
+    State(state): State<SharedState>,
+    Json(write): Json<EntryWrite>,
+) -> Result<Response, ApiError> {
+    let connection = state.defaults().connection().await?;
+    let uid = find_flow(&state, &connection, &slug).await?;
+    state.flows().create_entry(&connection, uid, &write).await?;
+    invalidate(&state, uid).await;
+    let flow = load_flow(&state, uid).await?;
+    Ok((StatusCode::CREATED, Json(flow)).into_response())
+}
+
+#[instrument(skip(state))]
+async fn update_entry(
+    _: AdminSession,
+    Path((slug, entry)): Path<(String, i32)>,
+    State(state): State<SharedState>,
+    Json(write): Json<EntryWrite>,
+) -> Result<Json<Flow>, ApiError> {
+    let connection = state.defaults().connection().await?;
+    let uid = find_flow(&state, &connection, &slug).await?;
+    if !state
+        .flows()
+        .update_entry(&connection, uid, entry, &write)
+        .await?
+    {
+        return Err(ApiErrorKind::NotFound.into_api());
+    }
+    invalidate(&state, uid).await;
+    load_flow(&state, uid).await.map(Json)
+}
+
+#[instrument(skip(state))]
+async fn delete_entry(
+    _: AdminSession,
+    Path((slug, entry)): Path<(String, i32)>,
+    State(state): State<SharedState>,
+) -> Result<Json<Flow>, ApiError> {
+    let connection = state.defaults().connection().await?;
+    let uid = find_flow(&state, &connection, &slug).await?;
+    if !state.flows().delete_entry(&connection, uid, entry).await? {
+        return Err(ApiErrorKind::NotFound.into_api());
+    }
+    invalidate(&state, uid).await;
+    load_flow(&state, uid).await.map(Json)
+}
+
+/// Expects the uids of all entries of the flow in their new order.
+#[instrument(skip(state))]
+async fn reorder_entries(
+    _: AdminSession,
+    Path(slug): Path<String>,
+    State(state): State<SharedState>,
+    Json(entries): Json<Vec<i32>>,
+) -> Result<Response, ApiError> {
+    let mut connection = state.defaults().connection().await?;
+    let connection = connection.transaction().await?;
+    let uid = find_flow(&state, &connection, &slug).await?;
+    if !state
+        .flows()
+        .reorder_entries(&connection, uid, &entries)
+        .await?
+    {
+        return Ok((
+            StatusCode::BAD_REQUEST,
+            "Every entry of the flow must be listed exactly once",
+        )
+            .into_response());
+    }
+    connection.commit().await?;
+    invalidate(&state, uid).await;
+    Ok(Json(load_flow(&state, uid).await?).into_response())
+}
+
+#[instrument(skip(state))]
+async fn create_flow_binding(
+    _: AdminSession,
+    Path(slug): Path<String>,
+    State(state): State<SharedState>,
+    Json(write): Json<BindingWrite>,
+) -> Result<Response, ApiError> {
+    let connection = state.defaults().connection().await?;
+    let uid = find_flow(&state, &connection, &slug).await?;
+    state
+        .flows()
+        .create_binding(&connection, BindingOwner::Flow(uid), &write)
+        .await?;
+    invalidate(&state, uid).await;
+    let flow = load_flow(&state, uid).await?;
+    Ok((StatusCode::CREATED, Json(flow)).into_response())
+}
+
+#[instrument(skip(state))]
+async fn delete_flow_binding(
+    _: AdminSession,
+    Path((slug, binding)): Path<(String, i32)>,
+    State(state): State<SharedState>,
+) -> Result<Json<Flow>, ApiError> {
+    let connection = state.defaults().connection().await?;
+    let uid = find_flow(&state, &connection, &slug).await?;
+    if !state
+        .flows()
+        .delete_binding(&connection, BindingOwner::Flow(uid), binding)
+        .await?
+    {
+        return Err(ApiErrorKind::NotFound.into_api());
+    }
+    invalidate(&state, uid).await;
+    load_flow(&state, uid).await.map(Json)
+}
+
+#[instrument(skip(state))]
+async fn create_entry_binding(
+    _: AdminSession,
+    Path((slug, entry)): Path<(String, i32)>,
+    State(state): State<SharedState>,
+    Json(write): Json<BindingWrite>,
+) -> Result<Response, ApiError> {
+    let connection = state.defaults().connection().await?;
+    let uid = find_flow(&state, &connection, &slug).await?;
+    if !state.flows().entry_exists(&connection, uid, entry).await? {
+        return Err(ApiErrorKind::NotFound.into_api());
+    }
+    state
+        .flows()
+        .create_binding(&connection, BindingOwner::Entry(entry), &write)
+        .await?;
+    invalidate(&state, uid).await;
+    let flow = load_flow(&state, uid).await?;
+    Ok((StatusCode::CREATED, Json(flow)).into_response())
+}
+
+#[instrument(skip(state))]
+async fn delete_entry_binding(
+    _: AdminSession,
+    Path((slug, entry, binding)): Path<(String, i32, i32)>,
+    State(state): State<SharedState>,
+) -> Result<Json<Flow>, ApiError> {
+    let connection = state.defaults().connection().await?;
+    let uid = find_flow(&state, &connection, &slug).await?;
+    if !state.flows().entry_exists(&connection, uid, entry).await?
+        || !state
+            .flows()
+            .delete_binding(&connection, BindingOwner::Entry(entry), binding)
+            .await?
+    {
+        return Err(ApiErrorKind::NotFound.into_api());
+    }
+    invalidate(&state, uid).await;
+    load_flow(&state, uid).await.map(Json)
+}
+
+fn check_flow(write: &FlowWrite) -> Option<Response> {
+    if write.slug.is_empty() || write.slug != write.slug.to_lowercase() {
+        return Some((StatusCode::BAD_REQUEST, "Slug must be lowercase").into_response());
+    }
+    None
+}
+
+fn flow_ref(uid: i32) -> DataRef<Flow> {
+    DataRef::new(FlowQuery::uid(uid))
+}
+
+async fn find_flow(
+    state: &SharedState,
+    client: &impl GenericClient,
+    slug: &str,
+) -> Result<i32, ApiError> {
+    state
+        .flows()
+        .uid_by_slug(client, slug)
+        .await?
+        .ok_or(ApiErrorKind::NotFound.into_api())
+}
+
+async fn load_flow(state: &SharedState, uid: i32) -> Result<Flow, ApiError> {
+    let flow = state.storage().lookup(&flow_ref(uid)).await;
+    flow.map(|flow| flow.as_ref().clone())
+        .ok_or(ApiErrorKind::NotFound.into_api())
+}
+
+/// Drops the cached flow, so new executions load the changed flow.
+async fn invalidate(state: &SharedState, uid: i32) {
+    let result = state
+        .storage()
+        .get_for_data::<Flow>()
+        .expect("Failed to get Flow storage")
+        .invalidate(&FlowQuery::uid(uid))
+        .await;
+    if let Err(err) = result {
+        tracing::warn!("Failed to invalidate flow {uid} {err}");
+    }
+}
@@ -0,0 +1,200 @@
+use axum::{
+    extract::{Path, State},
+    response::{IntoResponse, Response},
+    routing::{get, put},
+    Json, Router,
+};
+use deadpool_postgres::GenericClient;
+use http::StatusCode;
+use model::{user::PartialUser, Group};
+use serde::Deserialize;
+use serde_json::Value;
+use tracing::instrument;
+use uuid::Uuid;
+
+use crate::{
+    api::{ApiError, ApiErrorKind},
+    SharedState,
+};
+
+use super::auth::AdminSession;
+
+pub fn setup_group_router() -> Router<SharedState> {
+    Router::new()
+        .route("/", get(list).post(create))
+        .route("/:uid", get(get_group).put(update).delete(delete))
+        .route("/:uid/members", get(members))
+        .route("/:uid/members/:user", put(add_member).delete(remove_member))
+}
+
+#[derive(Debug, Deserialize)]
+struct GroupRequest {
+    name: String,
+    #[serde(default)]
+    parent: Option<Uuid>,
+    #[serde(default)]
+    attributes: Option<Value>,
+}
+
+impl GroupRequest {
+    fn attributes(&self) -> Value {
+        self.attributes
+            .clone()
+            .unwrap_or_else(|| Value::Object(Default::default()))
+    }
+}
+
+#[instrument(skip(state))]
+async fn list(
+    _: AdminSession,
+    State(state): State<SharedState>,
+) -> Result<Json<Vec<Group>>, ApiError> {
+    let connection = state.defaults().connection().await?;
+    Ok(Json(state.groups().list(&connection).await?))
+}
+
+#[instrument(skip(state))]
+async fn get_group(
+    _: AdminSession,
+    Path(uid): Path<Uuid>,
+    State(state): State<SharedState>,
+) -> Result<Json<Group>, ApiError> {
+    let connection = state.defaults().connection().await?;
+    let group = state.groups().get(&connection, uid).await?;
+    group.map(Json).ok_or(ApiErrorKind::NotFound.into_api())
+}
+
+#[instrument(skip(state))]
+async fn create(
+    _: AdminSession,
+    State(state): State<SharedState>,
+    Json(request): Json<GroupRequest>,
+) -> Result<Response, ApiError> {
+    let mut connection = state.defaults().connection().await?;
+    let connection = connection.transaction().await?;
+    if let Some(response) = check_parent(&connection, &state, None, request.parent).await? {
+        return Ok(response);
+    }
+    let group = state
+        .groups()
+        .create(
+            &connection,
+            &request.name,
+            request.parent,
+            &request.attributes(),
+        )
+        .await?;
+    connection.commit().await?;
+    Ok((StatusCode::CREATED, Json(group)).into_response())
+}
+
+#[instrument(skip(state))]
+async fn update(
+    _: AdminSession,
+    Path(uid): Path<Uuid>,
+    State(state): State<SharedState>,
+    Json(request): Json<GroupRequest>,
+) -> Result<Response, ApiError> {
+    let mut connection = state.defaults().connection().await?;
+    let connection = connection.transaction().await?;
+    if let Some(response) = check_parent(&connection, &state, Some(uid), request.parent).await? {
+        return Ok(response);
+    }
+    let group = state
+        .groups()
+        .update(
+            &connection,
+            uid,
+            &request.name,
+            request.parent,
+            &request.attributes(),
+        )
+        .await?
+        .ok_or(ApiErrorKind::NotFound)?;
+    connection.commit().await?;
+    Ok(Json(group).into_response())
+}
+
+#[instrument(skip(state))]
+async fn delete(
+    _: AdminSession,
+    Path(uid): Path<Uuid>,
+    State(state): State<SharedState>,
+) -> Result<StatusCode, ApiError> {
+    let connection = state.defaults().connection().await?;
+    if state.groups().delete(&connection, uid).await? {
+        Ok(StatusCode::NO_CONTENT)
+    } else {
+        Err(ApiErrorKind::NotFound.into_api())
+    }
+}
+
+#[instrument(skip(state))]
+async fn members(
+    _: AdminSession,
+    Path(uid): Path<Uuid>,
+    State(state): State<SharedState>,
+) -> Result<Json<Vec<PartialUser>>, ApiError> {
+    let connection = state.defaults().connection().await?;
+    if state.groups().get(&connection, uid).await?.is_none() {
+        return Err(ApiErrorKind::NotFound.into_api());
+    }
+    Ok(Json(state.groups().members(&connection, uid).await?))
+}
+
+#[instrument(skip(state))]
+async fn add_member(
+    _: AdminSession,
+    Path((uid, user)): Path<(Uuid, Uuid)>,
+    State(state): State<SharedState>,
+) -> Result<StatusCode, ApiError> {
+    let connection = state.defaults().connection().await?;
+    if state.groups().get(&connection, uid).await?.is_none()
+        || state
+            .users()
+            .lookup_user_uid(&connection, user)
+            .await?
+            .is_none()
+    {
+        return Err(ApiErrorKind::NotFound.into_api());
+    }
+    state.groups().add_member(&connection, uid, user).await?;
+    Ok(StatusCode::NO_CONTENT)
+}
+
+#[instrument(skip(state))]
+async fn remove_member(
+    _: AdminSession,
+    Path((uid, user)): Path<(Uuid, Uuid)>,
+    State(state): State<SharedState>,
+) -> Result<StatusCode, ApiError> {
+    let connection = state.defaults().connection().await?;
+    if state.groups().remove_member(&connection, uid, user).await? {
+        Ok(StatusCode::NO_CONTENT)
+    } else {
+        Err(ApiErrorKind::NotFound.into_api())
+    }
+}
+
+/// Rejects parents that don't exist or would create a cycle.
+async fn check_parent(
+    client: &impl GenericClient,
+    state: &SharedState,
+    group: Option<Uuid>,
+    parent: Option<Uuid>,
+) -> Result<Option<Response>, ApiError> {
+    let Some(parent) = parent else { return Ok(None) };
+    if state.groups().get(client, parent).await?.is_none() {
+        return Ok(Some(
+            (StatusCode::BAD_REQUEST, "Parent group does not exist").into_response(),
+        ));
+    }
+    if let Some(group) = group {
+        if state.groups().is_ancestor(client, group, parent).await? {
+            return Ok(Some(
+                (StatusCode::BAD_REQUEST, "Parent group would create a cycle").into_response(),
+            ));
+        }
+    }
+    Ok(None)
+}
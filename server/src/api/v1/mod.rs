@@ -5,28 +5,52 @@ use tower::ServiceBuilder;
 use crate::{
     api::{
         csrf::CsrfLayer,
-        v1::{auth::setup_auth_router, flow::setup_flow_router},
+        v1::{
+            auth::setup_auth_router,
+            flow::{setup_flow_admin_router, setup_flow_router},
+        },
     },
     SharedState,
 };
 
-use self::{auth::AuthLayer, policy::setup_policy_router};
+use self::{
+    application::setup_application_router, auth::AuthLayer, blueprint::setup_blueprint_router,
+    consent::setup_consent_router, group::setup_group_router, policy::setup_policy_router,
+    prompt::setup_prompt_router, session::setup_session_router, stage::setup_stage_router,
+    user::setup_user_router,
+};
 
 pub mod application;
 pub mod auth;
+pub mod blueprint;
+pub mod consent;
 pub mod executor;
 pub mod flow;
+pub mod group;
 pub mod policy;
+pub mod prompt;
+pub mod session;
+pub mod stage;
+pub mod user;
 
-pub async fn setup_api_v1(_secret: &str, state: SharedState) -> Router<SharedState> {
+pub async fn setup_api_v1(state: SharedState) -> Router<SharedState> {
     let service = ServiceBuilder::new()
         .layer(CsrfLayer::new(vec!["*".into()]))
         .layer(AuthLayer::new(state.auth_data().clone()));
     let router = Router::new()
         .route("/ping", get(ping_handler))
         .nest("/flow", setup_flow_router())
+        .nest("/flows", setup_flow_admin_router())
+        .nest("/stages", setup_stage_router())
+        .nest("/prompts", setup_prompt_router())
         .nest("/auth", setup_auth_router())
         .nest("/policies", setup_policy_router())
+        .nest("/blueprint", setup_blueprint_router())
+        .nest("/consents", setup_consent_router())
+        .nest("/groups", setup_group_router())
+        .nest("/users", setup_user_router())
+        .nest("/sessions", setup_session_router())
+        .nest("/application", setup_application_router())
         .layer(service);
     router
 }
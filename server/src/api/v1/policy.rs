@@ -7,19 +7,33 @@ use axum::{
 use deadpool_postgres::GenericClient;
 use futures::StreamExt;
 use http::StatusCode;
-use model::{PartialPolicy, PolicyKind, PolicyKindSimple};
+use model::{PartialPolicy, PasswordRules, Policy, PolicyKind, PolicyKindSimple, PolicyQuery};
 use policy_engine::{compile, encode_base64};
 use serde::Deserialize;
+use storage::datacache::{DataRef, LookupRef};
+use tokio_postgres::Row;
 use tracing::instrument;
 
-use crate::{api::ApiError, service::policy::DUMMY_SCOPE, SharedState};
+use crate::{
+    api::{ApiError, ApiErrorKind},
+    executor::fields::PASSWORD,
+    service::policy::{
+        evaluate::{evaluate, PolicyEvaluation, TestContext},
+        DUMMY_SCOPE,
+    },
+    SharedState,
+};
 
 use super::auth::AdminSession;
 
 pub fn setup_policy_router() -> Router<SharedState> {
     Router::new()
         .route("/", get(list))
+        .route("/:slug", get(get_policy).put(update).delete(delete_policy))
+        .route("/:slug/test", post(test_policy))
         .route("/:slug/expiration", post(create_expiration))
+        .route("/:slug/strength", post(create_strength))
+        .route("/:slug/breached", post(create_breached))
         .route("/:slug/expression", post(create_expression))
 }
 
@@ -62,6 +76,49 @@ async fn create_expiration(
     Ok(Json(partial).into_response())
 }
 
+async fn create_strength(
+    _: AdminSession,
+    Path(slug): Path<String>,
+    State(state): State<SharedState>,
+    Json(rules): Json<PasswordRules>,
+) -> Result<Response, ApiError> {
+    if let Some(response) = check_rules(&rules) {
+        return Ok(response);
+    }
+    let mut connection = state.defaults().connection().await?;
+    let connection = connection.transaction().await?;
+    let partial = create(slug, PolicyKind::PasswordStrength(rules), &connection).await?;
+    connection.commit().await?;
+    Ok(Json(partial).into_response())
+}
+
+#[derive(Deserialize)]
+struct BreachedQuery {
+    field_key: Option<String>,
+}
+
+async fn create_breached(
+    _: AdminSession,
+    Path(slug): Path<String>,
+    Query(query): Query<BreachedQuery>,
+    State(state): State<SharedState>,
+) -> Result<Response, ApiError> {
+    if state.policies().breached_passwords().is_none() {
+        tracing::warn!("Creating breached password policy without a configured filter");
+    }
+    let field_key = query.field_key.unwrap_or_else(|| PASSWORD.name.to_owned());
+    let mut connection = state.defaults().connection().await?;
+    let connection = connection.transaction().await?;
+    let partial = create(
+        slug,
+        PolicyKind::BreachedPassword { field_key },
+        &connection,
+    )
+    .await?;
+    connection.commit().await?;
+    Ok(Json(partial).into_response())
+}
+
 const MAX_EXPRESSION_LEN: usize = 2048;
 
 async fn create_expression(
@@ -85,20 +142,210 @@ async fn create_expression(
         Err(_) => return Ok((StatusCode::BAD_REQUEST, "Requset body must be utf8").into_response()),
     };
     let expression = encode_base64(&expression);
-    match compile(&expression, &DUMMY_SCOPE) {
-        Ok(_) => {}
+    if let Some(response) = check_expression(&expression) {
+        return Ok(response);
+    }
+    let partial = create(slug, PolicyKind::Expression { expression }, &connection).await?;
+    connection.commit().await?;
+    Ok(Json(partial).into_response())
+}
+
+#[instrument(skip(state))]
+async fn get_policy(
+    _: AdminSession,
+    Path(slug): Path<String>,
+    State(state): State<SharedState>,
+) -> Result<Json<Policy>, ApiError> {
+    let policy = state
+        .storage()
+        .lookup(&DataRef::new(PolicyQuery::slug(slug)))
+        .await;
+    policy
+        .map(|policy| Json(policy.as_ref().clone()))
+        .ok_or(ApiErrorKind::NotFound.into_api())
+}
+
+/// Replaces the policy, the policy may change its kind.
+/// Expressions are expected as plain text.
+#[instrument(skip(state))]
+async fn update(
+    _: AdminSession,
+    Path(slug): Path<String>,
+    State(state): State<SharedState>,
+    Json(kind): Json<PolicyKind>,
+) -> Result<Response, ApiError> {
+    let kind = match kind {
+        PolicyKind::Expression { expression } => PolicyKind::Expression {
+            expression: encode_base64(&expression),
+        },
+        kind => kind,
+    };
+    if let Some(response) = check_kind(&kind) {
+        return Ok(response);
+    }
+    let mut connection = state.defaults().connection().await?;
+    let connection = connection.transaction().await?;
+    let Some(row) = find_for_update(&connection, &slug).await? else {
+        return Err(ApiErrorKind::NotFound.into_api());
+    };
+    let uid: i32 = row.get("uid");
+    let simple_kind = PolicyKindSimple::from(&kind);
+    let statement = connection
+        .prepare_cached(
+            "update policies set kind = $2, password_expiration = null, password_strength = null, expression = null, breached_password = null where uid = $1",
+        )
+        .await?;
+    connection
+        .execute(&statement, &[&uid, &simple_kind])
+        .await?;
+    delete_kind(&connection, &row).await?;
+    insert_kind(&connection, uid, kind).await?;
+    connection.commit().await?;
+    state.policies().invalidate(uid).await;
+    Ok(Json(PartialPolicy {
+        uid,
+        slug,
+        kind: simple_kind,
+    })
+    .into_response())
+}
+
+#[instrument(skip(state))]
+async fn delete_policy(
+    _: AdminSession,
+    Path(slug): Path<String>,
+    State(state): State<SharedState>,
+) -> Result<Response, ApiError> {
+    let mut connection = state.defaults().connection().await?;
+    let connection = connection.transaction().await?;
+    let Some(row) = find_for_update(&connection, &slug).await? else {
+        return Err(ApiErrorKind::NotFound.into_api());
+    };
+    let uid: i32 = row.get("uid");
+    let statement = connection
+        .prepare_cached("select exists(select 1 from flow_bindings where policy = $1)")
+        .await?;
+    if connection.query_one(&statement, &[&uid]).await?.get(0) {
+        return Ok((StatusCode::CONFLICT, "Policy is used by a flow").into_response());
+    }
+    let statement = connection
+        .prepare_cached("delete from policies where uid = $1")
+        .await?;
+    connection.execute(&statement, &[&uid]).await?;
+    delete_kind(&connection, &row).await?;
+    connection.commit().await?;
+    state.policies().invalidate(uid).await;
+    Ok(StatusCode::NO_CONTENT.into_response())
+}
+
+/// Evaluates the policy against a synthetic context, so policies can be debugged without a flow.
+#[instrument(skip(state))]
+async fn test_policy(
+    _: AdminSession,
+    Path(slug): Path<String>,
+    State(state): State<SharedState>,
+    Json(context): Json<TestContext>,
+) -> Result<Response, ApiError> {
+    let policy = state
+        .storage()
+        .lookup(&DataRef::new(PolicyQuery::slug(slug)))
+        .await
+        .ok_or(ApiErrorKind::NotFound.into_api())?;
+    let check_context = match context.check_context() {
+        Ok(check_context) => check_context,
+        Err(message) => return Ok((StatusCode::BAD_REQUEST, message).into_response()),
+    };
+    let evaluation: PolicyEvaluation = evaluate(
+        policy.as_ref(),
+        &check_context,
+        context.password.as_deref(),
+        state.policies().breached_passwords(),
+    );
+    Ok(Json(evaluation).into_response())
+}
+
+fn check_kind(kind: &PolicyKind) -> Option<Response> {
+    match kind {
+        PolicyKind::PasswordExpiry { max_age } if *max_age < 0 => {
+            Some((StatusCode::BAD_REQUEST, "max_age must be positive").into_response())
+        }
+        PolicyKind::PasswordStrength(rules) => check_rules(rules),
+        PolicyKind::Expression { expression } => check_expression(expression),
+        _ => None,
+    }
+}
+
+fn check_rules(rules: &PasswordRules) -> Option<Response> {
+    let counts = [
+        rules.min_length,
+        rules.min_uppercase,
+        rules.min_lowercase,
+        rules.min_digits,
+        rules.min_symbols,
+    ];
+    if counts.iter().any(|count| *count < 0) || matches!(rules.max_repeated, Some(max) if max < 1) {
+        return Some((StatusCode::BAD_REQUEST, "Rules must be positive").into_response());
+    }
+    if !(0..=4).contains(&rules.min_score) {
+        return Some(
+            (StatusCode::BAD_REQUEST, "min_score must be between 0 and 4").into_response(),
+        );
+    }
+    None
+}
+
+/// Expects the expression to be encoded already.
+fn check_expression(expression: &str) -> Option<Response> {
+    match compile(expression, &DUMMY_SCOPE) {
+        Ok(_) => None,
         Err(err) => match err {
             policy_engine::ExpressionCompilationError::InvalidBase64 => {
-                return Ok(StatusCode::INTERNAL_SERVER_ERROR.into_response())
+                Some(StatusCode::INTERNAL_SERVER_ERROR.into_response())
             }
             policy_engine::ExpressionCompilationError::Parse(parse) => {
-                return Ok((StatusCode::BAD_REQUEST, format!("{parse}")).into_response());
+                Some((StatusCode::BAD_REQUEST, format!("{parse}")).into_response())
             }
         },
-    };
-    let partial = create(slug, PolicyKind::Expression(expression), &connection).await?;
-    connection.commit().await?;
-    Ok(Json(partial).into_response())
+    }
+}
+
+async fn find_for_update<C: GenericClient>(
+    client: &C,
+    slug: &str,
+) -> Result<Option<Row>, ApiError> {
+    let statement = client
+        .prepare_cached("select * from policies where slug = $1 for update")
+        .await?;
+    Ok(client.query_opt(&statement, &[&slug]).await?)
+}
+
+/// Deletes the row of the policy's kind, the policy must not reference it anymore.
+async fn delete_kind<C: GenericClient>(client: &C, policy: &Row) -> Result<(), ApiError> {
+    let tables = [
+        (
+            "password_expiration",
+            "delete from password_expiration_policies where uid = $1",
+        ),
+        (
+            "password_strength",
+            "delete from password_strength_policies where uid = $1",
+        ),
+        (
+            "expression",
+            "delete from expression_policies where uid = $1",
+        ),
+        (
+            "breached_password",
+            "delete from breached_password_policies where uid = $1",
+        ),
+    ];
+    for (column, query) in tables {
+        if let Some(uid) = policy.get::<_, Option<i32>>(column) {
+            let statement = client.prepare_cached(query).await?;
+            client.execute(&statement, &[&uid]).await?;
+        }
+    }
+    Ok(())
 }
 
 async fn create<C: GenericClient>(
@@ -107,7 +354,6 @@ async fn create<C: GenericClient>(
     client: &C,
 ) -> Result<PartialPolicy, ApiError> {
     let simple_kind = PolicyKindSimple::from(&kind);
-    // let connection = connection.transaction().await?;
     let statement = client
         .prepare_cached("insert into policies(slug, kind) values($1, $2) returning uid")
         .await?;
@@ -115,6 +361,20 @@ async fn create<C: GenericClient>(
         .query_one(&statement, &[&slug, &simple_kind])
         .await?
         .get(0);
+    insert_kind(client, uid, kind).await?;
+    Ok(PartialPolicy {
+        uid,
+        slug,
+        kind: simple_kind,
+    })
+}
+
+/// Inserts the row of the policy's kind and links the policy to it.
+async fn insert_kind<C: GenericClient>(
+    client: &C,
+    uid: i32,
+    kind: PolicyKind,
+) -> Result<(), ApiError> {
     match kind {
         PolicyKind::PasswordExpiry { max_age } => {
             let statement = client
@@ -129,8 +389,36 @@ async fn create<C: GenericClient>(
                 .await?;
             client.execute(&statement, &[&sub_uid, &uid]).await?;
         }
-        PolicyKind::PasswordStrength => todo!(),
-        PolicyKind::Expression(expression) => {
+        PolicyKind::PasswordStrength(rules) => {
+            let statement = client
+                .prepare_cached(
+                    "insert into password_strength_policies(field_key, min_length, min_uppercase, min_lowercase, min_digits, min_symbols, max_repeated, check_user_attributes, min_score) values ($1, $2, $3, $4, $5, $6, $7, $8, $9) returning uid",
+                )
+                .await?;
+            let sub_uid: i32 = client
+                .query_one(
+                    &statement,
+                    &[
+                        &rules.field_key,
+                        &rules.min_length,
+                        &rules.min_uppercase,
+                        &rules.min_lowercase,
+                        &rules.min_digits,
+                        &rules.min_symbols,
+                        &rules.max_repeated,
+                        &rules.check_user_attributes,
+                        &rules.min_score,
+                    ],
+                )
+                .await?
+                .get(0);
+
+            let statement = client
+                .prepare_cached("update policies set password_strength=$1 where uid = $2")
+                .await?;
+            client.execute(&statement, &[&sub_uid, &uid]).await?;
+        }
+        PolicyKind::Expression { expression } => {
             let statement = client
                 .prepare_cached(
                     "insert into expression_policies(expression) values ($1) returning uid",
@@ -143,10 +431,19 @@ async fn create<C: GenericClient>(
                 .await?;
             client.execute(&statement, &[&sub_uid, &uid]).await?;
         }
+        PolicyKind::BreachedPassword { field_key } => {
+            let statement = client
+                .prepare_cached(
+                    "insert into breached_password_policies(field_key) values ($1) returning uid",
+                )
+                .await?;
+            let sub_uid: i32 = client.query_one(&statement, &[&field_key]).await?.get(0);
+
+            let statement = client
+                .prepare_cached("update policies set breached_password=$1 where uid = $2")
+                .await?;
+            client.execute(&statement, &[&sub_uid, &uid]).await?;
+        }
     }
-    Ok(PartialPolicy {
-        uid,
-        slug,
-        kind: simple_kind,
-    })
+    Ok(())
 }
@@ -0,0 +1,161 @@
+use axum::{
+    extract::{Path, State},
+    response::{IntoResponse, Response},
+    routing::get,
+    Json, Router,
+};
+use deadpool_postgres::GenericClient;
+use http::StatusCode;
+use model::{Prompt, PromptQuery};
+use storage::datacache::{DataRef, DataStorage, LookupRef};
+use tracing::instrument;
+
+use crate::{
+    api::{ApiError, ApiErrorKind},
+    service::prompt::PromptWrite,
+    SharedState,
+};
+
+use super::auth::AdminSession;
+
+/// Admin api to manage prompts, they are bound to prompt stages through the stage api.
+pub fn setup_prompt_router() -> Router<SharedState> {
+    Router::new()
+        .route("/", get(list).post(create))
+        .route("/:slug", get(get_prompt).put(update).delete(delete_prompt))
+}
+
+#[instrument(skip(state))]
+async fn list(
+    _: AdminSession,
+    State(state): State<SharedState>,
+) -> Result<Json<Vec<Prompt>>, ApiError> {
+    let connection = state.defaults().connection().await?;
+    let mut prompts = Vec::new();
+    for uid in state.prompts().list_uids(&connection).await? {
+        if let Some(prompt) = state.storage().lookup(&prompt_ref(uid)).await {
+            prompts.push(prompt.as_ref().clone());
+        }
+    }
+    Ok(Json(prompts))
+}
+
+#[instrument(skip(state))]
+async fn get_prompt(
+    _: AdminSession,
+    Path(slug): Path<String>,
+    State(state): State<SharedState>,
+) -> Result<Json<Prompt>, ApiError> {
+    let connection = state.defaults().connection().await?;
+    let uid = find_prompt(&state, &connection, &slug).await?;
+    load_prompt(&state, uid).await.map(Json)
+}
+
+#[instrument(skip(state))]
+async fn create(
+    _: AdminSession,
+    State(state): State<SharedState>,
+    Json(write): Json<PromptWrite>,
+) -> Result<Response, ApiError> {
+    if let Some(response) = check_prompt(&write) {
+        return Ok(response);
+    }
+    let connection = state.defaults().connection().await?;
+    let uid = state.prompts().create(&connection, &write).await?;
+    let prompt = load_prompt(&state, uid).await?;
+    Ok((StatusCode::CREATED, Json(prompt)).into_response())
+}
+
+#[instrument(skip(state))]
+async fn update(
+    _: AdminSession,
+    Path(slug): Path<String>,
+    State(state): State<SharedState>,
+    Json(write): Json<PromptWrite>,
+) -> Result<Response, ApiError> {
+    if let Some(response) = check_prompt(&write) {
+        return Ok(response);
+    }
+    let connection = state.defaults().connection().await?;
+    let uid = find_prompt(&state, &connection, &slug).await?;
+    if !state.prompts().update(&connection, uid, &write).await? {
+        return Err(ApiErrorKind::NotFound.into_api());
+    }
+    invalidate(&state, uid).await;
+    Ok(Json(load_prompt(&state, uid).await?).into_response())
+}
+
+#[instrument(skip(state))]
+async fn delete_prompt(
+    _: AdminSession,
+    Path(slug): Path<String>,
+    State(state): State<SharedState>,
+) -> Result<Response, ApiError> {
+    let mut connection = state.defaults().connection().await?;
+    let connection = connection.transaction().await?;
+    let uid = find_prompt(&state, &connection, &slug).await?;
+    if state.prompts().used_by_stage(&connection, uid).await? {
+        return Ok((StatusCode::CONFLICT, "Prompt is used by a stage").into_response());
+    }
+    if !state.prompts().delete(&connection, uid).await? {
+        return Err(ApiErrorKind::NotFound.into_api());
+    }
+    connection.commit().await?;
+    invalidate(&state, uid).await;
+    Ok(StatusCode::NO_CONTENT.into_response())
+}
+
+/// The limits match the columns of the prompts table.
+fn check_prompt(write: &PromptWrite) -> Option<Response> {
+    let too_long =
+        |value: &Option<String>| matches!(value, Some(value) if value.chars().count() > 128);
+    let message = if write.slug.is_empty() || write.slug != write.slug.to_lowercase() {
+        "Slug must be lowercase"
+    } else if write.field_key.is_empty() || write.field_key.chars().count() > 32 {
+        "Field key must have between 1 and 32 characters"
+    } else if write.label.chars().count() > 32 {
+        "Label must have at most 32 characters"
+    } else if too_long(&write.placeholder) || too_long(&write.help_text) {
+        "Placeholder and help text must have at most 128 characters"
+    } else {
+        return None;
+    };
+    Some((StatusCode::BAD_REQUEST, message).into_response())
+}
+
+async fn find_prompt(
+    state: &SharedState,
+    client: &impl GenericClient,
+    slug: &str,
+) -> Result<i32, ApiError> {
+    state
+        .prompts()
+        .uid_by_slug(client, slug)
+        .await?
+        .ok_or(ApiErrorKind::NotFound.into_api())
+}
+
+fn prompt_ref(uid: i32) -> DataRef<Prompt> {
+    DataRef::new(PromptQuery::uid(uid))
+}
+
+async fn load_prompt(state: &SharedState, uid: i32) -> Result<Prompt, ApiError> {
+    let prompt = state.storage().lookup(&prompt_ref(uid)).await;
+    prompt
+        .map(|prompt| prompt.as_ref().clone())
+        .ok_or(ApiErrorKind::NotFound.into_api())
+}
+
+/// Drops the cached prompt, stages reference their prompts
+/// so they pick up the change without being invalidated themselves.
+async fn invalidate(state: &SharedState, uid: i32) {
+    let result = state
+        .storage()
+        .get_for_data::<Prompt>()
+        .expect("Failed to get Prompt storage")
+        .invalidate(&PromptQuery::uid(uid))
+        .await;
+    if let Err(err) = result {
+        tracing::warn!("Failed to invalidate prompt {uid} {err}");
+    }
+}
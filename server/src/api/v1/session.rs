@@ -0,0 +1,97 @@
+use axum::{
+    extract::{Path, State},
+    response::{IntoResponse, Response},
+    routing::{delete, get},
+    Json, Router,
+};
+use http::StatusCode;
+use model::SessionInfo;
+use tracing::instrument;
+use uuid::Uuid;
+
+use crate::{
+    api::{ApiError, ApiErrorKind},
+    auth::Session,
+    SharedState,
+};
+
+use super::auth::AdminSession;
+
+pub fn setup_session_router() -> Router<SharedState> {
+    Router::new()
+        .route("/", get(list))
+        .route("/:id", delete(revoke))
+        .route(
+            "/users/:user",
+            get(list_for_user).delete(revoke_all_for_user),
+        )
+        .route("/users/:user/:id", delete(revoke_for_user))
+}
+
+#[instrument(skip(state, session))]
+async fn list(
+    session: Session,
+    State(state): State<SharedState>,
+) -> Result<Json<Vec<SessionInfo>>, ApiError> {
+    let user = session.user_id.ok_or(ApiErrorKind::Unauthorized)?;
+    let connection = state.defaults().connection().await?;
+    let sessions = state
+        .sessions()
+        .list_for_user(&connection, user, &session.session_id)
+        .await?;
+    Ok(Json(sessions))
+}
+
+#[instrument(skip(state, session))]
+async fn revoke(
+    session: Session,
+    Path(id): Path<Uuid>,
+    State(state): State<SharedState>,
+) -> Result<Response, ApiError> {
+    let user = session.user_id.ok_or(ApiErrorKind::Unauthorized)?;
+    revoke_session(&state, user, id).await
+}
+
+#[instrument(skip(state, session))]
+async fn list_for_user(
+    _: AdminSession,
+    session: Session,
+    Path(user): Path<Uuid>,
+    State(state): State<SharedState>,
+) -> Result<Json<Vec<SessionInfo>>, ApiError> {
+    let connection = state.defaults().connection().await?;
+    let sessions = state
+        .sessions()
+        .list_for_user(&connection, user, &session.session_id)
+        .await?;
+    Ok(Json(sessions))
+}
+
+#[instrument(skip(state))]
+async fn revoke_for_user(
+    _: AdminSession,
+    Path((user, id)): Path<(Uuid, Uuid)>,
+    State(state): State<SharedState>,
+) -> Result<Response, ApiError> {
+    revoke_session(&state, user, id).await
+}
+
+#[instrument(skip(state))]
+async fn revoke_all_for_user(
+    _: AdminSession,
+    Path(user): Path<Uuid>,
+    State(state): State<SharedState>,
+) -> Result<Response, ApiError> {
+    let connection = state.defaults().connection().await?;
+    state.sessions().revoke_all(&connection, user).await?;
+    Ok(StatusCode::NO_CONTENT.into_response())
+}
+
+async fn revoke_session(state: &SharedState, user: Uuid, id: Uuid) -> Result<Response, ApiError> {
+    let connection = state.defaults().connection().await?;
+    if state.sessions().revoke(&connection, user, id).await? {
+        Ok(StatusCode::NO_CONTENT.into_response())
+    } else {
+        Err(ApiErrorKind::NotFound.into_api())
+    }
+}
@@ -0,0 +1,239 @@
+use std::collections::HashSet;
+
+use axum::{
+    extract::{Path, State},
+    response::{IntoResponse, Response},
+    routing::get,
+    Json, Router,
+};
+use deadpool_postgres::GenericClient;
+use http::StatusCode;
+use model::{ConsentMode, Stage, StageQuery};
+use storage::datacache::{DataRef, DataStorage, LookupRef};
+use tracing::instrument;
+
+use crate::{
+    api::{ApiError, ApiErrorKind},
+    service::stage::{StageKindWrite, StageWrite},
+    SharedState,
+};
+
+use super::auth::AdminSession;
+
+/// Admin api to manage stages and the rows of their kind.
+pub fn setup_stage_router() -> Router<SharedState> {
+    Router::new()
+        .route("/", get(list).post(create))
+        .route("/:slug", get(get_stage).put(update).delete(delete_stage))
+}
+
+#[instrument(skip(state))]
+async fn list(
+    _: AdminSession,
+    State(state): State<SharedState>,
+) -> Result<Json<Vec<Stage>>, ApiError> {
+    let connection = state.defaults().connection().await?;
+    let mut stages = Vec::new();
+    for uid in state.stages().list_uids(&connection).await? {
+        if let Some(stage) = state.storage().lookup(&stage_ref(uid)).await {
+            stages.push(stage.as_ref().clone());
+        }
+    }
+    Ok(Json(stages))
+}
+
+#[instrument(skip(state))]
+async fn get_stage(
+    _: AdminSession,
+    Path(slug): Path<String>,
+    State(state): State<SharedState>,
+) -> Result<Json<Stage>, ApiError> {
+    let connection = state.defaults().connection().await?;
+    let uid = find_stage(&state, &connection, &slug).await?;
+    load_stage(&state, uid).await.map(Json)
+}
+
+#[instrument(skip(state))]
+async fn create(
+    _: AdminSession,
+    State(state): State<SharedState>,
+    Json(write): Json<StageWrite>,
+) -> Result<Response, ApiError> {
+    if let Some(response) = check_stage(&state, &write) {
+        return Ok(response);
+    }
+    let mut connection = state.defaults().connection().await?;
+    let connection = connection.transaction().await?;
+    if let Some(message) = state
+        .stages()
+        .check_references(&connection, &write.kind)
+        .await?
+    {
+        return Ok((StatusCode::BAD_REQUEST, message).into_response());
+    }
+    let uid = state.stages().create(&connection, &write).await?;
+    connection.commit().await?;
+    let stage = load_stage(&state, uid).await?;
+    Ok((StatusCode::CREATED, Json(stage)).into_response())
+}
+
+#[instrument(skip(state))]
+async fn update(
+    _: AdminSession,
+    Path(slug): Path<String>,
+    State(state): State<SharedState>,
+    Json(write): Json<StageWrite>,
+) -> Result<Response, ApiError> {
+    if let Some(response) = check_stage(&state, &write) {
+        return Ok(response);
+    }
+    let mut connection = state.defaults().connection().await?;
+    let connection = connection.transaction().await?;
+    let uid = find_stage(&state, &connection, &slug).await?;
+    if let Some(message) = state
+        .stages()
+        .check_references(&connection, &write.kind)
+        .await?
+    {
+        return Ok((StatusCode::BAD_REQUEST, message).into_response());
+    }
+    if !matches!(write.kind, StageKindWrite::Password { .. })
+        && state
+            .stages()
+            .used_by_identification(&connection, uid)
+            .await?
+    {
+        return Ok((
+            StatusCode::CONFLICT,
+            "Stage is used as password stage by an identification stage",
+        )
+            .into_response());
+    }
+    if !state.stages().update(&connection, uid, &write).await? {
+        return Err(ApiErrorKind::NotFound.into_api());
+    }
+    connection.commit().await?;
+    invalidate(&state, uid).await;
+    Ok(Json(load_stage(&state, uid).await?).into_response())
+}
+
+#[instrument(skip(state))]
+async fn delete_stage(
+    _: AdminSession,
+    Path(slug): Path<String>,
+    State(state): State<SharedState>,
+) -> Result<Response, ApiError> {
+    let mut connection = state.defaults().connection().await?;
+    let connection = connection.transaction().await?;
+    let uid = find_stage(&state, &connection, &slug).await?;
+    if state.stages().used_by_flow(&connection, uid).await? {
+        return Ok((StatusCode::CONFLICT, "Stage is used by a flow").into_response());
+    }
+    if state
+        .stages()
+        .used_by_identification(&connection, uid)
+        .await?
+    {
+        return Ok((
+            StatusCode::CONFLICT,
+            "Stage is used as password stage by an identification stage",
+        )
+            .into_response());
+    }
+    if !state.stages().delete(&connection, uid).await? {
+        return Err(ApiErrorKind::NotFound.into_api());
+    }
+    connection.commit().await?;
+    invalidate(&state, uid).await;
+    Ok(StatusCode::NO_CONTENT.into_response())
+}
+
+fn check_stage(state: &SharedState, write: &StageWrite) -> Option<Response> {
+    let message = if write.slug.is_empty() || write.slug != write.slug.to_lowercase() {
+        "Slug must be lowercase"
+    } else if write.timeout < 0 {
+        "Timeout must not be negative"
+    } else {
+        match &write.kind {
+            StageKindWrite::Identification { user_fields, .. } if user_fields.is_empty() => {
+                "Identification stage needs at least one user field"
+            }
+            StageKindWrite::Password { backends } if backends.is_empty() => {
+                "Password stage needs at least one backend"
+            }
+            StageKindWrite::Consent {
+                mode: ConsentMode::Until { duration },
+            } if *duration <= 0 => "Consent duration must be positive",
+            // The issuer prefixes the account name of the otpauth uri, separated by a colon
+            StageKindWrite::AuthenticatorTotp { issuer, .. }
+                if issuer.is_empty() || issuer.len() > 64 || issuer.contains(':') =>
+            {
+                "Issuer must be between 1 and 64 characters long and must not contain colons"
+            }
+            StageKindWrite::AuthenticatorTotp { drift, .. } if !(0..=10).contains(drift) => {
+                "Drift must be between 0 and 10"
+            }
+            StageKindWrite::AuthenticatorStatic { count, .. } if !(1..=32).contains(count) => {
+                "Count must be between 1 and 32"
+            }
+            StageKindWrite::Email { subject, .. } if subject.is_empty() => {
+                "Subject must not be empty"
+            }
+            StageKindWrite::Email { template, .. } if !state.email().has_template(template) => {
+                "Template does not exist"
+            }
+            StageKindWrite::Email { token_expiry, .. } if *token_expiry <= 0 => {
+                "Token expiry must be positive"
+            }
+            StageKindWrite::Prompt { bindings } => {
+                let mut prompts = HashSet::new();
+                if bindings
+                    .iter()
+                    .all(|binding| prompts.insert(binding.prompt))
+                {
+                    return None;
+                }
+                "Prompt is bound more than once"
+            }
+            _ => return None,
+        }
+    };
+    Some((StatusCode::BAD_REQUEST, message).into_response())
+}
+
+fn stage_ref(uid: i32) -> DataRef<Stage> {
+    DataRef::new(StageQuery::uid(uid))
+}
+
+async fn find_stage(
+    state: &SharedState,
+    client: &impl GenericClient,
+    slug: &str,
+) -> Result<i32, ApiError> {
+    state
+        .stages()
+        .uid_by_slug(client, slug)
+        .await?
+        .ok_or(ApiErrorKind::NotFound.into_api())
+}
+
+async fn load_stage(state: &SharedState, uid: i32) -> Result<Stage, ApiError> {
+    let stage = state.storage().lookup(&stage_ref(uid)).await;
+    stage
+        .map(|stage| stage.as_ref().clone())
+        .ok_or(ApiErrorKind::NotFound.into_api())
+}
+
+/// Drops the cached stage, flows reference their stages
+/// so they pick up the change without being invalidated themselves.
+async fn invalidate(state: &SharedState, uid: i32) {
+    let result = state
+        .storage()
+        .get_for_data::<Stage>()
+        .expect("Failed to get Stage storage")
+        .invalidate(&StageQuery::uid(uid))
+        .await;
+    if let Err(err) = result {
+        tracing::warn!("Failed to invalidate stage {uid} {err}");
+    }
+}
@@ -0,0 +1,68 @@
+use axum::{
+    extract::{Path, State},
+    routing::post,
+    Json, Router,
+};
+use http::StatusCode;
+use tracing::instrument;
+use uuid::Uuid;
+
+use crate::{
+    api::{ApiError, ApiErrorKind},
+    service::static_codes::{generate_codes, DEFAULT_COUNT},
+    SharedState,
+};
+
+use super::auth::AdminSession;
+
+pub fn setup_user_router() -> Router<SharedState> {
+    Router::new().route(
+        "/:user/static_codes",
+        post(regenerate_static_codes).delete(delete_static_codes),
+    )
+}
+
+/// Replaces the recovery codes of the user, the new codes are only returned once.
+#[instrument(skip(state))]
+async fn regenerate_static_codes(
+    _: AdminSession,
+    Path(user): Path<Uuid>,
+    State(state): State<SharedState>,
+) -> Result<Json<Vec<String>>, ApiError> {
+    let mut connection = state.defaults().connection().await?;
+    let connection = connection.transaction().await?;
+    if state
+        .users()
+        .lookup_user_uid(&connection, user)
+        .await?
+        .is_none()
+    {
+        return Err(ApiErrorKind::NotFound.into_api());
+    }
+    let codes = generate_codes(DEFAULT_COUNT);
+    state
+        .static_codes()
+        .replace(&connection, user, &codes)
+        .await?;
+    connection.commit().await?;
+    Ok(Json(codes))
+}
+
+#[instrument(skip(state))]
+async fn delete_static_codes(
+    _: AdminSession,
+    Path(user): Path<Uuid>,
+    State(state): State<SharedState>,
+) -> Result<StatusCode, ApiError> {
+    let connection = state.defaults().connection().await?;
+    if state
+        .users()
+        .lookup_user_uid(&connection, user)
+        .await?
+        .is_none()
+    {
+        return Err(ApiErrorKind::NotFound.into_api());
+    }
+    state.static_codes().delete_all(&connection, user).await?;
+    Ok(StatusCode::NO_CONTENT)
+}
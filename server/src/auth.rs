@@ -4,8 +4,11 @@ use uuid::Uuid;
 
 use crate::{api::ApiError, SharedState};
 
+#[derive(Clone)]
 pub struct Session {
     pub session_id: String,
+    /// Public id of the session, the session id itself is never exposed
+    pub id: Uuid,
     pub user_id: Option<Uuid>,
     pub is_admin: bool,
 }
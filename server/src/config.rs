@@ -1,6 +1,10 @@
-use std::net::{Ipv6Addr, SocketAddr};
+use std::{
+    net::{Ipv6Addr, SocketAddr},
+    path::PathBuf,
+};
 
 use config::{Config, ConfigError};
+use model::SigningAlgorithm;
 use serde::Deserialize;
 
 #[derive(Debug, Clone)]
@@ -12,8 +16,25 @@ pub struct AuthustConfiguration {
 pub struct InternalAuthustConfiguration {
     pub listen: ListenConfiguration,
     pub postgres: PostgresConfiguration,
-    pub secret: String,
     pub jaeger_endpoint: Option<String>,
+    #[serde(default)]
+    pub execution_store: ExecutionStoreKind,
+    /// Secret the private signing keys and the fields of persisted executions are encrypted with.
+    /// Required, so that a missing secret is reported when loading the configuration
+    pub secret: String,
+    /// Public base url, used as the oidc issuer. Required by OAuth2 and links sent by email.
+    pub issuer: Option<String>,
+    #[serde(default)]
+    pub keys: KeyConfiguration,
+    #[serde(default)]
+    pub sessions: SessionConfiguration,
+    /// Filter file built with `tools breached-filter`, used by breached password policies
+    pub breached_passwords: Option<PathBuf>,
+    /// Comma separated origins flows may redirect to, e.g. `https://app.example.com`.
+    /// The host of the tenant and redirect uris of applications are always allowed.
+    pub allowed_redirects: Option<String>,
+    #[serde(default)]
+    pub email: EmailConfiguration,
     // pub allowed_hosts: Vec<String>,
 }
 
@@ -24,6 +45,79 @@ pub struct ListenConfiguration {
     pub metrics: SocketAddr,
 }
 
+#[derive(Debug, Clone, Default, Deserialize)]
+#[serde(rename_all = "snake_case")]
+pub enum ExecutionStoreKind {
+    #[default]
+    Memory,
+    Postgres,
+}
+
+#[derive(Debug, Clone, Deserialize)]
+#[serde(default)]
+pub struct KeyConfiguration {
+    /// Algorithm of newly generated signing keys
+    pub algorithm: SigningAlgorithm,
+    /// Seconds a key is used for signing before a new one is generated
+    pub rotation: u64,
+    /// Seconds a new key is published before it signs and a rotated key is still accepted for verification.
+    /// Has to be longer than an hour, the interval in which every instance reloads the keys.
+    pub overlap: u64,
+}
+
+#[derive(Debug, Clone, Deserialize)]
+#[serde(default)]
+pub struct SessionConfiguration {
+    /// Seconds after which a session expires regardless of activity
+    pub lifetime: u64,
+    /// Seconds after which an unused session expires
+    pub idle_timeout: u64,
+}
+
+#[derive(Debug, Clone, Deserialize)]
+#[serde(default)]
+pub struct EmailConfiguration {
+    /// Sender of all messages, e.g. `Authust <authust@example.com>`
+    pub from: String,
+    pub transport: EmailTransportKind,
+    /// Directory the file transport writes messages to
+    pub directory: PathBuf,
+    /// Directory with additional `.hbs` templates, which may replace the built-in ones
+    pub templates: Option<PathBuf>,
+    pub smtp: SmtpConfiguration,
+}
+
+#[derive(Debug, Clone, Default, Deserialize)]
+#[serde(rename_all = "snake_case")]
+pub enum EmailTransportKind {
+    /// Prints messages to stdout, for local testing
+    #[default]
+    Stdout,
+    /// Writes every message to a `.eml` file
+    File,
+    Smtp,
+}
+
+#[derive(Debug, Clone, Deserialize)]
+#[serde(default)]
+pub struct SmtpConfiguration {
+    pub host: String,
+    pub port: u16,
+    pub username: Option<String>,
+    pub password: Option<String>,
+    pub tls: SmtpTls,
+}
+
+#[derive(Debug, Clone, Copy, Default, Deserialize)]
+#[serde(rename_all = "snake_case")]
+pub enum SmtpTls {
+    /// Plain connection, only meant for local stand-in servers
+    #[default]
+    None,
+    StartTls,
+    Tls,
+}
+
 #[derive(Debug, Clone, Deserialize)]
 pub struct PostgresConfiguration {
     pub host: String,
@@ -43,6 +137,53 @@ impl Default for ListenConfiguration {
     }
 }
 
+impl Default for KeyConfiguration {
+    fn default() -> Self {
+        Self {
+            algorithm: SigningAlgorithm::Rs256,
+            // 30 Days
+            rotation: 60 * 60 * 24 * 30,
+            // 3 Days
+            overlap: 60 * 60 * 24 * 3,
+        }
+    }
+}
+
+impl Default for SessionConfiguration {
+    fn default() -> Self {
+        Self {
+            // 14 Days
+            lifetime: 60 * 60 * 24 * 14,
+            // 3 Days
+            idle_timeout: 60 * 60 * 24 * 3,
+        }
+    }
+}
+
+impl Default for EmailConfiguration {
+    fn default() -> Self {
+        Self {
+            from: "Authust <authust@localhost>".to_owned(),
+            transport: EmailTransportKind::Stdout,
+            directory: PathBuf::from("mail"),
+            templates: None,
+            smtp: SmtpConfiguration::default(),
+        }
+    }
+}
+
+impl Default for SmtpConfiguration {
+    fn default() -> Self {
+        Self {
+            host: "localhost".to_owned(),
+            port: 25,
+            username: None,
+            password: None,
+            tls: SmtpTls::None,
+        }
+    }
+}
+
 impl From<InternalAuthustConfiguration> for AuthustConfiguration {
     fn from(_value: InternalAuthustConfiguration) -> Self {
         Self {
@@ -65,6 +206,16 @@ fn leak_vec(vec: Vec<String>) -> &'static [&'static str] {
 }
 
 impl InternalAuthustConfiguration {
+    pub fn allowed_redirect_origins(&self) -> Vec<String> {
+        self.allowed_redirects
+            .iter()
+            .flat_map(|origins| origins.split(','))
+            .map(str::trim)
+            .filter(|origin| !origin.is_empty())
+            .map(ToOwned::to_owned)
+            .collect()
+    }
+
     pub fn load() -> Result<Self, ConfigError> {
         let default_listen = ListenConfiguration::default();
         let loaded = Config::builder()
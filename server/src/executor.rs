@@ -4,28 +4,41 @@ use std::{
 };
 
 use ::storage::{
-    datacache::{DataRef, LookupRef},
-    ReverseLookup, StorageManager,
+    datacache::{Data, DataRef, LookupRef},
+    FreezedStorage, ReverseLookup, StorageError, StorageManager,
 };
 use derive_more::Display;
-use moka::sync::Cache;
 use parking_lot::{Mutex, RwLock};
 
-use crate::{auth::Session, service::policy::PolicyService};
-use model::{Flow, FlowQuery, PolicyKind, PolicyResult};
+use crate::{
+    auth::Session,
+    service::{policy::PolicyService, redirect::RedirectService},
+};
+use model::{Flow, FlowQuery, PolicyKind, PolicyResult, Tenant};
 
-use self::flow::{FlowExecution, FlowExecutionInternal};
+use self::{
+    flow::{FlowExecution, FlowExecutionInternal},
+    storage::{ExecutionState, ExecutionStore},
+};
 
 mod context;
 pub mod data;
+pub mod fields;
 pub mod flow;
+pub mod password;
+pub mod prompt;
 pub mod storage;
+pub mod totp;
+pub mod webauthn;
 pub use context::*;
 
+/// Session id of simulated executions, real session ids are longer
+pub(crate) const SIMULATION_SESSION: &str = "simulation";
+
 // 12 Hours
-const TIME_TO_IDLE: Duration = Duration::from_secs(60 * 60 * 12);
+pub(crate) const TIME_TO_IDLE: Duration = Duration::from_secs(60 * 60 * 12);
 // 36 Hours
-const TIME_TO_LIVE: Duration = Duration::from_secs(60 * 60 * 36);
+pub(crate) const TIME_TO_LIVE: Duration = Duration::from_secs(60 * 60 * 36);
 
 #[derive(Debug, Display, Clone, Hash, PartialEq, Eq)]
 #[display("FlowKey({session}, {flow:?})")]
@@ -49,33 +62,66 @@ pub struct FlowExecutor {
 }
 
 struct FlowExecutorInternal {
-    executions: Cache<FlowKey, FlowExecution>,
+    store: Arc<dyn ExecutionStore>,
     storage: StorageManager,
     policy_service: PolicyService,
+    redirects: RedirectService,
+    /// Serves hosts without a tenant of their own
+    default_tenant: Option<Data<Tenant>>,
 }
 
 impl FlowExecutorInternal {
-    pub fn new(storage: StorageManager, policy_service: PolicyService) -> Self {
+    pub fn new(
+        storage: StorageManager,
+        policy_service: PolicyService,
+        redirects: RedirectService,
+        store: Arc<dyn ExecutionStore>,
+        default_tenant: Option<Data<Tenant>>,
+    ) -> Self {
         Self {
-            executions: Cache::builder()
-                .time_to_idle(TIME_TO_IDLE.clone())
-                .time_to_live(TIME_TO_LIVE.clone())
-                .build(),
+            store,
             storage,
             policy_service,
+            redirects,
+            default_tenant,
         }
     }
 }
 
 impl FlowExecutor {
-    pub fn new(storage: StorageManager, policy_service: PolicyService) -> Self {
+    pub fn new(
+        storage: StorageManager,
+        policy_service: PolicyService,
+        redirects: RedirectService,
+        store: Arc<dyn ExecutionStore>,
+        default_tenant: Option<Data<Tenant>>,
+    ) -> Self {
         Self {
-            internal: Arc::new(FlowExecutorInternal::new(storage, policy_service)),
+            internal: Arc::new(FlowExecutorInternal::new(
+                storage,
+                policy_service,
+                redirects,
+                store,
+                default_tenant,
+            )),
         }
     }
 
-    pub fn invalidate_flow(&self, key: &FlowKey) {
-        self.internal.executions.invalidate(key);
+    pub fn redirects(&self) -> &RedirectService {
+        &self.internal.redirects
+    }
+
+    pub async fn invalidate_flow(&self, key: &FlowKey) {
+        if let Err(err) = self.internal.store.remove(key).await {
+            tracing::error!(key = %key, "Failed to remove flow execution {err}");
+        }
+    }
+
+    /// Drops all other executions belonging to the session of `key`.
+    pub async fn invalidate_session(&self, key: &FlowKey) {
+        if let Err(err) = self.internal.store.remove_session(key).await {
+            tracing::error!(key = %key, "Failed to remove flow executions of session {err}");
+        }
     }
 
     pub fn get_key(&self, session: &Session, flow: DataRef<Flow>) -> Option<FlowKey> {
@@ -88,9 +134,12 @@ impl FlowExecutor {
     }
 
     pub async fn get_execution(&self, key: &FlowKey, start: bool) -> Option<FlowExecution> {
-        let execution = self.internal.executions.get(key);
-        if execution.is_some() {
-            return execution;
+        match self.internal.store.load(self, key).await {
+            Ok(Some(execution)) => return Some(execution),
+            Ok(None) => {}
+            Err(err) => {
+                tracing::error!(key = %key, "Failed to load flow execution {err}");
+            }
         }
 
         if start {
@@ -100,6 +149,14 @@ impl FlowExecutor {
         }
     }
 
+    pub async fn persist(&self, execution: &FlowExecution) -> Result<(), StorageError> {
+        self.internal.store.save(execution).await
+    }
+
+    pub async fn purge_expired(&self) -> Result<u64, StorageError> {
+        self.internal.store.purge_expired().await
+    }
+
     pub async fn start(&self, key: &FlowKey) -> Option<FlowExecution> {
         let flow = match self.internal.storage.lookup(&key.flow).await {
             Some(flow) => flow,
@@ -107,27 +164,71 @@ impl FlowExecutor {
                 return None;
             }
         };
-        let proxied = ::storage::create_proxied(&self.internal.storage);
         if flow.entries.is_empty() {
             return None;
         }
-        proxied.reverse_lookup(&*flow).await;
-        let storage = ::storage::create_freezed(proxied);
+        let storage = self.freeze(&flow).await;
+        let context = ExecutionContext::new(key.session.clone(), storage);
+        let execution = self.create_execution(key, flow, context, 0, false);
+        if let Err(err) = self.persist(&execution).await {
+            tracing::error!(key = %key, "Failed to store flow execution {err}");
+            return None;
+        }
+        Some(execution)
+    }
+
+    /// Starts an execution which is never persisted, so it can be driven without a session.
+    pub async fn simulate(&self, flow: DataRef<Flow>) -> Option<FlowExecution> {
+        let flow_data = self.internal.storage.lookup(&flow).await?;
+        if flow_data.entries.is_empty() {
+            return None;
+        }
+        let key = FlowKey {
+            session: SIMULATION_SESSION.to_owned(),
+            flow,
+        };
+        let storage = self.freeze(&flow_data).await;
         let context = ExecutionContext::new(key.session.clone(), storage);
+        Some(self.create_execution(&key, flow_data, context, 0, false))
+    }
+
+    async fn restore(&self, key: &FlowKey, state: ExecutionState) -> Option<FlowExecution> {
+        let flow = self.internal.storage.lookup(&key.flow).await?;
+        if state.current_entry_idx >= flow.entries.len() {
+            tracing::warn!(key = %key, "Stored flow execution does not match flow anymore");
+            return None;
+        }
+        let storage = self.freeze(&flow).await;
+        let current_entry_idx = state.current_entry_idx;
+        let is_completed = state.is_completed;
+        let context = state.into_context(key.session.clone(), storage);
+        Some(self.create_execution(key, flow, context, current_entry_idx, is_completed))
+    }
+
+    async fn freeze(&self, flow: &Flow) -> FreezedStorage {
+        let proxied = ::storage::create_proxied(&self.internal.storage);
+        proxied.reverse_lookup(flow).await;
+        ::storage::create_freezed(proxied)
+    }
+
+    fn create_execution(
+        &self,
+        key: &FlowKey,
+        flow: Data<Flow>,
+        context: ExecutionContext,
+        current_entry_idx: usize,
+        is_completed: bool,
+    ) -> FlowExecution {
         let execution = FlowExecutionInternal {
             flow,
             context: RwLock::new(context),
-            current_entry_idx: Mutex::new(0),
-            is_completed: AtomicBool::new(false),
+            current_entry_idx: Mutex::new(current_entry_idx),
+            is_completed: AtomicBool::new(is_completed),
             executor: self.clone(),
             key: key.clone(),
             policy_service: self.internal.policy_service.clone(),
         };
-        let execution = FlowExecution(Arc::new(execution));
-        self.internal
-            .executions
-            .insert(key.clone(), execution.clone());
-        Some(execution)
+        FlowExecution(Arc::new(execution))
     }
 
     // pub fn init(&mut self) {}
@@ -151,8 +252,16 @@ impl Validate for PolicyKind {
                     (&(context.start_time - date) > &time::Duration::seconds(*max_age as i64))
                         .into()
                 }),
-            PolicyKind::PasswordStrength => todo!(),
-            PolicyKind::Expression(..) => todo!(),
+            PolicyKind::PasswordStrength(rules) => {
+                let names: Vec<&str> = context.user.iter().map(|user| user.name.as_str()).collect();
+                password::check_collected(rules, &context.fields, &names)
+                    .map_or(PolicyResult::NotApplicable, |messages| {
+                        messages.is_empty().into()
+                    })
+            }
+            PolicyKind::Expression { .. } => todo!(),
+            // The filter is held by the policy service, see `check_policy`
+            PolicyKind::BreachedPassword { .. } => PolicyResult::NotApplicable,
         }
     }
 }
@@ -1,10 +1,8 @@
-use std::{
-    any::{Any, TypeId},
-    collections::HashMap,
-    marker::PhantomData,
-};
+use std::{collections::HashMap, marker::PhantomData};
 
 use model::{PendingUser, Stage};
+use serde::{de::DeserializeOwned, Deserialize, Serialize};
+use serde_json::Value;
 use storage::{datacache::DataRef, FreezedStorage};
 use time::OffsetDateTime;
 use uuid::Uuid;
@@ -12,6 +10,8 @@ use uuid::Uuid;
 pub struct ExecutionContext {
     pub session_id: String,
     pub start_time: OffsetDateTime,
+    /// When the current entry became current
+    pub entry_start: OffsetDateTime,
     pub fields: FieldStorage,
     pub pending: Option<PendingUser>,
     pub user: Option<PolicyUser>,
@@ -19,6 +19,7 @@ pub struct ExecutionContext {
     pub error: Option<ExecutionError>,
 }
 
+#[derive(Debug, Clone, Serialize, Deserialize)]
 pub struct ExecutionError {
     pub stage: Option<DataRef<Stage>>,
     pub message: String,
@@ -26,9 +27,11 @@ pub struct ExecutionError {
 
 impl ExecutionContext {
     pub fn new(session_id: String, storage: FreezedStorage) -> Self {
+        let now = OffsetDateTime::now_utc();
         Self {
             session_id,
-            start_time: OffsetDateTime::now_utc(),
+            start_time: now,
+            entry_start: now,
             fields: FieldStorage::new(),
             user: None,
             storage,
@@ -38,27 +41,33 @@ impl ExecutionContext {
     }
 }
 
-#[derive(Debug, Clone, PartialEq)]
+#[derive(Debug, Clone, PartialEq, Serialize, Deserialize)]
 pub struct PolicyUser {
     pub uid: Uuid,
     pub name: String,
     pub password_change_date: OffsetDateTime,
 }
 
+#[derive(Debug)]
 pub enum FieldStorageError {
     WrongType,
+    Serialization(serde_json::Error),
 }
 
+/// Values collected by the stages of an execution.
+/// Everything is kept as json so the storage can be persisted together with the execution.
+#[derive(Debug, Clone, Default, Serialize, Deserialize)]
+#[serde(transparent)]
 pub struct FieldStorage {
-    fields: HashMap<String, Box<dyn Any + Send + Sync>>,
+    fields: HashMap<String, Value>,
 }
 
-pub struct FieldKey<Value: Any> {
+pub struct FieldKey<Value> {
     pub name: &'static str,
     _value: PhantomData<Value>,
 }
 
-impl<Value: Any> FieldKey<Value> {
+impl<Value> FieldKey<Value> {
     pub const fn new(key: &'static str) -> Self {
         Self {
             name: key,
@@ -75,28 +84,56 @@ impl FieldStorage {
     }
 
     #[inline(always)]
-    pub fn get_typed<T: Any>(&self, key: FieldKey<T>) -> Result<Option<&T>, FieldStorageError> {
+    pub fn get_typed<T: DeserializeOwned>(
+        &self,
+        key: FieldKey<T>,
+    ) -> Result<Option<T>, FieldStorageError> {
         self.get_dynamic(key.name)
     }
-    pub fn get_dynamic<T: Any>(&self, field_name: &str) -> Result<Option<&T>, FieldStorageError> {
-        if let Some(entry) = self.fields.get(field_name) {
-            let id = TypeId::of::<T>();
-            if entry.type_id() != id {
-                Err(FieldStorageError::WrongType)
-            } else {
-                Ok(Some(entry.downcast_ref().expect("Downcast failed")))
-            }
-        } else {
-            Ok(None)
+    pub fn get_dynamic<T: DeserializeOwned>(
+        &self,
+        field_name: &str,
+    ) -> Result<Option<T>, FieldStorageError> {
+        match self.fields.get(field_name) {
+            Some(entry) => T::deserialize(entry)
+                .map(Some)
+                .map_err(|_| FieldStorageError::WrongType),
+            None => Ok(None),
         }
     }
 
+    pub fn get_value(&self, field_name: &str) -> Option<&Value> {
+        self.fields.get(field_name)
+    }
+
     #[inline(always)]
-    pub fn insert_typed<T: Any + Send + Sync + 'static>(&mut self, key: FieldKey<T>, value: T) {
+    pub fn insert_typed<T: Serialize>(
+        &mut self,
+        key: FieldKey<T>,
+        value: T,
+    ) -> Result<(), FieldStorageError> {
         self.insert_dynamic(key.name, value)
     }
 
-    pub fn insert_dynamic<T: Any + Send + Sync + 'static>(&mut self, name: &str, value: T) {
-        self.fields.insert(name.to_owned(), Box::new(value));
+    pub fn insert_dynamic<T: Serialize>(
+        &mut self,
+        name: &str,
+        value: T,
+    ) -> Result<(), FieldStorageError> {
+        let value = serde_json::to_value(value).map_err(FieldStorageError::Serialization)?;
+        self.fields.insert(name.to_owned(), value);
+        Ok(())
+    }
+
+    pub fn insert_value(&mut self, name: &str, value: Value) {
+        self.fields.insert(name.to_owned(), value);
+    }
+
+    pub fn remove(&mut self, name: &str) -> Option<Value> {
+        self.fields.remove(name)
+    }
+
+    pub fn iter(&self) -> impl Iterator<Item = (&String, &Value)> {
+        self.fields.iter()
     }
 }
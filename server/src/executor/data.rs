@@ -1,51 +1,240 @@
 use async_trait::async_trait;
+use serde_json::Value;
 
-use model::{FlowComponent, PasswordComponentData, Sources, Stage, StageKind};
+use model::{
+    FlowComponent, PasswordComponentData, Sources, Stage, StageKind, StaticMode, TotpMode,
+    TotpSetupData, UserVerification, WebAuthnMode,
+};
 
-use super::flow::FlowExecution;
+use crate::service::static_codes::generate_codes;
+
+use super::{
+    fields::{
+        APPLICATION_NAME, EMAIL_SENT_TO, SCOPES, STATIC_CODES, TOTP_SECRET, WEBAUTHN_CHALLENGE,
+        WEBAUTHN_CREDENTIALS,
+    },
+    flow::{CheckContext, FlowExecution},
+    totp,
+    webauthn::Challenge,
+};
 
 #[async_trait]
 pub trait AsComponent {
-    async fn as_component(&self, execution: &FlowExecution) -> Option<FlowComponent>;
+    async fn as_component(
+        &self,
+        execution: &FlowExecution,
+        context: &CheckContext,
+    ) -> Option<FlowComponent>;
 }
 
 #[async_trait]
 impl AsComponent for Stage {
-    async fn as_component(&self, execution: &FlowExecution) -> Option<FlowComponent> {
+    async fn as_component(
+        &self,
+        execution: &FlowExecution,
+        context: &CheckContext,
+    ) -> Option<FlowComponent> {
         match &self.kind {
             StageKind::Deny => Some(FlowComponent::AccessDenied {
                 message: "Access denied".to_owned(),
             }),
-            StageKind::Prompt { bindings: _ } => todo!(),
+            StageKind::Prompt { bindings } => {
+                let mut bindings = bindings.clone();
+                bindings.sort_by_key(|binding| binding.order);
+                let mut prompts = Vec::with_capacity(bindings.len());
+                for binding in &bindings {
+                    let prompt = execution.lookup_prompt(&binding.prompt).await;
+                    prompts.push(prompt.as_ref().clone());
+                }
+                Some(FlowComponent::Prompt { prompts })
+            }
             StageKind::Identification {
                 password,
                 user_fields,
+                passwordless,
             } => {
                 let _stage = match password {
                     Some(v) => Some(execution.lookup_stage(&v).await),
                     None => None,
                 };
+                // Discoverable credentials identify the user themselves, so none are allowed explicitly
+                let webauthn = if *passwordless {
+                    let challenge =
+                        webauthn_challenge(execution, context, UserVerification::Required).await?;
+                    Some(challenge.request_options(&[]))
+                } else {
+                    None
+                };
                 Some(FlowComponent::Identification {
                     user_fields: user_fields.to_owned(),
                     sources: Sources {
                         sources: vec![],
                         show_source_labels: false,
                     },
-                    password: password.clone().map(|_| PasswordComponentData {
-                        recovery_url: "".into(),
-                    }),
+                    password: match password {
+                        Some(_) => Some(PasswordComponentData {
+                            recovery_url: execution.recovery_url(&context.request.host).await,
+                        }),
+                        None => None,
+                    },
+                    webauthn,
                 })
             }
-            StageKind::UserLogin | StageKind::UserLogout | StageKind::UserWrite => {
-                tracing::warn!("Tried to send server side stage to client");
-                None
+            StageKind::UserLogin | StageKind::UserLogout { .. } | StageKind::UserWrite => {
+                Some(FlowComponent::Confirm)
             }
             StageKind::Password { .. } => Some(FlowComponent::Password {
                 data: PasswordComponentData {
-                    recovery_url: "".into(),
+                    recovery_url: execution.recovery_url(&context.request.host).await,
                 },
             }),
-            StageKind::Consent { mode: _ } => todo!(),
+            StageKind::Consent { mode } => {
+                let context = execution.get_context();
+                let application = match context.fields.get_typed(APPLICATION_NAME) {
+                    Ok(Some(name)) => name,
+                    _ => execution.flow_title(),
+                };
+                let scopes = context
+                    .fields
+                    .get_typed(SCOPES)
+                    .ok()
+                    .flatten()
+                    .unwrap_or_default();
+                Some(FlowComponent::Consent {
+                    application,
+                    scopes,
+                    mode: mode.clone(),
+                })
+            }
+            StageKind::AuthenticatorTotp {
+                mode: TotpMode::Setup,
+                issuer,
+                ..
+            } => {
+                let account = match (&context.pending_user, &context.request.user) {
+                    (Some(pending), _) => pending.name.clone(),
+                    (None, Some(user)) => user.name.clone(),
+                    (None, None) => return None,
+                };
+                let secret = totp_secret(execution).await?;
+                Some(FlowComponent::AuthenticatorTotp {
+                    setup: Some(TotpSetupData {
+                        config_url: totp::config_url(issuer, &account, &secret),
+                        secret: totp::encode_base32(&secret),
+                    }),
+                })
+            }
+            StageKind::AuthenticatorTotp {
+                mode: TotpMode::Validate,
+                ..
+            } => Some(FlowComponent::AuthenticatorTotp { setup: None }),
+            StageKind::AuthenticatorWebAuthn {
+                mode,
+                user_verification,
+            } => {
+                let challenge = webauthn_challenge(execution, context, *user_verification).await?;
+                let credentials = execution
+                    .get_context()
+                    .fields
+                    .get_typed(WEBAUTHN_CREDENTIALS)
+                    .ok()
+                    .flatten()
+                    .unwrap_or_default();
+                let options = match mode {
+                    WebAuthnMode::Register => {
+                        let (uid, name) = match (&context.pending_user, &context.request.user) {
+                            (Some(pending), _) => (pending.uid, pending.name.clone()),
+                            (None, Some(user)) => (user.uid, user.name.clone()),
+                            (None, None) => return None,
+                        };
+                        let rp_name = execution.flow_title();
+                        challenge.creation_options(&rp_name, uid, &name, &credentials)
+                    }
+                    WebAuthnMode::Authenticate => challenge.request_options(&credentials),
+                };
+                Some(FlowComponent::AuthenticatorWebAuthn {
+                    mode: *mode,
+                    options,
+                })
+            }
+            StageKind::AuthenticatorStatic {
+                mode: StaticMode::Setup,
+                count,
+            } => Some(FlowComponent::AuthenticatorStatic {
+                codes: Some(static_codes(execution, *count).await?),
+            }),
+            StageKind::AuthenticatorStatic {
+                mode: StaticMode::Validate,
+                ..
+            } => Some(FlowComponent::AuthenticatorStatic { codes: None }),
+            StageKind::Email { .. } => {
+                let sent_to = execution.get_context().fields.get_typed(EMAIL_SENT_TO);
+                Some(FlowComponent::Email {
+                    sent_to: sent_to.ok().flatten(),
+                })
+            }
+            StageKind::PasswordChange => Some(FlowComponent::PasswordChange),
         }
     }
 }
+
+/// The secret of the device which is being set up, it is kept until the device has been confirmed.
+async fn totp_secret(execution: &FlowExecution) -> Option<Vec<u8>> {
+    let stored = execution.get_context().fields.get_typed(TOTP_SECRET);
+    if let Ok(Some(secret)) = stored {
+        return totp::decode_base32(&secret);
+    }
+    let secret = totp::generate_secret();
+    let encoded = totp::encode_base32(&secret);
+    execution.use_mut_context(|ctx| {
+        ctx.fields
+            .insert_value(TOTP_SECRET.name, Value::String(encoded));
+    });
+    if let Err(err) = execution.persist().await {
+        tracing::error!("Failed to store totp secret {err}");
+        return None;
+    }
+    Some(secret)
+}
+
+/// The codes which are being set up, they are kept until the user has confirmed them.
+async fn static_codes(execution: &FlowExecution, count: i16) -> Option<Vec<String>> {
+    let stored = execution.get_context().fields.get_typed(STATIC_CODES);
+    if let Ok(Some(codes)) = stored {
+        return Some(codes);
+    }
+    let codes = generate_codes(count);
+    execution.use_mut_context(|ctx| {
+        let _ = ctx.fields.insert_typed(STATIC_CODES, codes.clone());
+    });
+    if let Err(err) = execution.persist().await {
+        tracing::error!("Failed to store static codes {err}");
+        return None;
+    }
+    Some(codes)
+}
+
+/// The challenge of the current ceremony, it is kept until the stage has been submitted.
+async fn webauthn_challenge(
+    execution: &FlowExecution,
+    context: &CheckContext,
+    user_verification: UserVerification,
+) -> Option<Challenge> {
+    let stored = execution.get_context().fields.get_typed(WEBAUTHN_CHALLENGE);
+    if let Ok(Some(challenge)) = stored {
+        return Some(challenge);
+    }
+    let host = &context.request.host;
+    let rp_id = execution.rp_id(host).await;
+    let challenge = Challenge::new(host, &rp_id, user_verification);
+    execution.use_mut_context(|ctx| {
+        let _ = ctx
+            .fields
+            .insert_typed(WEBAUTHN_CHALLENGE, challenge.clone());
+    });
+    if let Err(err) = execution.persist().await {
+        tracing::error!("Failed to store webauthn challenge {err}");
+        return None;
+    }
+    Some(challenge)
+}
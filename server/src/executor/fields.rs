@@ -0,0 +1,36 @@
+use uuid::Uuid;
+
+use super::{webauthn::Challenge, FieldKey};
+
+/// Prefix of the fields that are written into `users.attributes`.
+pub const ATTRIBUTE_PREFIX: &str = "attributes.";
+
+pub const USERNAME: FieldKey<String> = FieldKey::new("username");
+pub const EMAIL: FieldKey<String> = FieldKey::new("email");
+pub const PASSWORD: FieldKey<String> = FieldKey::new("password");
+pub const DISPLAY_NAME: FieldKey<String> = FieldKey::new("display_name");
+/// Base32 encoded secret of the totp device which is being set up.
+pub const TOTP_SECRET: FieldKey<String> = FieldKey::new("totp_secret");
+/// Recovery codes which are being set up, they are stored once the user has confirmed them.
+pub const STATIC_CODES: FieldKey<Vec<String>> = FieldKey::new("static_codes");
+/// Codes rejected by the current TOTP validation stage.
+pub const TOTP_FAILURES: FieldKey<u32> = FieldKey::new("totp_failures");
+/// Uid of the pending user whose password has been verified,
+/// a later validation stage decides whether that authenticates them.
+pub const PASSWORD_VERIFIED: FieldKey<Uuid> = FieldKey::new("password_verified");
+/// Nonce of the link sent by the email stage, it is removed once the link has been opened.
+pub const EMAIL_NONCE: FieldKey<String> = FieldKey::new("email_nonce");
+/// Masked address the link of the email stage has been sent to.
+pub const EMAIL_SENT_TO: FieldKey<String> = FieldKey::new("email_sent_to");
+/// Ceremony of the WebAuthn stage or passwordless identification which is in progress.
+pub const WEBAUTHN_CHALLENGE: FieldKey<Challenge> = FieldKey::new("webauthn_challenge");
+/// Base64url encoded ids of the WebAuthn credentials of the user.
+pub const WEBAUTHN_CREDENTIALS: FieldKey<Vec<String>> = FieldKey::new("webauthn_credentials");
+
+/// Uid of the application that requested the execution.
+pub const APPLICATION: FieldKey<i32> = FieldKey::new("application");
+pub const APPLICATION_NAME: FieldKey<String> = FieldKey::new("application_name");
+/// Scopes requested by the application.
+pub const SCOPES: FieldKey<Vec<String>> = FieldKey::new("scopes");
+/// Uid of the oauth2 authorization request which is approved once the execution completes.
+pub const OAUTH2_REQUEST: FieldKey<Uuid> = FieldKey::new("oauth2_request");
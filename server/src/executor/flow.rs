@@ -9,20 +9,26 @@ use std::{
 use http::Uri;
 use parking_lot::{lock_api::RwLockReadGuard, Mutex, RawRwLock, RwLock};
 use policy_engine::{execute, uri::Scheme, LogEntry};
-use storage::datacache::{Data, DataRef, LookupRef};
+use serde::Serialize;
+use storage::{
+    datacache::{Data, DataRef, LookupRef},
+    StorageError,
+};
+use time::OffsetDateTime;
 use uuid::Uuid;
 
 use crate::{
     api::ExecutorQuery,
+    interface::flow::INTERFACE_BASE_URI,
     service::policy::{create_scope, PolicyService},
 };
 use model::{
     error::SubmissionError, user::PartialUser, AuthenticationRequirement, Flow, FlowBinding,
     FlowBindingKind, FlowComponent, FlowData, FlowEntry, FlowInfo, PendingUser, Policy,
-    PolicyQuery, Stage,
+    PolicyQuery, Prompt, Stage, Tenant, TenantQuery,
 };
 
-use super::{data::AsComponent, ExecutionContext, ExecutionError, FlowExecutor, FlowKey};
+use super::{data::AsComponent, password, ExecutionContext, ExecutionError, FlowExecutor, FlowKey};
 
 #[derive(Clone)]
 pub struct FlowExecution(pub(super) Arc<FlowExecutionInternal>);
@@ -73,20 +79,25 @@ impl FlowExecution {
         }
         let component = if is_completed {
             match &context.request.query.next {
-                Some(to) => {
-                    self.0.executor.invalidate_flow(&self.0.key);
+                Some(to) if self.is_redirect_allowed(to, &context.request.host).await => {
+                    self.0.executor.invalidate_flow(&self.0.key).await;
                     FlowComponent::Redirect { to: to.clone() }
                 }
+                Some(_) => FlowComponent::Error {
+                    message: "Invalid Redirect".to_owned(),
+                },
                 None => FlowComponent::Error {
                     message: "Missing Redirect".to_owned(),
                 },
             }
         } else {
-            match &self.get_context().error {
+            // Components may write to the context
+            let error = self.get_context().error.clone();
+            match error {
                 Some(err) => FlowComponent::Error {
-                    message: err.message.clone(),
+                    message: err.message,
                 },
-                None => match stage.as_component(self).await {
+                None => match stage.as_component(self, context).await {
                     Some(v) => v,
                     None => FlowComponent::AccessDenied {
                         message: "Internal error while constructing component".to_owned(),
@@ -101,22 +112,140 @@ impl FlowExecution {
             error,
         }
     }
+    async fn is_redirect_allowed(&self, to: &str, host: &str) -> bool {
+        match self.0.executor.redirects().is_allowed(to, host).await {
+            Ok(true) => return true,
+            Ok(false) => {}
+            Err(err) => tracing::error!("Failed to check redirect {err:?}"),
+        }
+        tracing::warn!(
+            target: "security",
+            flow = %self.0.flow.slug,
+            host,
+            to,
+            "Rejected redirect of completed flow"
+        );
+        false
+    }
+
     pub fn get_entry(&self) -> &FlowEntry {
         let entry_idx = self.0.current_entry_idx.lock().to_owned();
         let Some(entry) =self.0.flow.entries.get(entry_idx) else { panic!("Entry index out of bounds") };
         entry
     }
 
+    /// Entries after the current one, their bindings might still skip them.
+    pub fn upcoming_entries(&self) -> &[FlowEntry] {
+        let entry_idx = *self.0.current_entry_idx.lock();
+        let entries = &self.0.flow.entries;
+        &entries[(entry_idx + 1).min(entries.len())..]
+    }
+
     pub fn complete_current(&self) {
-        let mut lock = self.0.current_entry_idx.lock();
-        let new = *lock + 1;
-        if self.0.flow.entries.get(new).is_some() {
-            *lock = new;
+        let advanced = {
+            let mut lock = self.0.current_entry_idx.lock();
+            let new = *lock + 1;
+            let exists = self.0.flow.entries.get(new).is_some();
+            if exists {
+                *lock = new;
+            }
+            exists
+        };
+        if advanced {
+            self.restart_entry();
         } else {
             self.0.is_completed.store(true, Ordering::Relaxed);
         }
     }
 
+    /// Resets the timeout of the current entry.
+    /// Must not be called while holding the index, snapshots lock the context before the index.
+    fn restart_entry(&self) {
+        self.0.context.write().entry_start = OffsetDateTime::now_utc();
+    }
+
+    pub async fn persist(&self) -> Result<(), StorageError> {
+        self.0.executor.persist(self).await
+    }
+
+    pub async fn invalidate_other_executions(&self) {
+        self.0.executor.invalidate_session(&self.0.key).await;
+    }
+
+    /// Host of the tenant serving `host`, which WebAuthn credentials are bound to.
+    pub async fn rp_id(&self, host: &str) -> String {
+        let reference: DataRef<Tenant> = DataRef::new(TenantQuery::host(host.to_owned()));
+        match self.0.executor.internal.storage.lookup(&reference).await {
+            Some(tenant) => tenant.host.clone(),
+            None => host.to_owned(),
+        }
+    }
+
+    /// Link to the recovery flow of the tenant serving `host`, the default tenant serves unknown hosts.
+    pub async fn recovery_url(&self, host: &str) -> Option<String> {
+        let internal = &self.0.executor.internal;
+        let reference: DataRef<Tenant> = DataRef::new(TenantQuery::host(host.to_owned()));
+        let tenant = match internal.storage.lookup(&reference).await {
+            Some(tenant) => tenant,
+            None => internal.default_tenant.clone()?,
+        };
+        let flow = internal
+            .storage
+            .lookup(tenant.recovery_flow.as_ref()?)
+            .await?;
+        Some(format!("{}/flow/{}", *INTERFACE_BASE_URI, flow.slug))
+    }
+
+    pub fn flow_uid(&self) -> i32 {
+        self.0.flow.uid
+    }
+
+    pub fn flow_title(&self) -> String {
+        self.0.flow.title.clone()
+    }
+
+    pub fn flow_slug(&self) -> String {
+        self.0.flow.slug.clone()
+    }
+
+    pub fn entry_index(&self) -> usize {
+        *self.0.current_entry_idx.lock()
+    }
+
+    /// Moves the execution back to an earlier entry, e.g. when a server side stage rejected the submitted data.
+    pub fn rewind(&self, entry_idx: usize) {
+        *self.0.current_entry_idx.lock() = entry_idx;
+        self.0.is_completed.store(false, Ordering::Relaxed);
+        self.restart_entry();
+    }
+
+    /// Whether the current stage has been current for longer than its timeout.
+    pub async fn is_expired(&self) -> bool {
+        if self.is_completed() {
+            return false;
+        }
+        let entry = self.get_entry();
+        let stage = self.lookup_stage(&entry.stage).await;
+        if stage.timeout <= 0 {
+            return false;
+        }
+        let timeout = time::Duration::minutes(stage.timeout as i64);
+        OffsetDateTime::now_utc() - self.get_context().entry_start > timeout
+    }
+
+    pub fn expired_data(&self) -> FlowData {
+        FlowData {
+            flow: FlowInfo {
+                title: self.0.flow.title.clone(),
+            },
+            error: None,
+            pending_user: None,
+            component: FlowComponent::Expired {
+                message: "This step has expired, please start again".to_owned(),
+            },
+        }
+    }
+
     pub fn is_completed(&self) -> bool {
         self.0.is_completed.load(Ordering::Relaxed)
     }
@@ -137,21 +266,44 @@ impl FlowExecution {
             None => panic!("Missing policy in storage {reference:?}"),
         }
     }
+    pub async fn lookup_prompt(&self, reference: &DataRef<Prompt>) -> Data<Prompt> {
+        let lock = self.0.context.read();
+        let storage = &lock.storage;
+        match storage.lookup(reference).await {
+            Some(v) => v,
+            None => panic!("Missing prompt in storage {reference:?}"),
+        }
+    }
 
     pub async fn check(&self, context: &CheckContext) -> Result<Option<String>, ()> {
+        self.check_traced(context, &mut Vec::new()).await
+    }
+
+    /// Same as `check`, but records every decision up to the first failing one.
+    pub async fn check_traced(
+        &self,
+        context: &CheckContext,
+        trace: &mut Vec<CheckDecision>,
+    ) -> Result<Option<String>, ()> {
         let flow = &self.0.flow;
         let auth_check = FlowCheck::Authentication(flow.authentication.clone());
-        if !*auth_check.check(context).await {
+        let result = auth_check.check(context).await;
+        let passed = *result;
+        trace.push(CheckDecision::Authentication {
+            requirement: flow.authentication.clone(),
+            result,
+        });
+        if !passed {
             return Ok(Some(auth_check.message(context)));
         }
         for binding in &flow.bindings {
-            if let Some(message) = check_binding(&binding, context).await? {
+            if let Some(message) = check_binding(&binding, context, false, trace).await? {
                 return Ok(Some(message));
             }
         }
         let entry = self.get_entry();
         for binding in &entry.bindings {
-            if let Some(message) = check_binding(&binding, context).await? {
+            if let Some(message) = check_binding(&binding, context, true, trace).await? {
                 return Ok(Some(message));
             }
         }
@@ -162,8 +314,15 @@ impl FlowExecution {
 async fn check_binding(
     binding: &FlowBinding,
     context: &CheckContext,
+    entry: bool,
+    trace: &mut Vec<CheckDecision>,
 ) -> Result<Option<String>, ()> {
     if !binding.enabled {
+        trace.push(CheckDecision::Binding {
+            binding: binding.clone(),
+            entry,
+            result: None,
+        });
         return Ok(None);
     }
     let check = FlowCheck::from(binding.kind.clone());
@@ -173,14 +332,38 @@ async fn check_binding(
     } else {
         result
     };
-    let result = *result;
-    if !result {
+    let passed = *result;
+    trace.push(CheckDecision::Binding {
+        binding: binding.clone(),
+        entry,
+        result: Some(result),
+    });
+    if !passed {
         Ok(Some(check.message(&context)))
     } else {
         Ok(None)
     }
 }
 
+/// A decision made while checking whether an execution may continue.
+#[derive(Debug, Serialize)]
+#[serde(tag = "check", rename_all = "snake_case")]
+pub enum CheckDecision {
+    Authentication {
+        requirement: AuthenticationRequirement,
+        result: FlowCheckOutput,
+    },
+    /// Bindings of the flow or, if `entry` is set, of the current entry.
+    /// Disabled bindings have no result.
+    Binding {
+        binding: FlowBinding,
+        entry: bool,
+        result: Option<FlowCheckOutput>,
+    },
+}
+
+#[derive(Debug, Clone, Copy, Serialize)]
+#[serde(rename_all = "snake_case")]
 pub enum FlowCheckOutput {
     Passed,
     Failed,
@@ -227,6 +410,7 @@ impl IntoFlowCheckOutput for bool {
 pub enum FlowCheck {
     Authentication(AuthenticationRequirement),
     IsUser(Uuid),
+    InGroup(Uuid),
     Policy(DataRef<Policy>),
 }
 
@@ -248,6 +432,13 @@ impl FlowCheck {
             FlowCheck::IsUser(id) => {
                 (context.request.user.as_ref().map(|v| &v.uid) == Some(id)).into_output()
             }
+            FlowCheck::InGroup(id) => context
+                .request
+                .user
+                .as_ref()
+                .map(|user| user.groups.iter().any(|group| &group.uid == id))
+                .unwrap_or(false)
+                .into_output(),
             FlowCheck::Policy(policy) => {
                 let policy = context.execution.lookup_policy(policy).await;
                 check_policy(context, &policy).await
@@ -267,6 +458,7 @@ impl FlowCheck {
             }
             .into(),
             FlowCheck::IsUser(_) => "Access denied".into(),
+            FlowCheck::InGroup(_) => "Access denied".into(),
             FlowCheck::Policy(_) => "Access denied".into(),
         }
     }
@@ -275,6 +467,7 @@ impl FlowCheck {
         match self {
             FlowCheck::Authentication(_) => self.message(context),
             FlowCheck::IsUser(_) => self.message(context),
+            FlowCheck::InGroup(_) => self.message(context),
             FlowCheck::Policy(_) => self.message(context),
         }
     }
@@ -293,8 +486,29 @@ async fn check_policy(context: &CheckContext, policy: &Policy) -> FlowCheckOutpu
                 .map(|date| ((start_time - date) < duration).into_output())
                 .unwrap_or(FlowCheckOutput::Neutral)
         }
-        model::PolicyKind::PasswordStrength => FlowCheckOutput::Neutral,
-        model::PolicyKind::Expression(_) => {
+        model::PolicyKind::PasswordStrength(rules) => {
+            let names: Vec<&str> = context
+                .request
+                .user
+                .iter()
+                .map(|user| user.name.as_str())
+                .chain(context.pending_user.iter().map(|user| user.name.as_str()))
+                .collect();
+            let execution_context = context.execution.get_context();
+            password::check_collected(rules, &execution_context.fields, &names)
+                .map(|messages| messages.is_empty().into_output())
+                .unwrap_or(FlowCheckOutput::Neutral)
+        }
+        model::PolicyKind::BreachedPassword { field_key } => {
+            let Some(filter) = context.execution.0.policy_service.breached_passwords() else {
+                tracing::warn!(policy = ?policy, "No breached password filter has been configured");
+                return FlowCheckOutput::Neutral };
+            let execution_context = context.execution.get_context();
+            password::is_breached(filter, field_key, &execution_context.fields)
+                .map(|breached| (!breached).into_output())
+                .unwrap_or(FlowCheckOutput::Neutral)
+        }
+        model::PolicyKind::Expression { .. } => {
             let reference = DataRef::new(PolicyQuery::uid(policy.uid));
             let ast = context
                 .execution
@@ -356,7 +570,7 @@ fn dispatch_expression_log_entries(
 impl From<FlowBindingKind> for FlowCheck {
     fn from(value: FlowBindingKind) -> Self {
         match value {
-            FlowBindingKind::Group(_) => todo!(),
+            FlowBindingKind::Group(id) => FlowCheck::InGroup(id),
             FlowBindingKind::User(id) => FlowCheck::IsUser(id),
             FlowBindingKind::Policy(policy) => FlowCheck::Policy(policy),
         }
@@ -0,0 +1,166 @@
+use model::PasswordRules;
+use storage::breached::BreachedFilter;
+
+use super::{
+    fields::{DISPLAY_NAME, EMAIL, USERNAME},
+    FieldStorage,
+};
+
+/// User attributes shorter than this are not checked, as they would match too many passwords.
+const MIN_ATTRIBUTE_LENGTH: usize = 3;
+
+/// Checks the password collected in `fields` against the rules.
+/// `names` are further names of the user which are not part of the fields.
+/// Returns `None` if no password has been collected yet.
+pub fn check_collected(
+    rules: &PasswordRules,
+    fields: &FieldStorage,
+    names: &[&str],
+) -> Option<Vec<String>> {
+    let password: String = fields.get_dynamic(&rules.field_key).ok().flatten()?;
+    let attributes: Vec<String> = [USERNAME, EMAIL, DISPLAY_NAME]
+        .into_iter()
+        .filter_map(|key| fields.get_typed(key).ok().flatten())
+        .chain(names.iter().map(|name| name.to_string()))
+        .collect();
+    let attributes: Vec<&str> = attributes.iter().map(String::as_str).collect();
+    Some(check_password(rules, &password, &attributes))
+}
+
+/// Looks up the password collected in `fields` in the breached password filter.
+/// Returns `None` if no password has been collected yet.
+pub fn is_breached(
+    filter: &BreachedFilter,
+    field_key: &str,
+    fields: &FieldStorage,
+) -> Option<bool> {
+    let password: String = fields.get_dynamic(field_key).ok().flatten()?;
+    Some(filter.contains_password(&password))
+}
+
+/// Returns a human readable message for every rule the password violates.
+pub fn check_password(rules: &PasswordRules, password: &str, attributes: &[&str]) -> Vec<String> {
+    let mut messages = Vec::new();
+    let length = password.chars().count();
+    if length < rules.min_length as usize {
+        messages.push(format!(
+            "Password must be at least {} characters long.",
+            rules.min_length
+        ));
+    }
+    let classes: [(i16, &str, fn(&char) -> bool); 4] = [
+        (rules.min_uppercase, "uppercase letter", |c| c.is_uppercase()),
+        (rules.min_lowercase, "lowercase letter", |c| c.is_lowercase()),
+        (rules.min_digits, "digit", |c| c.is_numeric()),
+        (rules.min_symbols, "symbol", |c| !c.is_alphanumeric()),
+    ];
+    for (min, name, filter) in classes {
+        if password.chars().filter(filter).count() < min as usize {
+            let plural = if min == 1 { "" } else { "s" };
+            messages.push(format!("Password must contain at least {min} {name}{plural}."));
+        }
+    }
+    if let Some(max) = rules.max_repeated {
+        if longest_run(password) > max as usize {
+            messages.push(format!(
+                "Password must not repeat the same character more than {max} times in a row."
+            ));
+        }
+    }
+    if rules.check_user_attributes && contains_attribute(password, attributes) {
+        messages.push("Password must not contain your username or email address.".to_owned());
+    }
+    if rules.min_score > 0 {
+        let entropy = zxcvbn::zxcvbn(password, attributes).ok();
+        let score = entropy.as_ref().map_or(0, |entropy| entropy.score());
+        if (score as i16) < rules.min_score {
+            let warning = entropy
+                .as_ref()
+                .and_then(|entropy| entropy.feedback().as_ref())
+                .and_then(|feedback| feedback.warning());
+            match warning {
+                Some(warning) => {
+                    messages.push(format!("Password is too easy to guess. {warning}."))
+                }
+                None => messages.push("Password is too easy to guess.".to_owned()),
+            }
+        }
+    }
+    messages
+}
+
+fn longest_run(password: &str) -> usize {
+    let mut longest = 0;
+    let mut current = 0;
+    let mut previous = None;
+    for c in password.chars() {
+        if previous == Some(c) {
+            current += 1;
+        } else {
+            current = 1;
+            previous = Some(c);
+        }
+        longest = longest.max(current);
+    }
+    longest
+}
+
+fn contains_attribute(password: &str, attributes: &[&str]) -> bool {
+    let password = password.to_lowercase();
+    attributes
+        .iter()
+        .flat_map(|attribute| {
+            // The local part of an email address is checked on its own as well
+            let local = attribute.split_once('@').map(|(local, _)| local);
+            std::iter::once(*attribute).chain(local)
+        })
+        .map(|attribute| attribute.trim().to_lowercase())
+        .filter(|attribute| attribute.chars().count() >= MIN_ATTRIBUTE_LENGTH)
+        .any(|attribute| password.contains(&attribute))
+}
+
+#[cfg(test)]
+mod test {
+    use model::PasswordRules;
+
+    use super::check_password;
+
+    fn rules() -> PasswordRules {
+        PasswordRules {
+            min_length: 10,
+            min_uppercase: 1,
+            min_digits: 2,
+            max_repeated: Some(2),
+            ..Default::default()
+        }
+    }
+
+    #[test]
+    fn test_valid_password() {
+        let messages = check_password(&rules(), "Correct-Horse-42", &["alice"]);
+        assert!(messages.is_empty(), "Unexpected failures {messages:?}");
+    }
+
+    #[test]
+    fn test_character_rules() {
+        let messages = check_password(&rules(), "shorrrt1", &[]);
+        assert_eq!(messages.len(), 4, "Unexpected failures {messages:?}");
+    }
+
+    #[test]
+    fn test_user_attributes() {
+        let attributes = ["alice", "alice.smith@example.com"];
+        let messages = check_password(&rules(), "Alice.Smith-42x", &attributes);
+        assert_eq!(messages.len(), 1, "Unexpected failures {messages:?}");
+    }
+
+    #[test]
+    fn test_score() {
+        let rules = PasswordRules {
+            min_score: 3,
+            ..Default::default()
+        };
+        assert_eq!(check_password(&rules, "password123", &[]).len(), 1);
+        assert!(check_password(&rules, "tumble-quartz-lantern-97", &[]).is_empty());
+    }
+}
@@ -0,0 +1,366 @@
+use model::{
+    error::{FieldError, FieldErrorKind, FieldType},
+    Prompt, PromptKind,
+};
+use serde_json::{Number, Value};
+use time::{
+    format_description::{well_known::Rfc3339, FormatItem},
+    macros::format_description,
+    Date, OffsetDateTime, PrimitiveDateTime,
+};
+
+const DATE_FORMAT: &[FormatItem<'static>] = format_description!("[year]-[month]-[day]");
+const DATE_TIME_FORMAT: &[FormatItem<'static>] =
+    format_description!("[year]-[month]-[day]T[hour]:[minute]");
+const DATE_TIME_SECONDS_FORMAT: &[FormatItem<'static>] =
+    format_description!("[year]-[month]-[day]T[hour]:[minute]:[second]");
+
+pub fn is_input(kind: &PromptKind) -> bool {
+    !matches!(
+        kind,
+        PromptKind::TextReadOnly | PromptKind::Seperator | PromptKind::Static
+    )
+}
+
+/// Validates the submitted value of a single prompt.
+/// Returns the normalized value that should be stored or `None` if nothing was submitted.
+pub fn validate_prompt(
+    prompt: &Prompt,
+    value: Option<&Value>,
+) -> Result<Option<Value>, FieldError> {
+    if !is_input(&prompt.kind) {
+        return Ok(None);
+    }
+    let value = match value {
+        Some(Value::Null) | None => None,
+        Some(Value::String(text)) if text.is_empty() => None,
+        Some(value) => Some(value),
+    };
+    let value = match (&prompt.kind, value) {
+        (PromptKind::Checkbox | PromptKind::Switch, value) => {
+            let checked = match value {
+                Some(value) => parse_bool(prompt, value)?,
+                None => false,
+            };
+            if prompt.required && !checked {
+                return Err(FieldError::new(&prompt.field_key, FieldErrorKind::Missing));
+            }
+            return Ok(Some(Value::Bool(checked)));
+        }
+        (_, Some(value)) => value,
+        (_, None) if prompt.required => {
+            return Err(FieldError::new(&prompt.field_key, FieldErrorKind::Missing))
+        }
+        (_, None) => return Ok(None),
+    };
+    let value = match &prompt.kind {
+        PromptKind::Username => {
+            let name = expect_str(prompt, value)?.trim().to_lowercase();
+            if name.is_empty() || name.chars().any(char::is_whitespace) {
+                return Err(invalid(prompt, "Username must not contain whitespace"));
+            }
+            Value::String(name)
+        }
+        PromptKind::Email => {
+            let email = expect_str(prompt, value)?.trim();
+            if !is_valid_email(email) {
+                return Err(invalid(prompt, "Invalid email address"));
+            }
+            Value::String(email.to_owned())
+        }
+        PromptKind::Password | PromptKind::Text | PromptKind::Locale => {
+            Value::String(expect_str(prompt, value)?.to_owned())
+        }
+        PromptKind::SignedNumber => match value {
+            Value::Number(number) if number.is_i64() => value.clone(),
+            Value::String(text) => match text.trim().parse::<i64>() {
+                Ok(number) => Value::Number(number.into()),
+                Err(_) => return Err(invalid(prompt, "Expected a whole number")),
+            },
+            other => return Err(invalid_type(prompt, FieldType::Number, other)),
+        },
+        PromptKind::UnsignedNumber => match value {
+            Value::Number(number) if number.is_u64() => value.clone(),
+            Value::String(text) => match text.trim().parse::<u64>() {
+                Ok(number) => Value::Number(Number::from(number)),
+                Err(_) => return Err(invalid(prompt, "Expected a positive whole number")),
+            },
+            other => return Err(invalid_type(prompt, FieldType::Number, other)),
+        },
+        PromptKind::Date => {
+            let text = expect_str(prompt, value)?.trim();
+            let date = Date::parse(text, DATE_FORMAT)
+                .map_err(|_| invalid(prompt, "Expected a date in the format YYYY-MM-DD"))?;
+            Value::String(date.format(DATE_FORMAT).expect("Failed to format date"))
+        }
+        PromptKind::DateTime => {
+            let text = expect_str(prompt, value)?.trim();
+            let date_time =
+                parse_date_time(text).ok_or_else(|| invalid(prompt, "Expected a date and time"))?;
+            Value::String(
+                date_time
+                    .format(&Rfc3339)
+                    .expect("Failed to format date time"),
+            )
+        }
+        PromptKind::Checkbox
+        | PromptKind::Switch
+        | PromptKind::TextReadOnly
+        | PromptKind::Seperator
+        | PromptKind::Static => unreachable!("Handled above"),
+    };
+    Ok(Some(value))
+}
+
+fn parse_date_time(text: &str) -> Option<OffsetDateTime> {
+    if let Ok(date_time) = OffsetDateTime::parse(text, &Rfc3339) {
+        return Some(date_time);
+    }
+    PrimitiveDateTime::parse(text, DATE_TIME_SECONDS_FORMAT)
+        .or_else(|_| PrimitiveDateTime::parse(text, DATE_TIME_FORMAT))
+        .ok()
+        .map(PrimitiveDateTime::assume_utc)
+}
+
+fn parse_bool(prompt: &Prompt, value: &Value) -> Result<bool, FieldError> {
+    match value {
+        Value::Bool(value) => Ok(*value),
+        Value::String(text) => match text.as_str() {
+            "on" | "true" | "1" => Ok(true),
+            "off" | "false" | "0" => Ok(false),
+            _ => Err(invalid(prompt, "Expected a boolean")),
+        },
+        other => Err(invalid_type(prompt, FieldType::Boolean, other)),
+    }
+}
+
+fn is_valid_email(email: &str) -> bool {
+    let Some((local, domain)) = email.rsplit_once('@') else { return false };
+    !local.is_empty()
+        && local.len() <= 64
+        && domain.len() <= 255
+        && !email.chars().any(|c| c.is_whitespace() || c.is_control())
+        && domain
+            .split('.')
+            .all(|label| !label.is_empty() && !label.starts_with('-') && !label.ends_with('-'))
+        && domain.contains('.')
+}
+
+fn expect_str<'a>(prompt: &Prompt, value: &'a Value) -> Result<&'a str, FieldError> {
+    match value {
+        Value::String(text) => Ok(text),
+        other => Err(invalid_type(prompt, FieldType::String, other)),
+    }
+}
+
+fn invalid(prompt: &Prompt, message: &str) -> FieldError {
+    FieldError::new(&prompt.field_key, FieldErrorKind::invalid(message))
+}
+
+fn invalid_type(prompt: &Prompt, expected: FieldType, got: &Value) -> FieldError {
+    FieldError::new(
+        &prompt.field_key,
+        FieldErrorKind::invalid_type(expected, got.into()),
+    )
+}
+
+#[cfg(test)]
+mod test {
+    use model::{error::FieldError, Prompt, PromptKind};
+    use serde_json::{json, Value};
+
+    use super::validate_prompt;
+
+    fn prompt(kind: PromptKind, required: bool) -> Prompt {
+        Prompt {
+            uid: 1,
+            slug: "prompt".to_owned(),
+            field_key: "field".to_owned(),
+            label: "Field".to_owned(),
+            kind,
+            placeholder: None,
+            required,
+            help_text: None,
+        }
+    }
+
+    fn validate(kind: PromptKind, value: Value) -> Result<Option<Value>, FieldError> {
+        validate_prompt(&prompt(kind, false), Some(&value))
+    }
+
+    /// Kind of the error as sent to the client, e.g. `missing` or `invalid`
+    fn error_kind(result: Result<Option<Value>, FieldError>) -> String {
+        let err = result.expect_err("Value should have been rejected");
+        let value = serde_json::to_value(err).unwrap();
+        value["kind"].as_str().unwrap().to_owned()
+    }
+
+    #[test]
+    fn test_required() {
+        let required = prompt(PromptKind::Text, true);
+        assert_eq!(error_kind(validate_prompt(&required, None)), "missing");
+        assert_eq!(
+            error_kind(validate_prompt(&required, Some(&json!("")))),
+            "missing"
+        );
+        let optional = prompt(PromptKind::Text, false);
+        assert_eq!(validate_prompt(&optional, None).unwrap(), None);
+        assert_eq!(
+            validate_prompt(&optional, Some(&Value::Null)).unwrap(),
+            None
+        );
+    }
+
+    #[test]
+    fn test_not_input() {
+        for kind in [
+            PromptKind::TextReadOnly,
+            PromptKind::Seperator,
+            PromptKind::Static,
+        ] {
+            let prompt = prompt(kind, true);
+            assert_eq!(validate_prompt(&prompt, Some(&json!("x"))).unwrap(), None);
+        }
+    }
+
+    #[test]
+    fn test_username() {
+        assert_eq!(
+            validate(PromptKind::Username, json!(" Alice ")).unwrap(),
+            Some(json!("alice"))
+        );
+        assert_eq!(
+            error_kind(validate(PromptKind::Username, json!("al ice"))),
+            "invalid"
+        );
+        assert_eq!(
+            error_kind(validate(PromptKind::Username, json!(42))),
+            "invalid_type"
+        );
+    }
+
+    #[test]
+    fn test_email() {
+        assert_eq!(
+            validate(PromptKind::Email, json!(" alice@example.com ")).unwrap(),
+            Some(json!("alice@example.com"))
+        );
+        for email in [
+            "alice",
+            "@example.com",
+            "alice@example",
+            "alice@-example.com",
+            "al ice@example.com",
+        ] {
+            assert_eq!(
+                error_kind(validate(PromptKind::Email, json!(email))),
+                "invalid",
+                "{email} should be rejected"
+            );
+        }
+    }
+
+    #[test]
+    fn test_text() {
+        for kind in [PromptKind::Password, PromptKind::Text, PromptKind::Locale] {
+            assert_eq!(
+                validate(kind.clone(), json!(" text ")).unwrap(),
+                Some(json!(" text "))
+            );
+            assert_eq!(error_kind(validate(kind, json!(true))), "invalid_type");
+        }
+    }
+
+    #[test]
+    fn test_signed_number() {
+        assert_eq!(
+            validate(PromptKind::SignedNumber, json!("-12")).unwrap(),
+            Some(json!(-12))
+        );
+        assert_eq!(
+            validate(PromptKind::SignedNumber, json!(7)).unwrap(),
+            Some(json!(7))
+        );
+        assert_eq!(
+            error_kind(validate(PromptKind::SignedNumber, json!("1.5"))),
+            "invalid"
+        );
+        assert_eq!(
+            error_kind(validate(PromptKind::SignedNumber, json!(1.5))),
+            "invalid_type"
+        );
+    }
+
+    #[test]
+    fn test_unsigned_number() {
+        assert_eq!(
+            validate(PromptKind::UnsignedNumber, json!(" 12 ")).unwrap(),
+            Some(json!(12))
+        );
+        assert_eq!(
+            error_kind(validate(PromptKind::UnsignedNumber, json!("-1"))),
+            "invalid"
+        );
+        assert_eq!(
+            error_kind(validate(PromptKind::UnsignedNumber, json!(-1))),
+            "invalid_type"
+        );
+    }
+
+    #[test]
+    fn test_checkbox() {
+        for kind in [PromptKind::Checkbox, PromptKind::Switch] {
+            assert_eq!(
+                validate(kind.clone(), json!("on")).unwrap(),
+                Some(json!(true))
+            );
+            assert_eq!(
+                validate(kind.clone(), json!(false)).unwrap(),
+                Some(json!(false))
+            );
+            // Unchecked boxes are not submitted at all
+            assert_eq!(
+                validate_prompt(&prompt(kind.clone(), false), None).unwrap(),
+                Some(json!(false))
+            );
+            assert_eq!(
+                error_kind(validate_prompt(&prompt(kind.clone(), true), None)),
+                "missing"
+            );
+            assert_eq!(error_kind(validate(kind.clone(), json!("yes"))), "invalid");
+            assert_eq!(error_kind(validate(kind, json!(1))), "invalid_type");
+        }
+    }
+
+    #[test]
+    fn test_date() {
+        assert_eq!(
+            validate(PromptKind::Date, json!("2023-02-28")).unwrap(),
+            Some(json!("2023-02-28"))
+        );
+        assert_eq!(
+            error_kind(validate(PromptKind::Date, json!("2023-02-30"))),
+            "invalid"
+        );
+        assert_eq!(
+            error_kind(validate(PromptKind::Date, json!("28.02.2023"))),
+            "invalid"
+        );
+    }
+
+    #[test]
+    fn test_date_time() {
+        assert_eq!(
+            validate(PromptKind::DateTime, json!("2023-02-28T12:30")).unwrap(),
+            Some(json!("2023-02-28T12:30:00Z"))
+        );
+        assert_eq!(
+            validate(PromptKind::DateTime, json!("2023-02-28T12:30:15+01:00")).unwrap(),
+            Some(json!("2023-02-28T12:30:15+01:00"))
+        );
+        assert_eq!(
+            error_kind(validate(PromptKind::DateTime, json!("2023-02-28"))),
+            "invalid"
+        );
+    }
+}
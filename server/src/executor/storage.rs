@@ -1 +1,336 @@
+use std::sync::atomic::Ordering;
 
+use async_trait::async_trait;
+use base64::{engine::general_purpose::URL_SAFE_NO_PAD, Engine};
+use chacha20poly1305::{
+    aead::{Aead, AeadCore, KeyInit, OsRng, Payload},
+    ChaCha20Poly1305, Nonce,
+};
+use deadpool_postgres::Pool;
+use model::{FlowQuery, PendingUser};
+use moka::sync::Cache;
+use serde::{Deserialize, Serialize};
+use sha2::{Digest, Sha256};
+use storage::StorageError;
+use time::OffsetDateTime;
+use tokio_postgres::types::Json;
+
+use super::{
+    flow::FlowExecution, ExecutionContext, ExecutionError, FieldStorage, FlowExecutor, FlowKey,
+    PolicyUser, TIME_TO_IDLE, TIME_TO_LIVE,
+};
+
+#[async_trait]
+pub trait ExecutionStore: Send + Sync {
+    async fn load(
+        &self,
+        executor: &FlowExecutor,
+        key: &FlowKey,
+    ) -> Result<Option<FlowExecution>, StorageError>;
+
+    async fn save(&self, execution: &FlowExecution) -> Result<(), StorageError>;
+
+    async fn remove(&self, key: &FlowKey) -> Result<(), StorageError>;
+
+    /// Removes every execution of the session of `except` apart from `except` itself.
+    async fn remove_session(&self, except: &FlowKey) -> Result<(), StorageError>;
+
+    async fn purge_expired(&self) -> Result<u64, StorageError> {
+        Ok(0)
+    }
+}
+
+pub struct MemoryExecutionStore {
+    executions: Cache<FlowKey, FlowExecution>,
+}
+
+impl MemoryExecutionStore {
+    pub fn new() -> Self {
+        Self {
+            executions: Cache::builder()
+                .time_to_idle(TIME_TO_IDLE.clone())
+                .time_to_live(TIME_TO_LIVE.clone())
+                .build(),
+        }
+    }
+}
+
+#[async_trait]
+impl ExecutionStore for MemoryExecutionStore {
+    async fn load(
+        &self,
+        _executor: &FlowExecutor,
+        key: &FlowKey,
+    ) -> Result<Option<FlowExecution>, StorageError> {
+        Ok(self.executions.get(key))
+    }
+
+    async fn save(&self, execution: &FlowExecution) -> Result<(), StorageError> {
+        self.executions
+            .insert(execution.0.key.clone(), execution.clone());
+        Ok(())
+    }
+
+    async fn remove(&self, key: &FlowKey) -> Result<(), StorageError> {
+        self.executions.invalidate(key);
+        Ok(())
+    }
+
+    async fn remove_session(&self, except: &FlowKey) -> Result<(), StorageError> {
+        for (key, _) in self.executions.iter() {
+            if key.session == except.session && key.as_ref() != except {
+                self.executions.invalidate(key.as_ref());
+            }
+        }
+        Ok(())
+    }
+}
+
+pub struct PostgresExecutionStore {
+    pool: Pool,
+    cipher: FieldCipher,
+}
+
+impl PostgresExecutionStore {
+    pub fn new(pool: Pool, secret: &str) -> Self {
+        Self {
+            pool,
+            cipher: FieldCipher::new(secret),
+        }
+    }
+}
+
+/// Persisted state of an execution.
+/// The fields hold passwords and secrets which are being set up, so they are only stored encrypted.
+#[derive(Serialize, Deserialize)]
+struct StoredState {
+    #[serde(flatten)]
+    state: ExecutionState,
+    fields: String,
+}
+
+/// Encrypts the fields of persisted executions, they are bound to the key of their execution.
+struct FieldCipher(ChaCha20Poly1305);
+
+impl FieldCipher {
+    const NONCE_LENGTH: usize = 12;
+
+    fn new(secret: &str) -> Self {
+        let key = Sha256::new()
+            .chain_update(b"authust execution fields")
+            .chain_update(secret.as_bytes())
+            .finalize();
+        Self(ChaCha20Poly1305::new_from_slice(&key).expect("Digest has the length of a key"))
+    }
+
+    fn seal(&self, key: &FlowKey, fields: &FieldStorage) -> String {
+        let plaintext = serde_json::to_vec(fields).expect("Fields are always valid json");
+        let aad = format!("{}/{}", key.session, key.flow_id());
+        let nonce = ChaCha20Poly1305::generate_nonce(&mut OsRng);
+        let payload = Payload {
+            msg: &plaintext,
+            aad: aad.as_bytes(),
+        };
+        let mut sealed = nonce.to_vec();
+        sealed.extend(
+            self.0
+                .encrypt(&nonce, payload)
+                .expect("Fields exceed the size limit of the cipher"),
+        );
+        URL_SAFE_NO_PAD.encode(sealed)
+    }
+
+    /// Returns `None` if the fields have been tampered with or were sealed with another secret.
+    fn open(&self, key: &FlowKey, sealed: &str) -> Option<FieldStorage> {
+        let sealed = URL_SAFE_NO_PAD.decode(sealed).ok()?;
+        if sealed.len() < Self::NONCE_LENGTH {
+            return None;
+        }
+        let (nonce, ciphertext) = sealed.split_at(Self::NONCE_LENGTH);
+        let aad = format!("{}/{}", key.session, key.flow_id());
+        let payload = Payload {
+            msg: ciphertext,
+            aad: aad.as_bytes(),
+        };
+        let plaintext = self.0.decrypt(Nonce::from_slice(nonce), payload).ok()?;
+        serde_json::from_slice(&plaintext).ok()
+    }
+}
+
+#[async_trait]
+impl ExecutionStore for PostgresExecutionStore {
+    async fn load(
+        &self,
+        executor: &FlowExecutor,
+        key: &FlowKey,
+    ) -> Result<Option<FlowExecution>, StorageError> {
+        let now = OffsetDateTime::now_utc();
+        let conn = self.pool.get().await?;
+        let statement = conn
+            .prepare_cached(
+                "update flow_executions set last_used = now() where session = $1 and flow = $2 and last_used > $3 and created > $4 returning state",
+            )
+            .await?;
+        let row = conn
+            .query_opt(
+                &statement,
+                &[
+                    &key.session,
+                    &key.flow_id(),
+                    &(now - TIME_TO_IDLE),
+                    &(now - TIME_TO_LIVE),
+                ],
+            )
+            .await?;
+        let Some(row) = row else { return Ok(None) };
+        let Json(stored): Json<StoredState> = row.try_get("state")?;
+        let Some(fields) = self.cipher.open(key, &stored.fields) else {
+            tracing::warn!(key = %key, "Discarding flow execution with unreadable fields");
+            return Ok(None) };
+        let mut state = stored.state;
+        state.fields = fields;
+        Ok(executor.restore(key, state).await)
+    }
+
+    async fn save(&self, execution: &FlowExecution) -> Result<(), StorageError> {
+        let key = &execution.0.key;
+        let mut state = execution.snapshot();
+        let fields = self.cipher.seal(key, &std::mem::take(&mut state.fields));
+        let state = Json(StoredState { state, fields });
+        let conn = self.pool.get().await?;
+        let statement = conn
+            .prepare_cached(
+                "insert into flow_executions(session, flow, state) values ($1, $2, $3) on conflict (session, flow) do update set state = excluded.state, last_used = now()",
+            )
+            .await?;
+        conn.execute(&statement, &[&key.session, &key.flow_id(), &state])
+            .await?;
+        Ok(())
+    }
+
+    async fn remove(&self, key: &FlowKey) -> Result<(), StorageError> {
+        let conn = self.pool.get().await?;
+        let statement = conn
+            .prepare_cached("delete from flow_executions where session = $1 and flow = $2")
+            .await?;
+        conn.execute(&statement, &[&key.session, &key.flow_id()])
+            .await?;
+        Ok(())
+    }
+
+    async fn remove_session(&self, except: &FlowKey) -> Result<(), StorageError> {
+        let conn = self.pool.get().await?;
+        let statement = conn
+            .prepare_cached("delete from flow_executions where session = $1 and flow <> $2")
+            .await?;
+        conn.execute(&statement, &[&except.session, &except.flow_id()])
+            .await?;
+        Ok(())
+    }
+
+    async fn purge_expired(&self) -> Result<u64, StorageError> {
+        let now = OffsetDateTime::now_utc();
+        let conn = self.pool.get().await?;
+        let statement = conn
+            .prepare_cached("delete from flow_executions where last_used <= $1 or created <= $2")
+            .await?;
+        Ok(conn
+            .execute(&statement, &[&(now - TIME_TO_IDLE), &(now - TIME_TO_LIVE)])
+            .await?)
+    }
+}
+
+impl FlowKey {
+    pub(super) fn flow_id(&self) -> String {
+        match &self.flow.0 {
+            FlowQuery::slug(slug) => slug.clone(),
+            FlowQuery::uid(uid) => uid.to_string(),
+        }
+    }
+}
+
+#[derive(Serialize, Deserialize)]
+pub struct ExecutionState {
+    pub current_entry_idx: usize,
+    pub is_completed: bool,
+    pub start_time: OffsetDateTime,
+    /// Missing for executions stored before stages timed out
+    #[serde(default = "OffsetDateTime::now_utc")]
+    pub entry_start: OffsetDateTime,
+    /// Persisted separately by the store
+    #[serde(skip)]
+    pub fields: FieldStorage,
+    pub pending: Option<PendingUser>,
+    pub user: Option<PolicyUser>,
+    pub error: Option<ExecutionError>,
+}
+
+impl ExecutionState {
+    pub(super) fn into_context(
+        self,
+        session_id: String,
+        storage: storage::FreezedStorage,
+    ) -> ExecutionContext {
+        ExecutionContext {
+            session_id,
+            start_time: self.start_time,
+            entry_start: self.entry_start,
+            fields: self.fields,
+            pending: self.pending,
+            user: self.user,
+            storage,
+            error: self.error,
+        }
+    }
+}
+
+impl FlowExecution {
+    pub(super) fn snapshot(&self) -> ExecutionState {
+        let context = self.get_context();
+        ExecutionState {
+            current_entry_idx: *self.0.current_entry_idx.lock(),
+            is_completed: self.0.is_completed.load(Ordering::Relaxed),
+            start_time: context.start_time,
+            entry_start: context.entry_start,
+            fields: context.fields.clone(),
+            pending: context.pending.clone(),
+            user: context.user.clone(),
+            error: context.error.clone(),
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use model::FlowQuery;
+    use serde_json::Value;
+    use storage::datacache::DataRef;
+
+    use super::{FieldCipher, FieldStorage, FlowKey};
+
+    fn key(session: &str) -> FlowKey {
+        FlowKey {
+            session: session.to_owned(),
+            flow: DataRef::new(FlowQuery::slug("flow".to_owned())),
+        }
+    }
+
+    #[test]
+    fn sealed_fields() {
+        let cipher = FieldCipher::new("secret");
+        let mut fields = FieldStorage::new();
+        fields.insert_value("password", Value::String("hunter22".to_owned()));
+        let sealed = cipher.seal(&key("session"), &fields);
+        assert!(!sealed.contains("hunter22"));
+        let opened = cipher.open(&key("session"), &sealed).unwrap();
+        assert_eq!(
+            opened.get_value("password"),
+            Some(&Value::String("hunter22".to_owned()))
+        );
+        assert!(cipher.open(&key("other"), &sealed).is_none());
+        assert!(FieldCipher::new("other")
+            .open(&key("session"), &sealed)
+            .is_none());
+        assert!(cipher.open(&key("session"), "").is_none());
+    }
+}
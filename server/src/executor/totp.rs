@@ -0,0 +1,200 @@
+use hmac::{Hmac, Mac};
+use rand::{rngs::OsRng, RngCore};
+use sha1::Sha1;
+
+use super::{fields::TOTP_FAILURES, FieldStorage};
+
+/// Length of generated secrets in bytes, as recommended by RFC 4226.
+const SECRET_LENGTH: usize = 20;
+/// Seconds a code is valid for.
+const PERIOD: i64 = 30;
+const DIGITS: u32 = 6;
+const BASE32_ALPHABET: &[u8; 32] = b"ABCDEFGHIJKLMNOPQRSTUVWXYZ234567";
+/// Rejected codes after which the flow starts over, so that codes cannot be guessed.
+pub const MAX_FAILED_ATTEMPTS: u32 = 5;
+
+pub fn generate_secret() -> Vec<u8> {
+    let mut secret = vec![0; SECRET_LENGTH];
+    OsRng.fill_bytes(&mut secret);
+    secret
+}
+
+/// Base32 without padding, which is what authenticator apps expect.
+pub fn encode_base32(data: &[u8]) -> String {
+    let mut encoded = String::with_capacity((data.len() * 8 + 4) / 5);
+    let mut buffer: u16 = 0;
+    let mut bits = 0;
+    for byte in data {
+        buffer = (buffer << 8) | *byte as u16;
+        bits += 8;
+        while bits >= 5 {
+            bits -= 5;
+            encoded.push(BASE32_ALPHABET[((buffer >> bits) & 0x1f) as usize] as char);
+        }
+    }
+    if bits > 0 {
+        encoded.push(BASE32_ALPHABET[((buffer << (5 - bits)) & 0x1f) as usize] as char);
+    }
+    encoded
+}
+
+/// Returns `None` if `encoded` contains characters outside of the base32 alphabet.
+/// Padding is ignored.
+pub fn decode_base32(encoded: &str) -> Option<Vec<u8>> {
+    let mut decoded = Vec::with_capacity(encoded.len() * 5 / 8);
+    let mut buffer: u16 = 0;
+    let mut bits = 0;
+    for c in encoded.trim_end_matches('=').bytes() {
+        let value = BASE32_ALPHABET
+            .iter()
+            .position(|a| *a == c.to_ascii_uppercase())?;
+        buffer = (buffer << 5) | value as u16;
+        bits += 5;
+        if bits >= 8 {
+            bits -= 8;
+            decoded.push((buffer >> bits) as u8);
+        }
+    }
+    Some(decoded)
+}
+
+/// Time step of the unix timestamp `now`.
+fn time_step(now: i64) -> i64 {
+    now.div_euclid(PERIOD)
+}
+
+/// HOTP value of `counter` as described by RFC 4226.
+fn hotp(secret: &[u8], counter: u64) -> u32 {
+    let mut mac = Hmac::<Sha1>::new_from_slice(secret).expect("Hmac accepts keys of any length");
+    mac.update(&counter.to_be_bytes());
+    let hash = mac.finalize().into_bytes();
+    let offset = (hash[hash.len() - 1] & 0x0f) as usize;
+    let value = u32::from_be_bytes([
+        hash[offset] & 0x7f,
+        hash[offset + 1],
+        hash[offset + 2],
+        hash[offset + 3],
+    ]);
+    value % 10u32.pow(DIGITS)
+}
+
+/// Checks the code against the time steps within `drift` of the step of `now`.
+/// Steps up to `last_step` have already been used and are rejected.
+/// Returns the step the code belongs to.
+pub fn verify(
+    secret: &[u8],
+    code: &str,
+    now: i64,
+    drift: i16,
+    last_step: Option<i64>,
+) -> Option<i64> {
+    let code = code.trim();
+    if code.len() != DIGITS as usize || !code.bytes().all(|c| c.is_ascii_digit()) {
+        return None;
+    }
+    let code: u32 = code.parse().ok()?;
+    let current = time_step(now);
+    let drift = drift.max(0) as i64;
+    (current - drift..=current + drift)
+        .filter(|step| *step >= 0 && last_step.map_or(true, |last| *step > last))
+        .find(|step| hotp(secret, *step as u64) == code)
+}
+
+/// Counts a rejected code, returns whether the attempts are used up.
+/// The counter starts over once they are.
+pub fn record_failure(fields: &mut FieldStorage) -> bool {
+    let failures = fields.get_typed(TOTP_FAILURES).ok().flatten().unwrap_or(0) + 1;
+    if failures >= MAX_FAILED_ATTEMPTS {
+        fields.remove(TOTP_FAILURES.name);
+        return true;
+    }
+    let _ = fields.insert_typed(TOTP_FAILURES, failures);
+    false
+}
+
+/// `otpauth://` uri as understood by authenticator apps.
+pub fn config_url(issuer: &str, account: &str, secret: &[u8]) -> String {
+    let issuer = percent_encode(issuer);
+    format!(
+        "otpauth://totp/{issuer}:{}?secret={}&issuer={issuer}&algorithm=SHA1&digits={DIGITS}&period={PERIOD}",
+        percent_encode(account),
+        encode_base32(secret),
+    )
+}
+
+fn percent_encode(value: &str) -> String {
+    let mut encoded = String::with_capacity(value.len());
+    for byte in value.bytes() {
+        if byte.is_ascii_alphanumeric() || matches!(byte, b'-' | b'.' | b'_' | b'~') {
+            encoded.push(byte as char);
+        } else {
+            encoded.push_str(&format!("%{byte:02X}"));
+        }
+    }
+    encoded
+}
+
+#[cfg(test)]
+mod test {
+    use super::{
+        config_url, decode_base32, encode_base32, hotp, record_failure, verify, MAX_FAILED_ATTEMPTS,
+    };
+    use crate::executor::{fields::TOTP_FAILURES, FieldStorage};
+
+    const SECRET: &[u8] = b"12345678901234567890";
+
+    #[test]
+    fn base32() {
+        assert_eq!(encode_base32(b"foobar"), "MZXW6YTBOI");
+        assert_eq!(encode_base32(SECRET), "GEZDGNBVGY3TQOJQGEZDGNBVGY3TQOJQ");
+        assert_eq!(decode_base32("MZXW6YTBOI").unwrap(), b"foobar");
+        assert_eq!(decode_base32("mzxw6ytboi======").unwrap(), b"foobar");
+        assert!(decode_base32("MZXW1").is_none());
+    }
+
+    #[test]
+    fn rfc_vectors() {
+        assert_eq!(hotp(SECRET, 0), 755224);
+        assert_eq!(hotp(SECRET, 9), 520489);
+        assert_eq!(verify(SECRET, "287082", 59, 0, None), Some(1));
+        assert_eq!(
+            verify(SECRET, "081804", 1111111109, 0, None),
+            Some(37037036)
+        );
+        assert_eq!(
+            verify(SECRET, "005924", 1234567890, 0, None),
+            Some(41152263)
+        );
+    }
+
+    #[test]
+    fn drift_and_replay() {
+        // Code of step 1
+        assert_eq!(verify(SECRET, "287082", 89, 0, None), None);
+        assert_eq!(verify(SECRET, "287082", 89, 1, None), Some(1));
+        assert_eq!(verify(SECRET, "287082", 59, 1, Some(1)), None);
+        assert_eq!(verify(SECRET, "287082", 59, 1, Some(0)), Some(1));
+        assert_eq!(verify(SECRET, "28708", 59, 1, None), None);
+        assert_eq!(verify(SECRET, "+87082", 59, 1, None), None);
+    }
+
+    #[test]
+    fn url() {
+        assert_eq!(
+            config_url("Authust Demo", "jane@example.com", b"foobar"),
+            "otpauth://totp/Authust%20Demo:jane%40example.com?secret=MZXW6YTBOI&issuer=Authust%20Demo&algorithm=SHA1&digits=6&period=30"
+        );
+    }
+
+    #[test]
+    fn failed_attempts() {
+        let mut fields = FieldStorage::new();
+        for _ in 1..MAX_FAILED_ATTEMPTS {
+            assert!(!record_failure(&mut fields));
+        }
+        assert!(record_failure(&mut fields));
+        assert_eq!(fields.get_typed(TOTP_FAILURES).unwrap(), None);
+        assert!(!record_failure(&mut fields));
+        assert_eq!(fields.get_typed(TOTP_FAILURES).unwrap(), Some(1));
+    }
+}
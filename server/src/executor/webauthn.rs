@@ -0,0 +1,732 @@
+use base64::{engine::general_purpose::URL_SAFE_NO_PAD, Engine};
+use derive_more::Display;
+use model::UserVerification;
+use p256::{
+    ecdsa::{signature::Verifier, Signature},
+    pkcs8::DecodePublicKey,
+};
+use rand::{rngs::OsRng, RngCore};
+use rsa::{BigUint, Pkcs1v15Sign, RsaPublicKey};
+use serde::{Deserialize, Serialize};
+use serde_json::{json, Value};
+use sha2::{Digest, Sha256};
+use uuid::Uuid;
+
+use self::cbor::Cbor;
+
+mod cbor;
+
+const CHALLENGE_LENGTH: usize = 32;
+/// Milliseconds the browser waits for the authenticator.
+const TIMEOUT: u32 = 5 * 60 * 1000;
+
+// COSE algorithms
+const ES256: i64 = -7;
+const EDDSA: i64 = -8;
+const RS256: i64 = -257;
+/// DigestInfo of SHA-256 hashes, which prefixes them in PKCS#1 v1.5 signatures.
+const SHA256_PREFIX: &[u8] = &[
+    0x30, 0x31, 0x30, 0x0d, 0x06, 0x09, 0x60, 0x86, 0x48, 0x01, 0x65, 0x03, 0x04, 0x02, 0x01, 0x05,
+    0x00, 0x04, 0x20,
+];
+
+const FLAG_USER_PRESENT: u8 = 0x01;
+const FLAG_USER_VERIFIED: u8 = 0x04;
+const FLAG_ATTESTED_CREDENTIAL: u8 = 0x40;
+
+#[derive(Debug, Display, PartialEq, Eq)]
+pub enum WebAuthnError {
+    #[display("Malformed {}", _0)]
+    Malformed(&'static str),
+    #[display("{} does not match", _0)]
+    Mismatch(&'static str),
+    #[display("Unsupported {}", _0)]
+    Unsupported(&'static str),
+    #[display("User is not present")]
+    UserNotPresent,
+    #[display("User has not been verified")]
+    UserNotVerified,
+    #[display("Invalid signature")]
+    InvalidSignature,
+    /// The authenticator might have been cloned
+    #[display("Sign counter did not increase")]
+    CounterRegressed,
+}
+
+/// State of a ceremony, kept in the execution until the stage is submitted.
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct Challenge {
+    /// Base64url encoded
+    pub challenge: String,
+    pub rp_id: String,
+    /// Origins the client data may come from
+    pub origins: Vec<String>,
+    pub user_verification: UserVerification,
+}
+
+impl Challenge {
+    /// `host` is the host the request has been sent to, `rp_id` the host of its tenant.
+    pub fn new(host: &str, rp_id: &str, user_verification: UserVerification) -> Self {
+        let mut challenge = [0; CHALLENGE_LENGTH];
+        OsRng.fill_bytes(&mut challenge);
+        let rp_id = rp_id.split(':').next().unwrap_or(rp_id).to_lowercase();
+        let mut origins = vec![format!("https://{host}")];
+        // Browsers only allow insecure origins on localhost
+        if rp_id == "localhost" {
+            origins.push(format!("http://{host}"));
+        }
+        Self {
+            challenge: URL_SAFE_NO_PAD.encode(challenge),
+            rp_id,
+            origins,
+            user_verification,
+        }
+    }
+
+    /// Options of `navigator.credentials.create`, `exclude` are the base64url encoded ids
+    /// of the credentials the user already has.
+    pub fn creation_options(
+        &self,
+        rp_name: &str,
+        user: Uuid,
+        name: &str,
+        exclude: &[String],
+    ) -> Value {
+        json!({
+            "rp": { "id": self.rp_id, "name": rp_name },
+            "user": {
+                "id": URL_SAFE_NO_PAD.encode(user.as_bytes()),
+                "name": name,
+                "displayName": name,
+            },
+            "challenge": self.challenge,
+            "pubKeyCredParams": [ES256, EDDSA, RS256]
+                .map(|alg| json!({ "type": "public-key", "alg": alg })),
+            "timeout": TIMEOUT,
+            "excludeCredentials": descriptors(exclude),
+            "authenticatorSelection": {
+                "residentKey": "preferred",
+                "userVerification": self.user_verification,
+            },
+            "attestation": "direct",
+        })
+    }
+
+    /// Options of `navigator.credentials.get`.
+    /// Without `allow` the user chooses from the discoverable credentials of the relying party.
+    pub fn request_options(&self, allow: &[String]) -> Value {
+        json!({
+            "challenge": self.challenge,
+            "rpId": self.rp_id,
+            "timeout": TIMEOUT,
+            "allowCredentials": descriptors(allow),
+            "userVerification": self.user_verification,
+        })
+    }
+}
+
+fn descriptors(ids: &[String]) -> Vec<Value> {
+    ids.iter()
+        .map(|id| json!({ "type": "public-key", "id": id }))
+        .collect()
+}
+
+/// `PublicKeyCredential` returned by `navigator.credentials.create`, binary values are base64url encoded.
+#[derive(Debug, Deserialize)]
+pub struct RegistrationResponse {
+    pub id: String,
+    pub response: AttestationResponse,
+}
+
+#[derive(Debug, Deserialize)]
+pub struct AttestationResponse {
+    #[serde(rename = "clientDataJSON")]
+    pub client_data_json: String,
+    #[serde(rename = "attestationObject")]
+    pub attestation_object: String,
+}
+
+/// `PublicKeyCredential` returned by `navigator.credentials.get`, binary values are base64url encoded.
+#[derive(Debug, Deserialize)]
+pub struct AssertionResponse {
+    pub id: String,
+    pub response: AssertionData,
+}
+
+#[derive(Debug, Deserialize)]
+pub struct AssertionData {
+    #[serde(rename = "clientDataJSON")]
+    pub client_data_json: String,
+    #[serde(rename = "authenticatorData")]
+    pub authenticator_data: String,
+    pub signature: String,
+    #[serde(rename = "userHandle", default)]
+    pub user_handle: Option<String>,
+}
+
+impl AssertionResponse {
+    pub fn credential_id(&self) -> Result<Vec<u8>, WebAuthnError> {
+        decode(&self.id, "credential id")
+    }
+
+    /// The user the credential has been created for, only discoverable credentials return it.
+    pub fn user_handle(&self) -> Result<Option<Uuid>, WebAuthnError> {
+        let Some(handle) = &self.response.user_handle else { return Ok(None) };
+        let handle = decode(handle, "user handle")?;
+        Uuid::from_slice(&handle)
+            .map(Some)
+            .map_err(|_| WebAuthnError::Malformed("user handle"))
+    }
+}
+
+/// A credential which has been registered.
+#[derive(Debug)]
+pub struct Credential {
+    pub id: Vec<u8>,
+    /// COSE encoded
+    pub public_key: Vec<u8>,
+    pub sign_count: u32,
+}
+
+/// Verifies the response of the authenticator to `navigator.credentials.create`.
+/// Attestation statements are verified, their certificates are not checked against trust anchors.
+pub fn verify_registration(
+    challenge: &Challenge,
+    response: &RegistrationResponse,
+) -> Result<Credential, WebAuthnError> {
+    let client_data_json = decode(&response.response.client_data_json, "client data")?;
+    verify_client_data(challenge, &client_data_json, "webauthn.create")?;
+    let attestation = decode(&response.response.attestation_object, "attestation object")?;
+    let attestation = match cbor::decode(&attestation) {
+        Some((Cbor::Map(attestation), _)) => attestation,
+        _ => return Err(WebAuthnError::Malformed("attestation object")),
+    };
+    let (format, statement, auth_data) = match (
+        cbor::get(&attestation, "fmt"),
+        cbor::get(&attestation, "attStmt"),
+        cbor::get(&attestation, "authData"),
+    ) {
+        (Some(Cbor::Text(format)), Some(Cbor::Map(statement)), Some(Cbor::Bytes(auth_data))) => {
+            (format, statement, auth_data)
+        }
+        _ => return Err(WebAuthnError::Malformed("attestation object")),
+    };
+    let data = AuthenticatorData::parse(auth_data)?;
+    data.check(challenge)?;
+    let (id, public_key) = data
+        .attested
+        .ok_or(WebAuthnError::Malformed("attested credential data"))?;
+    if decode(&response.id, "credential id")? != id {
+        return Err(WebAuthnError::Mismatch("Credential id"));
+    }
+    let (key, algorithm) = CoseKey::parse(public_key)?;
+    let client_data_hash = Sha256::digest(&client_data_json);
+    match format.as_str() {
+        "none" if statement.is_empty() => {}
+        "none" => return Err(WebAuthnError::Malformed("attestation statement")),
+        "packed" => verify_packed(statement, auth_data, &client_data_hash, &key, algorithm)?,
+        _ => return Err(WebAuthnError::Unsupported("attestation format")),
+    }
+    Ok(Credential {
+        id: id.to_vec(),
+        public_key: public_key.to_vec(),
+        sign_count: data.sign_count,
+    })
+}
+
+fn verify_packed(
+    statement: &[(Cbor, Cbor)],
+    auth_data: &[u8],
+    client_data_hash: &[u8],
+    key: &CoseKey,
+    algorithm: i64,
+) -> Result<(), WebAuthnError> {
+    let (statement_algorithm, signature) =
+        match (cbor::get(statement, "alg"), cbor::get(statement, "sig")) {
+            (Some(Cbor::Integer(algorithm)), Some(Cbor::Bytes(signature))) => {
+                (algorithm, signature)
+            }
+            _ => return Err(WebAuthnError::Malformed("attestation statement")),
+        };
+    let signed = [auth_data, client_data_hash].concat();
+    match cbor::get(statement, "x5c") {
+        // Self attestation, signed by the credential itself
+        None if *statement_algorithm == algorithm => key.verify(&signed, signature),
+        None => Err(WebAuthnError::Mismatch("Attestation algorithm")),
+        Some(Cbor::Array(certificates)) => {
+            let malformed = WebAuthnError::Malformed("attestation certificate");
+            let Some(Cbor::Bytes(certificate)) = certificates.first() else { return Err(malformed) };
+            if *statement_algorithm != ES256 {
+                return Err(WebAuthnError::Unsupported("attestation algorithm"));
+            }
+            let key = certificate_public_key(certificate)
+                .and_then(|spki| p256::PublicKey::from_public_key_der(spki).ok())
+                .ok_or(malformed)?;
+            CoseKey::Es256(key.into()).verify(&signed, signature)
+        }
+        Some(_) => Err(WebAuthnError::Malformed("attestation statement")),
+    }
+}
+
+/// Verifies the response of the authenticator to `navigator.credentials.get`
+/// with the stored public key of the credential.
+/// Returns the new value of the sign counter.
+pub fn verify_assertion(
+    challenge: &Challenge,
+    response: &AssertionResponse,
+    public_key: &[u8],
+    sign_count: u32,
+) -> Result<u32, WebAuthnError> {
+    let client_data_json = decode(&response.response.client_data_json, "client data")?;
+    verify_client_data(challenge, &client_data_json, "webauthn.get")?;
+    let auth_data = decode(&response.response.authenticator_data, "authenticator data")?;
+    let data = AuthenticatorData::parse(&auth_data)?;
+    data.check(challenge)?;
+    let (key, _) = CoseKey::parse(public_key)?;
+    let signature = decode(&response.response.signature, "signature")?;
+    let signed = [auth_data.as_slice(), &Sha256::digest(&client_data_json)].concat();
+    key.verify(&signed, &signature)?;
+    // Authenticators without a counter always return 0
+    if (data.sign_count != 0 || sign_count != 0) && data.sign_count <= sign_count {
+        return Err(WebAuthnError::CounterRegressed);
+    }
+    Ok(data.sign_count)
+}
+
+fn decode(value: &str, name: &'static str) -> Result<Vec<u8>, WebAuthnError> {
+    URL_SAFE_NO_PAD
+        .decode(value)
+        .map_err(|_| WebAuthnError::Malformed(name))
+}
+
+#[derive(Deserialize)]
+struct ClientData {
+    #[serde(rename = "type")]
+    kind: String,
+    challenge: String,
+    origin: String,
+}
+
+fn verify_client_data(
+    challenge: &Challenge,
+    client_data_json: &[u8],
+    kind: &str,
+) -> Result<(), WebAuthnError> {
+    let client_data: ClientData = serde_json::from_slice(client_data_json)
+        .map_err(|_| WebAuthnError::Malformed("client data"))?;
+    if client_data.kind != kind {
+        return Err(WebAuthnError::Mismatch("Ceremony"));
+    }
+    if client_data.challenge != challenge.challenge {
+        return Err(WebAuthnError::Mismatch("Challenge"));
+    }
+    if !challenge.origins.contains(&client_data.origin) {
+        return Err(WebAuthnError::Mismatch("Origin"));
+    }
+    Ok(())
+}
+
+struct AuthenticatorData<'a> {
+    rp_id_hash: &'a [u8],
+    flags: u8,
+    sign_count: u32,
+    /// Id and COSE encoded public key of the credential, only included during registration
+    attested: Option<(&'a [u8], &'a [u8])>,
+}
+
+impl<'a> AuthenticatorData<'a> {
+    fn parse(data: &'a [u8]) -> Result<Self, WebAuthnError> {
+        let malformed = WebAuthnError::Malformed("authenticator data");
+        if data.len() < 37 {
+            return Err(malformed);
+        }
+        let flags = data[32];
+        let sign_count = u32::from_be_bytes([data[33], data[34], data[35], data[36]]);
+        let attested = if flags & FLAG_ATTESTED_CREDENTIAL != 0 {
+            // The credential is preceded by the aaguid and the length of its id
+            let rest = data.get(37 + 16..).ok_or(malformed)?;
+            let (length, rest) = match rest {
+                [high, low, rest @ ..] => (u16::from_be_bytes([*high, *low]) as usize, rest),
+                _ => return Err(WebAuthnError::Malformed("authenticator data")),
+            };
+            if rest.len() < length {
+                return Err(WebAuthnError::Malformed("authenticator data"));
+            }
+            let (id, rest) = rest.split_at(length);
+            let (_, key_length) =
+                cbor::decode(rest).ok_or(WebAuthnError::Malformed("credential public key"))?;
+            Some((id, &rest[..key_length]))
+        } else {
+            None
+        };
+        Ok(Self {
+            rp_id_hash: &data[..32],
+            flags,
+            sign_count,
+            attested,
+        })
+    }
+
+    fn check(&self, challenge: &Challenge) -> Result<(), WebAuthnError> {
+        if self.rp_id_hash != Sha256::digest(challenge.rp_id.as_bytes()).as_slice() {
+            return Err(WebAuthnError::Mismatch("Relying party"));
+        }
+        if self.flags & FLAG_USER_PRESENT == 0 {
+            return Err(WebAuthnError::UserNotPresent);
+        }
+        if challenge.user_verification == UserVerification::Required
+            && self.flags & FLAG_USER_VERIFIED == 0
+        {
+            return Err(WebAuthnError::UserNotVerified);
+        }
+        Ok(())
+    }
+}
+
+enum CoseKey {
+    Es256(p256::ecdsa::VerifyingKey),
+    EdDsa(ed25519_dalek::VerifyingKey),
+    Rs256(RsaPublicKey),
+}
+
+impl CoseKey {
+    /// Returns the key together with its algorithm.
+    fn parse(encoded: &[u8]) -> Result<(Self, i64), WebAuthnError> {
+        let malformed = || WebAuthnError::Malformed("credential public key");
+        let Some((Cbor::Map(map), _)) = cbor::decode(encoded) else { return Err(malformed()) };
+        let integer = |label| match cbor::label(&map, label) {
+            Some(Cbor::Integer(value)) => Ok(*value),
+            _ => Err(malformed()),
+        };
+        let bytes = |label| match cbor::label(&map, label) {
+            Some(Cbor::Bytes(value)) => Ok(value.as_slice()),
+            _ => Err(malformed()),
+        };
+        let algorithm = integer(3)?;
+        let key = match (integer(1)?, algorithm) {
+            // EC2 key on P-256
+            (2, ES256) if integer(-1)? == 1 => {
+                let point = [&[0x04], bytes(-2)?, bytes(-3)?].concat();
+                let key =
+                    p256::ecdsa::VerifyingKey::from_sec1_bytes(&point).map_err(|_| malformed())?;
+                CoseKey::Es256(key)
+            }
+            // OKP key on Ed25519
+            (1, EDDSA) if integer(-1)? == 6 => {
+                let x: &[u8; 32] = bytes(-2)?.try_into().map_err(|_| malformed())?;
+                let key = ed25519_dalek::VerifyingKey::from_bytes(x).map_err(|_| malformed())?;
+                CoseKey::EdDsa(key)
+            }
+            (3, RS256) => {
+                let n = BigUint::from_bytes_be(bytes(-1)?);
+                let e = BigUint::from_bytes_be(bytes(-2)?);
+                CoseKey::Rs256(RsaPublicKey::new(n, e).map_err(|_| malformed())?)
+            }
+            _ => return Err(WebAuthnError::Unsupported("credential algorithm")),
+        };
+        Ok((key, algorithm))
+    }
+
+    fn verify(&self, data: &[u8], signature: &[u8]) -> Result<(), WebAuthnError> {
+        let is_valid = match self {
+            CoseKey::Es256(key) => Signature::from_der(signature)
+                .map_or(false, |signature| key.verify(data, &signature).is_ok()),
+            CoseKey::EdDsa(key) => ed25519_dalek::Signature::from_slice(signature)
+                .map_or(false, |signature| {
+                    key.verify_strict(data, &signature).is_ok()
+                }),
+            CoseKey::Rs256(key) => {
+                let scheme = Pkcs1v15Sign {
+                    hash_len: Some(32),
+                    prefix: SHA256_PREFIX.into(),
+                };
+                key.verify(scheme, &Sha256::digest(data), signature).is_ok()
+            }
+        };
+        if is_valid {
+            Ok(())
+        } else {
+            Err(WebAuthnError::InvalidSignature)
+        }
+    }
+}
+
+/// Subject public key info of a DER encoded X.509 certificate.
+fn certificate_public_key(certificate: &[u8]) -> Option<&[u8]> {
+    let ((_, certificate), _) = der_element(certificate, 0x30)?;
+    let ((_, mut rest), _) = der_element(certificate, 0x30)?;
+    // The version is optional and explicitly tagged
+    if rest.first() == Some(&0xa0) {
+        rest = der_element(rest, 0xa0)?.1;
+    }
+    // Serial number, signature algorithm, issuer, validity and subject precede the key
+    for tag in [0x02, 0x30, 0x30, 0x30, 0x30] {
+        rest = der_element(rest, tag)?.1;
+    }
+    let ((key, _), _) = der_element(rest, 0x30)?;
+    Some(key)
+}
+
+/// Splits the element with `tag` off the start of `data`.
+/// Returns the whole element together with its content, and the data following it.
+fn der_element(data: &[u8], tag: u8) -> Option<((&[u8], &[u8]), &[u8])> {
+    if *data.first()? != tag {
+        return None;
+    }
+    let (length, header) = match *data.get(1)? {
+        length @ 0..=0x7f => (length as usize, 2),
+        0x81 => (*data.get(2)? as usize, 3),
+        0x82 => (
+            u16::from_be_bytes([*data.get(2)?, *data.get(3)?]) as usize,
+            4,
+        ),
+        _ => return None,
+    };
+    let end = header + length;
+    let whole = data.get(..end)?;
+    Some(((whole, &whole[header..]), &data[end..]))
+}
+
+#[cfg(test)]
+mod test {
+    use base64::{engine::general_purpose::URL_SAFE_NO_PAD, Engine};
+    use model::UserVerification;
+    use p256::ecdsa::{signature::Signer, Signature, SigningKey};
+    use rand::rngs::OsRng;
+    use serde_json::json;
+    use sha2::{Digest, Sha256};
+    use uuid::Uuid;
+
+    use super::{
+        cbor::Cbor, verify_assertion, verify_registration, AssertionData, AssertionResponse,
+        AttestationResponse, Challenge, RegistrationResponse, WebAuthnError,
+    };
+
+    const HOST: &str = "auth.example.com";
+
+    fn encode(item: &Cbor) -> Vec<u8> {
+        fn head(major: u8, argument: usize, out: &mut Vec<u8>) {
+            match argument {
+                0..=23 => out.push(major << 5 | argument as u8),
+                24..=0xff => out.extend([major << 5 | 24, argument as u8]),
+                _ => {
+                    out.push(major << 5 | 25);
+                    out.extend((argument as u16).to_be_bytes());
+                }
+            }
+        }
+        let mut out = Vec::new();
+        match item {
+            Cbor::Integer(value) if *value >= 0 => head(0, *value as usize, &mut out),
+            Cbor::Integer(value) => head(1, (-1 - *value) as usize, &mut out),
+            Cbor::Bytes(bytes) => {
+                head(2, bytes.len(), &mut out);
+                out.extend(bytes);
+            }
+            Cbor::Text(text) => {
+                head(3, text.len(), &mut out);
+                out.extend(text.as_bytes());
+            }
+            Cbor::Array(items) => {
+                head(4, items.len(), &mut out);
+                for item in items {
+                    out.extend(encode(item));
+                }
+            }
+            Cbor::Map(entries) => {
+                head(5, entries.len(), &mut out);
+                for (key, value) in entries {
+                    out.extend(encode(key));
+                    out.extend(encode(value));
+                }
+            }
+            Cbor::Bool(value) => out.push(0xf4 + *value as u8),
+            Cbor::Null => out.push(0xf6),
+        }
+        out
+    }
+
+    fn text(value: &str) -> Cbor {
+        Cbor::Text(value.to_owned())
+    }
+
+    /// Software authenticator with a P-256 credential
+    struct Authenticator {
+        key: SigningKey,
+        id: Vec<u8>,
+        user: Uuid,
+        sign_count: u32,
+        flags: u8,
+    }
+
+    impl Authenticator {
+        fn new() -> Self {
+            Self {
+                key: SigningKey::random(&mut OsRng),
+                id: vec![7; 16],
+                user: Uuid::from_u128(42),
+                sign_count: 0,
+                // User present and verified
+                flags: 0x05,
+            }
+        }
+
+        fn public_key(&self) -> Vec<u8> {
+            let point = self.key.verifying_key().to_encoded_point(false);
+            encode(&Cbor::Map(vec![
+                (Cbor::Integer(1), Cbor::Integer(2)),
+                (Cbor::Integer(3), Cbor::Integer(-7)),
+                (Cbor::Integer(-1), Cbor::Integer(1)),
+                (Cbor::Integer(-2), Cbor::Bytes(point.x().unwrap().to_vec())),
+                (Cbor::Integer(-3), Cbor::Bytes(point.y().unwrap().to_vec())),
+            ]))
+        }
+
+        fn auth_data(&self, rp_id: &str, attested: bool) -> Vec<u8> {
+            let mut data = Sha256::digest(rp_id.as_bytes()).to_vec();
+            data.push(self.flags | if attested { 0x40 } else { 0 });
+            data.extend(self.sign_count.to_be_bytes());
+            if attested {
+                data.extend([0; 16]);
+                data.extend((self.id.len() as u16).to_be_bytes());
+                data.extend(&self.id);
+                data.extend(self.public_key());
+            }
+            data
+        }
+
+        fn client_data(kind: &str, challenge: &Challenge) -> Vec<u8> {
+            let client_data = json!({
+                "type": kind,
+                "challenge": challenge.challenge,
+                "origin": format!("https://{HOST}"),
+                "crossOrigin": false,
+            });
+            serde_json::to_vec(&client_data).unwrap()
+        }
+
+        fn sign(&self, auth_data: &[u8], client_data: &[u8]) -> Vec<u8> {
+            let signed = [auth_data, &Sha256::digest(client_data)].concat();
+            let signature: Signature = self.key.sign(&signed);
+            signature.to_der().as_bytes().to_vec()
+        }
+
+        fn register(&self, challenge: &Challenge, packed: bool) -> RegistrationResponse {
+            let client_data = Self::client_data("webauthn.create", challenge);
+            let auth_data = self.auth_data(&challenge.rp_id, true);
+            let (format, statement) = if packed {
+                let signature = self.sign(&auth_data, &client_data);
+                let statement = vec![
+                    (text("alg"), Cbor::Integer(-7)),
+                    (text("sig"), Cbor::Bytes(signature)),
+                ];
+                ("packed", statement)
+            } else {
+                ("none", vec![])
+            };
+            let attestation = encode(&Cbor::Map(vec![
+                (text("fmt"), text(format)),
+                (text("attStmt"), Cbor::Map(statement)),
+                (text("authData"), Cbor::Bytes(auth_data)),
+            ]));
+            RegistrationResponse {
+                id: URL_SAFE_NO_PAD.encode(&self.id),
+                response: AttestationResponse {
+                    client_data_json: URL_SAFE_NO_PAD.encode(client_data),
+                    attestation_object: URL_SAFE_NO_PAD.encode(attestation),
+                },
+            }
+        }
+
+        fn assert(&mut self, challenge: &Challenge) -> AssertionResponse {
+            self.sign_count += 1;
+            let client_data = Self::client_data("webauthn.get", challenge);
+            let auth_data = self.auth_data(&challenge.rp_id, false);
+            let signature = self.sign(&auth_data, &client_data);
+            AssertionResponse {
+                id: URL_SAFE_NO_PAD.encode(&self.id),
+                response: AssertionData {
+                    client_data_json: URL_SAFE_NO_PAD.encode(client_data),
+                    authenticator_data: URL_SAFE_NO_PAD.encode(auth_data),
+                    signature: URL_SAFE_NO_PAD.encode(signature),
+                    user_handle: Some(URL_SAFE_NO_PAD.encode(self.user.as_bytes())),
+                },
+            }
+        }
+    }
+
+    fn challenge() -> Challenge {
+        Challenge::new(HOST, HOST, UserVerification::Required)
+    }
+
+    #[test]
+    fn registration() {
+        let authenticator = Authenticator::new();
+        let challenge = challenge();
+        for packed in [false, true] {
+            let credential =
+                verify_registration(&challenge, &authenticator.register(&challenge, packed))
+                    .unwrap();
+            assert_eq!(credential.id, authenticator.id);
+            assert_eq!(credential.public_key, authenticator.public_key());
+        }
+    }
+
+    #[test]
+    fn registration_rejected() {
+        let mut authenticator = Authenticator::new();
+        let challenge = challenge();
+        let response = authenticator.register(&challenge, true);
+        let other = Challenge::new(HOST, "example.com", UserVerification::Required);
+        assert_eq!(
+            verify_registration(&other, &response).unwrap_err(),
+            WebAuthnError::Mismatch("Challenge")
+        );
+        let mut other_rp = other.clone();
+        other_rp.challenge = challenge.challenge.clone();
+        assert_eq!(
+            verify_registration(&other_rp, &response).unwrap_err(),
+            WebAuthnError::Mismatch("Relying party")
+        );
+        authenticator.flags = 0x01;
+        let response = authenticator.register(&challenge, false);
+        assert_eq!(
+            verify_registration(&challenge, &response).unwrap_err(),
+            WebAuthnError::UserNotVerified
+        );
+        let preferred = Challenge {
+            user_verification: UserVerification::Preferred,
+            ..challenge.clone()
+        };
+        assert!(verify_registration(&preferred, &response).is_ok());
+    }
+
+    #[test]
+    fn assertion() {
+        let mut authenticator = Authenticator::new();
+        let challenge = challenge();
+        let public_key = authenticator.public_key();
+        let response = authenticator.assert(&challenge);
+        assert_eq!(response.user_handle().unwrap(), Some(Uuid::from_u128(42)));
+        assert_eq!(
+            verify_assertion(&challenge, &response, &public_key, 0),
+            Ok(1)
+        );
+        // Replayed or cloned
+        assert_eq!(
+            verify_assertion(&challenge, &response, &public_key, 1),
+            Err(WebAuthnError::CounterRegressed)
+        );
+        let other = Authenticator::new();
+        assert_eq!(
+            verify_assertion(&challenge, &response, &other.public_key(), 0),
+            Err(WebAuthnError::InvalidSignature)
+        );
+        let mut origin = challenge.clone();
+        origin.origins = vec!["https://evil.example".to_owned()];
+        assert_eq!(
+            verify_assertion(&origin, &response, &public_key, 0),
+            Err(WebAuthnError::Mismatch("Origin"))
+        );
+    }
+}
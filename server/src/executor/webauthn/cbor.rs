@@ -0,0 +1,114 @@
+/// Data items of CBOR (RFC 8949), as far as they are used by WebAuthn.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub enum Cbor {
+    Integer(i64),
+    Bytes(Vec<u8>),
+    Text(String),
+    Array(Vec<Cbor>),
+    Map(Vec<(Cbor, Cbor)>),
+    Bool(bool),
+    Null,
+}
+
+/// Items are decoded recursively, so their nesting is limited.
+const MAX_DEPTH: usize = 16;
+
+/// Decodes the first item of `data`.
+/// Returns the item together with the number of bytes it spans.
+pub fn decode(data: &[u8]) -> Option<(Cbor, usize)> {
+    let mut decoder = Decoder { data, position: 0 };
+    let item = decoder.item(0)?;
+    Some((item, decoder.position))
+}
+
+/// Value of a text key of the map.
+pub fn get<'a>(map: &'a [(Cbor, Cbor)], key: &str) -> Option<&'a Cbor> {
+    map.iter()
+        .find(|(k, _)| matches!(k, Cbor::Text(k) if k == key))
+        .map(|(_, value)| value)
+}
+
+/// Value of an integer key of the map, which COSE calls labels.
+pub fn label(map: &[(Cbor, Cbor)], label: i64) -> Option<&Cbor> {
+    map.iter()
+        .find(|(k, _)| *k == Cbor::Integer(label))
+        .map(|(_, value)| value)
+}
+
+struct Decoder<'a> {
+    data: &'a [u8],
+    position: usize,
+}
+
+impl<'a> Decoder<'a> {
+    fn take(&mut self, length: usize) -> Option<&'a [u8]> {
+        let end = self.position.checked_add(length)?;
+        let bytes = self.data.get(self.position..end)?;
+        self.position = end;
+        Some(bytes)
+    }
+
+    /// Items of indefinite length are not supported.
+    fn argument(&mut self, info: u8) -> Option<u64> {
+        Some(match info {
+            0..=23 => info as u64,
+            24 => self.take(1)?[0] as u64,
+            25 => u16::from_be_bytes(self.take(2)?.try_into().ok()?) as u64,
+            26 => u32::from_be_bytes(self.take(4)?.try_into().ok()?) as u64,
+            27 => u64::from_be_bytes(self.take(8)?.try_into().ok()?),
+            _ => return None,
+        })
+    }
+
+    /// Rejects lengths which exceed the remaining data before anything is allocated.
+    fn length(&self, argument: u64, item_size: usize) -> Option<usize> {
+        let length = usize::try_from(argument).ok()?;
+        let remaining = self.data.len() - self.position;
+        (length.checked_mul(item_size)? <= remaining).then_some(length)
+    }
+
+    fn item(&mut self, depth: usize) -> Option<Cbor> {
+        if depth > MAX_DEPTH {
+            return None;
+        }
+        let head = self.take(1)?[0];
+        let (major, info) = (head >> 5, head & 0x1f);
+        if major == 7 {
+            return match info {
+                20 => Some(Cbor::Bool(false)),
+                21 => Some(Cbor::Bool(true)),
+                22 => Some(Cbor::Null),
+                _ => None,
+            };
+        }
+        let argument = self.argument(info)?;
+        Some(match major {
+            0 => Cbor::Integer(i64::try_from(argument).ok()?),
+            1 => Cbor::Integer(-1 - i64::try_from(argument).ok()?),
+            2 => Cbor::Bytes(self.take(self.length(argument, 1)?)?.to_vec()),
+            3 => {
+                let text = self.take(self.length(argument, 1)?)?;
+                Cbor::Text(String::from_utf8(text.to_vec()).ok()?)
+            }
+            4 => {
+                let length = self.length(argument, 1)?;
+                let mut items = Vec::with_capacity(length);
+                for _ in 0..length {
+                    items.push(self.item(depth + 1)?);
+                }
+                Cbor::Array(items)
+            }
+            5 => {
+                let length = self.length(argument, 2)?;
+                let mut entries = Vec::with_capacity(length);
+                for _ in 0..length {
+                    let key = self.item(depth + 1)?;
+                    entries.push((key, self.item(depth + 1)?));
+                }
+                Cbor::Map(entries)
+            }
+            // Tags are not used by WebAuthn
+            _ => return None,
+        })
+    }
+}
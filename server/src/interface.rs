@@ -5,7 +5,7 @@ use crate::SharedState;
 
 use self::flow::setup_flow_router;
 
-mod flow;
+pub(crate) mod flow;
 
 pub fn setup_interface_router() -> Router<SharedState> {
     let spa = spa_router();
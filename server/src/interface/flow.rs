@@ -17,7 +17,7 @@ pub fn setup_flow_router() -> Router<SharedState> {
     Router::new().route("/:flow_designation", get(tenant_flow_redirect))
 }
 
-static INTERFACE_BASE_URI: Lazy<&'static str> = Lazy::new(base_uri);
+pub(crate) static INTERFACE_BASE_URI: Lazy<&'static str> = Lazy::new(base_uri);
 
 fn base_uri() -> &'static str {
     let env = std::env::var("INTERFACE_BASE_URI").ok();
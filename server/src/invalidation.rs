@@ -0,0 +1,207 @@
+use std::time::Duration;
+
+use futures::{channel::mpsc, StreamExt};
+use model::{Flow, FlowQuery, Policy, Prompt, PromptQuery, Stage, StageQuery, Tenant, TenantQuery};
+use serde::Deserialize;
+use storage::{datacache::DataStorage, StorageManager};
+use tokio_postgres::{AsyncMessage, Client, Config, NoTls};
+
+use crate::service::policy::PolicyService;
+
+/// Channel the triggers on the configuration tables notify.
+const CHANNEL: &str = "authust_invalidation";
+const RECONNECT_INTERVAL: Duration = Duration::from_secs(5);
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Deserialize)]
+#[serde(rename_all = "snake_case")]
+enum InvalidationKind {
+    Flow,
+    Stage,
+    Policy,
+    Prompt,
+    Tenant,
+}
+
+#[derive(Debug, PartialEq, Eq, Deserialize)]
+struct Invalidation {
+    #[serde(rename = "type")]
+    kind: InvalidationKind,
+    uid: i32,
+}
+
+/// Listens for changes of the cached configuration made by any server
+/// and drops the changed entries from the caches of this one.
+pub async fn listen(config: Config, storage: StorageManager, policies: PolicyService) {
+    let mut connected_before = false;
+    loop {
+        match listen_once(&config, &storage, &policies, connected_before).await {
+            Ok(()) => tracing::warn!("Cache invalidation listener disconnected"),
+            Err(err) => tracing::warn!("Cache invalidation listener failed {err}"),
+        }
+        connected_before = true;
+        tokio::time::sleep(RECONNECT_INTERVAL).await;
+    }
+}
+
+async fn listen_once(
+    config: &Config,
+    storage: &StorageManager,
+    policies: &PolicyService,
+    reconnect: bool,
+) -> Result<(), tokio_postgres::Error> {
+    let (client, mut connection) = config.connect(NoTls).await?;
+    let (sender, mut receiver) = mpsc::unbounded();
+    // Notifications are only received while the connection is polled
+    let connection = tokio::spawn(async move {
+        let mut messages = futures::stream::poll_fn(move |cx| connection.poll_message(cx));
+        while let Some(message) = messages.next().await {
+            match message {
+                Ok(AsyncMessage::Notification(notification)) => {
+                    if sender.unbounded_send(notification).is_err() {
+                        break;
+                    }
+                }
+                Ok(_) => {}
+                Err(err) => return Err(err),
+            }
+        }
+        Ok(())
+    });
+    client.batch_execute(&format!("listen {CHANNEL}")).await?;
+    if reconnect {
+        // Changes made while disconnected have been missed
+        invalidate_all(&client, storage, policies).await?;
+    }
+    tracing::info!("Listening for cache invalidations");
+    while let Some(notification) = receiver.next().await {
+        match parse(notification.payload()) {
+            Ok(invalidation) => invalidate(storage, policies, invalidation).await,
+            Err(err) => tracing::warn!(
+                payload = notification.payload(),
+                "Received invalid cache invalidation {err}"
+            ),
+        }
+    }
+    match connection.await {
+        Ok(result) => result,
+        Err(err) => {
+            tracing::warn!("Cache invalidation connection panicked {err}");
+            Ok(())
+        }
+    }
+}
+
+/// Parses the payload built by `notify_invalidation`.
+fn parse(payload: &str) -> Result<Invalidation, serde_json::Error> {
+    serde_json::from_str(payload)
+}
+
+async fn invalidate(
+    storage: &StorageManager,
+    policies: &PolicyService,
+    invalidation: Invalidation,
+) {
+    tracing::debug!(?invalidation, "Invalidating cache entry");
+    let uid = invalidation.uid;
+    let result = match invalidation.kind {
+        InvalidationKind::Flow => {
+            storage
+                .get_for_data::<Flow>()
+                .expect("Failed to get Flow storage")
+                .invalidate(&FlowQuery::uid(uid))
+                .await
+        }
+        InvalidationKind::Stage => {
+            storage
+                .get_for_data::<Stage>()
+                .expect("Failed to get Stage storage")
+                .invalidate(&StageQuery::uid(uid))
+                .await
+        }
+        InvalidationKind::Policy => {
+            // Drops the compiled expression as well
+            policies.invalidate(uid).await;
+            Ok(())
+        }
+        InvalidationKind::Prompt => {
+            storage
+                .get_for_data::<Prompt>()
+                .expect("Failed to get Prompt storage")
+                .invalidate(&PromptQuery::uid(uid))
+                .await
+        }
+        InvalidationKind::Tenant => {
+            storage
+                .get_for_data::<Tenant>()
+                .expect("Failed to get Tenant storage")
+                .invalidate(&TenantQuery::uid(uid))
+                .await
+        }
+    };
+    if let Err(err) = result {
+        tracing::warn!(?invalidation, "Failed to invalidate cache entry {err}");
+    }
+}
+
+async fn invalidate_all(
+    client: &Client,
+    storage: &StorageManager,
+    policies: &PolicyService,
+) -> Result<(), tokio_postgres::Error> {
+    let tables = [
+        ("flows", InvalidationKind::Flow),
+        ("stages", InvalidationKind::Stage),
+        ("policies", InvalidationKind::Policy),
+        ("prompts", InvalidationKind::Prompt),
+        ("tenants", InvalidationKind::Tenant),
+    ];
+    for (table, kind) in tables {
+        let rows = client
+            .query(format!("select uid from {table}").as_str(), &[])
+            .await?;
+        for row in rows {
+            let invalidation = Invalidation {
+                kind,
+                uid: row.get("uid"),
+            };
+            invalidate(storage, policies, invalidation).await;
+        }
+    }
+    Ok(())
+}
+
+#[cfg(test)]
+mod test {
+    use super::{parse, Invalidation, InvalidationKind};
+
+    #[test]
+    fn kinds() {
+        let kinds = [
+            ("flow", InvalidationKind::Flow),
+            ("stage", InvalidationKind::Stage),
+            ("policy", InvalidationKind::Policy),
+            ("prompt", InvalidationKind::Prompt),
+            ("tenant", InvalidationKind::Tenant),
+        ];
+        for (name, kind) in kinds {
+            let payload = format!(r#"{{"type" : "{name}", "uid" : 7}}"#);
+            assert_eq!(parse(&payload).unwrap(), Invalidation { kind, uid: 7 });
+        }
+    }
+
+    #[test]
+    fn unknown_kind() {
+        assert!(parse(r#"{"type": "user", "uid": 1}"#).is_err());
+        assert!(parse(r#"{"type": "Flow", "uid": 1}"#).is_err());
+    }
+
+    #[test]
+    fn malformed() {
+        assert!(parse("").is_err());
+        assert!(parse("flow 1").is_err());
+        assert!(parse(r#"{"type": "flow"}"#).is_err());
+        assert!(parse(r#"{"uid": 1}"#).is_err());
+        assert!(parse(r#"{"type": "flow", "uid": "1"}"#).is_err());
+        assert!(parse(r#"{"type": "flow", "uid": 4294967296}"#).is_err());
+    }
+}
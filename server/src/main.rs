@@ -1,14 +1,28 @@
 use std::ops::DerefMut;
 
+use std::net::SocketAddr;
 use std::sync::Arc;
 use std::time::Duration;
 
-use crate::api::setup_api_v1;
-use crate::config::PostgresConfiguration;
+use crate::api::{jwks, openid_configuration, setup_api_v1};
 use crate::config::{AuthustConfiguration, InternalAuthustConfiguration};
+use crate::config::{ExecutionStoreKind, PostgresConfiguration};
 use crate::interface::setup_interface_router;
 use crate::otel_middleware::{otel_layer, ExtensionLayer};
+use crate::service::consent::ConsentService;
+use crate::service::email::EmailService;
+use crate::service::flow::FlowService;
+use crate::service::group::GroupService;
+use crate::service::keys::KeyService;
+use crate::service::oauth2::OAuth2Service;
+use crate::service::prompt::PromptService;
+use crate::service::redirect::RedirectService;
+use crate::service::session::SessionService;
+use crate::service::stage::StageService;
+use crate::service::static_codes::StaticCodeService;
+use crate::service::totp::TotpService;
 use crate::service::user::UserService;
+use crate::service::webauthn::WebAuthnService;
 use api::AuthServiceData;
 
 use axum::error_handling::HandleErrorLayer;
@@ -17,10 +31,10 @@ use axum::routing::get;
 use axum::{BoxError, Router};
 use deadpool_postgres::{GenericClient, Manager, ManagerConfig, Object, Pool, PoolError};
 
+use executor::storage::{ExecutionStore, MemoryExecutionStore, PostgresExecutionStore};
 use executor::FlowExecutor;
 use futures::{Future, FutureExt};
 use http::StatusCode;
-use jsonwebtoken::{DecodingKey, EncodingKey};
 use model::{Flow, Policy, Prompt, Stage, Tenant, TenantQuery};
 
 use opentelemetry::sdk::resource::{EnvResourceDetector, ResourceDetector};
@@ -30,9 +44,11 @@ use opentelemetry_otlp::{self as otlp, ExportConfig};
 use otlp::{SpanExporterBuilder, TonicExporterBuilder, WithExportConfig};
 use service::policy::PolicyService;
 
+use storage::breached::BreachedFilter;
 use storage::datacache::{Data, DataStorage};
 use storage::{StorageError, StorageManager};
 use tokio::signal;
+use tokio::time::{self, Instant, Interval};
 use tower::ServiceBuilder;
 use tower_cookies::CookieManagerLayer;
 use tracing::info;
@@ -45,9 +61,19 @@ pub mod auth;
 pub mod config;
 pub mod executor;
 pub mod interface;
+mod invalidation;
 mod otel_middleware;
 pub mod service;
 
+// 10 Minutes
+const EXECUTION_PURGE_INTERVAL: Duration = Duration::from_secs(60 * 10);
+// 10 Minutes
+const OAUTH2_PURGE_INTERVAL: Duration = Duration::from_secs(60 * 10);
+// 1 Hour
+const SESSION_PURGE_INTERVAL: Duration = Duration::from_secs(60 * 60);
+// 1 Hour
+const KEY_ROTATION_INTERVAL: Duration = Duration::from_secs(60 * 60);
+
 #[tokio::main]
 async fn main() {
     setup().await;
@@ -133,15 +159,19 @@ async fn setup() {
         fut.expect("Error occurred while waiting for Ctrl+C signal");
         tracing::info!("Received shutdown signal");
     });
-    let pool = setup_database(&configuration.postgres, &password).await;
-    let app_future = start_server(configuration, pool, shutdown_future);
+    let postgres = postgres_config(&configuration.postgres, &password);
+    let pool = setup_database(postgres.clone()).await;
+    let app_future = start_server(configuration, pool, postgres, shutdown_future);
     app_future.await;
     tracing::info!("Shutting down opentelemetry provider");
     opentelemetry::global::shutdown_tracer_provider();
     tracing::info!("Shutdown complete");
 }
 
-async fn setup_database(configuration: &PostgresConfiguration, password: &str) -> Pool {
+fn postgres_config(
+    configuration: &PostgresConfiguration,
+    password: &str,
+) -> tokio_postgres::Config {
     let mut cfg = tokio_postgres::Config::new();
     cfg.host(&configuration.host)
         .port(configuration.port)
@@ -149,6 +179,10 @@ async fn setup_database(configuration: &PostgresConfiguration, password: &str) -
         .user(&configuration.user)
         .password(&password)
         .application_name("Authust");
+    cfg
+}
+
+async fn setup_database(cfg: tokio_postgres::Config) -> Pool {
     let mgr_config = ManagerConfig {
         recycling_method: deadpool_postgres::RecyclingMethod::Fast,
     };
@@ -241,6 +275,45 @@ impl SharedState {
     pub fn policies(&self) -> &PolicyService {
         &self.0.policies
     }
+    pub fn consents(&self) -> &ConsentService {
+        &self.0.consents
+    }
+    pub fn groups(&self) -> &GroupService {
+        &self.0.groups
+    }
+    pub fn flows(&self) -> &FlowService {
+        &self.0.flows
+    }
+    pub fn stages(&self) -> &StageService {
+        &self.0.stages
+    }
+    pub fn totp(&self) -> &TotpService {
+        &self.0.totp
+    }
+    pub fn webauthn(&self) -> &WebAuthnService {
+        &self.0.webauthn
+    }
+    pub fn static_codes(&self) -> &StaticCodeService {
+        &self.0.static_codes
+    }
+    pub fn email(&self) -> &EmailService {
+        &self.0.email
+    }
+    pub fn prompts(&self) -> &PromptService {
+        &self.0.prompts
+    }
+    pub fn oauth2(&self) -> &OAuth2Service {
+        &self.0.oauth2
+    }
+    pub fn sessions(&self) -> &SessionService {
+        &self.0.sessions
+    }
+    pub fn keys(&self) -> &KeyService {
+        &self.0.auth_data.keys
+    }
+    pub fn issuer(&self) -> Option<&str> {
+        self.0.issuer.as_deref()
+    }
 }
 
 struct InternalSharedState {
@@ -250,6 +323,18 @@ struct InternalSharedState {
     storage: StorageManager,
     defaults: Arc<Defaults>,
     policies: PolicyService,
+    consents: ConsentService,
+    groups: GroupService,
+    flows: FlowService,
+    stages: StageService,
+    totp: TotpService,
+    webauthn: WebAuthnService,
+    static_codes: StaticCodeService,
+    email: EmailService,
+    prompts: PromptService,
+    oauth2: OAuth2Service,
+    sessions: SessionService,
+    issuer: Option<String>,
 }
 
 pub struct Defaults {
@@ -310,6 +395,7 @@ impl Defaults {
 async fn start_server(
     config: InternalAuthustConfiguration,
     pool: Pool,
+    postgres: tokio_postgres::Config,
     future: impl Future<Output = ()>,
 ) {
     #[cfg(debug_assertions)]
@@ -317,20 +403,65 @@ async fn start_server(
     let cors = tower_http::cors::CorsLayer::very_permissive();
     let storage = storage::create_manager(pool.clone());
     preload(&storage).await.expect("Preloading failed");
-    let policies = PolicyService::new(storage.clone(), pool.clone());
+    let breached_passwords = config.breached_passwords.as_ref().map(|path| {
+        info!("Loading breached password filter...");
+        BreachedFilter::open(path).expect("Failed to load breached password filter")
+    });
+    let policies = PolicyService::new(storage.clone(), pool.clone(), breached_passwords);
+    tokio::spawn(invalidation::listen(
+        postgres,
+        storage.clone(),
+        policies.clone(),
+    ));
     let defaults = Defaults::new(storage.clone(), pool.clone()).await;
-    let executor = FlowExecutor::new(storage.clone(), policies.clone());
+    let store: Arc<dyn ExecutionStore> = match config.execution_store {
+        ExecutionStoreKind::Memory => Arc::new(MemoryExecutionStore::new()),
+        ExecutionStoreKind::Postgres => {
+            Arc::new(PostgresExecutionStore::new(pool.clone(), &config.secret))
+        }
+    };
+    let redirects = RedirectService::new(
+        storage.clone(),
+        pool.clone(),
+        config.allowed_redirect_origins(),
+    );
+    let executor = FlowExecutor::new(
+        storage.clone(),
+        policies.clone(),
+        redirects,
+        store,
+        defaults.tenant(),
+    );
+    tokio::spawn(purge_executions(executor.clone()));
+    let oauth2 = OAuth2Service::new();
+    tokio::spawn(purge_oauth2(oauth2.clone(), pool.clone()));
+    let sessions = SessionService::new(&config.sessions);
+    tokio::spawn(purge_sessions(sessions.clone(), pool.clone()));
     let users = UserService::new();
+    let keys = KeyService::new(pool.clone(), config.keys.clone(), &config.secret)
+        .await
+        .expect("Failed to load signing keys");
+    tokio::spawn(rotate_keys(keys.clone()));
+    let email = EmailService::new(&config.email).expect("Failed to set up email transport");
     let internal_state = InternalSharedState {
         users,
         executor,
-        auth_data: AuthServiceData {
-            encoding_key: EncodingKey::from_secret(config.secret.as_bytes()),
-            decoding_key: DecodingKey::from_secret(config.secret.as_bytes()),
-        },
+        auth_data: AuthServiceData { keys },
         storage: storage.clone(),
         defaults: Arc::new(defaults),
         policies,
+        consents: ConsentService::new(),
+        groups: GroupService::new(),
+        flows: FlowService::new(),
+        stages: StageService::new(),
+        totp: TotpService::new(),
+        webauthn: WebAuthnService::new(),
+        static_codes: StaticCodeService::new(),
+        email,
+        prompts: PromptService::new(),
+        oauth2,
+        sessions,
+        issuer: config.issuer.clone(),
     };
     let state = SharedState(Arc::new(internal_state));
     let service = ServiceBuilder::new()
@@ -344,19 +475,104 @@ async fn start_server(
     let service = service.layer(cors);
     let router = Router::new()
         .route("/test", get(hello_world))
-        .nest("/api/v1", setup_api_v1(&config.secret, state.clone()).await)
+        .route(
+            "/.well-known/openid-configuration",
+            get(openid_configuration),
+        )
+        .route("/.well-known/jwks.json", get(jwks))
+        .nest("/api/v1", setup_api_v1(state.clone()).await)
         .nest("/", setup_interface_router())
         .layer(service)
         .with_state(state);
     let bind = axum::Server::bind(&config.listen.http);
     info!("Listening on {}...", config.listen.http);
-    bind.serve(router.into_make_service())
+    bind.serve(router.into_make_service_with_connect_info::<SocketAddr>())
         .with_graceful_shutdown(future)
         .await
         .expect("Server crashed");
     // server.await.expect("Server crashed");
 }
 
+/// Runs the purge on every tick of the interval and logs how many entries it removed.
+async fn purge_periodically<F, Fut>(mut interval: Interval, what: &str, mut purge: F)
+where
+    F: FnMut() -> Fut,
+    Fut: Future<Output = Result<u64, String>>,
+{
+    loop {
+        interval.tick().await;
+        match purge().await {
+            Ok(0) => {}
+            Ok(purged) => tracing::debug!("Purged {purged} {what}"),
+            Err(err) => tracing::warn!("Failed to purge {what} {err}"),
+        }
+    }
+}
+
+async fn purge_executions(executor: FlowExecutor) {
+    let executor = &executor;
+    purge_periodically(
+        time::interval(EXECUTION_PURGE_INTERVAL),
+        "expired flow executions",
+        move || async move {
+            executor
+                .purge_expired()
+                .await
+                .map_err(|err| err.to_string())
+        },
+    )
+    .await
+}
+
+async fn rotate_keys(keys: KeyService) {
+    let keys = &keys;
+    // The keys have just been rotated on startup
+    let start = Instant::now() + KEY_ROTATION_INTERVAL;
+    purge_periodically(
+        time::interval_at(start, KEY_ROTATION_INTERVAL),
+        "expired signing keys",
+        move || async move {
+            if let Err(err) = keys.rotate().await {
+                tracing::warn!("Failed to rotate signing keys {err}");
+            }
+            keys.purge_expired().await.map_err(|err| err.to_string())
+        },
+    )
+    .await
+}
+
+async fn purge_oauth2(oauth2: OAuth2Service, pool: Pool) {
+    let (oauth2, pool) = (&oauth2, &pool);
+    purge_periodically(
+        time::interval(OAUTH2_PURGE_INTERVAL),
+        "expired oauth2 tokens and requests",
+        move || async move {
+            let connection = pool.get().await.map_err(|err| err.to_string())?;
+            oauth2
+                .purge_expired(&connection)
+                .await
+                .map_err(|err| err.kind.to_string())
+        },
+    )
+    .await
+}
+
+async fn purge_sessions(sessions: SessionService, pool: Pool) {
+    let (sessions, pool) = (&sessions, &pool);
+    purge_periodically(
+        time::interval(SESSION_PURGE_INTERVAL),
+        "expired sessions",
+        move || async move {
+            let connection = pool.get().await.map_err(|err| err.to_string())?;
+            sessions
+                .purge_expired(&connection)
+                .await
+                .map_err(|err| err.kind.to_string())
+        },
+    )
+    .await
+}
+
 async fn handle_timeout_error(err: BoxError) -> (StatusCode, String) {
     if err.is::<tower::timeout::error::Elapsed>() {
         (
@@ -1,2 +1,15 @@
+pub mod consent;
+pub mod email;
+pub mod flow;
+pub mod group;
+pub mod keys;
+pub mod oauth2;
 pub mod policy;
+pub mod prompt;
+pub mod redirect;
+pub mod session;
+pub mod stage;
+pub mod static_codes;
+pub mod totp;
 pub mod user;
+pub mod webauthn;
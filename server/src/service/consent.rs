@@ -0,0 +1,124 @@
+use deadpool_postgres::GenericClient;
+use model::Consent;
+use time::OffsetDateTime;
+use tokio_postgres::Row;
+use uuid::Uuid;
+
+use crate::api::ApiError;
+
+/// What a consent has been given to.
+/// Executions started by an application are bound to the application, all other ones to the flow.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub enum ConsentTarget {
+    Application(i32),
+    Flow(i32),
+}
+
+impl ConsentTarget {
+    fn columns(&self) -> (Option<i32>, Option<i32>) {
+        match self {
+            ConsentTarget::Application(uid) => (Some(*uid), None),
+            ConsentTarget::Flow(uid) => (None, Some(*uid)),
+        }
+    }
+}
+
+#[derive(Clone)]
+pub struct ConsentService {}
+
+impl ConsentService {
+    pub fn new() -> Self {
+        Self {}
+    }
+}
+
+fn from_row(row: &Row) -> Consent {
+    Consent {
+        uid: row.get("uid"),
+        user: row.get("user_id"),
+        application: row.get("application"),
+        flow: row.get("flow"),
+        scopes: row.get("scopes"),
+        created: row.get("created"),
+        expires: row.get("expires"),
+    }
+}
+
+impl ConsentService {
+    /// Checks whether the user has a consent for the target which is still valid and covers all scopes.
+    pub async fn has_consent(
+        &self,
+        client: &impl GenericClient,
+        user: Uuid,
+        target: &ConsentTarget,
+        scopes: &[String],
+    ) -> Result<bool, ApiError> {
+        let (application, flow) = target.columns();
+        let statement = client
+            .prepare_cached(
+                "select exists(select 1 from consents where user_id = $1 and application is not distinct from $2 and flow is not distinct from $3 and scopes @> $4 and (expires is null or expires > now()))",
+            )
+            .await?;
+        Ok(client
+            .query_one(&statement, &[&user, &application, &flow, &scopes])
+            .await?
+            .get(0))
+    }
+
+    /// Stores a consent, replacing the previous consent of the user for the same target.
+    pub async fn grant(
+        &self,
+        client: &impl GenericClient,
+        user: Uuid,
+        target: &ConsentTarget,
+        scopes: &[String],
+        expires: Option<OffsetDateTime>,
+    ) -> Result<(), ApiError> {
+        let (application, flow) = target.columns();
+        let statement = client
+            .prepare_cached(
+                "delete from consents where user_id = $1 and application is not distinct from $2 and flow is not distinct from $3",
+            )
+            .await?;
+        client
+            .execute(&statement, &[&user, &application, &flow])
+            .await?;
+        let statement = client
+            .prepare_cached(
+                "insert into consents(user_id, application, flow, scopes, expires) values ($1, $2, $3, $4, $5)",
+            )
+            .await?;
+        client
+            .execute(&statement, &[&user, &application, &flow, &scopes, &expires])
+            .await?;
+        Ok(())
+    }
+
+    pub async fn list_for_user(
+        &self,
+        client: &impl GenericClient,
+        user: Uuid,
+    ) -> Result<Vec<Consent>, ApiError> {
+        let statement = client
+            .prepare_cached(
+                "select * from consents where user_id = $1 and (expires is null or expires > now()) order by created",
+            )
+            .await?;
+        let rows = client.query(&statement, &[&user]).await?;
+        Ok(rows.iter().map(from_row).collect())
+    }
+
+    /// Deletes a consent of the user.
+    /// Returns false if the user has no consent with this uid.
+    pub async fn revoke(
+        &self,
+        client: &impl GenericClient,
+        user: Uuid,
+        uid: Uuid,
+    ) -> Result<bool, ApiError> {
+        let statement = client
+            .prepare_cached("delete from consents where uid = $1 and user_id = $2")
+            .await?;
+        Ok(client.execute(&statement, &[&uid, &user]).await? > 0)
+    }
+}
@@ -0,0 +1,242 @@
+use std::fs;
+
+use derive_more::{Display, Error, From};
+use handlebars::{Handlebars, RenderError, TemplateError};
+use jsonwebtoken::Validation;
+use lettre::{
+    address::AddressError,
+    message::{header::ContentType, Mailbox},
+    transport::{file, smtp, smtp::authentication::Credentials},
+    AsyncFileTransport, AsyncSmtpTransport, AsyncTransport, Message, Tokio1Executor,
+};
+use once_cell::sync::Lazy;
+use rand::{
+    distributions::{Alphanumeric, DistString},
+    rngs::OsRng,
+};
+use serde::{Deserialize, Serialize};
+use uuid::Uuid;
+
+use crate::{
+    api::ApiError,
+    config::{EmailConfiguration, EmailTransportKind, SmtpConfiguration, SmtpTls},
+    service::keys::KeyService,
+};
+
+/// Templates which are always available, the template directory may replace them.
+const BUILTIN_TEMPLATES: &[(&str, &str)] = &[
+    (
+        "email_verification",
+        include_str!("../../templates/email_verification.hbs"),
+    ),
+    (
+        "password_recovery",
+        include_str!("../../templates/password_recovery.hbs"),
+    ),
+];
+/// Audience of verification link tokens, so they can't be mistaken for other tokens.
+const AUDIENCE: &str = "email";
+const NONCE_LENGTH: usize = 32;
+
+static VALIDATION: Lazy<Validation> = Lazy::new(|| {
+    let mut validation = Validation::default();
+    validation.set_required_spec_claims(&["iss", "aud", "exp"]);
+    validation.set_issuer(&["authust"]);
+    validation.set_audience(&[AUDIENCE]);
+    validation
+});
+
+#[derive(Debug, Display, Error, From)]
+pub enum EmailError {
+    Address(#[error(source)] AddressError),
+    Message(#[error(source)] lettre::error::Error),
+    Smtp(#[error(source)] smtp::Error),
+    File(#[error(source)] file::Error),
+    Template(#[error(source)] TemplateError),
+    Render(#[error(source)] RenderError),
+    Io(#[error(source)] std::io::Error),
+}
+
+/// Claims of the token in links sent by the email stage.
+#[derive(Debug, Serialize, Deserialize)]
+pub struct EmailClaims {
+    pub iss: String,
+    pub aud: String,
+    /// Public id of the session the execution belongs to
+    pub session: Uuid,
+    pub flow: String,
+    /// Kept in the execution until the link has been opened, so every link works only once
+    pub nonce: String,
+    pub sub: Uuid,
+    /// The address the link has been sent to
+    pub email: String,
+    pub exp: i64,
+}
+
+impl EmailClaims {
+    pub fn new(session: Uuid, flow: String, user: Uuid, email: String, exp: i64) -> Self {
+        Self {
+            iss: "authust".to_owned(),
+            aud: AUDIENCE.to_owned(),
+            session,
+            flow,
+            nonce: Alphanumeric.sample_string(&mut OsRng, NONCE_LENGTH),
+            sub: user,
+            email,
+            exp,
+        }
+    }
+}
+
+enum Transport {
+    Stdout,
+    File(AsyncFileTransport<Tokio1Executor>),
+    Smtp(AsyncSmtpTransport<Tokio1Executor>),
+}
+
+/// Renders messages from handlebars templates and sends them with the configured transport.
+pub struct EmailService {
+    from: Mailbox,
+    transport: Transport,
+    templates: Handlebars<'static>,
+}
+
+impl EmailService {
+    pub fn new(config: &EmailConfiguration) -> Result<Self, EmailError> {
+        let transport = match config.transport {
+            EmailTransportKind::Stdout => Transport::Stdout,
+            EmailTransportKind::File => {
+                fs::create_dir_all(&config.directory)?;
+                Transport::File(AsyncFileTransport::new(&config.directory))
+            }
+            EmailTransportKind::Smtp => Transport::Smtp(smtp_transport(&config.smtp)?),
+        };
+        let mut templates = Handlebars::new();
+        templates.set_strict_mode(true);
+        for (name, template) in BUILTIN_TEMPLATES {
+            templates.register_template_string(name, template)?;
+        }
+        if let Some(directory) = &config.templates {
+            for entry in fs::read_dir(directory)? {
+                let path = entry?.path();
+                if path.extension().map_or(true, |extension| extension != "hbs") {
+                    continue;
+                }
+                let Some(name) = path.file_stem().and_then(|name| name.to_str()) else { continue };
+                templates.register_template_file(name, &path)?;
+            }
+        }
+        Ok(Self {
+            from: config.from.parse()?,
+            transport,
+            templates,
+        })
+    }
+}
+
+fn smtp_transport(
+    config: &SmtpConfiguration,
+) -> Result<AsyncSmtpTransport<Tokio1Executor>, EmailError> {
+    let builder = match config.tls {
+        SmtpTls::None => AsyncSmtpTransport::<Tokio1Executor>::builder_dangerous(&config.host),
+        SmtpTls::StartTls => AsyncSmtpTransport::<Tokio1Executor>::starttls_relay(&config.host)?,
+        SmtpTls::Tls => AsyncSmtpTransport::<Tokio1Executor>::relay(&config.host)?,
+    };
+    let mut builder = builder.port(config.port);
+    if let (Some(username), Some(password)) = (&config.username, &config.password) {
+        builder = builder.credentials(Credentials::new(username.clone(), password.clone()));
+    }
+    Ok(builder.build())
+}
+
+impl EmailService {
+    pub fn has_template(&self, name: &str) -> bool {
+        self.templates.has_template(name)
+    }
+
+    /// Sends the html message rendered from `template` with `data`.
+    pub async fn send(
+        &self,
+        to: &str,
+        subject: &str,
+        template: &str,
+        data: &impl Serialize,
+    ) -> Result<(), EmailError> {
+        let body = self.templates.render(template, data)?;
+        let message = Message::builder()
+            .from(self.from.clone())
+            .to(to.parse()?)
+            .subject(subject)
+            .header(ContentType::TEXT_HTML)
+            .body(body)?;
+        match &self.transport {
+            Transport::Stdout => println!("{}", String::from_utf8_lossy(&message.formatted())),
+            Transport::File(transport) => {
+                transport.send(message).await?;
+            }
+            Transport::Smtp(transport) => {
+                transport.send(message).await?;
+            }
+        }
+        Ok(())
+    }
+}
+
+pub fn encode_token(keys: &KeyService, claims: &EmailClaims) -> Result<String, ApiError> {
+    keys.sign(claims)
+}
+
+pub async fn decode_token(keys: &KeyService, token: &str) -> Result<EmailClaims, ApiError> {
+    Ok(keys.verify(token, &VALIDATION).await?.claims)
+}
+
+/// Absolute url of the interface, which links sent by email point to.
+/// `interface` is relative to the public base url `issuer`, unless it is absolute itself.
+pub fn link_base(interface: &str, issuer: Option<&str>) -> Option<String> {
+    let interface = interface.trim_end_matches('/');
+    if interface.starts_with("http://") || interface.starts_with("https://") {
+        return Some(interface.to_owned());
+    }
+    Some(format!("{}{interface}", issuer?.trim_end_matches('/')))
+}
+
+/// Hides most of the local part, so the address can be shown before the user is authenticated.
+pub fn mask_address(address: &str) -> String {
+    match address.split_once('@') {
+        Some((local, domain)) => {
+            let first: String = local.chars().take(1).collect();
+            format!("{first}***@{domain}")
+        }
+        None => "***".to_owned(),
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::{link_base, mask_address};
+
+    #[test]
+    fn link_bases() {
+        let issuer = Some("https://auth.example.com/");
+        assert_eq!(
+            link_base("", issuer).as_deref(),
+            Some("https://auth.example.com")
+        );
+        assert_eq!(
+            link_base("/ui/", issuer).as_deref(),
+            Some("https://auth.example.com/ui")
+        );
+        assert_eq!(
+            link_base("https://ui.example.com", None).as_deref(),
+            Some("https://ui.example.com")
+        );
+        assert_eq!(link_base("/ui", None), None);
+    }
+
+    #[test]
+    fn masked_address() {
+        assert_eq!(mask_address("jane@example.com"), "j***@example.com");
+        assert_eq!(mask_address("@example.com"), "***@example.com");
+        assert_eq!(mask_address("jane"), "***");
+    }
+}
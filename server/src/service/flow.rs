@@ -0,0 +1,306 @@
+use deadpool_postgres::GenericClient;
+use model::{AuthenticationRequirement, FlowDesignation};
+use serde::Deserialize;
+use uuid::Uuid;
+
+use crate::api::ApiError;
+
+#[derive(Debug, Deserialize)]
+pub struct FlowWrite {
+    pub slug: String,
+    pub title: String,
+    pub designation: FlowDesignation,
+    pub authentication: AuthenticationRequirement,
+}
+
+#[derive(Debug, Deserialize)]
+pub struct EntryWrite {
+    pub stage: i32,
+    pub ordering: i16,
+}
+
+#[derive(Debug, Deserialize)]
+pub struct BindingWrite {
+    #[serde(flatten)]
+    pub kind: BindingKindWrite,
+    #[serde(default = "enabled_default")]
+    pub enabled: bool,
+    #[serde(default)]
+    pub negate: bool,
+    pub order: i16,
+}
+
+fn enabled_default() -> bool {
+    true
+}
+
+#[derive(Debug, Deserialize)]
+#[serde(rename_all = "snake_case")]
+pub enum BindingKindWrite {
+    Policy(i32),
+    Group(Uuid),
+    User(Uuid),
+}
+
+impl BindingKindWrite {
+    fn columns(&self) -> (Option<i32>, Option<Uuid>, Option<Uuid>) {
+        match self {
+            BindingKindWrite::Policy(uid) => (Some(*uid), None, None),
+            BindingKindWrite::Group(uid) => (None, Some(*uid), None),
+            BindingKindWrite::User(uid) => (None, None, Some(*uid)),
+        }
+    }
+}
+
+/// What a binding is attached to, bindings of an entry decide whether the entry is executed.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum BindingOwner {
+    Flow(i32),
+    Entry(i32),
+}
+
+impl BindingOwner {
+    fn columns(&self) -> (Option<i32>, Option<i32>) {
+        match self {
+            BindingOwner::Flow(uid) => (Some(*uid), None),
+            BindingOwner::Entry(uid) => (None, Some(*uid)),
+        }
+    }
+}
+
+/// Writes flows to the database, reads go through the `FlowStorage`,
+/// so its cache entry has to be invalidated after every change.
+#[derive(Clone)]
+pub struct FlowService {}
+
+impl FlowService {
+    pub fn new() -> Self {
+        Self {}
+    }
+}
+
+impl FlowService {
+    pub async fn list_uids(&self, client: &impl GenericClient) -> Result<Vec<i32>, ApiError> {
+        let statement = client
+            .prepare_cached("select uid from flows order by slug")
+            .await?;
+        let rows = client.query(&statement, &[]).await?;
+        Ok(rows.into_iter().map(|row| row.get("uid")).collect())
+    }
+
+    pub async fn uid_by_slug(
+        &self,
+        client: &impl GenericClient,
+        slug: &str,
+    ) -> Result<Option<i32>, ApiError> {
+        let statement = client
+            .prepare_cached("select uid from flows where slug = $1")
+            .await?;
+        let row = client.query_opt(&statement, &[&slug]).await?;
+        Ok(row.map(|row| row.get("uid")))
+    }
+
+    pub async fn create(
+        &self,
+        client: &impl GenericClient,
+        write: &FlowWrite,
+    ) -> Result<i32, ApiError> {
+        let statement = client
+            .prepare_cached(
+                "insert into flows(slug, title, designation, authentication) values ($1, $2, $3, $4) returning uid",
+            )
+            .await?;
+        let row = client
+            .query_one(
+                &statement,
+                &[
+                    &write.slug,
+                    &write.title,
+                    &write.designation,
+                    &write.authentication,
+                ],
+            )
+            .await?;
+        Ok(row.get("uid"))
+    }
+
+    pub async fn update(
+        &self,
+        client: &impl GenericClient,
+        uid: i32,
+        write: &FlowWrite,
+    ) -> Result<bool, ApiError> {
+        let statement = client
+            .prepare_cached(
+                "update flows set slug = $2, title = $3, designation = $4, authentication = $5 where uid = $1",
+            )
+            .await?;
+        let updated = client
+            .execute(
+                &statement,
+                &[
+                    &uid,
+                    &write.slug,
+                    &write.title,
+                    &write.designation,
+                    &write.authentication,
+                ],
+            )
+            .await?;
+        Ok(updated > 0)
+    }
+
+    /// Deletes the flow together with its entries and bindings.
+    pub async fn delete(&self, client: &impl GenericClient, uid: i32) -> Result<bool, ApiError> {
+        let statement = client
+            .prepare_cached("delete from flows where uid = $1")
+            .await?;
+        Ok(client.execute(&statement, &[&uid]).await? > 0)
+    }
+
+    pub async fn create_entry(
+        &self,
+        client: &impl GenericClient,
+        flow: i32,
+        write: &EntryWrite,
+    ) -> Result<i32, ApiError> {
+        let statement = client
+            .prepare_cached(
+                "insert into flow_entries(flow, stage, ordering) values ($1, $2, $3) returning uid",
+            )
+            .await?;
+        let row = client
+            .query_one(&statement, &[&flow, &write.stage, &write.ordering])
+            .await?;
+        Ok(row.get("uid"))
+    }
+
+    /// Returns false if the flow has no entry with this uid.
+    pub async fn update_entry(
+        &self,
+        client: &impl GenericClient,
+        flow: i32,
+        entry: i32,
+        write: &EntryWrite,
+    ) -> Result<bool, ApiError> {
+        let statement = client
+            .prepare_cached(
+                "update flow_entries set stage = $3, ordering = $4 where uid = $2 and flow = $1",
+            )
+            .await?;
+        let updated = client
+            .execute(&statement, &[&flow, &entry, &write.stage, &write.ordering])
+            .await?;
+        Ok(updated > 0)
+    }
+
+    /// Returns false if the flow has no entry with this uid.
+    pub async fn delete_entry(
+        &self,
+        client: &impl GenericClient,
+        flow: i32,
+        entry: i32,
+    ) -> Result<bool, ApiError> {
+        let statement = client
+            .prepare_cached("delete from flow_entries where uid = $2 and flow = $1")
+            .await?;
+        Ok(client.execute(&statement, &[&flow, &entry]).await? > 0)
+    }
+
+    /// Orders the entries of the flow like `entries`, which must contain every entry exactly once.
+    /// Returns false if it does not.
+    pub async fn reorder_entries(
+        &self,
+        client: &impl GenericClient,
+        flow: i32,
+        entries: &[i32],
+    ) -> Result<bool, ApiError> {
+        let statement = client
+            .prepare_cached("select uid from flow_entries where flow = $1 for update")
+            .await?;
+        let mut existing: Vec<i32> = client
+            .query(&statement, &[&flow])
+            .await?
+            .into_iter()
+            .map(|row| row.get("uid"))
+            .collect();
+        let mut requested = entries.to_vec();
+        existing.sort_unstable();
+        requested.sort_unstable();
+        if existing != requested {
+            return Ok(false);
+        }
+        let statement = client
+            .prepare_cached("update flow_entries set ordering = $2 where uid = $1")
+            .await?;
+        for (index, entry) in entries.iter().enumerate() {
+            let ordering = ((index + 1) * 10) as i16;
+            client.execute(&statement, &[entry, &ordering]).await?;
+        }
+        Ok(true)
+    }
+
+    pub async fn entry_exists(
+        &self,
+        client: &impl GenericClient,
+        flow: i32,
+        entry: i32,
+    ) -> Result<bool, ApiError> {
+        let statement = client
+            .prepare_cached(
+                "select exists(select 1 from flow_entries where uid = $2 and flow = $1)",
+            )
+            .await?;
+        Ok(client.query_one(&statement, &[&flow, &entry]).await?.get(0))
+    }
+
+    pub async fn create_binding(
+        &self,
+        client: &impl GenericClient,
+        owner: BindingOwner,
+        write: &BindingWrite,
+    ) -> Result<i32, ApiError> {
+        let (flow, entry) = owner.columns();
+        let (policy, group, user) = write.kind.columns();
+        let statement = client
+            .prepare_cached(
+                "insert into flow_bindings(flow, entry, policy, group_binding, user_binding, ordering, enabled, negate) values ($1, $2, $3, $4, $5, $6, $7, $8) returning uid",
+            )
+            .await?;
+        let row = client
+            .query_one(
+                &statement,
+                &[
+                    &flow,
+                    &entry,
+                    &policy,
+                    &group,
+                    &user,
+                    &write.order,
+                    &write.enabled,
+                    &write.negate,
+                ],
+            )
+            .await?;
+        Ok(row.get("uid"))
+    }
+
+    /// Returns false if the owner has no binding with this uid.
+    pub async fn delete_binding(
+        &self,
+        client: &impl GenericClient,
+        owner: BindingOwner,
+        binding: i32,
+    ) -> Result<bool, ApiError> {
+        let (flow, entry) = owner.columns();
+        let statement = client
+            .prepare_cached(
+                "delete from flow_bindings where uid = $1 and flow is not distinct from $2 and entry is not distinct from $3",
+            )
+            .await?;
+        let deleted = client
+            .execute(&statement, &[&binding, &flow, &entry])
+            .await?;
+        Ok(deleted > 0)
+    }
+}
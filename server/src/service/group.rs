@@ -0,0 +1,162 @@
+use deadpool_postgres::GenericClient;
+use model::{user::PartialUser, Group};
+use serde_json::Value;
+use tokio_postgres::Row;
+use uuid::Uuid;
+
+use crate::api::ApiError;
+
+#[derive(Clone)]
+pub struct GroupService {}
+
+impl GroupService {
+    pub fn new() -> Self {
+        Self {}
+    }
+}
+
+fn from_row(row: &Row) -> Group {
+    Group {
+        uid: row.get("uid"),
+        name: row.get("name"),
+        parent: row.get("parent"),
+        attributes: row.get("attributes"),
+    }
+}
+
+impl GroupService {
+    pub async fn list(&self, client: &impl GenericClient) -> Result<Vec<Group>, ApiError> {
+        let statement = client
+            .prepare_cached("select * from groups order by name")
+            .await?;
+        let rows = client.query(&statement, &[]).await?;
+        Ok(rows.iter().map(from_row).collect())
+    }
+
+    pub async fn get(
+        &self,
+        client: &impl GenericClient,
+        uid: Uuid,
+    ) -> Result<Option<Group>, ApiError> {
+        let statement = client
+            .prepare_cached("select * from groups where uid = $1")
+            .await?;
+        let row = client.query_opt(&statement, &[&uid]).await?;
+        Ok(row.as_ref().map(from_row))
+    }
+
+    pub async fn create(
+        &self,
+        client: &impl GenericClient,
+        name: &str,
+        parent: Option<Uuid>,
+        attributes: &Value,
+    ) -> Result<Group, ApiError> {
+        let statement = client
+            .prepare_cached(
+                "insert into groups(name, parent, attributes) values ($1, $2, $3) returning *",
+            )
+            .await?;
+        let row = client
+            .query_one(&statement, &[&name, &parent, &attributes])
+            .await?;
+        Ok(from_row(&row))
+    }
+
+    pub async fn update(
+        &self,
+        client: &impl GenericClient,
+        uid: Uuid,
+        name: &str,
+        parent: Option<Uuid>,
+        attributes: &Value,
+    ) -> Result<Option<Group>, ApiError> {
+        let statement = client
+            .prepare_cached(
+                "update groups set name = $2, parent = $3, attributes = $4 where uid = $1 returning *",
+            )
+            .await?;
+        let row = client
+            .query_opt(&statement, &[&uid, &name, &parent, &attributes])
+            .await?;
+        Ok(row.as_ref().map(from_row))
+    }
+
+    pub async fn delete(&self, client: &impl GenericClient, uid: Uuid) -> Result<bool, ApiError> {
+        let statement = client
+            .prepare_cached("delete from groups where uid = $1")
+            .await?;
+        Ok(client.execute(&statement, &[&uid]).await? > 0)
+    }
+
+    /// Checks whether `group` is `parent` or one of its ancestors.
+    /// Such a parent would create a cycle in the group hierarchy.
+    pub async fn is_ancestor(
+        &self,
+        client: &impl GenericClient,
+        group: Uuid,
+        parent: Uuid,
+    ) -> Result<bool, ApiError> {
+        let statement = client
+            .prepare_cached(
+                "with recursive ancestors(uid) as (select $2::uuid union select g.parent from groups g join ancestors a on g.uid = a.uid where g.parent is not null) select exists(select 1 from ancestors where uid = $1)",
+            )
+            .await?;
+        Ok(client
+            .query_one(&statement, &[&group, &parent])
+            .await?
+            .get(0))
+    }
+
+    /// Lists the direct members of the group.
+    pub async fn members(
+        &self,
+        client: &impl GenericClient,
+        uid: Uuid,
+    ) -> Result<Vec<PartialUser>, ApiError> {
+        let statement = client
+            .prepare_cached(
+                "select u.uid,u.name,u.administrator,u.password_change_date from users u join group_memberships m on m.user_id = u.uid where m.group_id = $1 order by u.name",
+            )
+            .await?;
+        let rows = client.query(&statement, &[&uid]).await?;
+        Ok(rows
+            .into_iter()
+            .map(|row| PartialUser {
+                uid: row.get("uid"),
+                name: row.get("name"),
+                avatar_url: None,
+                is_admin: row.get("administrator"),
+                password_change_date: row.get("password_change_date"),
+                groups: Vec::new(),
+            })
+            .collect())
+    }
+
+    pub async fn add_member(
+        &self,
+        client: &impl GenericClient,
+        group: Uuid,
+        user: Uuid,
+    ) -> Result<(), ApiError> {
+        let statement = client
+            .prepare_cached(
+                "insert into group_memberships(group_id, user_id) values ($1, $2) on conflict do nothing",
+            )
+            .await?;
+        client.execute(&statement, &[&group, &user]).await?;
+        Ok(())
+    }
+
+    pub async fn remove_member(
+        &self,
+        client: &impl GenericClient,
+        group: Uuid,
+        user: Uuid,
+    ) -> Result<bool, ApiError> {
+        let statement = client
+            .prepare_cached("delete from group_memberships where group_id = $1 and user_id = $2")
+            .await?;
+        Ok(client.execute(&statement, &[&group, &user]).await? > 0)
+    }
+}
@@ -0,0 +1,272 @@
+use std::{collections::HashMap, sync::Arc, time::Instant};
+
+use deadpool_postgres::Pool;
+use jsonwebtoken::{Algorithm, DecodingKey, EncodingKey, Header, TokenData, Validation};
+use model::{SigningAlgorithm, SigningKey};
+use parking_lot::RwLock;
+use serde::{de::DeserializeOwned, Serialize};
+use serde_json::{json, Value};
+use storage::{key::KeyStore, StorageError};
+use time::{Duration, OffsetDateTime};
+
+use crate::{
+    api::{ApiError, ApiErrorKind},
+    config::KeyConfiguration,
+};
+
+/// Unknown key ids only trigger a reload if the keys are older than this,
+/// so tokens with made up key ids can't flood the database
+const MIN_RELOAD_INTERVAL: std::time::Duration = std::time::Duration::from_secs(30);
+
+/// Signs and verifies session cookies and the tokens handed out to applications
+/// with the keys stored in the database.
+#[derive(Clone)]
+pub struct KeyService(Arc<KeyServiceInternal>);
+
+struct KeyServiceInternal {
+    store: KeyStore,
+    config: KeyConfiguration,
+    keys: RwLock<LoadedKeys>,
+}
+
+struct ActiveKey {
+    kid: String,
+    algorithm: Algorithm,
+    key: EncodingKey,
+    activates: OffsetDateTime,
+    retires: Option<OffsetDateTime>,
+}
+
+impl ActiveKey {
+    fn signs_at(&self, time: OffsetDateTime) -> bool {
+        self.activates <= time && self.retires.map_or(true, |retires| retires > time)
+    }
+}
+
+struct LoadedKeys {
+    /// Keys which sign now or in the future, the most recently activated first.
+    /// The key to sign with is picked when signing, so keys published ahead of time
+    /// are used as soon as they activate.
+    signing: Vec<ActiveKey>,
+    verifying: HashMap<String, (Algorithm, DecodingKey)>,
+    jwks: Value,
+    loaded: Instant,
+}
+
+fn jwt_algorithm(algorithm: SigningAlgorithm) -> Algorithm {
+    match algorithm {
+        SigningAlgorithm::Rs256 => Algorithm::RS256,
+        SigningAlgorithm::Es256 => Algorithm::ES256,
+        SigningAlgorithm::EdDsa => Algorithm::EdDSA,
+    }
+}
+
+fn encoding_key(key: &SigningKey) -> Result<EncodingKey, jsonwebtoken::errors::Error> {
+    let pem = key.private_key.as_bytes();
+    match key.algorithm {
+        SigningAlgorithm::Rs256 => EncodingKey::from_rsa_pem(pem),
+        SigningAlgorithm::Es256 => EncodingKey::from_ec_pem(pem),
+        SigningAlgorithm::EdDsa => EncodingKey::from_ed_pem(pem),
+    }
+}
+
+fn decoding_key(key: &SigningKey) -> Result<DecodingKey, jsonwebtoken::errors::Error> {
+    let component = |name: &str| key.jwk[name].as_str().unwrap_or_default();
+    match key.algorithm {
+        SigningAlgorithm::Rs256 => DecodingKey::from_rsa_components(component("n"), component("e")),
+        SigningAlgorithm::Es256 => DecodingKey::from_ec_components(component("x"), component("y")),
+        SigningAlgorithm::EdDsa => DecodingKey::from_ed_components(component("x")),
+    }
+}
+
+impl LoadedKeys {
+    /// Expects the keys to be ordered by activation, the most recent first.
+    fn new(keys: &[SigningKey]) -> Self {
+        let now = OffsetDateTime::now_utc();
+        let mut signing = Vec::new();
+        let mut verifying = HashMap::new();
+        let mut jwks = Vec::new();
+        for key in keys.iter().filter(|key| key.verifies_at(now)) {
+            let algorithm = jwt_algorithm(key.algorithm);
+            match decoding_key(key) {
+                Ok(decoding) => {
+                    verifying.insert(key.kid.clone(), (algorithm, decoding));
+                    jwks.push(key.jwk.clone());
+                }
+                Err(err) => {
+                    tracing::error!(kid = key.kid, "Failed to load public key {err}");
+                    continue;
+                }
+            }
+            if key.retires.map_or(true, |retires| retires > now) {
+                match encoding_key(key) {
+                    Ok(encoding) => signing.push(ActiveKey {
+                        kid: key.kid.clone(),
+                        algorithm,
+                        key: encoding,
+                        activates: key.activates,
+                        retires: key.retires,
+                    }),
+                    Err(err) => tracing::error!(kid = key.kid, "Failed to load private key {err}"),
+                }
+            }
+        }
+        Self {
+            signing,
+            verifying,
+            jwks: json!({ "keys": jwks }),
+            loaded: Instant::now(),
+        }
+    }
+
+    fn signing_key(&self, time: OffsetDateTime) -> Option<&ActiveKey> {
+        self.signing.iter().find(|key| key.signs_at(time))
+    }
+}
+
+impl KeyService {
+    /// Loads the stored keys and generates one if there is no key to sign with.
+    /// The private keys are stored encrypted with `secret`.
+    pub async fn new(
+        pool: Pool,
+        config: KeyConfiguration,
+        secret: &str,
+    ) -> Result<Self, StorageError> {
+        let service = Self(Arc::new(KeyServiceInternal {
+            store: KeyStore::new(pool, secret),
+            config,
+            keys: RwLock::new(LoadedKeys::new(&[])),
+        }));
+        service.rotate().await?;
+        Ok(service)
+    }
+
+    /// Publishes a new key if the current one has been used for longer than the rotation period
+    /// and reloads the keys afterwards, so keys created by other instances are picked up.
+    /// The new key only signs after `overlap`, by then every instance has loaded it.
+    pub async fn rotate(&self) -> Result<(), StorageError> {
+        let config = &self.0.config;
+        let rotated = self
+            .0
+            .store
+            .rotate(
+                config.algorithm,
+                Duration::seconds(config.rotation as i64),
+                Duration::seconds(config.overlap as i64),
+            )
+            .await?;
+        if let Some(key) = rotated {
+            tracing::info!(kid = key.kid, "Generated new signing key");
+        }
+        self.reload().await
+    }
+
+    pub async fn reload(&self) -> Result<(), StorageError> {
+        let keys = self.0.store.all().await?;
+        *self.0.keys.write() = LoadedKeys::new(&keys);
+        Ok(())
+    }
+
+    pub async fn purge_expired(&self) -> Result<u64, StorageError> {
+        self.0.store.purge_expired().await
+    }
+
+    pub fn sign<T: Serialize>(&self, claims: &T) -> Result<String, ApiError> {
+        let keys = self.0.keys.read();
+        let Some(signing) = keys.signing_key(OffsetDateTime::now_utc()) else {
+            return Err(ApiErrorKind::MiscInternal("No signing key available").into_api()) };
+        let mut header = Header::new(signing.algorithm);
+        header.kid = Some(signing.kid.clone());
+        Ok(jsonwebtoken::encode(&header, claims, &signing.key)?)
+    }
+
+    /// Verifies the token with the key referenced by its `kid` header.
+    /// The algorithm of `validation` is replaced with the one of the key.
+    /// Unknown keys are looked up once, they may have been generated by another instance.
+    pub async fn verify<T: DeserializeOwned>(
+        &self,
+        token: &str,
+        validation: &Validation,
+    ) -> Result<TokenData<T>, ApiError> {
+        let header = jsonwebtoken::decode_header(token)?;
+        let Some(kid) = header.kid else { return Err(ApiErrorKind::InvalidSessionCookie.into_api()) };
+        let reload = {
+            let keys = self.0.keys.read();
+            !keys.verifying.contains_key(&kid) && keys.loaded.elapsed() >= MIN_RELOAD_INTERVAL
+        };
+        if reload {
+            self.reload().await?;
+        }
+        let keys = self.0.keys.read();
+        let Some((algorithm, key)) = keys.verifying.get(&kid) else {
+            return Err(ApiErrorKind::InvalidSessionCookie.into_api()) };
+        let mut validation = validation.clone();
+        validation.algorithms = vec![*algorithm];
+        Ok(jsonwebtoken::decode(token, key, &validation)?)
+    }
+
+    /// Algorithms tokens are signed with, the configured one and those of the loaded keys
+    /// which sign now or in the future.
+    pub fn signing_algorithms(&self) -> Vec<Algorithm> {
+        let mut algorithms = vec![jwt_algorithm(self.0.config.algorithm)];
+        for key in self.0.keys.read().signing.iter() {
+            if !algorithms.contains(&key.algorithm) {
+                algorithms.push(key.algorithm);
+            }
+        }
+        algorithms
+    }
+
+    /// The public keys in the JWK Set format.
+    pub fn jwks(&self) -> Value {
+        self.0.keys.read().jwks.clone()
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use model::{SigningAlgorithm, SigningKey};
+    use storage::key::generate;
+    use time::{Duration, OffsetDateTime};
+
+    use super::LoadedKeys;
+
+    fn key(activates: OffsetDateTime, retires: Option<OffsetDateTime>) -> SigningKey {
+        let mut key = generate(SigningAlgorithm::Es256).unwrap();
+        key.activates = activates;
+        key.retires = retires;
+        key.expires = retires.map(|retires| retires + Duration::days(3));
+        key
+    }
+
+    #[test]
+    fn rotation_window() {
+        let now = OffsetDateTime::now_utc();
+        let rotation = now + Duration::days(1);
+        let next = key(rotation, None);
+        let current = key(now - Duration::days(30), Some(rotation));
+        let keys = LoadedKeys::new(&[next.clone(), current.clone()]);
+        let kid = |time| keys.signing_key(time).map(|key| key.kid.as_str());
+        assert_eq!(kid(now), Some(current.kid.as_str()));
+        assert_eq!(
+            kid(rotation - Duration::nanoseconds(1)),
+            Some(current.kid.as_str())
+        );
+        assert_eq!(kid(rotation), Some(next.kid.as_str()));
+        assert_eq!(kid(rotation + Duration::days(3)), Some(next.kid.as_str()));
+        assert_eq!(kid(now - Duration::days(31)), None);
+        // Both are published for verification
+        assert!(keys.verifying.contains_key(&current.kid));
+        assert!(keys.verifying.contains_key(&next.kid));
+        assert_eq!(keys.jwks["keys"].as_array().map(Vec::len), Some(2));
+    }
+
+    #[test]
+    fn retired_keys_only_verify() {
+        let now = OffsetDateTime::now_utc();
+        let retired = key(now - Duration::days(30), Some(now - Duration::days(1)));
+        let keys = LoadedKeys::new(&[retired.clone()]);
+        assert!(keys.signing_key(now).is_none());
+        assert!(keys.verifying.contains_key(&retired.kid));
+    }
+}
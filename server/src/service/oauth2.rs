@@ -0,0 +1,401 @@
+use argon2::{password_hash::Encoding, PasswordHash};
+use deadpool_postgres::GenericClient;
+use model::{OAuth2Client, OAuth2TokenKind};
+use rand::{
+    distributions::{Alphanumeric, DistString},
+    rngs::OsRng,
+};
+use sha2::{Digest, Sha256};
+use time::{Duration, OffsetDateTime};
+use tokio_postgres::Row;
+use uuid::Uuid;
+
+use crate::api::{ApiError, ApiErrorKind};
+
+// 10 Minutes
+const REQUEST_VALIDITY: Duration = Duration::minutes(10);
+// 1 Minute
+const CODE_VALIDITY: Duration = Duration::minutes(1);
+const TOKEN_LENGTH: usize = 64;
+
+/// An authorization request which waits for the authorization flow to be completed.
+#[derive(Debug, Clone)]
+pub struct AuthorizationRequest {
+    pub application: i32,
+    pub redirect_uri: String,
+    pub scopes: Vec<String>,
+    pub state: Option<String>,
+    pub nonce: Option<String>,
+    pub code_challenge: Option<String>,
+    pub code_challenge_method: Option<String>,
+    pub user: Option<Uuid>,
+    pub auth_time: Option<OffsetDateTime>,
+}
+
+#[derive(Debug, Clone)]
+pub struct AuthorizationCode {
+    pub user: Uuid,
+    pub redirect_uri: String,
+    pub scopes: Vec<String>,
+    pub nonce: Option<String>,
+    pub code_challenge: Option<String>,
+    pub code_challenge_method: Option<String>,
+    pub auth_time: OffsetDateTime,
+}
+
+/// What an issued token grants access to.
+#[derive(Debug, Clone)]
+pub struct TokenGrant {
+    pub application: i32,
+    pub user: Option<Uuid>,
+    pub scopes: Vec<String>,
+    pub auth_time: Option<OffsetDateTime>,
+}
+
+#[derive(Debug, Clone)]
+pub struct StoredToken {
+    pub kind: OAuth2TokenKind,
+    pub application: i32,
+    pub user: Option<Uuid>,
+    pub scopes: Vec<String>,
+    pub auth_time: Option<OffsetDateTime>,
+    pub created: OffsetDateTime,
+    pub expires: OffsetDateTime,
+}
+
+/// The user data which can be released through id tokens and the userinfo endpoint.
+#[derive(Debug, Clone)]
+pub struct UserClaims {
+    pub name: String,
+    pub display_name: Option<String>,
+    pub email: Option<String>,
+    pub email_verified: bool,
+}
+
+#[derive(Clone)]
+pub struct OAuth2Service {}
+
+impl OAuth2Service {
+    pub fn new() -> Self {
+        Self {}
+    }
+}
+
+/// Tokens and codes are only stored as sha256 hashes.
+pub fn hash_token(token: &str) -> String {
+    format!("{:x}", Sha256::digest(token.as_bytes()))
+}
+
+fn generate_token() -> String {
+    Alphanumeric.sample_string(&mut OsRng, TOKEN_LENGTH)
+}
+
+fn client_from_row(row: &Row) -> OAuth2Client {
+    OAuth2Client {
+        provider: row.get("uid"),
+        application: row.get("application"),
+        application_name: row.get("application_name"),
+        client_id: row.get("client_id"),
+        client_secret: row.get("client_secret"),
+        client_type: row.get("client_type"),
+        redirect_uris: row.get("redirect_uris"),
+        scopes: row.get("scopes"),
+        authorization_flow: row.get("authorization_flow"),
+        access_token_validity: row.get("access_token_validity"),
+        refresh_token_validity: row.get("refresh_token_validity"),
+    }
+}
+
+fn request_from_row(row: &Row) -> AuthorizationRequest {
+    AuthorizationRequest {
+        application: row.get("application"),
+        redirect_uri: row.get("redirect_uri"),
+        scopes: row.get("scopes"),
+        state: row.get("state"),
+        nonce: row.get("nonce"),
+        code_challenge: row.get("code_challenge"),
+        code_challenge_method: row.get("code_challenge_method"),
+        user: row.get("user_id"),
+        auth_time: row.get("auth_time"),
+    }
+}
+
+fn token_from_row(row: &Row) -> StoredToken {
+    StoredToken {
+        kind: row.get("kind"),
+        application: row.get("application"),
+        user: row.get("user_id"),
+        scopes: row.get("scopes"),
+        auth_time: row.get("auth_time"),
+        created: row.get("created"),
+        expires: row.get("expires"),
+    }
+}
+
+impl OAuth2Service {
+    pub async fn find_client(
+        &self,
+        client: &impl GenericClient,
+        client_id: &str,
+    ) -> Result<Option<OAuth2Client>, ApiError> {
+        let statement = client
+            .prepare_cached(
+                "select o.*, a.uid as application, a.display_name as application_name from oauth2_providers o inner join applications a on a.provider = o.uid where o.client_id = $1",
+            )
+            .await?;
+        let row = client.query_opt(&statement, &[&client_id]).await?;
+        Ok(row.as_ref().map(client_from_row))
+    }
+
+    /// Checks the secret sent by the client.
+    /// Public clients have no secret and must not send one.
+    pub fn verify_secret(&self, client: &OAuth2Client, secret: Option<&str>) -> bool {
+        match (&client.client_secret, secret) {
+            (None, None) => true,
+            (Some(hash), Some(secret)) => match PasswordHash::parse(hash, Encoding::B64) {
+                Ok(hash) => hash
+                    .verify_password(&[&argon2::Argon2::default()], secret)
+                    .is_ok(),
+                Err(err) => {
+                    tracing::error!(
+                        client_id = client.client_id,
+                        "Invalid client secret hash {err}"
+                    );
+                    false
+                }
+            },
+            _ => false,
+        }
+    }
+
+    pub async fn create_request(
+        &self,
+        client: &impl GenericClient,
+        session: &str,
+        request: &AuthorizationRequest,
+    ) -> Result<Uuid, ApiError> {
+        let statement = client
+            .prepare_cached(
+                "insert into oauth2_authorization_requests(session, application, redirect_uri, scopes, state, nonce, code_challenge, code_challenge_method, expires) values ($1, $2, $3, $4, $5, $6, $7, $8, $9) returning uid",
+            )
+            .await?;
+        let expires = OffsetDateTime::now_utc() + REQUEST_VALIDITY;
+        let row = client
+            .query_one(
+                &statement,
+                &[
+                    &session,
+                    &request.application,
+                    &request.redirect_uri,
+                    &request.scopes,
+                    &request.state,
+                    &request.nonce,
+                    &request.code_challenge,
+                    &request.code_challenge_method,
+                    &expires,
+                ],
+            )
+            .await?;
+        Ok(row.get(0))
+    }
+
+    /// Marks the request as authorized by `user`, called once the authorization flow completed.
+    pub async fn approve_request(
+        &self,
+        client: &impl GenericClient,
+        uid: Uuid,
+        session: &str,
+        user: Uuid,
+    ) -> Result<bool, ApiError> {
+        let statement = client
+            .prepare_cached(
+                "update oauth2_authorization_requests set user_id = $1, auth_time = now() where uid = $2 and session = $3 and expires > now()",
+            )
+            .await?;
+        Ok(client.execute(&statement, &[&user, &uid, &session]).await? > 0)
+    }
+
+    /// Removes an approved request, so every request results in at most one code.
+    pub async fn take_approved_request(
+        &self,
+        client: &impl GenericClient,
+        uid: Uuid,
+        session: &str,
+    ) -> Result<Option<AuthorizationRequest>, ApiError> {
+        let statement = client
+            .prepare_cached(
+                "delete from oauth2_authorization_requests where uid = $1 and session = $2 and user_id is not null and expires > now() returning *",
+            )
+            .await?;
+        let row = client.query_opt(&statement, &[&uid, &session]).await?;
+        Ok(row.as_ref().map(request_from_row))
+    }
+
+    /// Issues an authorization code for an approved request and returns it.
+    pub async fn create_code(
+        &self,
+        client: &impl GenericClient,
+        request: &AuthorizationRequest,
+    ) -> Result<String, ApiError> {
+        let (Some(user), Some(auth_time)) = (request.user, request.auth_time) else {
+            return Err(ApiErrorKind::MiscInternal("Authorization request has not been approved").into_api()) };
+        let code = generate_token();
+        let statement = client
+            .prepare_cached(
+                "insert into oauth2_authorization_codes(code_hash, application, user_id, redirect_uri, scopes, nonce, code_challenge, code_challenge_method, auth_time, expires) values ($1, $2, $3, $4, $5, $6, $7, $8, $9, $10)",
+            )
+            .await?;
+        let expires = OffsetDateTime::now_utc() + CODE_VALIDITY;
+        client
+            .execute(
+                &statement,
+                &[
+                    &hash_token(&code),
+                    &request.application,
+                    &user,
+                    &request.redirect_uri,
+                    &request.scopes,
+                    &request.nonce,
+                    &request.code_challenge,
+                    &request.code_challenge_method,
+                    &auth_time,
+                    &expires,
+                ],
+            )
+            .await?;
+        Ok(code)
+    }
+
+    /// Redeems an authorization code. Codes can only be used once.
+    pub async fn take_code(
+        &self,
+        client: &impl GenericClient,
+        code: &str,
+        application: i32,
+    ) -> Result<Option<AuthorizationCode>, ApiError> {
+        let statement = client
+            .prepare_cached(
+                "delete from oauth2_authorization_codes where code_hash = $1 and application = $2 and expires > now() returning *",
+            )
+            .await?;
+        let row = client
+            .query_opt(&statement, &[&hash_token(code), &application])
+            .await?;
+        Ok(row.map(|row| AuthorizationCode {
+            user: row.get("user_id"),
+            redirect_uri: row.get("redirect_uri"),
+            scopes: row.get("scopes"),
+            nonce: row.get("nonce"),
+            code_challenge: row.get("code_challenge"),
+            code_challenge_method: row.get("code_challenge_method"),
+            auth_time: row.get("auth_time"),
+        }))
+    }
+
+    /// Stores a new token and returns it together with its expiry.
+    pub async fn issue_token(
+        &self,
+        client: &impl GenericClient,
+        kind: OAuth2TokenKind,
+        grant: &TokenGrant,
+        validity: i32,
+    ) -> Result<(String, OffsetDateTime), ApiError> {
+        let token = generate_token();
+        let expires = OffsetDateTime::now_utc() + Duration::seconds(validity as i64);
+        let statement = client
+            .prepare_cached(
+                "insert into oauth2_tokens(token_hash, kind, application, user_id, scopes, auth_time, expires) values ($1, $2, $3, $4, $5, $6, $7)",
+            )
+            .await?;
+        client
+            .execute(
+                &statement,
+                &[
+                    &hash_token(&token),
+                    &kind,
+                    &grant.application,
+                    &grant.user,
+                    &grant.scopes,
+                    &grant.auth_time,
+                    &expires,
+                ],
+            )
+            .await?;
+        Ok((token, expires))
+    }
+
+    pub async fn find_token(
+        &self,
+        client: &impl GenericClient,
+        token: &str,
+    ) -> Result<Option<StoredToken>, ApiError> {
+        let statement = client
+            .prepare_cached("select * from oauth2_tokens where token_hash = $1 and expires > now()")
+            .await?;
+        let row = client.query_opt(&statement, &[&hash_token(token)]).await?;
+        Ok(row.as_ref().map(token_from_row))
+    }
+
+    /// Redeems a refresh token, refresh tokens are rotated on every use.
+    pub async fn take_refresh_token(
+        &self,
+        client: &impl GenericClient,
+        token: &str,
+        application: i32,
+    ) -> Result<Option<StoredToken>, ApiError> {
+        let statement = client
+            .prepare_cached(
+                "delete from oauth2_tokens where token_hash = $1 and kind = 'refresh' and application = $2 and expires > now() returning *",
+            )
+            .await?;
+        let row = client
+            .query_opt(&statement, &[&hash_token(token), &application])
+            .await?;
+        Ok(row.as_ref().map(token_from_row))
+    }
+
+    pub async fn revoke_token(
+        &self,
+        client: &impl GenericClient,
+        token: &str,
+        application: i32,
+    ) -> Result<bool, ApiError> {
+        let statement = client
+            .prepare_cached("delete from oauth2_tokens where token_hash = $1 and application = $2")
+            .await?;
+        Ok(client
+            .execute(&statement, &[&hash_token(token), &application])
+            .await?
+            > 0)
+    }
+
+    pub async fn purge_expired(&self, client: &impl GenericClient) -> Result<u64, ApiError> {
+        let mut purged = 0;
+        for query in [
+            "delete from oauth2_tokens where expires <= now()",
+            "delete from oauth2_authorization_codes where expires <= now()",
+            "delete from oauth2_authorization_requests where expires <= now()",
+        ] {
+            let statement = client.prepare_cached(query).await?;
+            purged += client.execute(&statement, &[]).await?;
+        }
+        Ok(purged)
+    }
+
+    pub async fn user_claims(
+        &self,
+        client: &impl GenericClient,
+        user: Uuid,
+    ) -> Result<Option<UserClaims>, ApiError> {
+        let statement = client
+            .prepare_cached("select name, display_name, email, email_verified from users where uid = $1")
+            .await?;
+        let Some(row) = client.query_opt(&statement, &[&user]).await? else { return Ok(None) };
+        Ok(Some(UserClaims {
+            name: row.get("name"),
+            display_name: row.get("display_name"),
+            email: row.get("email"),
+            email_verified: row.get("email_verified"),
+        }))
+    }
+}
@@ -1,3 +1,4 @@
+pub mod evaluate;
 mod service;
 
 use http::Uri;
@@ -0,0 +1,302 @@
+use http::Uri;
+use model::{user::PartialUser, PartialGroup, PendingUser, Policy, PolicyKind, PolicyResult};
+use policy_engine::{compile, execute, rhai::Position, uri::Scheme, LogEntry};
+use serde::{Deserialize, Serialize};
+use storage::breached::BreachedFilter;
+use time::{Duration, OffsetDateTime};
+use uuid::Uuid;
+
+use super::{create_scope, DUMMY_SCOPE};
+use crate::{
+    api::ExecutorQuery,
+    executor::{
+        flow::{CheckContextData, CheckContextRequest},
+        password,
+    },
+};
+
+/// A synthetic context to evaluate a policy against outside of a flow execution.
+#[derive(Debug, Deserialize)]
+pub struct TestContext {
+    #[serde(default)]
+    pub user: Option<TestUser>,
+    #[serde(default)]
+    pub pending_user: Option<TestPendingUser>,
+    /// Absolute or relative uri of the request
+    #[serde(default = "uri_default")]
+    pub uri: String,
+    /// Host of the request, defaults to the host of the uri
+    #[serde(default)]
+    pub host: Option<String>,
+    /// Password checked by password strength and breached password policies
+    #[serde(default)]
+    pub password: Option<String>,
+}
+
+fn uri_default() -> String {
+    "/".to_owned()
+}
+
+#[derive(Debug, Deserialize)]
+pub struct TestUser {
+    #[serde(default)]
+    pub uid: Uuid,
+    pub name: String,
+    #[serde(default)]
+    pub avatar_url: Option<String>,
+    #[serde(default)]
+    pub is_admin: bool,
+    #[serde(default, with = "time::serde::rfc3339::option")]
+    pub password_change_date: Option<OffsetDateTime>,
+    #[serde(default)]
+    pub groups: Vec<PartialGroup>,
+}
+
+#[derive(Debug, Deserialize)]
+pub struct TestPendingUser {
+    #[serde(default)]
+    pub uid: Uuid,
+    pub name: String,
+    #[serde(default)]
+    pub avatar_url: Option<String>,
+    #[serde(default)]
+    pub authenticated: bool,
+}
+
+impl TestContext {
+    /// Returns a message if the uri is invalid.
+    pub fn check_context(&self) -> Result<CheckContextData, &'static str> {
+        let uri: Uri = self.uri.parse().map_err(|_| "Invalid uri")?;
+        let scheme = match uri.scheme() {
+            Some(scheme) => Scheme::try_from(scheme).map_err(|_| "Unsupported scheme")?,
+            None => Scheme::Https,
+        };
+        let host = self
+            .host
+            .clone()
+            .or_else(|| uri.host().map(ToOwned::to_owned))
+            .unwrap_or_else(|| "localhost".to_owned());
+        let user = self.user.as_ref().map(|user| PartialUser {
+            uid: user.uid,
+            name: user.name.clone(),
+            avatar_url: user.avatar_url.clone(),
+            is_admin: user.is_admin,
+            password_change_date: user
+                .password_change_date
+                .unwrap_or_else(OffsetDateTime::now_utc),
+            groups: user.groups.clone(),
+        });
+        let pending_user = self.pending_user.as_ref().map(|user| PendingUser {
+            uid: user.uid,
+            name: user.name.clone(),
+            avatar_url: user.avatar_url.clone(),
+            authenticated: user.authenticated,
+            is_admin: false,
+        });
+        Ok(CheckContextData {
+            request: CheckContextRequest {
+                uri,
+                host,
+                scheme,
+                query: ExecutorQuery::default(),
+                user,
+            },
+            pending_user,
+        })
+    }
+}
+
+#[derive(Debug, Serialize)]
+pub struct PolicyEvaluation {
+    pub result: PolicyResult,
+    pub output: Vec<EvaluationLogEntry>,
+    pub error: Option<EvaluationError>,
+}
+
+#[derive(Debug, Serialize)]
+#[serde(tag = "kind", rename_all = "snake_case")]
+pub enum EvaluationLogEntry {
+    Print {
+        text: String,
+    },
+    Debug {
+        text: String,
+        source: Option<String>,
+        line: Option<usize>,
+        position: Option<usize>,
+    },
+}
+
+impl From<LogEntry> for EvaluationLogEntry {
+    fn from(value: LogEntry) -> Self {
+        match value {
+            LogEntry::Text(text) => Self::Print { text },
+            LogEntry::Debug(entry) => Self::Debug {
+                text: entry.text,
+                source: entry.source,
+                line: entry.position.line(),
+                position: entry.position.position(),
+            },
+        }
+    }
+}
+
+/// An error compiling or running an expression, the position refers to the decoded expression.
+#[derive(Debug, Serialize)]
+pub struct EvaluationError {
+    pub message: String,
+    pub line: Option<usize>,
+    pub position: Option<usize>,
+}
+
+impl EvaluationError {
+    fn new(message: impl ToString, position: Position) -> Self {
+        Self {
+            message: message.to_string(),
+            line: position.line(),
+            position: position.position(),
+        }
+    }
+}
+
+impl PolicyEvaluation {
+    fn result(result: PolicyResult) -> Self {
+        Self {
+            result,
+            output: Vec::new(),
+            error: None,
+        }
+    }
+}
+
+/// Evaluates the policy the same way a flow execution would.
+/// Expressions failing at runtime fail the check, like they do in flows.
+pub fn evaluate(
+    policy: &Policy,
+    context: &CheckContextData,
+    password: Option<&str>,
+    breached_passwords: Option<&BreachedFilter>,
+) -> PolicyEvaluation {
+    match &policy.kind {
+        PolicyKind::PasswordExpiry { max_age } => {
+            let max_age = Duration::seconds(*max_age as i64);
+            let result = match &context.request.user {
+                Some(user) => {
+                    (OffsetDateTime::now_utc() - user.password_change_date < max_age).into()
+                }
+                None => PolicyResult::NotApplicable,
+            };
+            PolicyEvaluation::result(result)
+        }
+        PolicyKind::PasswordStrength(rules) => {
+            let Some(password) = password else {
+                return PolicyEvaluation::result(PolicyResult::NotApplicable) };
+            let names: Vec<&str> = context
+                .request
+                .user
+                .iter()
+                .map(|user| user.name.as_str())
+                .chain(context.pending_user.iter().map(|user| user.name.as_str()))
+                .collect();
+            let messages = password::check_password(rules, password, &names);
+            PolicyEvaluation {
+                result: messages.is_empty().into(),
+                output: messages
+                    .into_iter()
+                    .map(|text| EvaluationLogEntry::Print { text })
+                    .collect(),
+                error: None,
+            }
+        }
+        PolicyKind::BreachedPassword { .. } => match (breached_passwords, password) {
+            (Some(filter), Some(password)) => {
+                PolicyEvaluation::result((!filter.contains_password(password)).into())
+            }
+            (Some(_), None) => PolicyEvaluation::result(PolicyResult::NotApplicable),
+            (None, _) => PolicyEvaluation {
+                result: PolicyResult::NotApplicable,
+                output: vec![EvaluationLogEntry::Print {
+                    text: "No breached password filter has been configured".to_owned(),
+                }],
+                error: None,
+            },
+        },
+        PolicyKind::Expression { expression } => {
+            let ast = match compile(expression, &DUMMY_SCOPE) {
+                Ok(ast) => ast,
+                Err(policy_engine::ExpressionCompilationError::Parse(err)) => {
+                    let position = err.position();
+                    return PolicyEvaluation {
+                        result: false.into(),
+                        output: Vec::new(),
+                        error: Some(EvaluationError::new(err, position)),
+                    };
+                }
+                Err(err) => {
+                    return PolicyEvaluation {
+                        result: false.into(),
+                        output: Vec::new(),
+                        error: Some(EvaluationError::new(err, Position::NONE)),
+                    }
+                }
+            };
+            let result = execute(&ast, || create_scope(context));
+            let output = result.output.into_iter().map(Into::into).collect();
+            match result.result {
+                Ok(passed) => PolicyEvaluation {
+                    result: passed.into(),
+                    output,
+                    error: None,
+                },
+                Err(err) => PolicyEvaluation {
+                    result: false.into(),
+                    output,
+                    error: Some(EvaluationError::new(&err, err.position())),
+                },
+            }
+        }
+    }
+}
+
+#[cfg(test)]
+mod test {
+    use model::{Policy, PolicyKind, PolicyResult};
+    use policy_engine::encode_base64;
+
+    use super::{evaluate, EvaluationLogEntry, TestContext};
+
+    fn expression(expression: &str) -> Policy {
+        Policy {
+            uid: 1,
+            slug: "test".to_owned(),
+            kind: PolicyKind::Expression {
+                expression: encode_base64(expression),
+            },
+        }
+    }
+
+    fn context() -> TestContext {
+        serde_json::from_str(r#"{ "uri": "https://example.com/flow" }"#).unwrap()
+    }
+
+    #[test]
+    fn test_output() {
+        let context = context().check_context().unwrap();
+        let evaluation = evaluate(&expression("print(\"hi\"); true"), &context, None, None);
+        assert!(matches!(evaluation.result, PolicyResult::Value(true)));
+        assert!(evaluation.error.is_none());
+        assert!(matches!(
+            evaluation.output.as_slice(),
+            [EvaluationLogEntry::Print { text }] if text == "hi"
+        ));
+    }
+
+    #[test]
+    fn test_error_position() {
+        let context = context().check_context().unwrap();
+        let evaluation = evaluate(&expression("true &&\n  missing"), &context, None, None);
+        assert!(matches!(evaluation.result, PolicyResult::Value(false)));
+        let error = evaluation.error.expect("Expected an error");
+        assert_eq!(error.line, Some(2));
+    }
+}
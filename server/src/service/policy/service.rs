@@ -5,6 +5,7 @@ use model::{PartialPolicy, Policy, PolicyQuery};
 use moka::sync::Cache;
 use policy_engine::{compile, rhai::AST};
 use storage::{
+    breached::BreachedFilter,
     datacache::{DataRef, DataStorage, LookupRef},
     StorageManager,
 };
@@ -16,14 +17,24 @@ use super::DUMMY_SCOPE;
 pub struct PolicyService(Arc<InternalPolicyService>);
 
 impl PolicyService {
-    pub fn new(storage: StorageManager, pool: Pool) -> Self {
+    pub fn new(
+        storage: StorageManager,
+        pool: Pool,
+        breached_passwords: Option<BreachedFilter>,
+    ) -> Self {
         Self(Arc::new(InternalPolicyService {
             storage,
             pool,
             asts: Cache::builder().build(),
+            breached_passwords,
         }))
     }
 
+    /// The filter breached password policies check against, if one has been configured.
+    pub fn breached_passwords(&self) -> Option<&BreachedFilter> {
+        self.0.breached_passwords.as_ref()
+    }
+
     pub async fn get_ast(&self, policy: DataRef<Policy>) -> Option<Arc<AST>> {
         self.0.get_ast(policy).await
     }
@@ -44,6 +55,7 @@ struct InternalPolicyService {
     storage: StorageManager,
     pool: Pool,
     asts: Cache<i32, Option<Arc<AST>>>,
+    breached_passwords: Option<BreachedFilter>,
 }
 
 impl InternalPolicyService {
@@ -51,8 +63,8 @@ impl InternalPolicyService {
         let Some(policy) = self.storage.lookup(&policy).await else { return None};
         self.asts
             .optionally_get_with(policy.uid, move || match &policy.kind {
-                model::PolicyKind::Expression(expr) => {
-                    let compiled = compile(&expr, &DUMMY_SCOPE);
+                model::PolicyKind::Expression { expression } => {
+                    let compiled = compile(expression, &DUMMY_SCOPE);
                     Some(match compiled {
                         Ok(ast) => Some(Arc::new(ast)),
                         Err(err) => {
@@ -97,11 +109,14 @@ impl InternalPolicyService {
 
     pub async fn invalidate(&self, policy: i32) {
         self.asts.invalidate(&policy);
-        self.storage
+        let result = self
+            .storage
             .get_for_data::<Policy>()
             .expect("Failed to get Policy storage")
             .invalidate(&PolicyQuery::uid(policy))
-            .await
-            .expect("Invalidation of policy failed");
+            .await;
+        if let Err(err) = result {
+            tracing::warn!("Failed to invalidate policy {policy} {err}");
+        }
     }
 }
@@ -0,0 +1,128 @@
+use deadpool_postgres::GenericClient;
+use model::PromptKind;
+use serde::Deserialize;
+
+use crate::api::ApiError;
+
+#[derive(Debug, Deserialize)]
+pub struct PromptWrite {
+    pub slug: String,
+    pub field_key: String,
+    pub label: String,
+    pub kind: PromptKind,
+    #[serde(default)]
+    pub placeholder: Option<String>,
+    #[serde(default)]
+    pub required: bool,
+    #[serde(default)]
+    pub help_text: Option<String>,
+}
+
+/// Writes prompts to the database, reads go through the `PromptStorage`,
+/// so its cache entry has to be invalidated after every change.
+#[derive(Clone)]
+pub struct PromptService {}
+
+impl PromptService {
+    pub fn new() -> Self {
+        Self {}
+    }
+}
+
+impl PromptService {
+    pub async fn list_uids(&self, client: &impl GenericClient) -> Result<Vec<i32>, ApiError> {
+        let statement = client
+            .prepare_cached("select uid from prompts order by uid")
+            .await?;
+        let rows = client.query(&statement, &[]).await?;
+        Ok(rows.into_iter().map(|row| row.get("uid")).collect())
+    }
+
+    pub async fn uid_by_slug(
+        &self,
+        client: &impl GenericClient,
+        slug: &str,
+    ) -> Result<Option<i32>, ApiError> {
+        let statement = client
+            .prepare_cached("select uid from prompts where slug = $1")
+            .await?;
+        let row = client.query_opt(&statement, &[&slug]).await?;
+        Ok(row.map(|row| row.get("uid")))
+    }
+
+    pub async fn create(
+        &self,
+        client: &impl GenericClient,
+        write: &PromptWrite,
+    ) -> Result<i32, ApiError> {
+        let statement = client
+            .prepare_cached(
+                "insert into prompts(slug, field_key, label, kind, placeholder, required, help_text) values ($1, $2, $3, $4, $5, $6, $7) returning uid",
+            )
+            .await?;
+        let row = client
+            .query_one(
+                &statement,
+                &[
+                    &write.slug,
+                    &write.field_key,
+                    &write.label,
+                    &write.kind,
+                    &write.placeholder,
+                    &write.required,
+                    &write.help_text,
+                ],
+            )
+            .await?;
+        Ok(row.get("uid"))
+    }
+
+    /// Returns false if there is no prompt with this uid.
+    pub async fn update(
+        &self,
+        client: &impl GenericClient,
+        uid: i32,
+        write: &PromptWrite,
+    ) -> Result<bool, ApiError> {
+        let statement = client
+            .prepare_cached(
+                "update prompts set slug = $2, field_key = $3, label = $4, kind = $5, placeholder = $6, required = $7, help_text = $8 where uid = $1",
+            )
+            .await?;
+        let updated = client
+            .execute(
+                &statement,
+                &[
+                    &uid,
+                    &write.slug,
+                    &write.field_key,
+                    &write.label,
+                    &write.kind,
+                    &write.placeholder,
+                    &write.required,
+                    &write.help_text,
+                ],
+            )
+            .await?;
+        Ok(updated > 0)
+    }
+
+    pub async fn used_by_stage(
+        &self,
+        client: &impl GenericClient,
+        uid: i32,
+    ) -> Result<bool, ApiError> {
+        let statement = client
+            .prepare_cached("select exists(select 1 from stage_prompt_bindings where prompt = $1)")
+            .await?;
+        Ok(client.query_one(&statement, &[&uid]).await?.get(0))
+    }
+
+    /// Returns false if there is no prompt with this uid.
+    pub async fn delete(&self, client: &impl GenericClient, uid: i32) -> Result<bool, ApiError> {
+        let statement = client
+            .prepare_cached("delete from prompts where uid = $1")
+            .await?;
+        Ok(client.execute(&statement, &[&uid]).await? > 0)
+    }
+}
@@ -0,0 +1,162 @@
+use std::sync::Arc;
+
+use deadpool_postgres::Pool;
+use http::Uri;
+use model::{Tenant, TenantQuery};
+use storage::{
+    datacache::{DataRef, LookupRef},
+    StorageManager,
+};
+
+use crate::api::ApiError;
+
+/// Decides where flows may redirect to once they have been completed.
+#[derive(Clone)]
+#[repr(transparent)]
+pub struct RedirectService(Arc<InternalRedirectService>);
+
+struct InternalRedirectService {
+    storage: StorageManager,
+    pool: Pool,
+    allowed_origins: Vec<String>,
+}
+
+impl RedirectService {
+    pub fn new(storage: StorageManager, pool: Pool, allowed_origins: Vec<String>) -> Self {
+        Self(Arc::new(InternalRedirectService {
+            storage,
+            pool,
+            allowed_origins,
+        }))
+    }
+
+    /// Relative paths are always allowed.
+    /// Absolute uris must point to the tenant of `host`, an allowed origin or be the redirect uri of an application.
+    pub async fn is_allowed(&self, target: &str, host: &str) -> Result<bool, ApiError> {
+        let tenant_host = self.tenant_host(host).await;
+        match check_target(target, tenant_host.as_deref(), &self.0.allowed_origins) {
+            TargetCheck::Allowed => Ok(true),
+            TargetCheck::Invalid => Ok(false),
+            TargetCheck::Unknown => self.is_redirect_uri(target).await,
+        }
+    }
+
+    async fn tenant_host(&self, host: &str) -> Option<String> {
+        let reference: DataRef<Tenant> = DataRef::new(TenantQuery::host(host.to_owned()));
+        if let Some(tenant) = self.0.storage.lookup(&reference).await {
+            return Some(tenant.host.clone());
+        }
+        let client = self.0.pool.get().await.ok()?;
+        let statement = client
+            .prepare_cached("select host from tenants where is_default")
+            .await
+            .ok()?;
+        let row = client.query_opt(&statement, &[]).await.ok()??;
+        Some(row.get("host"))
+    }
+
+    async fn is_redirect_uri(&self, target: &str) -> Result<bool, ApiError> {
+        let client = self.0.pool.get().await?;
+        let statement = client
+            .prepare_cached(
+                "select exists(select 1 from oauth2_providers where $1 = any(redirect_uris))",
+            )
+            .await?;
+        Ok(client.query_one(&statement, &[&target]).await?.get(0))
+    }
+}
+
+#[derive(Debug, PartialEq, Eq)]
+enum TargetCheck {
+    Allowed,
+    Invalid,
+    /// An absolute uri which might be the redirect uri of an application
+    Unknown,
+}
+
+fn check_target(
+    target: &str,
+    tenant_host: Option<&str>,
+    allowed_origins: &[String],
+) -> TargetCheck {
+    // Browsers drop control characters and treat backslashes like slashes
+    if target.is_empty() || target.chars().any(|c| c.is_control() || c == '\\') {
+        return TargetCheck::Invalid;
+    }
+    if target.starts_with('/') {
+        return if target.starts_with("//") {
+            TargetCheck::Invalid
+        } else {
+            TargetCheck::Allowed
+        };
+    }
+    let Ok(uri) = target.parse::<Uri>() else { return TargetCheck::Invalid };
+    let (Some(scheme), Some(authority)) = (uri.scheme_str(), uri.authority()) else {
+        return TargetCheck::Invalid };
+    if scheme != "https" && scheme != "http" {
+        return TargetCheck::Unknown;
+    }
+    let is_tenant = tenant_host.map_or(false, |tenant_host| {
+        let tenant_host = tenant_host.split(':').next().unwrap_or(tenant_host);
+        !authority.as_str().contains('@') && authority.host().eq_ignore_ascii_case(tenant_host)
+    });
+    let origin = format!("{scheme}://{authority}");
+    let is_allowed = allowed_origins
+        .iter()
+        .any(|allowed| allowed.trim_end_matches('/').eq_ignore_ascii_case(&origin));
+    if is_tenant || is_allowed {
+        TargetCheck::Allowed
+    } else {
+        TargetCheck::Unknown
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::{check_target, TargetCheck};
+
+    fn check(target: &str) -> TargetCheck {
+        check_target(
+            target,
+            Some("auth.example.com"),
+            &["https://app.example.com".to_owned()],
+        )
+    }
+
+    #[test]
+    fn relative() {
+        assert_eq!(check("/"), TargetCheck::Allowed);
+        assert_eq!(
+            check("/api/v1/application/oauth2/authorize?a=b"),
+            TargetCheck::Allowed
+        );
+        assert_eq!(check("//evil.example"), TargetCheck::Invalid);
+        assert_eq!(check("/\\evil.example"), TargetCheck::Invalid);
+        assert_eq!(check("/\t/evil.example"), TargetCheck::Invalid);
+        assert_eq!(check("evil.example"), TargetCheck::Invalid);
+        assert_eq!(check(""), TargetCheck::Invalid);
+    }
+
+    #[test]
+    fn absolute() {
+        assert_eq!(check("https://auth.example.com/user"), TargetCheck::Allowed);
+        assert_eq!(
+            check("https://AUTH.example.com:8443/"),
+            TargetCheck::Allowed
+        );
+        assert_eq!(
+            check("https://app.example.com/callback"),
+            TargetCheck::Allowed
+        );
+        assert_eq!(
+            check("http://app.example.com/callback"),
+            TargetCheck::Unknown
+        );
+        assert_eq!(
+            check("https://auth.example.com@evil.example/"),
+            TargetCheck::Unknown
+        );
+        assert_eq!(check("https://evil.example/"), TargetCheck::Unknown);
+        assert_eq!(check("javascript:alert(1)"), TargetCheck::Invalid);
+    }
+}
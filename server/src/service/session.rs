@@ -0,0 +1,174 @@
+use std::net::IpAddr;
+
+use deadpool_postgres::GenericClient;
+use model::SessionInfo;
+use rand::{
+    distributions::{Alphanumeric, DistString},
+    rngs::OsRng,
+};
+use time::{Duration, OffsetDateTime};
+use tokio_postgres::Row;
+use uuid::Uuid;
+
+use crate::{api::ApiError, config::SessionConfiguration};
+
+const SESSION_KEY_LENGTH: usize = 96;
+const MAX_USER_AGENT_LENGTH: usize = 512;
+/// `last_seen` is only updated if it is older than this, so not every request causes a write.
+const TOUCH_INTERVAL: Duration = Duration::minutes(1);
+
+/// A session which has not expired yet.
+#[derive(Debug, Clone)]
+pub struct ActiveSession {
+    pub session_id: String,
+    pub id: Uuid,
+    pub user_id: Option<Uuid>,
+    pub created: OffsetDateTime,
+}
+
+#[derive(Clone)]
+pub struct SessionService {
+    lifetime: Duration,
+    idle_timeout: Duration,
+}
+
+fn from_row(row: &Row, current: &str) -> SessionInfo {
+    SessionInfo {
+        id: row.get("id"),
+        user: row.get("user_id"),
+        created: row.get("created"),
+        last_seen: row.get("last_seen"),
+        expires: row.get("expires"),
+        ip: row.get("ip"),
+        user_agent: row.get("user_agent"),
+        current: row.get::<_, &str>("uid") == current,
+    }
+}
+
+impl SessionService {
+    pub fn new(config: &SessionConfiguration) -> Self {
+        Self {
+            lifetime: Duration::seconds(config.lifetime as i64),
+            idle_timeout: Duration::seconds(config.idle_timeout as i64),
+        }
+    }
+
+    /// A session expires after being idle for `idle_timeout`, but never later than `lifetime` after its creation.
+    fn expiry(&self, created: OffsetDateTime, now: OffsetDateTime) -> OffsetDateTime {
+        (created + self.lifetime).min(now + self.idle_timeout)
+    }
+
+    /// The expiry of the session cookie, sessions are never extended beyond it.
+    pub fn cookie_expiry(&self, session: &ActiveSession) -> OffsetDateTime {
+        session.created + self.lifetime
+    }
+
+    pub async fn create(
+        &self,
+        client: &impl GenericClient,
+        ip: Option<IpAddr>,
+        user_agent: Option<&str>,
+    ) -> Result<ActiveSession, ApiError> {
+        let session_id = Alphanumeric.sample_string(&mut OsRng, SESSION_KEY_LENGTH);
+        let user_agent: Option<String> =
+            user_agent.map(|agent| agent.chars().take(MAX_USER_AGENT_LENGTH).collect());
+        let now = OffsetDateTime::now_utc();
+        let expires = self.expiry(now, now);
+        let statement = client
+            .prepare_cached(
+                "insert into sessions(uid, created, last_seen, expires, ip, user_agent) values ($1, $2, $2, $3, $4, $5) returning id",
+            )
+            .await?;
+        let row = client
+            .query_one(&statement, &[&session_id, &now, &expires, &ip, &user_agent])
+            .await?;
+        Ok(ActiveSession {
+            session_id,
+            id: row.get("id"),
+            user_id: None,
+            created: now,
+        })
+    }
+
+    /// Looks up a session which has not expired and extends it if it has not been seen for a while.
+    pub async fn lookup(
+        &self,
+        client: &impl GenericClient,
+        session_id: &str,
+    ) -> Result<Option<ActiveSession>, ApiError> {
+        let statement = client
+            .prepare_cached(
+                "select id, user_id, created, last_seen from sessions where uid = $1 and expires > now()",
+            )
+            .await?;
+        let Some(row) = client.query_opt(&statement, &[&session_id]).await? else {
+            return Ok(None) };
+        let session = ActiveSession {
+            session_id: session_id.to_owned(),
+            id: row.get("id"),
+            user_id: row.get("user_id"),
+            created: row.get("created"),
+        };
+        let now = OffsetDateTime::now_utc();
+        let last_seen: OffsetDateTime = row.get("last_seen");
+        if now - last_seen >= TOUCH_INTERVAL {
+            let expires = self.expiry(session.created, now);
+            let statement = client
+                .prepare_cached("update sessions set last_seen = $2, expires = $3 where uid = $1")
+                .await?;
+            client
+                .execute(&statement, &[&session_id, &now, &expires])
+                .await?;
+        }
+        Ok(Some(session))
+    }
+
+    /// Lists the sessions of the user which have not expired, `current` is the session id of the request.
+    pub async fn list_for_user(
+        &self,
+        client: &impl GenericClient,
+        user: Uuid,
+        current: &str,
+    ) -> Result<Vec<SessionInfo>, ApiError> {
+        let statement = client
+            .prepare_cached(
+                "select * from sessions where user_id = $1 and expires > now() order by last_seen desc",
+            )
+            .await?;
+        let rows = client.query(&statement, &[&user]).await?;
+        Ok(rows.iter().map(|row| from_row(row, current)).collect())
+    }
+
+    /// Deletes a session of the user.
+    /// Returns false if the user has no session with this id.
+    pub async fn revoke(
+        &self,
+        client: &impl GenericClient,
+        user: Uuid,
+        id: Uuid,
+    ) -> Result<bool, ApiError> {
+        let statement = client
+            .prepare_cached("delete from sessions where id = $1 and user_id = $2")
+            .await?;
+        Ok(client.execute(&statement, &[&id, &user]).await? > 0)
+    }
+
+    /// Deletes all sessions of the user, returns the number of deleted sessions.
+    pub async fn revoke_all(
+        &self,
+        client: &impl GenericClient,
+        user: Uuid,
+    ) -> Result<u64, ApiError> {
+        let statement = client
+            .prepare_cached("delete from sessions where user_id = $1")
+            .await?;
+        Ok(client.execute(&statement, &[&user]).await?)
+    }
+
+    pub async fn purge_expired(&self, client: &impl GenericClient) -> Result<u64, ApiError> {
+        let statement = client
+            .prepare_cached("delete from sessions where expires <= now()")
+            .await?;
+        Ok(client.execute(&statement, &[]).await?)
+    }
+}
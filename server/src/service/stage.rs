@@ -0,0 +1,477 @@
+use deadpool_postgres::GenericClient;
+use model::{
+    ConsentMode, PasswordBackend, PgConsentMode, StaticMode, TotpMode, UserField, UserVerification,
+    WebAuthnMode,
+};
+use serde::Deserialize;
+use tokio_postgres::Row;
+
+use crate::api::ApiError;
+
+#[derive(Debug, Deserialize)]
+pub struct StageWrite {
+    pub slug: String,
+    pub timeout: i32,
+    #[serde(flatten)]
+    pub kind: StageKindWrite,
+}
+
+/// Like `StageKind`, but references other stages and prompts by their uid.
+#[derive(Debug, Deserialize)]
+#[serde(tag = "kind", rename_all = "snake_case")]
+pub enum StageKindWrite {
+    Deny,
+    Prompt {
+        bindings: Vec<PromptBindingWrite>,
+    },
+    Identification {
+        password: Option<i32>,
+        user_fields: Vec<UserField>,
+        #[serde(default)]
+        passwordless: bool,
+    },
+    UserLogin,
+    UserLogout {
+        #[serde(default)]
+        terminate_all: bool,
+    },
+    UserWrite,
+    Password {
+        backends: Vec<PasswordBackend>,
+    },
+    Consent {
+        mode: ConsentMode,
+    },
+    AuthenticatorTotp {
+        mode: TotpMode,
+        issuer: String,
+        drift: i16,
+    },
+    AuthenticatorWebAuthn {
+        mode: WebAuthnMode,
+        user_verification: UserVerification,
+    },
+    AuthenticatorStatic {
+        mode: StaticMode,
+        count: i16,
+    },
+    Email {
+        subject: String,
+        template: String,
+        token_expiry: i32,
+    },
+    PasswordChange,
+}
+
+#[derive(Debug, Deserialize)]
+pub struct PromptBindingWrite {
+    pub prompt: i32,
+    pub order: i16,
+}
+
+impl StageKindWrite {
+    fn name(&self) -> &'static str {
+        match self {
+            StageKindWrite::Deny => "deny",
+            StageKindWrite::Prompt { .. } => "prompt",
+            StageKindWrite::Identification { .. } => "identification",
+            StageKindWrite::UserLogin => "user_login",
+            StageKindWrite::UserLogout { .. } => "user_logout",
+            StageKindWrite::UserWrite => "user_write",
+            StageKindWrite::Password { .. } => "password",
+            StageKindWrite::Consent { .. } => "consent",
+            StageKindWrite::AuthenticatorTotp { .. } => "authenticator_totp",
+            StageKindWrite::AuthenticatorWebAuthn { .. } => "authenticator_webauthn",
+            StageKindWrite::AuthenticatorStatic { .. } => "authenticator_static",
+            StageKindWrite::Email { .. } => "email",
+            StageKindWrite::PasswordChange => "password_change",
+        }
+    }
+}
+
+/// Rows of the kind specific tables a stage points to.
+#[derive(Default)]
+struct SideRows {
+    identification_password_stage: Option<i32>,
+    identification_stage: Option<i32>,
+    consent_stage: Option<i32>,
+    user_logout_stage: Option<i32>,
+    totp_stage: Option<i32>,
+    webauthn_stage: Option<i32>,
+    static_stage: Option<i32>,
+    email_stage: Option<i32>,
+}
+
+impl SideRows {
+    fn from_row(row: &Row) -> Self {
+        Self {
+            identification_password_stage: row.get("identification_password_stage"),
+            identification_stage: row.get("identification_stage"),
+            consent_stage: row.get("consent_stage"),
+            user_logout_stage: row.get("user_logout_stage"),
+            totp_stage: row.get("totp_stage"),
+            webauthn_stage: row.get("webauthn_stage"),
+            static_stage: row.get("static_stage"),
+            email_stage: row.get("email_stage"),
+        }
+    }
+}
+
+/// Writes stages to the database, reads go through the `StageStorage`,
+/// so its cache entry has to be invalidated after every change.
+/// All methods changing a stage expect to be called inside of a transaction.
+#[derive(Clone)]
+pub struct StageService {}
+
+impl StageService {
+    pub fn new() -> Self {
+        Self {}
+    }
+}
+
+impl StageService {
+    pub async fn list_uids(&self, client: &impl GenericClient) -> Result<Vec<i32>, ApiError> {
+        let statement = client
+            .prepare_cached("select uid from stages order by slug")
+            .await?;
+        let rows = client.query(&statement, &[]).await?;
+        Ok(rows.into_iter().map(|row| row.get("uid")).collect())
+    }
+
+    pub async fn uid_by_slug(
+        &self,
+        client: &impl GenericClient,
+        slug: &str,
+    ) -> Result<Option<i32>, ApiError> {
+        let statement = client
+            .prepare_cached("select uid from stages where slug = $1")
+            .await?;
+        let row = client.query_opt(&statement, &[&slug]).await?;
+        Ok(row.map(|row| row.get("uid")))
+    }
+
+    /// Checks that the stages and prompts referenced by `kind` exist.
+    /// Returns a message describing the first invalid reference.
+    pub async fn check_references(
+        &self,
+        client: &impl GenericClient,
+        kind: &StageKindWrite,
+    ) -> Result<Option<&'static str>, ApiError> {
+        match kind {
+            StageKindWrite::Identification {
+                password: Some(password),
+                ..
+            } => {
+                let statement = client
+                    .prepare_cached(
+                        "select exists(select 1 from stages where uid = $1 and kind = 'password')",
+                    )
+                    .await?;
+                let exists: bool = client.query_one(&statement, &[password]).await?.get(0);
+                Ok((!exists).then_some("Password stage does not exist"))
+            }
+            StageKindWrite::Prompt { bindings } => {
+                let prompts: Vec<i32> = bindings.iter().map(|binding| binding.prompt).collect();
+                let statement = client
+                    .prepare_cached("select count(*) from prompts where uid = any($1)")
+                    .await?;
+                let count: i64 = client.query_one(&statement, &[&prompts]).await?.get(0);
+                Ok((count != prompts.len() as i64).then_some("Prompt does not exist"))
+            }
+            _ => Ok(None),
+        }
+    }
+
+    pub async fn used_by_flow(
+        &self,
+        client: &impl GenericClient,
+        uid: i32,
+    ) -> Result<bool, ApiError> {
+        let statement = client
+            .prepare_cached("select exists(select 1 from flow_entries where stage = $1)")
+            .await?;
+        Ok(client.query_one(&statement, &[&uid]).await?.get(0))
+    }
+
+    /// Whether an identification stage links to this stage as its password stage.
+    pub async fn used_by_identification(
+        &self,
+        client: &impl GenericClient,
+        uid: i32,
+    ) -> Result<bool, ApiError> {
+        let statement = client
+            .prepare_cached(
+                "select exists(select 1 from stages where identification_password_stage = $1)",
+            )
+            .await?;
+        Ok(client.query_one(&statement, &[&uid]).await?.get(0))
+    }
+
+    pub async fn create(
+        &self,
+        client: &impl GenericClient,
+        write: &StageWrite,
+    ) -> Result<i32, ApiError> {
+        let side = insert_side_rows(client, &write.kind).await?;
+        let statement = client
+            .prepare_cached(
+                "insert into stages(slug, kind, timeout, identification_password_stage, identification_stage, consent_stage, user_logout_stage, password_backends, totp_stage, webauthn_stage, static_stage, email_stage) values ($1, $2::text::stage_kind, $3, $4, $5, $6, $7, $8, $9, $10, $11, $12) returning uid",
+            )
+            .await?;
+        let row = client
+            .query_one(
+                &statement,
+                &[
+                    &write.slug,
+                    &write.kind.name(),
+                    &write.timeout,
+                    &side.identification_password_stage,
+                    &side.identification_stage,
+                    &side.consent_stage,
+                    &side.user_logout_stage,
+                    &backends(&write.kind),
+                    &side.totp_stage,
+                    &side.webauthn_stage,
+                    &side.static_stage,
+                    &side.email_stage,
+                ],
+            )
+            .await?;
+        let uid = row.get("uid");
+        insert_prompt_bindings(client, uid, &write.kind).await?;
+        Ok(uid)
+    }
+
+    /// Replaces the stage, the rows of its previous kind are deleted.
+    /// Returns false if there is no stage with this uid.
+    pub async fn update(
+        &self,
+        client: &impl GenericClient,
+        uid: i32,
+        write: &StageWrite,
+    ) -> Result<bool, ApiError> {
+        let statement = client
+            .prepare_cached("select * from stages where uid = $1 for update")
+            .await?;
+        let Some(row) = client.query_opt(&statement, &[&uid]).await? else { return Ok(false) };
+        let previous = SideRows::from_row(&row);
+        let side = insert_side_rows(client, &write.kind).await?;
+        let statement = client
+            .prepare_cached(
+                "update stages set slug = $2, kind = $3::text::stage_kind, timeout = $4, identification_password_stage = $5, identification_stage = $6, consent_stage = $7, user_logout_stage = $8, password_backends = $9, totp_stage = $10, webauthn_stage = $11, static_stage = $12, email_stage = $13 where uid = $1",
+            )
+            .await?;
+        client
+            .execute(
+                &statement,
+                &[
+                    &uid,
+                    &write.slug,
+                    &write.kind.name(),
+                    &write.timeout,
+                    &side.identification_password_stage,
+                    &side.identification_stage,
+                    &side.consent_stage,
+                    &side.user_logout_stage,
+                    &backends(&write.kind),
+                    &side.totp_stage,
+                    &side.webauthn_stage,
+                    &side.static_stage,
+                    &side.email_stage,
+                ],
+            )
+            .await?;
+        delete_side_rows(client, &previous).await?;
+        let statement = client
+            .prepare_cached("delete from stage_prompt_bindings where stage = $1")
+            .await?;
+        client.execute(&statement, &[&uid]).await?;
+        insert_prompt_bindings(client, uid, &write.kind).await?;
+        Ok(true)
+    }
+
+    /// Deletes the stage together with the rows of its kind.
+    /// Returns false if there is no stage with this uid.
+    pub async fn delete(&self, client: &impl GenericClient, uid: i32) -> Result<bool, ApiError> {
+        let statement = client
+            .prepare_cached("delete from stages where uid = $1 returning *")
+            .await?;
+        let Some(row) = client.query_opt(&statement, &[&uid]).await? else { return Ok(false) };
+        delete_side_rows(client, &SideRows::from_row(&row)).await?;
+        Ok(true)
+    }
+}
+
+fn backends(kind: &StageKindWrite) -> Vec<PasswordBackend> {
+    match kind {
+        StageKindWrite::Password { backends } => backends.clone(),
+        _ => vec![PasswordBackend::Internal],
+    }
+}
+
+async fn insert_side_rows(
+    client: &impl GenericClient,
+    kind: &StageKindWrite,
+) -> Result<SideRows, ApiError> {
+    let mut side = SideRows::default();
+    match kind {
+        StageKindWrite::Identification {
+            password,
+            user_fields,
+            passwordless,
+        } => {
+            let statement = client
+                .prepare_cached(
+                    "insert into identification_stages(fields, passwordless) values ($1, $2) returning uid",
+                )
+                .await?;
+            let row = client
+                .query_one(&statement, &[user_fields, passwordless])
+                .await?;
+            side.identification_stage = Some(row.get("uid"));
+            side.identification_password_stage = *password;
+        }
+        StageKindWrite::Consent { mode } => {
+            let (mode, until) = match mode {
+                ConsentMode::Always => (PgConsentMode::Always, None),
+                ConsentMode::Once => (PgConsentMode::Once, None),
+                ConsentMode::Until { duration } => (PgConsentMode::Until, Some(*duration)),
+            };
+            let statement = client
+                .prepare_cached(
+                    "insert into consent_stages(mode, until) values ($1, $2) returning uid",
+                )
+                .await?;
+            let row = client.query_one(&statement, &[&mode, &until]).await?;
+            side.consent_stage = Some(row.get("uid"));
+        }
+        StageKindWrite::UserLogout { terminate_all } => {
+            let statement = client
+                .prepare_cached(
+                    "insert into user_logout_stages(terminate_all) values ($1) returning uid",
+                )
+                .await?;
+            let row = client.query_one(&statement, &[terminate_all]).await?;
+            side.user_logout_stage = Some(row.get("uid"));
+        }
+        StageKindWrite::AuthenticatorTotp {
+            mode,
+            issuer,
+            drift,
+        } => {
+            let statement = client
+                .prepare_cached(
+                    "insert into totp_stages(mode, issuer, drift) values ($1, $2, $3) returning uid",
+                )
+                .await?;
+            let row = client.query_one(&statement, &[mode, issuer, drift]).await?;
+            side.totp_stage = Some(row.get("uid"));
+        }
+        StageKindWrite::AuthenticatorWebAuthn {
+            mode,
+            user_verification,
+        } => {
+            let statement = client
+                .prepare_cached(
+                    "insert into webauthn_stages(mode, user_verification) values ($1, $2) returning uid",
+                )
+                .await?;
+            let row = client
+                .query_one(&statement, &[mode, user_verification])
+                .await?;
+            side.webauthn_stage = Some(row.get("uid"));
+        }
+        StageKindWrite::AuthenticatorStatic { mode, count } => {
+            let statement = client
+                .prepare_cached(
+                    "insert into static_stages(mode, count) values ($1, $2) returning uid",
+                )
+                .await?;
+            let row = client.query_one(&statement, &[mode, count]).await?;
+            side.static_stage = Some(row.get("uid"));
+        }
+        StageKindWrite::Email {
+            subject,
+            template,
+            token_expiry,
+        } => {
+            let statement = client
+                .prepare_cached(
+                    "insert into email_stages(subject, template, token_expiry) values ($1, $2, $3) returning uid",
+                )
+                .await?;
+            let row = client
+                .query_one(&statement, &[subject, template, token_expiry])
+                .await?;
+            side.email_stage = Some(row.get("uid"));
+        }
+        _ => {}
+    }
+    Ok(side)
+}
+
+/// Expects the stage to no longer reference the rows.
+async fn delete_side_rows(client: &impl GenericClient, side: &SideRows) -> Result<(), ApiError> {
+    if let Some(uid) = side.identification_stage {
+        let statement = client
+            .prepare_cached("delete from identification_stages where uid = $1")
+            .await?;
+        client.execute(&statement, &[&uid]).await?;
+    }
+    if let Some(uid) = side.consent_stage {
+        let statement = client
+            .prepare_cached("delete from consent_stages where uid = $1")
+            .await?;
+        client.execute(&statement, &[&uid]).await?;
+    }
+    if let Some(uid) = side.user_logout_stage {
+        let statement = client
+            .prepare_cached("delete from user_logout_stages where uid = $1")
+            .await?;
+        client.execute(&statement, &[&uid]).await?;
+    }
+    if let Some(uid) = side.totp_stage {
+        let statement = client
+            .prepare_cached("delete from totp_stages where uid = $1")
+            .await?;
+        client.execute(&statement, &[&uid]).await?;
+    }
+    if let Some(uid) = side.webauthn_stage {
+        let statement = client
+            .prepare_cached("delete from webauthn_stages where uid = $1")
+            .await?;
+        client.execute(&statement, &[&uid]).await?;
+    }
+    if let Some(uid) = side.static_stage {
+        let statement = client
+            .prepare_cached("delete from static_stages where uid = $1")
+            .await?;
+        client.execute(&statement, &[&uid]).await?;
+    }
+    if let Some(uid) = side.email_stage {
+        let statement = client
+            .prepare_cached("delete from email_stages where uid = $1")
+            .await?;
+        client.execute(&statement, &[&uid]).await?;
+    }
+    Ok(())
+}
+
+async fn insert_prompt_bindings(
+    client: &impl GenericClient,
+    stage: i32,
+    kind: &StageKindWrite,
+) -> Result<(), ApiError> {
+    let StageKindWrite::Prompt { bindings } = kind else { return Ok(()) };
+    let statement = client
+        .prepare_cached(
+            "insert into stage_prompt_bindings(prompt, stage, ordering) values ($1, $2, $3)",
+        )
+        .await?;
+    for binding in bindings {
+        client
+            .execute(&statement, &[&binding.prompt, &stage, &binding.order])
+            .await?;
+    }
+    Ok(())
+}
@@ -0,0 +1,148 @@
+use argon2::{password_hash::Encoding, PasswordHash};
+use deadpool_postgres::GenericClient;
+use rand::{rngs::OsRng, Rng};
+use uuid::Uuid;
+
+use crate::api::ApiError;
+
+use super::user::hash_password;
+
+/// Characters of generated codes, without the ones which are easily confused.
+const ALPHABET: &[u8] = b"abcdefghjkmnpqrstuvwxyz23456789";
+/// Characters of a code, they are shown in two groups.
+const CODE_LENGTH: usize = 10;
+/// Codes generated when admins regenerate the codes of a user.
+pub const DEFAULT_COUNT: i16 = 10;
+
+/// One-time recovery codes of users.
+/// Codes are hashed like passwords, so they can only be shown when they are generated.
+#[derive(Clone)]
+pub struct StaticCodeService {}
+
+impl StaticCodeService {
+    pub fn new() -> Self {
+        Self {}
+    }
+}
+
+impl StaticCodeService {
+    pub async fn has_codes(
+        &self,
+        client: &impl GenericClient,
+        user: Uuid,
+    ) -> Result<bool, ApiError> {
+        let statement = client
+            .prepare_cached("select exists(select 1 from static_codes where user_id = $1)")
+            .await?;
+        Ok(client.query_one(&statement, &[&user]).await?.get(0))
+    }
+
+    /// Replaces all codes of the user.
+    pub async fn replace(
+        &self,
+        client: &impl GenericClient,
+        user: Uuid,
+        codes: &[String],
+    ) -> Result<(), ApiError> {
+        self.delete_all(client, user).await?;
+        let statement = client
+            .prepare_cached("insert into static_codes(user_id, code) values ($1, $2)")
+            .await?;
+        for code in codes {
+            let hash = hash_password(&normalize(code))?;
+            client.execute(&statement, &[&user, &hash]).await?;
+        }
+        Ok(())
+    }
+
+    pub async fn delete_all(
+        &self,
+        client: &impl GenericClient,
+        user: Uuid,
+    ) -> Result<(), ApiError> {
+        let statement = client
+            .prepare_cached("delete from static_codes where user_id = $1")
+            .await?;
+        client.execute(&statement, &[&user]).await?;
+        Ok(())
+    }
+
+    /// Checks the code against all codes of the user and deletes the matching one.
+    /// Every code is only accepted once, so this has to be called inside of a transaction.
+    pub async fn verify(
+        &self,
+        client: &impl GenericClient,
+        user: Uuid,
+        code: &str,
+    ) -> Result<bool, ApiError> {
+        let code = normalize(code);
+        if code.len() != CODE_LENGTH {
+            return Ok(false);
+        }
+        let statement = client
+            .prepare_cached("select uid, code from static_codes where user_id = $1 for update")
+            .await?;
+        for row in client.query(&statement, &[&user]).await? {
+            let hash: String = row.get("code");
+            let hash = PasswordHash::parse(&hash, Encoding::B64)?;
+            match hash.verify_password(&[&argon2::Argon2::default()], &code) {
+                Ok(_) => {}
+                Err(argon2::password_hash::Error::Password) => continue,
+                Err(err) => return Err(err.into()),
+            }
+            let statement = client
+                .prepare_cached("delete from static_codes where uid = $1")
+                .await?;
+            let uid: i32 = row.get("uid");
+            client.execute(&statement, &[&uid]).await?;
+            return Ok(true);
+        }
+        Ok(false)
+    }
+}
+
+/// Codes are formatted as two groups of five characters, e.g. `k7c2m-pq9xa`.
+pub fn generate_codes(count: i16) -> Vec<String> {
+    let mut rng = OsRng;
+    (0..count)
+        .map(|_| {
+            let code: String = (0..CODE_LENGTH)
+                .map(|_| ALPHABET[rng.gen_range(0..ALPHABET.len())] as char)
+                .collect();
+            format!("{}-{}", &code[..CODE_LENGTH / 2], &code[CODE_LENGTH / 2..])
+        })
+        .collect()
+}
+
+/// Users may enter codes without the separator and in any case.
+fn normalize(code: &str) -> String {
+    code.chars()
+        .filter(|c| *c != '-' && !c.is_whitespace())
+        .flat_map(char::to_lowercase)
+        .collect()
+}
+
+#[cfg(test)]
+mod tests {
+    use super::{generate_codes, normalize, ALPHABET, CODE_LENGTH};
+
+    #[test]
+    fn generated_codes() {
+        let codes = generate_codes(12);
+        assert_eq!(codes.len(), 12);
+        for code in &codes {
+            assert_eq!(code.len(), CODE_LENGTH + 1);
+            assert_eq!(code.as_bytes()[CODE_LENGTH / 2], b'-');
+            let normalized = normalize(code);
+            assert_eq!(normalized.len(), CODE_LENGTH);
+            assert!(normalized.bytes().all(|c| ALPHABET.contains(&c)));
+        }
+    }
+
+    #[test]
+    fn normalized_input() {
+        assert_eq!(normalize("k7c2m-pq9xa"), "k7c2mpq9xa");
+        assert_eq!(normalize(" K7C2M PQ9XA\n"), "k7c2mpq9xa");
+        assert_eq!(normalize("k7c2mpq9xa"), "k7c2mpq9xa");
+    }
+}
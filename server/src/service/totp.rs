@@ -0,0 +1,73 @@
+use deadpool_postgres::GenericClient;
+use time::OffsetDateTime;
+use uuid::Uuid;
+
+use crate::{api::ApiError, executor::totp};
+
+/// Totp devices of users, a user may have several devices which are all accepted.
+#[derive(Clone)]
+pub struct TotpService {}
+
+impl TotpService {
+    pub fn new() -> Self {
+        Self {}
+    }
+}
+
+impl TotpService {
+    pub async fn has_device(
+        &self,
+        client: &impl GenericClient,
+        user: Uuid,
+    ) -> Result<bool, ApiError> {
+        let statement = client
+            .prepare_cached("select exists(select 1 from totp_devices where user_id = $1)")
+            .await?;
+        Ok(client.query_one(&statement, &[&user]).await?.get(0))
+    }
+
+    /// `step` is the time step of the code the device has been confirmed with, so it cannot be used again.
+    pub async fn add_device(
+        &self,
+        client: &impl GenericClient,
+        user: Uuid,
+        secret: &[u8],
+        step: i64,
+    ) -> Result<(), ApiError> {
+        let statement = client
+            .prepare_cached(
+                "insert into totp_devices(user_id, secret, last_step) values ($1, $2, $3)",
+            )
+            .await?;
+        client.execute(&statement, &[&user, &secret, &step]).await?;
+        Ok(())
+    }
+
+    /// Checks the code against all devices of the user.
+    /// Every code is only accepted once, so this has to be called inside of a transaction.
+    pub async fn verify(
+        &self,
+        client: &impl GenericClient,
+        user: Uuid,
+        code: &str,
+        drift: i16,
+    ) -> Result<bool, ApiError> {
+        let statement = client
+            .prepare_cached(
+                "select uid, secret, last_step from totp_devices where user_id = $1 for update",
+            )
+            .await?;
+        let now = OffsetDateTime::now_utc().unix_timestamp();
+        for row in client.query(&statement, &[&user]).await? {
+            let secret: Vec<u8> = row.get("secret");
+            let Some(step) = totp::verify(&secret, code, now, drift, row.get("last_step")) else { continue };
+            let statement = client
+                .prepare_cached("update totp_devices set last_step = $2 where uid = $1")
+                .await?;
+            let uid: i32 = row.get("uid");
+            client.execute(&statement, &[&uid, &step]).await?;
+            return Ok(true);
+        }
+        Ok(false)
+    }
+}
@@ -1,10 +1,30 @@
+use argon2::{password_hash::SaltString, Params, PasswordHasher};
 use deadpool_postgres::GenericClient;
-use model::user::PartialUser;
+use model::{
+    error::{FieldError, FieldErrorKind, SubmissionError},
+    user::PartialUser,
+    PartialGroup,
+};
+use rand::rngs::OsRng;
+use serde_json::{Map, Value};
 use time::OffsetDateTime;
-use tokio_postgres::Row;
+use tokio_postgres::{error::SqlState, types::Json, Row};
 use uuid::Uuid;
 
-use crate::api::ApiError;
+use crate::{
+    api::ApiError,
+    executor::fields::{EMAIL, PASSWORD, USERNAME},
+};
+
+/// Changes that should be applied to a user, fields that are `None` are left untouched.
+#[derive(Debug, Default)]
+pub struct UserWrite {
+    pub name: Option<String>,
+    pub email: Option<String>,
+    pub display_name: Option<String>,
+    pub password_hash: Option<String>,
+    pub attributes: Map<String, Value>,
+}
 
 #[derive(Clone)]
 pub struct UserService {}
@@ -20,9 +40,162 @@ fn get_password_change_date(row: &Row) -> OffsetDateTime {
     timestamp
 }
 
+fn partial_user_from_row(row: &Row) -> PartialUser {
+    PartialUser {
+        uid: row.get("uid"),
+        name: row.get("name"),
+        avatar_url: None,
+        is_admin: row.get("administrator"),
+        password_change_date: get_password_change_date(row),
+        groups: Vec::new(),
+    }
+}
+
+fn missing(field: &str) -> ApiError {
+    SubmissionError::from(FieldError::new(field, FieldErrorKind::Missing)).into()
+}
+
+pub fn hash_password(password: &str) -> Result<String, ApiError> {
+    let salt = SaltString::generate(&mut OsRng);
+    let hash = argon2::Argon2::new(
+        argon2::Algorithm::Argon2id,
+        argon2::Version::V0x13,
+        Params::default(),
+    )
+    .hash_password(password.as_bytes(), &salt)?
+    .to_string();
+    Ok(hash)
+}
+
 impl UserService {
     pub fn delete_user(&self, _uid: i32) {}
 
+    async fn resolve_user(
+        &self,
+        client: &impl GenericClient,
+        row: &Row,
+    ) -> Result<PartialUser, ApiError> {
+        let mut user = partial_user_from_row(row);
+        user.groups = self.user_groups(client, user.uid).await?;
+        Ok(user)
+    }
+
+    /// Returns all groups of the user, including the parents of the groups the user is a member of.
+    pub async fn user_groups(
+        &self,
+        client: &impl GenericClient,
+        uid: Uuid,
+    ) -> Result<Vec<PartialGroup>, ApiError> {
+        let statement = client
+            .prepare_cached(
+                "with recursive effective(uid) as (select group_id from group_memberships where user_id = $1 union select g.parent from groups g join effective e on g.uid = e.uid where g.parent is not null) select g.uid, g.name from groups g join effective e on g.uid = e.uid order by g.name",
+            )
+            .await?;
+        let rows = client.query(&statement, &[&uid]).await?;
+        Ok(rows
+            .into_iter()
+            .map(|row| PartialGroup {
+                uid: row.get("uid"),
+                name: row.get("name"),
+            })
+            .collect())
+    }
+
+    /// Creates a new user or updates an existing one if `uid` is set.
+    /// Violated unique constraints are reported as errors of the corresponding execution field.
+    pub async fn write_user(
+        &self,
+        client: &impl GenericClient,
+        uid: Option<Uuid>,
+        write: UserWrite,
+    ) -> Result<PartialUser, ApiError> {
+        let attributes = Json(write.attributes);
+        let result = match uid {
+            Some(uid) => {
+                let statement = client
+                    .prepare_cached(
+                        "update users set name = coalesce($2, name), email = coalesce($3, email), email_verified = case when $3::varchar is null or $3::varchar = email then email_verified else false end, display_name = coalesce($4, display_name), password = coalesce($5::varchar, password), password_change_date = case when $5::varchar is null then password_change_date else now() end, attributes = attributes || $6::jsonb where uid = $1 returning uid,name,administrator,password_change_date",
+                    )
+                    .await?;
+                client
+                    .query_one(
+                        &statement,
+                        &[
+                            &uid,
+                            &write.name,
+                            &write.email,
+                            &write.display_name,
+                            &write.password_hash,
+                            &attributes,
+                        ],
+                    )
+                    .await
+            }
+            None => {
+                let Some(password) = &write.password_hash else { return Err(missing(PASSWORD.name)) };
+                let Some(name) = &write.name else { return Err(missing(USERNAME.name)) };
+                let statement = client
+                    .prepare_cached(
+                        "insert into users (name, email, display_name, password, attributes) values ($1, $2, $3, $4, $5::jsonb) returning uid,name,administrator,password_change_date",
+                    )
+                    .await?;
+                client
+                    .query_one(
+                        &statement,
+                        &[
+                            name,
+                            &write.email,
+                            &write.display_name,
+                            password,
+                            &attributes,
+                        ],
+                    )
+                    .await
+            }
+        };
+        match result {
+            Ok(row) => self.resolve_user(client, &row).await,
+            Err(err) if err.code() == Some(&SqlState::UNIQUE_VIOLATION) => {
+                let constraint = err.as_db_error().and_then(|err| err.constraint());
+                let field = match constraint {
+                    Some(constraint) if constraint.contains("email") => EMAIL.name,
+                    _ => USERNAME.name,
+                };
+                Err(SubmissionError::from(FieldError::new(
+                    field,
+                    FieldErrorKind::invalid("Already in use"),
+                ))
+                .into())
+            }
+            Err(err) => Err(err.into()),
+        }
+    }
+
+    pub async fn lookup_email(
+        &self,
+        client: &impl GenericClient,
+        uid: Uuid,
+    ) -> Result<Option<String>, ApiError> {
+        let statement = client
+            .prepare_cached("select email from users where uid = $1")
+            .await?;
+        let row = client.query_opt(&statement, &[&uid]).await?;
+        Ok(row.and_then(|row| row.get("email")))
+    }
+
+    /// Marks the email of the user as verified, unless it has been changed to another one in the meantime.
+    pub async fn verify_email(
+        &self,
+        client: &impl GenericClient,
+        uid: Uuid,
+        email: &str,
+    ) -> Result<bool, ApiError> {
+        let statement = client
+            .prepare_cached("update users set email_verified = true where uid = $1 and email = $2")
+            .await?;
+        Ok(client.execute(&statement, &[&uid, &email]).await? > 0)
+    }
+
     pub async fn lookup_user_uid(
         &self,
         client: &impl GenericClient,
@@ -35,13 +208,7 @@ impl UserService {
             .await?;
         let result = client.query_opt(&statement, &[&uid]).await?;
         Ok(if let Some(res) = result {
-            Some(PartialUser {
-                uid: res.get("uid"),
-                name: res.get("name"),
-                avatar_url: None,
-                is_admin: res.get("administrator"),
-                password_change_date: get_password_change_date(&res),
-            })
+            Some(self.resolve_user(client, &res).await?)
         } else {
             None
         })
@@ -63,13 +230,7 @@ impl UserService {
                 .await?;
             let result = client.query_opt(&statement, &[&text]).await?;
             if let Some(res) = result {
-                return Ok(Some(PartialUser {
-                    uid: res.get("uid"),
-                    name: res.get("name"),
-                    avatar_url: None,
-                    is_admin: res.get("administrator"),
-                    password_change_date: get_password_change_date(&res),
-                }));
+                return Ok(Some(self.resolve_user(client, &res).await?));
             }
         }
         if use_email {
@@ -80,13 +241,7 @@ impl UserService {
                 .await?;
             let result = client.query_opt(&statement, &[&text]).await?;
             if let Some(res) = result {
-                return Ok(Some(PartialUser {
-                    uid: res.get("uid"),
-                    name: res.get("name"),
-                    avatar_url: None,
-                    is_admin: res.get("administrator"),
-                    password_change_date: get_password_change_date(&res),
-                }));
+                return Ok(Some(self.resolve_user(client, &res).await?));
             }
         }
         if use_uuid {
@@ -101,13 +256,7 @@ impl UserService {
                 .await?;
             let result = client.query_opt(&statement, &[&uuid]).await?;
             if let Some(res) = result {
-                return Ok(Some(PartialUser {
-                    uid: res.get("uid"),
-                    name: res.get("name"),
-                    avatar_url: None,
-                    is_admin: res.get("administrator"),
-                    password_change_date: get_password_change_date(&res),
-                }));
+                return Ok(Some(self.resolve_user(client, &res).await?));
             }
         }
         return Ok(None);
@@ -0,0 +1,95 @@
+use deadpool_postgres::GenericClient;
+use uuid::Uuid;
+
+use crate::api::ApiError;
+
+/// WebAuthn credentials of users, any of them may be used to authenticate.
+#[derive(Clone)]
+pub struct WebAuthnService {}
+
+/// A stored credential, found by its id.
+pub struct StoredCredential {
+    pub uid: i32,
+    pub user: Uuid,
+    /// COSE encoded
+    pub public_key: Vec<u8>,
+    pub sign_count: u32,
+}
+
+impl WebAuthnService {
+    pub fn new() -> Self {
+        Self {}
+    }
+}
+
+impl WebAuthnService {
+    pub async fn credential_ids(
+        &self,
+        client: &impl GenericClient,
+        user: Uuid,
+    ) -> Result<Vec<Vec<u8>>, ApiError> {
+        let statement = client
+            .prepare_cached("select credential_id from webauthn_credentials where user_id = $1")
+            .await?;
+        let rows = client.query(&statement, &[&user]).await?;
+        Ok(rows.iter().map(|row| row.get(0)).collect())
+    }
+
+    /// Returns false if the credential has already been registered.
+    pub async fn add_credential(
+        &self,
+        client: &impl GenericClient,
+        user: Uuid,
+        credential_id: &[u8],
+        public_key: &[u8],
+        sign_count: u32,
+    ) -> Result<bool, ApiError> {
+        let statement = client
+            .prepare_cached(
+                "insert into webauthn_credentials(user_id, credential_id, public_key, sign_count) values ($1, $2, $3, $4) on conflict do nothing",
+            )
+            .await?;
+        let sign_count = sign_count as i64;
+        let inserted = client
+            .execute(
+                &statement,
+                &[&user, &credential_id, &public_key, &sign_count],
+            )
+            .await?;
+        Ok(inserted == 1)
+    }
+
+    /// Locks the credential until the transaction ends, so its sign count can be updated.
+    pub async fn find(
+        &self,
+        client: &impl GenericClient,
+        credential_id: &[u8],
+    ) -> Result<Option<StoredCredential>, ApiError> {
+        let statement = client
+            .prepare_cached(
+                "select uid, user_id, public_key, sign_count from webauthn_credentials where credential_id = $1 for update",
+            )
+            .await?;
+        let row = client.query_opt(&statement, &[&credential_id]).await?;
+        Ok(row.map(|row| StoredCredential {
+            uid: row.get("uid"),
+            user: row.get("user_id"),
+            public_key: row.get("public_key"),
+            sign_count: row.get::<_, i64>("sign_count") as u32,
+        }))
+    }
+
+    pub async fn update_sign_count(
+        &self,
+        client: &impl GenericClient,
+        uid: i32,
+        sign_count: u32,
+    ) -> Result<(), ApiError> {
+        let statement = client
+            .prepare_cached("update webauthn_credentials set sign_count = $2 where uid = $1")
+            .await?;
+        let sign_count = sign_count as i64;
+        client.execute(&statement, &[&uid, &sign_count]).await?;
+        Ok(())
+    }
+}
@@ -0,0 +1,1424 @@
+use std::{
+    collections::{HashMap, HashSet},
+    error::Error,
+    fmt::Display,
+};
+
+use base64::{engine::general_purpose::URL_SAFE_NO_PAD, Engine};
+use deadpool_postgres::GenericClient;
+use model::{
+    blueprint::{
+        BindingBlueprint, BindingBlueprintKind, Blueprint, EntryBlueprint, FlowBlueprint,
+        PolicyBlueprint, PromptBindingBlueprint, PromptBlueprint, StageBlueprint,
+        StageBlueprintKind, TenantBlueprint,
+    },
+    ConsentMode, PasswordBackend, PgConsentMode, PolicyKind, PolicyKindSimple, StaticMode,
+    TotpMode, UserField, WebAuthnMode,
+};
+use serde::Serialize;
+use tokio_postgres::Row;
+use uuid::Uuid;
+
+use crate::{include_sql, policy, StorageError};
+
+#[derive(Debug)]
+pub struct BlueprintError(String);
+
+impl Error for BlueprintError {}
+
+impl Display for BlueprintError {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        write!(f, "Invalid blueprint: {}", self.0)
+    }
+}
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Serialize)]
+#[serde(rename_all = "snake_case")]
+pub enum ChangeAction {
+    Create,
+    Update,
+}
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Serialize)]
+#[serde(rename_all = "snake_case")]
+pub enum ObjectKind {
+    Policy,
+    Prompt,
+    Stage,
+    Flow,
+    Tenant,
+}
+
+#[derive(Debug, Clone, PartialEq, Eq, Serialize)]
+pub struct BlueprintChange {
+    pub action: ChangeAction,
+    pub kind: ObjectKind,
+    /// Slug of the object, the host for tenants
+    pub key: String,
+}
+
+impl Display for BlueprintChange {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        let action = match self.action {
+            ChangeAction::Create => "create",
+            ChangeAction::Update => "update",
+        };
+        let kind = match self.kind {
+            ObjectKind::Policy => "policy",
+            ObjectKind::Prompt => "prompt",
+            ObjectKind::Stage => "stage",
+            ObjectKind::Flow => "flow",
+            ObjectKind::Tenant => "tenant",
+        };
+        write!(f, "{action} {kind} {}", self.key)
+    }
+}
+
+crate::executor!(pub BlueprintStore);
+
+impl BlueprintStore {
+    pub async fn export(&self) -> Result<Blueprint, StorageError> {
+        let conn = self.get_conn().await?;
+        export(&conn).await
+    }
+
+    /// Lists the changes applying the blueprint would make.
+    pub async fn diff(&self, blueprint: &Blueprint) -> Result<Vec<BlueprintChange>, StorageError> {
+        let conn = self.get_conn().await?;
+        let current = export(&conn).await?;
+        check_references(&current, blueprint)?;
+        let blueprint = normalize(blueprint.clone());
+        Ok(Plan::new(&current, &blueprint).changes())
+    }
+
+    /// Creates and updates objects until they match the blueprint, applying it again changes nothing.
+    /// Objects missing from the blueprint are left untouched.
+    pub async fn apply(&self, blueprint: &Blueprint) -> Result<Vec<BlueprintChange>, StorageError> {
+        let mut conn = self.get_conn().await?;
+        let transaction = conn.transaction().await?;
+        transaction
+            .execute(
+                &transaction
+                    .prepare_cached(include_sql!("blueprint/lock"))
+                    .await?,
+                &[],
+            )
+            .await?;
+        let current = export(&transaction).await?;
+        check_references(&current, blueprint)?;
+        let blueprint = normalize(blueprint.clone());
+        let plan = Plan::new(&current, &blueprint);
+        for (_, policy) in &plan.policies {
+            write_policy(&transaction, policy).await?;
+        }
+        for (_, prompt) in &plan.prompts {
+            write_prompt(&transaction, prompt).await?;
+        }
+        for (_, stage) in &plan.stages {
+            write_stage(&transaction, stage).await?;
+        }
+        for (_, flow) in &plan.flows {
+            write_flow(&transaction, flow).await?;
+        }
+        for (_, tenant) in &plan.tenants {
+            write_tenant(&transaction, tenant).await?;
+        }
+        let changes = plan.changes();
+        transaction.commit().await?;
+        Ok(changes)
+    }
+}
+
+/// The objects of a blueprint which differ from the current configuration, in the order they have to be written.
+struct Plan<'a> {
+    policies: Vec<(ChangeAction, &'a PolicyBlueprint)>,
+    prompts: Vec<(ChangeAction, &'a PromptBlueprint)>,
+    stages: Vec<(ChangeAction, &'a StageBlueprint)>,
+    flows: Vec<(ChangeAction, &'a FlowBlueprint)>,
+    tenants: Vec<(ChangeAction, &'a TenantBlueprint)>,
+}
+
+impl<'a> Plan<'a> {
+    fn new(current: &Blueprint, blueprint: &'a Blueprint) -> Self {
+        let mut stages = compare(&current.stages, &blueprint.stages, |stage| &stage.slug);
+        // Identification stages reference their password stage
+        stages.sort_by_key(|(_, stage)| {
+            matches!(stage.kind, StageBlueprintKind::Identification { .. })
+        });
+        Self {
+            policies: compare(&current.policies, &blueprint.policies, |policy| {
+                &policy.slug
+            }),
+            prompts: compare(&current.prompts, &blueprint.prompts, |prompt| &prompt.slug),
+            stages,
+            flows: compare(&current.flows, &blueprint.flows, |flow| &flow.slug),
+            tenants: compare(&current.tenants, &blueprint.tenants, |tenant| &tenant.host),
+        }
+    }
+
+    fn changes(&self) -> Vec<BlueprintChange> {
+        fn change(action: &ChangeAction, kind: ObjectKind, key: &str) -> BlueprintChange {
+            BlueprintChange {
+                action: *action,
+                kind,
+                key: key.to_owned(),
+            }
+        }
+        let policies = self
+            .policies
+            .iter()
+            .map(|(action, policy)| change(action, ObjectKind::Policy, &policy.slug));
+        let prompts = self
+            .prompts
+            .iter()
+            .map(|(action, prompt)| change(action, ObjectKind::Prompt, &prompt.slug));
+        let stages = self
+            .stages
+            .iter()
+            .map(|(action, stage)| change(action, ObjectKind::Stage, &stage.slug));
+        let flows = self
+            .flows
+            .iter()
+            .map(|(action, flow)| change(action, ObjectKind::Flow, &flow.slug));
+        let tenants = self
+            .tenants
+            .iter()
+            .map(|(action, tenant)| change(action, ObjectKind::Tenant, &tenant.host));
+        policies
+            .chain(prompts)
+            .chain(stages)
+            .chain(flows)
+            .chain(tenants)
+            .collect()
+    }
+}
+
+fn compare<'a, T: PartialEq>(
+    current: &[T],
+    wanted: &'a [T],
+    key: impl Fn(&T) -> &String,
+) -> Vec<(ChangeAction, &'a T)> {
+    wanted
+        .iter()
+        .filter_map(
+            |object| match current.iter().find(|current| key(current) == key(object)) {
+                None => Some((ChangeAction::Create, object)),
+                Some(current) if current != object => Some((ChangeAction::Update, object)),
+                Some(_) => None,
+            },
+        )
+        .collect()
+}
+
+/// Sorts everything with an explicit order the same way the export does,
+/// so listing bindings in another order isn't reported as a change.
+fn normalize(mut blueprint: Blueprint) -> Blueprint {
+    for stage in &mut blueprint.stages {
+        if let StageBlueprintKind::Prompt { bindings } = &mut stage.kind {
+            bindings.sort_by_key(|binding| binding.order);
+        }
+    }
+    for flow in &mut blueprint.flows {
+        flow.bindings.sort_by_key(|binding| binding.order);
+        flow.entries.sort_by_key(|entry| entry.ordering);
+        for entry in &mut flow.entries {
+            entry.bindings.sort_by_key(|binding| binding.order);
+        }
+    }
+    blueprint
+}
+
+/// Makes sure every slug is only used once and every referenced object
+/// either exists already or is part of the blueprint.
+fn check_references(current: &Blueprint, blueprint: &Blueprint) -> Result<(), BlueprintError> {
+    check_unique(
+        "policy",
+        blueprint.policies.iter().map(|policy| &policy.slug),
+    )?;
+    check_unique(
+        "prompt",
+        blueprint.prompts.iter().map(|prompt| &prompt.slug),
+    )?;
+    check_unique("stage", blueprint.stages.iter().map(|stage| &stage.slug))?;
+    check_unique("flow", blueprint.flows.iter().map(|flow| &flow.slug))?;
+    check_unique(
+        "tenant",
+        blueprint.tenants.iter().map(|tenant| &tenant.host),
+    )?;
+
+    let policies: HashSet<&str> = current
+        .policies
+        .iter()
+        .chain(&blueprint.policies)
+        .map(|policy| policy.slug.as_str())
+        .collect();
+    let prompts: HashSet<&str> = current
+        .prompts
+        .iter()
+        .chain(&blueprint.prompts)
+        .map(|prompt| prompt.slug.as_str())
+        .collect();
+    // Stages of the blueprint replace the current stages with the same slug
+    let stages: HashMap<&str, &StageBlueprintKind> = current
+        .stages
+        .iter()
+        .chain(&blueprint.stages)
+        .map(|stage| (stage.slug.as_str(), &stage.kind))
+        .collect();
+    let flows: HashSet<&str> = current
+        .flows
+        .iter()
+        .chain(&blueprint.flows)
+        .map(|flow| flow.slug.as_str())
+        .collect();
+
+    for stage in &blueprint.stages {
+        match &stage.kind {
+            StageBlueprintKind::Prompt { bindings } => {
+                for binding in bindings {
+                    if !prompts.contains(binding.prompt.as_str()) {
+                        return Err(missing("stage", &stage.slug, "prompt", &binding.prompt));
+                    }
+                }
+            }
+            StageBlueprintKind::Identification {
+                password: Some(password),
+                ..
+            } => {
+                if !matches!(
+                    stages.get(password.as_str()),
+                    Some(StageBlueprintKind::Password { .. })
+                ) {
+                    return Err(missing("stage", &stage.slug, "password stage", password));
+                }
+            }
+            _ => {}
+        }
+    }
+    for flow in &blueprint.flows {
+        for entry in &flow.entries {
+            if !stages.contains_key(entry.stage.as_str()) {
+                return Err(missing("flow", &flow.slug, "stage", &entry.stage));
+            }
+        }
+        let bindings = flow
+            .bindings
+            .iter()
+            .chain(flow.entries.iter().flat_map(|entry| &entry.bindings));
+        for binding in bindings {
+            if let BindingBlueprintKind::Policy(policy) = &binding.kind {
+                if !policies.contains(policy.as_str()) {
+                    return Err(missing("flow", &flow.slug, "policy", policy));
+                }
+            }
+        }
+    }
+    for tenant in &blueprint.tenants {
+        let tenant_flows = [
+            &tenant.invalidation_flow,
+            &tenant.authentication_flow,
+            &tenant.authorization_flow,
+            &tenant.enrollment_flow,
+            &tenant.recovery_flow,
+            &tenant.unenrollment_flow,
+            &tenant.configuration_flow,
+        ];
+        for flow in tenant_flows.into_iter().flatten() {
+            if !flows.contains(flow.as_str()) {
+                return Err(missing("tenant", &tenant.host, "flow", flow));
+            }
+        }
+    }
+    Ok(())
+}
+
+fn check_unique<'a>(
+    kind: &str,
+    keys: impl Iterator<Item = &'a String>,
+) -> Result<(), BlueprintError> {
+    let mut seen = HashSet::new();
+    for key in keys {
+        if !seen.insert(key) {
+            return Err(BlueprintError(format!("{kind} {key} is defined twice")));
+        }
+    }
+    Ok(())
+}
+
+fn missing(kind: &str, key: &str, reference_kind: &str, reference: &str) -> BlueprintError {
+    BlueprintError(format!(
+        "{kind} {key} references {reference_kind} {reference}, which does not exist"
+    ))
+}
+
+async fn export(client: &impl GenericClient) -> Result<Blueprint, StorageError> {
+    Ok(Blueprint {
+        policies: export_policies(client).await?,
+        prompts: export_prompts(client).await?,
+        stages: export_stages(client).await?,
+        flows: export_flows(client).await?,
+        tenants: export_tenants(client).await?,
+    })
+}
+
+async fn export_policies(
+    client: &impl GenericClient,
+) -> Result<Vec<PolicyBlueprint>, StorageError> {
+    let statement = client
+        .prepare_cached(include_sql!("blueprint/policies"))
+        .await?;
+    let mut policies = Vec::new();
+    for row in client.query(&statement, &[]).await? {
+        let policy = policy::from_row(client, row).await?;
+        let kind = match policy.kind {
+            PolicyKind::Expression { expression } => {
+                let expression = URL_SAFE_NO_PAD
+                    .decode(expression)
+                    .ok()
+                    .and_then(|bytes| String::from_utf8(bytes).ok())
+                    .ok_or_else(|| {
+                        BlueprintError(format!("policy {} has an invalid expression", policy.slug))
+                    })?;
+                PolicyKind::Expression { expression }
+            }
+            kind => kind,
+        };
+        policies.push(PolicyBlueprint {
+            slug: policy.slug,
+            kind,
+        });
+    }
+    Ok(policies)
+}
+
+async fn export_prompts(client: &impl GenericClient) -> Result<Vec<PromptBlueprint>, StorageError> {
+    let statement = client
+        .prepare_cached(include_sql!("blueprint/prompts"))
+        .await?;
+    let rows = client.query(&statement, &[]).await?;
+    Ok(rows
+        .into_iter()
+        .map(|row| PromptBlueprint {
+            slug: row.get("slug"),
+            field_key: row.get("field_key"),
+            label: row.get("label"),
+            kind: row.get("kind"),
+            placeholder: row.get("placeholder"),
+            required: row.get("required"),
+            help_text: row.get("help_text"),
+        })
+        .collect())
+}
+
+async fn export_stages(client: &impl GenericClient) -> Result<Vec<StageBlueprint>, StorageError> {
+    let statement = client
+        .prepare_cached(include_sql!("blueprint/prompt-bindings"))
+        .await?;
+    let mut prompt_bindings: HashMap<String, Vec<PromptBindingBlueprint>> = HashMap::new();
+    for row in client.query(&statement, &[]).await? {
+        prompt_bindings
+            .entry(row.get("stage"))
+            .or_default()
+            .push(PromptBindingBlueprint {
+                prompt: row.get("prompt"),
+                order: row.get("ordering"),
+            });
+    }
+    let statement = client
+        .prepare_cached(include_sql!("blueprint/stages"))
+        .await?;
+    let mut stages = Vec::new();
+    for row in client.query(&statement, &[]).await? {
+        let slug: String = row.get("slug");
+        let kind = match row.get::<_, &str>("kind") {
+            "deny" => StageBlueprintKind::Deny,
+            "prompt" => StageBlueprintKind::Prompt {
+                bindings: prompt_bindings.remove(&slug).unwrap_or_default(),
+            },
+            "identification" => StageBlueprintKind::Identification {
+                password: row.get("password"),
+                user_fields: row
+                    .get::<_, Option<Vec<UserField>>>("fields")
+                    .unwrap_or_default(),
+                passwordless: row
+                    .get::<_, Option<bool>>("passwordless")
+                    .unwrap_or_default(),
+            },
+            "user_login" => StageBlueprintKind::UserLogin,
+            "user_logout" => StageBlueprintKind::UserLogout {
+                terminate_all: row
+                    .get::<_, Option<bool>>("terminate_all")
+                    .unwrap_or_default(),
+            },
+            "user_write" => StageBlueprintKind::UserWrite,
+            "password" => StageBlueprintKind::Password {
+                backends: row.get("password_backends"),
+            },
+            "consent" => {
+                let mode = match row.get::<_, Option<PgConsentMode>>("mode") {
+                    Some(PgConsentMode::Always) => ConsentMode::Always,
+                    Some(PgConsentMode::Once) => ConsentMode::Once,
+                    Some(PgConsentMode::Until) => ConsentMode::Until {
+                        duration: row.get("until"),
+                    },
+                    None => {
+                        return Err(
+                            BlueprintError(format!("stage {slug} has no consent mode")).into()
+                        )
+                    }
+                };
+                StageBlueprintKind::Consent { mode }
+            }
+            "authenticator_totp" => match row.get::<_, Option<TotpMode>>("totp_mode") {
+                Some(mode) => StageBlueprintKind::AuthenticatorTotp {
+                    mode,
+                    issuer: row.get("issuer"),
+                    drift: row.get("drift"),
+                },
+                None => return Err(BlueprintError(format!("stage {slug} has no totp mode")).into()),
+            },
+            "authenticator_webauthn" => match row.get::<_, Option<WebAuthnMode>>("webauthn_mode") {
+                Some(mode) => StageBlueprintKind::AuthenticatorWebAuthn {
+                    mode,
+                    user_verification: row.get("user_verification"),
+                },
+                None => {
+                    return Err(BlueprintError(format!("stage {slug} has no webauthn mode")).into())
+                }
+            },
+            "authenticator_static" => match row.get::<_, Option<StaticMode>>("static_mode") {
+                Some(mode) => StageBlueprintKind::AuthenticatorStatic {
+                    mode,
+                    count: row.get("count"),
+                },
+                None => {
+                    return Err(BlueprintError(format!("stage {slug} has no static mode")).into())
+                }
+            },
+            "email" => StageBlueprintKind::Email {
+                subject: row.get("subject"),
+                template: row.get("template"),
+                token_expiry: row.get("token_expiry"),
+            },
+            "password_change" => StageBlueprintKind::PasswordChange,
+            kind => {
+                return Err(BlueprintError(format!("stage {slug} has unknown kind {kind}")).into())
+            }
+        };
+        stages.push(StageBlueprint {
+            slug,
+            timeout: row.get("timeout"),
+            kind,
+        });
+    }
+    Ok(stages)
+}
+
+async fn export_flows(client: &impl GenericClient) -> Result<Vec<FlowBlueprint>, StorageError> {
+    let statement = client
+        .prepare_cached(include_sql!("blueprint/bindings"))
+        .await?;
+    let mut flow_bindings: HashMap<String, Vec<BindingBlueprint>> = HashMap::new();
+    let mut entry_bindings: HashMap<i32, Vec<BindingBlueprint>> = HashMap::new();
+    for row in client.query(&statement, &[]).await? {
+        let binding = binding_from_row(&row);
+        match row.get::<_, Option<i32>>("entry") {
+            Some(entry) => entry_bindings.entry(entry).or_default().push(binding),
+            None => flow_bindings
+                .entry(row.get("flow"))
+                .or_default()
+                .push(binding),
+        }
+    }
+    let statement = client
+        .prepare_cached(include_sql!("blueprint/entries"))
+        .await?;
+    let mut entries: HashMap<String, Vec<EntryBlueprint>> = HashMap::new();
+    for row in client.query(&statement, &[]).await? {
+        let uid: i32 = row.get("uid");
+        entries
+            .entry(row.get("flow"))
+            .or_default()
+            .push(EntryBlueprint {
+                stage: row.get("stage"),
+                ordering: row.get("ordering"),
+                bindings: entry_bindings.remove(&uid).unwrap_or_default(),
+            });
+    }
+    let statement = client
+        .prepare_cached(include_sql!("blueprint/flows"))
+        .await?;
+    let rows = client.query(&statement, &[]).await?;
+    Ok(rows
+        .into_iter()
+        .map(|row| {
+            let slug: String = row.get("slug");
+            FlowBlueprint {
+                title: row.get("title"),
+                designation: row.get("designation"),
+                authentication: row.get("authentication"),
+                bindings: flow_bindings.remove(&slug).unwrap_or_default(),
+                entries: entries.remove(&slug).unwrap_or_default(),
+                slug,
+            }
+        })
+        .collect())
+}
+
+fn binding_from_row(row: &Row) -> BindingBlueprint {
+    let kind = if let Some(policy) = row.get("policy") {
+        BindingBlueprintKind::Policy(policy)
+    } else if let Some(group) = row.get("group_binding") {
+        BindingBlueprintKind::Group(group)
+    } else {
+        BindingBlueprintKind::User(row.get("user_binding"))
+    };
+    BindingBlueprint {
+        kind,
+        enabled: row.get("enabled"),
+        negate: row.get("negate"),
+        order: row.get("ordering"),
+    }
+}
+
+async fn export_tenants(client: &impl GenericClient) -> Result<Vec<TenantBlueprint>, StorageError> {
+    let statement = client
+        .prepare_cached(include_sql!("blueprint/tenants"))
+        .await?;
+    let rows = client.query(&statement, &[]).await?;
+    Ok(rows
+        .into_iter()
+        .map(|row| TenantBlueprint {
+            host: row.get("host"),
+            default: row.get("is_default"),
+            title: row.get("title"),
+            logo: row.get("logo"),
+            favicon: row.get("favicon"),
+            invalidation_flow: row.get("invalidation_flow"),
+            authentication_flow: row.get("authentication_flow"),
+            authorization_flow: row.get("authorization_flow"),
+            enrollment_flow: row.get("enrollment_flow"),
+            recovery_flow: row.get("recovery_flow"),
+            unenrollment_flow: row.get("unenrollment_flow"),
+            configuration_flow: row.get("configuration_flow"),
+        })
+        .collect())
+}
+
+/// Rows of the kind specific tables a policy points to.
+#[derive(Default)]
+struct PolicyRows {
+    password_expiration: Option<i32>,
+    password_strength: Option<i32>,
+    expression: Option<i32>,
+    breached_password: Option<i32>,
+}
+
+async fn write_policy(
+    client: &impl GenericClient,
+    policy: &PolicyBlueprint,
+) -> Result<(), StorageError> {
+    let statement = client
+        .prepare_cached(include_sql!("blueprint/policy-for-update"))
+        .await?;
+    let previous = client.query_opt(&statement, &[&policy.slug]).await?;
+    let kind = PolicyKindSimple::from(&policy.kind);
+    let rows = insert_policy_kind(client, &policy.kind).await?;
+    match &previous {
+        Some(previous) => {
+            let uid: i32 = previous.get("uid");
+            let statement = client
+                .prepare_cached(include_sql!("blueprint/update-policy"))
+                .await?;
+            client
+                .execute(
+                    &statement,
+                    &[
+                        &uid,
+                        &kind,
+                        &rows.password_expiration,
+                        &rows.password_strength,
+                        &rows.expression,
+                        &rows.breached_password,
+                    ],
+                )
+                .await?;
+            delete_policy_kind(client, previous).await?;
+        }
+        None => {
+            let statement = client
+                .prepare_cached(include_sql!("blueprint/insert-policy"))
+                .await?;
+            client
+                .execute(
+                    &statement,
+                    &[
+                        &policy.slug,
+                        &kind,
+                        &rows.password_expiration,
+                        &rows.password_strength,
+                        &rows.expression,
+                        &rows.breached_password,
+                    ],
+                )
+                .await?;
+        }
+    }
+    Ok(())
+}
+
+async fn insert_policy_kind(
+    client: &impl GenericClient,
+    kind: &PolicyKind,
+) -> Result<PolicyRows, StorageError> {
+    let mut rows = PolicyRows::default();
+    match kind {
+        PolicyKind::PasswordExpiry { max_age } => {
+            let statement = client
+                .prepare_cached(include_sql!("blueprint/insert-password-expiration"))
+                .await?;
+            rows.password_expiration = Some(client.query_one(&statement, &[max_age]).await?.get(0));
+        }
+        PolicyKind::PasswordStrength(rules) => {
+            let statement = client
+                .prepare_cached(include_sql!("blueprint/insert-password-strength"))
+                .await?;
+            let row = client
+                .query_one(
+                    &statement,
+                    &[
+                        &rules.field_key,
+                        &rules.min_length,
+                        &rules.min_uppercase,
+                        &rules.min_lowercase,
+                        &rules.min_digits,
+                        &rules.min_symbols,
+                        &rules.max_repeated,
+                        &rules.check_user_attributes,
+                        &rules.min_score,
+                    ],
+                )
+                .await?;
+            rows.password_strength = Some(row.get(0));
+        }
+        PolicyKind::Expression { expression } => {
+            let statement = client
+                .prepare_cached(include_sql!("blueprint/insert-expression"))
+                .await?;
+            let expression = URL_SAFE_NO_PAD.encode(expression);
+            rows.expression = Some(client.query_one(&statement, &[&expression]).await?.get(0));
+        }
+        PolicyKind::BreachedPassword { field_key } => {
+            let statement = client
+                .prepare_cached(include_sql!("blueprint/insert-breached-password"))
+                .await?;
+            rows.breached_password = Some(client.query_one(&statement, &[field_key]).await?.get(0));
+        }
+    }
+    Ok(rows)
+}
+
+/// Expects the policy to no longer reference the rows.
+async fn delete_policy_kind(
+    client: &impl GenericClient,
+    previous: &Row,
+) -> Result<(), StorageError> {
+    let tables = [
+        (
+            "password_expiration",
+            include_sql!("blueprint/delete-password-expiration"),
+        ),
+        (
+            "password_strength",
+            include_sql!("blueprint/delete-password-strength"),
+        ),
+        ("expression", include_sql!("blueprint/delete-expression")),
+        (
+            "breached_password",
+            include_sql!("blueprint/delete-breached-password"),
+        ),
+    ];
+    for (column, query) in tables {
+        if let Some(uid) = previous.get::<_, Option<i32>>(column) {
+            let statement = client.prepare_cached(query).await?;
+            client.execute(&statement, &[&uid]).await?;
+        }
+    }
+    Ok(())
+}
+
+async fn write_prompt(
+    client: &impl GenericClient,
+    prompt: &PromptBlueprint,
+) -> Result<(), StorageError> {
+    let statement = client
+        .prepare_cached(include_sql!("blueprint/upsert-prompt"))
+        .await?;
+    client
+        .execute(
+            &statement,
+            &[
+                &prompt.slug,
+                &prompt.field_key,
+                &prompt.label,
+                &prompt.kind,
+                &prompt.placeholder,
+                &prompt.required,
+                &prompt.help_text,
+            ],
+        )
+        .await?;
+    Ok(())
+}
+
+/// Rows of the kind specific tables a stage points to.
+#[derive(Default)]
+struct StageRows {
+    identification_stage: Option<i32>,
+    consent_stage: Option<i32>,
+    user_logout_stage: Option<i32>,
+    totp_stage: Option<i32>,
+    webauthn_stage: Option<i32>,
+    static_stage: Option<i32>,
+    email_stage: Option<i32>,
+}
+
+async fn write_stage(
+    client: &impl GenericClient,
+    stage: &StageBlueprint,
+) -> Result<(), StorageError> {
+    let statement = client
+        .prepare_cached(include_sql!("blueprint/stage-for-update"))
+        .await?;
+    let previous = client.query_opt(&statement, &[&stage.slug]).await?;
+    let rows = insert_stage_kind(client, &stage.kind).await?;
+    let (kind, password, backends) = match &stage.kind {
+        StageBlueprintKind::Deny => ("deny", None, None),
+        StageBlueprintKind::Prompt { .. } => ("prompt", None, None),
+        StageBlueprintKind::Identification { password, .. } => {
+            ("identification", password.as_deref(), None)
+        }
+        StageBlueprintKind::UserLogin => ("user_login", None, None),
+        StageBlueprintKind::UserLogout { .. } => ("user_logout", None, None),
+        StageBlueprintKind::UserWrite => ("user_write", None, None),
+        StageBlueprintKind::Password { backends } => ("password", None, Some(backends.clone())),
+        StageBlueprintKind::Consent { .. } => ("consent", None, None),
+        StageBlueprintKind::AuthenticatorTotp { .. } => ("authenticator_totp", None, None),
+        StageBlueprintKind::AuthenticatorWebAuthn { .. } => ("authenticator_webauthn", None, None),
+        StageBlueprintKind::AuthenticatorStatic { .. } => ("authenticator_static", None, None),
+        StageBlueprintKind::Email { .. } => ("email", None, None),
+        StageBlueprintKind::PasswordChange => ("password_change", None, None),
+    };
+    let backends = backends.unwrap_or_else(|| vec![PasswordBackend::Internal]);
+    let uid: i32 = match &previous {
+        Some(previous) => {
+            let uid: i32 = previous.get("uid");
+            let statement = client
+                .prepare_cached(include_sql!("blueprint/update-stage"))
+                .await?;
+            client
+                .execute(
+                    &statement,
+                    &[
+                        &uid,
+                        &kind,
+                        &stage.timeout,
+                        &password,
+                        &rows.identification_stage,
+                        &rows.consent_stage,
+                        &rows.user_logout_stage,
+                        &backends,
+                        &rows.totp_stage,
+                        &rows.webauthn_stage,
+                        &rows.static_stage,
+                        &rows.email_stage,
+                    ],
+                )
+                .await?;
+            delete_stage_kind(client, previous).await?;
+            uid
+        }
+        None => {
+            let statement = client
+                .prepare_cached(include_sql!("blueprint/insert-stage"))
+                .await?;
+            let row = client
+                .query_one(
+                    &statement,
+                    &[
+                        &stage.slug,
+                        &kind,
+                        &stage.timeout,
+                        &password,
+                        &rows.identification_stage,
+                        &rows.consent_stage,
+                        &rows.user_logout_stage,
+                        &backends,
+                        &rows.totp_stage,
+                        &rows.webauthn_stage,
+                        &rows.static_stage,
+                        &rows.email_stage,
+                    ],
+                )
+                .await?;
+            row.get("uid")
+        }
+    };
+    let statement = client
+        .prepare_cached(include_sql!("blueprint/delete-prompt-bindings"))
+        .await?;
+    client.execute(&statement, &[&uid]).await?;
+    if let StageBlueprintKind::Prompt { bindings } = &stage.kind {
+        let statement = client
+            .prepare_cached(include_sql!("blueprint/insert-prompt-binding"))
+            .await?;
+        for binding in bindings {
+            client
+                .execute(&statement, &[&binding.prompt, &uid, &binding.order])
+                .await?;
+        }
+    }
+    Ok(())
+}
+
+async fn insert_stage_kind(
+    client: &impl GenericClient,
+    kind: &StageBlueprintKind,
+) -> Result<StageRows, StorageError> {
+    let mut rows = StageRows::default();
+    match kind {
+        StageBlueprintKind::Identification {
+            user_fields,
+            passwordless,
+            ..
+        } => {
+            let statement = client
+                .prepare_cached(include_sql!("blueprint/insert-identification"))
+                .await?;
+            let row = client
+                .query_one(&statement, &[user_fields, passwordless])
+                .await?;
+            rows.identification_stage = Some(row.get("uid"));
+        }
+        StageBlueprintKind::Consent { mode } => {
+            let (mode, until) = match mode {
+                ConsentMode::Always => (PgConsentMode::Always, None),
+                ConsentMode::Once => (PgConsentMode::Once, None),
+                ConsentMode::Until { duration } => (PgConsentMode::Until, Some(*duration)),
+            };
+            let statement = client
+                .prepare_cached(include_sql!("blueprint/insert-consent"))
+                .await?;
+            let row = client.query_one(&statement, &[&mode, &until]).await?;
+            rows.consent_stage = Some(row.get("uid"));
+        }
+        StageBlueprintKind::UserLogout { terminate_all } => {
+            let statement = client
+                .prepare_cached(include_sql!("blueprint/insert-user-logout"))
+                .await?;
+            let row = client.query_one(&statement, &[terminate_all]).await?;
+            rows.user_logout_stage = Some(row.get("uid"));
+        }
+        StageBlueprintKind::AuthenticatorTotp {
+            mode,
+            issuer,
+            drift,
+        } => {
+            let statement = client
+                .prepare_cached(include_sql!("blueprint/insert-totp"))
+                .await?;
+            let row = client.query_one(&statement, &[mode, issuer, drift]).await?;
+            rows.totp_stage = Some(row.get("uid"));
+        }
+        StageBlueprintKind::AuthenticatorWebAuthn {
+            mode,
+            user_verification,
+        } => {
+            let statement = client
+                .prepare_cached(include_sql!("blueprint/insert-webauthn"))
+                .await?;
+            let row = client
+                .query_one(&statement, &[mode, user_verification])
+                .await?;
+            rows.webauthn_stage = Some(row.get("uid"));
+        }
+        StageBlueprintKind::AuthenticatorStatic { mode, count } => {
+            let statement = client
+                .prepare_cached(include_sql!("blueprint/insert-static"))
+                .await?;
+            let row = client.query_one(&statement, &[mode, count]).await?;
+            rows.static_stage = Some(row.get("uid"));
+        }
+        StageBlueprintKind::Email {
+            subject,
+            template,
+            token_expiry,
+        } => {
+            let statement = client
+                .prepare_cached(include_sql!("blueprint/insert-email"))
+                .await?;
+            let row = client
+                .query_one(&statement, &[subject, template, token_expiry])
+                .await?;
+            rows.email_stage = Some(row.get("uid"));
+        }
+        _ => {}
+    }
+    Ok(rows)
+}
+
+/// Expects the stage to no longer reference the rows.
+async fn delete_stage_kind(
+    client: &impl GenericClient,
+    previous: &Row,
+) -> Result<(), StorageError> {
+    let tables = [
+        (
+            "identification_stage",
+            include_sql!("blueprint/delete-identification"),
+        ),
+        ("consent_stage", include_sql!("blueprint/delete-consent")),
+        (
+            "user_logout_stage",
+            include_sql!("blueprint/delete-user-logout"),
+        ),
+        ("totp_stage", include_sql!("blueprint/delete-totp")),
+        ("webauthn_stage", include_sql!("blueprint/delete-webauthn")),
+        ("static_stage", include_sql!("blueprint/delete-static")),
+        ("email_stage", include_sql!("blueprint/delete-email")),
+    ];
+    for (column, query) in tables {
+        if let Some(uid) = previous.get::<_, Option<i32>>(column) {
+            let statement = client.prepare_cached(query).await?;
+            client.execute(&statement, &[&uid]).await?;
+        }
+    }
+    Ok(())
+}
+
+/// Entries and bindings of the flow are replaced as a whole.
+async fn write_flow(client: &impl GenericClient, flow: &FlowBlueprint) -> Result<(), StorageError> {
+    let statement = client
+        .prepare_cached(include_sql!("blueprint/upsert-flow"))
+        .await?;
+    let uid: i32 = client
+        .query_one(
+            &statement,
+            &[
+                &flow.slug,
+                &flow.title,
+                &flow.designation,
+                &flow.authentication,
+            ],
+        )
+        .await?
+        .get("uid");
+    let statement = client
+        .prepare_cached(include_sql!("blueprint/delete-bindings"))
+        .await?;
+    client.execute(&statement, &[&uid]).await?;
+    let statement = client
+        .prepare_cached(include_sql!("blueprint/delete-entries"))
+        .await?;
+    client.execute(&statement, &[&uid]).await?;
+    insert_bindings(client, Some(uid), None, &flow.bindings).await?;
+    let statement = client
+        .prepare_cached(include_sql!("blueprint/insert-entry"))
+        .await?;
+    for entry in &flow.entries {
+        let entry_uid: i32 = client
+            .query_one(&statement, &[&uid, &entry.stage, &entry.ordering])
+            .await?
+            .get("uid");
+        insert_bindings(client, None, Some(entry_uid), &entry.bindings).await?;
+    }
+    Ok(())
+}
+
+async fn insert_bindings(
+    client: &impl GenericClient,
+    flow: Option<i32>,
+    entry: Option<i32>,
+    bindings: &[BindingBlueprint],
+) -> Result<(), StorageError> {
+    let statement = client
+        .prepare_cached(include_sql!("blueprint/insert-binding"))
+        .await?;
+    for binding in bindings {
+        let (policy, group, user): (Option<&str>, Option<Uuid>, Option<Uuid>) = match &binding.kind
+        {
+            BindingBlueprintKind::Policy(policy) => (Some(policy), None, None),
+            BindingBlueprintKind::Group(group) => (None, Some(*group), None),
+            BindingBlueprintKind::User(user) => (None, None, Some(*user)),
+        };
+        client
+            .execute(
+                &statement,
+                &[
+                    &flow,
+                    &entry,
+                    &policy,
+                    &group,
+                    &user,
+                    &binding.order,
+                    &binding.enabled,
+                    &binding.negate,
+                ],
+            )
+            .await?;
+    }
+    Ok(())
+}
+
+async fn write_tenant(
+    client: &impl GenericClient,
+    tenant: &TenantBlueprint,
+) -> Result<(), StorageError> {
+    let statement = client
+        .prepare_cached(include_sql!("blueprint/upsert-tenant"))
+        .await?;
+    client
+        .execute(
+            &statement,
+            &[
+                &tenant.host,
+                &tenant.default,
+                &tenant.title,
+                &tenant.logo,
+                &tenant.favicon,
+                &tenant.invalidation_flow,
+                &tenant.authentication_flow,
+                &tenant.authorization_flow,
+                &tenant.enrollment_flow,
+                &tenant.recovery_flow,
+                &tenant.unenrollment_flow,
+                &tenant.configuration_flow,
+            ],
+        )
+        .await?;
+    Ok(())
+}
+
+#[cfg(test)]
+mod test {
+    use model::{
+        blueprint::{
+            BindingBlueprint, BindingBlueprintKind, Blueprint, EntryBlueprint, FlowBlueprint,
+            PromptBindingBlueprint, PromptBlueprint, StageBlueprint, StageBlueprintKind,
+            TenantBlueprint,
+        },
+        AuthenticationRequirement, FlowDesignation, PasswordBackend, PromptKind, UserField,
+    };
+    use uuid::Uuid;
+
+    use super::{check_references, normalize, BlueprintChange, ChangeAction, ObjectKind, Plan};
+
+    fn stage(slug: &str, kind: StageBlueprintKind) -> StageBlueprint {
+        StageBlueprint {
+            slug: slug.to_owned(),
+            timeout: 30,
+            kind,
+        }
+    }
+
+    fn password_stage(slug: &str) -> StageBlueprint {
+        stage(
+            slug,
+            StageBlueprintKind::Password {
+                backends: vec![PasswordBackend::Internal],
+            },
+        )
+    }
+
+    fn identification_stage(slug: &str, password: &str) -> StageBlueprint {
+        stage(
+            slug,
+            StageBlueprintKind::Identification {
+                password: Some(password.to_owned()),
+                user_fields: vec![UserField::Name],
+                passwordless: false,
+            },
+        )
+    }
+
+    fn prompt(slug: &str) -> PromptBlueprint {
+        PromptBlueprint {
+            slug: slug.to_owned(),
+            field_key: slug.to_owned(),
+            label: slug.to_owned(),
+            kind: PromptKind::Text,
+            placeholder: None,
+            required: false,
+            help_text: None,
+        }
+    }
+
+    fn flow(slug: &str, stages: &[&str]) -> FlowBlueprint {
+        FlowBlueprint {
+            slug: slug.to_owned(),
+            title: slug.to_owned(),
+            designation: FlowDesignation::Authentication,
+            authentication: AuthenticationRequirement::None,
+            bindings: Vec::new(),
+            entries: stages
+                .iter()
+                .zip(1..)
+                .map(|(stage, ordering)| EntryBlueprint {
+                    stage: (*stage).to_owned(),
+                    ordering: ordering * 10,
+                    bindings: Vec::new(),
+                })
+                .collect(),
+        }
+    }
+
+    fn policy_binding(policy: &str, order: i16) -> BindingBlueprint {
+        BindingBlueprint {
+            kind: BindingBlueprintKind::Policy(policy.to_owned()),
+            enabled: true,
+            negate: false,
+            order,
+        }
+    }
+
+    fn group_binding(group: u128, order: i16) -> BindingBlueprint {
+        BindingBlueprint {
+            kind: BindingBlueprintKind::Group(Uuid::from_u128(group)),
+            enabled: true,
+            negate: false,
+            order,
+        }
+    }
+
+    fn tenant(host: &str, authentication_flow: &str) -> TenantBlueprint {
+        TenantBlueprint {
+            host: host.to_owned(),
+            default: false,
+            title: host.to_owned(),
+            logo: String::new(),
+            favicon: String::new(),
+            invalidation_flow: None,
+            authentication_flow: Some(authentication_flow.to_owned()),
+            authorization_flow: None,
+            enrollment_flow: None,
+            recovery_flow: None,
+            unenrollment_flow: None,
+            configuration_flow: None,
+        }
+    }
+
+    fn changes(current: &Blueprint, blueprint: &Blueprint) -> Vec<BlueprintChange> {
+        check_references(current, blueprint).expect("Blueprint should be valid");
+        let blueprint = normalize(blueprint.clone());
+        Plan::new(current, &blueprint).changes()
+    }
+
+    fn change(action: ChangeAction, kind: ObjectKind, key: &str) -> BlueprintChange {
+        BlueprintChange {
+            action,
+            kind,
+            key: key.to_owned(),
+        }
+    }
+
+    fn error(current: &Blueprint, blueprint: &Blueprint) -> String {
+        check_references(current, blueprint)
+            .expect_err("Blueprint should be rejected")
+            .to_string()
+    }
+
+    #[test]
+    fn test_objects_are_matched_by_slug() {
+        let current = Blueprint {
+            stages: vec![password_stage("password")],
+            flows: vec![flow("login", &["password"])],
+            ..Default::default()
+        };
+        let mut login = flow("login", &["password"]);
+        login.title = "Sign in".to_owned();
+        let blueprint = Blueprint {
+            stages: vec![
+                password_stage("password"),
+                stage("deny", StageBlueprintKind::Deny),
+            ],
+            flows: vec![login],
+            ..Default::default()
+        };
+        assert_eq!(
+            changes(&current, &blueprint),
+            vec![
+                change(ChangeAction::Create, ObjectKind::Stage, "deny"),
+                change(ChangeAction::Update, ObjectKind::Flow, "login"),
+            ]
+        );
+        assert!(changes(&current, &current).is_empty());
+    }
+
+    #[test]
+    fn test_order_of_bindings_is_ignored() {
+        let mut current_flow = flow("login", &["password", "deny"]);
+        current_flow.bindings = vec![group_binding(1, 0), group_binding(2, 1)];
+        let current = Blueprint {
+            stages: vec![
+                password_stage("password"),
+                stage("deny", StageBlueprintKind::Deny),
+            ],
+            flows: vec![current_flow.clone()],
+            ..Default::default()
+        };
+        let mut reordered = current_flow;
+        reordered.bindings.reverse();
+        reordered.entries.reverse();
+        let blueprint = Blueprint {
+            flows: vec![reordered],
+            ..Default::default()
+        };
+        assert!(changes(&current, &blueprint).is_empty());
+    }
+
+    #[test]
+    fn test_password_stages_are_written_first() {
+        let blueprint = Blueprint {
+            stages: vec![
+                identification_stage("identification", "password"),
+                password_stage("password"),
+            ],
+            ..Default::default()
+        };
+        assert_eq!(
+            changes(&Blueprint::default(), &blueprint),
+            vec![
+                change(ChangeAction::Create, ObjectKind::Stage, "password"),
+                change(ChangeAction::Create, ObjectKind::Stage, "identification"),
+            ]
+        );
+    }
+
+    #[test]
+    fn test_duplicates() {
+        let blueprint = Blueprint {
+            stages: vec![password_stage("password"), password_stage("password")],
+            ..Default::default()
+        };
+        assert_eq!(
+            error(&Blueprint::default(), &blueprint),
+            "Invalid blueprint: stage password is defined twice"
+        );
+        let blueprint = Blueprint {
+            flows: vec![flow("login", &[])],
+            tenants: vec![
+                tenant("example.com", "login"),
+                tenant("example.com", "login"),
+            ],
+            ..Default::default()
+        };
+        assert_eq!(
+            error(&Blueprint::default(), &blueprint),
+            "Invalid blueprint: tenant example.com is defined twice"
+        );
+        // Objects of the blueprint replace the current ones with the same slug
+        let current = Blueprint {
+            stages: vec![password_stage("password")],
+            ..Default::default()
+        };
+        let blueprint = Blueprint {
+            stages: vec![password_stage("password")],
+            ..Default::default()
+        };
+        assert!(check_references(&current, &blueprint).is_ok());
+    }
+
+    #[test]
+    fn test_missing_references() {
+        let empty = Blueprint::default();
+        let blueprint = Blueprint {
+            stages: vec![stage(
+                "prompt",
+                StageBlueprintKind::Prompt {
+                    bindings: vec![PromptBindingBlueprint {
+                        prompt: "name".to_owned(),
+                        order: 0,
+                    }],
+                },
+            )],
+            ..Default::default()
+        };
+        assert_eq!(
+            error(&empty, &blueprint),
+            "Invalid blueprint: stage prompt references prompt name, which does not exist"
+        );
+        let blueprint = Blueprint {
+            stages: vec![identification_stage("identification", "password")],
+            ..Default::default()
+        };
+        assert_eq!(
+            error(&empty, &blueprint),
+            "Invalid blueprint: stage identification references password stage password, which does not exist"
+        );
+        let blueprint = Blueprint {
+            flows: vec![flow("login", &["password"])],
+            ..Default::default()
+        };
+        assert_eq!(
+            error(&empty, &blueprint),
+            "Invalid blueprint: flow login references stage password, which does not exist"
+        );
+        let mut login = flow("login", &[]);
+        login.bindings = vec![policy_binding("strength", 0)];
+        let blueprint = Blueprint {
+            flows: vec![login],
+            ..Default::default()
+        };
+        assert_eq!(
+            error(&empty, &blueprint),
+            "Invalid blueprint: flow login references policy strength, which does not exist"
+        );
+        let blueprint = Blueprint {
+            tenants: vec![tenant("example.com", "login")],
+            ..Default::default()
+        };
+        assert_eq!(
+            error(&empty, &blueprint),
+            "Invalid blueprint: tenant example.com references flow login, which does not exist"
+        );
+    }
+
+    #[test]
+    fn test_references_resolve_against_current() {
+        let current = Blueprint {
+            prompts: vec![prompt("name")],
+            stages: vec![password_stage("password")],
+            flows: vec![flow("login", &["password"])],
+            ..Default::default()
+        };
+        let blueprint = Blueprint {
+            stages: vec![
+                identification_stage("identification", "password"),
+                stage(
+                    "prompt",
+                    StageBlueprintKind::Prompt {
+                        bindings: vec![PromptBindingBlueprint {
+                            prompt: "name".to_owned(),
+                            order: 0,
+                        }],
+                    },
+                ),
+            ],
+            tenants: vec![tenant("example.com", "login")],
+            ..Default::default()
+        };
+        assert!(check_references(&current, &blueprint).is_ok());
+        // The password stage is replaced with a stage of another kind
+        let blueprint = Blueprint {
+            stages: vec![
+                identification_stage("identification", "password"),
+                stage("password", StageBlueprintKind::Deny),
+            ],
+            ..Default::default()
+        };
+        assert_eq!(
+            error(&current, &blueprint),
+            "Invalid blueprint: stage identification references password stage password, which does not exist"
+        );
+    }
+}
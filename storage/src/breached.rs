@@ -0,0 +1,224 @@
+use std::{
+    fs::File,
+    io::{self, BufReader, BufWriter, Read, Write},
+    path::Path,
+};
+
+use sha1::{Digest, Sha1};
+
+/// Identifies a filter file and the version of its layout.
+const MAGIC: &[u8; 8] = b"AUBLOOM1";
+/// 8 GiB, enough for every known breached password at a false positive rate of one in a billion.
+const MAX_BITS: u64 = 1 << 36;
+
+pub type PasswordHash = [u8; 20];
+
+/// A bloom filter of the SHA-1 hashes of breached passwords.
+/// It may report a password which has not been breached, but never misses a breached one.
+pub struct BreachedFilter {
+    num_bits: u64,
+    hashes: u32,
+    bits: Vec<u8>,
+}
+
+impl BreachedFilter {
+    /// Creates an empty filter sized for `expected` hashes with the given false positive rate.
+    pub fn with_capacity(expected: u64, false_positive_rate: f64) -> Self {
+        let expected = expected.max(1) as f64;
+        let ln2 = std::f64::consts::LN_2;
+        let num_bits = (-expected * false_positive_rate.ln() / (ln2 * ln2)).ceil() as u64;
+        let num_bits = num_bits.max(8);
+        let hashes = ((num_bits as f64 / expected) * ln2).round().max(1.0) as u32;
+        Self {
+            num_bits,
+            hashes,
+            bits: vec![0; num_bits.div_ceil(8) as usize],
+        }
+    }
+
+    pub fn open(path: impl AsRef<Path>) -> io::Result<Self> {
+        Self::read(BufReader::new(File::open(path)?))
+    }
+
+    pub fn read(mut reader: impl Read) -> io::Result<Self> {
+        let mut magic = [0; 8];
+        reader.read_exact(&mut magic)?;
+        if &magic != MAGIC {
+            return Err(io::Error::new(
+                io::ErrorKind::InvalidData,
+                "Not a breached password filter",
+            ));
+        }
+        let mut num_bits = [0; 8];
+        reader.read_exact(&mut num_bits)?;
+        let mut hashes = [0; 4];
+        reader.read_exact(&mut hashes)?;
+        let num_bits = u64::from_le_bytes(num_bits);
+        let hashes = u32::from_le_bytes(hashes);
+        if num_bits == 0 || hashes == 0 {
+            return Err(io::Error::new(
+                io::ErrorKind::InvalidData,
+                "Breached password filter is empty",
+            ));
+        }
+        if num_bits > MAX_BITS {
+            return Err(io::Error::new(
+                io::ErrorKind::InvalidData,
+                "Breached password filter is too large",
+            ));
+        }
+        // The header is not trusted, the buffer only grows with the data actually read
+        let len = num_bits.div_ceil(8);
+        let mut bits = Vec::new();
+        reader.take(len).read_to_end(&mut bits)?;
+        if bits.len() as u64 != len {
+            return Err(io::Error::new(
+                io::ErrorKind::UnexpectedEof,
+                "Breached password filter is truncated",
+            ));
+        }
+        Ok(Self {
+            num_bits,
+            hashes,
+            bits,
+        })
+    }
+
+    pub fn save(&self, path: impl AsRef<Path>) -> io::Result<()> {
+        let mut writer = BufWriter::new(File::create(path)?);
+        self.write(&mut writer)?;
+        writer.flush()
+    }
+
+    pub fn write(&self, mut writer: impl Write) -> io::Result<()> {
+        writer.write_all(MAGIC)?;
+        writer.write_all(&self.num_bits.to_le_bytes())?;
+        writer.write_all(&self.hashes.to_le_bytes())?;
+        writer.write_all(&self.bits)
+    }
+
+    pub fn insert(&mut self, hash: &PasswordHash) {
+        for index in indices(self.num_bits, self.hashes, hash) {
+            self.bits[(index / 8) as usize] |= 1 << (index % 8);
+        }
+    }
+
+    pub fn contains(&self, hash: &PasswordHash) -> bool {
+        indices(self.num_bits, self.hashes, hash)
+            .all(|index| self.bits[(index / 8) as usize] & (1 << (index % 8)) != 0)
+    }
+
+    pub fn contains_password(&self, password: &str) -> bool {
+        self.contains(&Sha1::digest(password.as_bytes()).into())
+    }
+}
+
+/// SHA-1 hashes are uniformly distributed already,
+/// so the indices are derived from the hash itself by double hashing.
+fn indices(num_bits: u64, hashes: u32, hash: &PasswordHash) -> impl Iterator<Item = u64> {
+    let first = u64::from_le_bytes(hash[0..8].try_into().expect("Slice has 8 bytes"));
+    let second = u64::from_le_bytes(hash[8..16].try_into().expect("Slice has 8 bytes")) | 1;
+    (0..hashes as u64).map(move |i| first.wrapping_add(i.wrapping_mul(second)) % num_bits)
+}
+
+/// Parses a line of a HIBP dump in the format `HASH:COUNT`.
+/// Files of the range api only contain the suffix of the hash, their prefix is passed separately.
+pub fn parse_line(prefix: &str, line: &str) -> Option<(PasswordHash, u64)> {
+    let (suffix, count) = line.trim().split_once(':')?;
+    let count = count.trim().parse().ok()?;
+    let hex = format!("{prefix}{suffix}");
+    if hex.len() != 40 {
+        return None;
+    }
+    let mut hash = [0; 20];
+    for (byte, pair) in hash.iter_mut().zip(hex.as_bytes().chunks(2)) {
+        *byte = u8::from_str_radix(std::str::from_utf8(pair).ok()?, 16).ok()?;
+    }
+    Some((hash, count))
+}
+
+#[cfg(test)]
+mod test {
+    use sha1::{Digest, Sha1};
+
+    use super::{parse_line, BreachedFilter, PasswordHash, MAGIC};
+
+    fn hash(password: &str) -> PasswordHash {
+        Sha1::digest(password.as_bytes()).into()
+    }
+
+    fn filter() -> BreachedFilter {
+        let mut filter = BreachedFilter::with_capacity(1000, 0.001);
+        for i in 0..1000 {
+            filter.insert(&hash(&format!("password{i}")));
+        }
+        filter
+    }
+
+    #[test]
+    fn test_no_false_negatives() {
+        let filter = filter();
+        for i in 0..1000 {
+            assert!(filter.contains(&hash(&format!("password{i}"))));
+        }
+        assert!(filter.contains_password("password0"));
+        let false_positives = (0..1000)
+            .filter(|i| filter.contains_password(&format!("other{i}")))
+            .count();
+        assert!(false_positives < 20, "{false_positives} false positives");
+    }
+
+    #[test]
+    fn test_parse_line() {
+        let line = "5BAA61E4C9B93F3F0682250B6CF8331B7EE68FD8:3861493";
+        assert_eq!(parse_line("", line), Some((hash("password"), 3861493)));
+        let suffix = "1E4C9B93F3F0682250B6CF8331B7EE68FD8:12\r\n";
+        assert_eq!(parse_line("5BAA6", suffix), Some((hash("password"), 12)));
+        let lowercase = "5baa61e4c9b93f3f0682250b6cf8331b7ee68fd8:1";
+        assert_eq!(parse_line("", lowercase), Some((hash("password"), 1)));
+        assert_eq!(
+            parse_line("", "5BAA61E4C9B93F3F0682250B6CF8331B7EE68FD8"),
+            None
+        );
+        assert_eq!(
+            parse_line("", "5BAA61E4C9B93F3F0682250B6CF8331B7EE68FD8:many"),
+            None
+        );
+        assert_eq!(
+            parse_line("", "5BAA61E4C9B93F3F0682250B6CF8331B7EE68F:1"),
+            None
+        );
+        assert_eq!(
+            parse_line("", "ZBAA61E4C9B93F3F0682250B6CF8331B7EE68FD8:1"),
+            None
+        );
+    }
+
+    #[test]
+    fn test_round_trip() {
+        let filter = filter();
+        let mut buffer = Vec::new();
+        filter.write(&mut buffer).unwrap();
+        let read = BreachedFilter::read(buffer.as_slice()).unwrap();
+        assert_eq!(read.num_bits, filter.num_bits);
+        assert_eq!(read.hashes, filter.hashes);
+        assert_eq!(read.bits, filter.bits);
+        assert!(read.contains_password("password999"));
+    }
+
+    #[test]
+    fn test_invalid_header() {
+        let header = |num_bits: u64| {
+            let mut buffer = MAGIC.to_vec();
+            buffer.extend_from_slice(&num_bits.to_le_bytes());
+            buffer.extend_from_slice(&3u32.to_le_bytes());
+            buffer
+        };
+        assert!(BreachedFilter::read(&b"NOTBLOOM"[..]).is_err());
+        assert!(BreachedFilter::read(header(0).as_slice()).is_err());
+        assert!(BreachedFilter::read(header(u64::MAX).as_slice()).is_err());
+        let mut truncated = header(1 << 20);
+        truncated.extend_from_slice(&[0; 16]);
+        assert!(BreachedFilter::read(truncated.as_slice()).is_err());
+    }
+}
@@ -124,6 +124,7 @@ fn binding_from_row(row: Row) -> FlowBinding {
         kind = FlowBindingKind::User(Uuid::nil());
     }
     FlowBinding {
+        uid: row.get("uid"),
         enabled: row.get("enabled"),
         negate: row.get("negate"),
         order: row.get("ordering"),
@@ -149,6 +150,7 @@ async fn entry_from_row(client: &impl GenericClient, row: Row) -> Result<FlowEnt
         .await?;
     let bindings = get_bindings(client, statement, row.get("uid")).await?;
     Ok(FlowEntry {
+        uid: row.get("uid"),
         ordering: row.get("ordering"),
         bindings,
         stage: DataRef::new(StageQuery::uid(row.get("stage"))),
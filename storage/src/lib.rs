@@ -17,7 +17,11 @@ use std::{
 };
 use tenant::{TenantExecutor, TenantStorage};
 
+pub mod blueprint;
+pub mod breached;
 pub mod flow;
+pub mod key;
+pub mod lint;
 pub mod policy;
 pub mod prompt;
 pub mod stage;
@@ -52,6 +56,8 @@ pub(crate) use executor;
 pub enum StorageError {
     Pool(deadpool_postgres::PoolError),
     Database(tokio_postgres::Error),
+    Key(key::KeyError),
+    Blueprint(blueprint::BlueprintError),
 }
 
 impl Error for StorageError {
@@ -59,6 +65,8 @@ impl Error for StorageError {
         Some(match self {
             StorageError::Pool(err) => err,
             StorageError::Database(err) => err,
+            StorageError::Key(err) => err,
+            StorageError::Blueprint(err) => err,
         })
     }
 }
@@ -73,12 +81,24 @@ impl From<tokio_postgres::Error> for StorageError {
         Self::Database(value)
     }
 }
+impl From<key::KeyError> for StorageError {
+    fn from(value: key::KeyError) -> Self {
+        Self::Key(value)
+    }
+}
+impl From<blueprint::BlueprintError> for StorageError {
+    fn from(value: blueprint::BlueprintError) -> Self {
+        Self::Blueprint(value)
+    }
+}
 
 impl Display for StorageError {
     fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
         match self {
             StorageError::Pool(err) => Display::fmt(err, f),
             StorageError::Database(err) => Display::fmt(err, f),
+            StorageError::Key(err) => Display::fmt(err, f),
+            StorageError::Blueprint(err) => Display::fmt(err, f),
         }
     }
 }
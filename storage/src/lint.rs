@@ -0,0 +1,574 @@
+use std::{
+    collections::{HashMap, HashSet},
+    fmt::Display,
+};
+
+use model::{
+    blueprint::{
+        BindingBlueprint, BindingBlueprintKind, Blueprint, FlowBlueprint, StageBlueprintKind,
+    },
+    AuthenticationRequirement, FlowDesignation, PolicyKind,
+};
+use serde::Serialize;
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Serialize)]
+#[serde(rename_all = "snake_case")]
+pub enum Severity {
+    /// The flow fails whenever a user reaches the reported entry
+    Error,
+    /// The flow works, but most likely not as intended
+    Warning,
+}
+
+#[derive(Debug, Clone, PartialEq, Eq, Serialize)]
+#[serde(tag = "code", rename_all = "snake_case")]
+pub enum DiagnosticKind {
+    EmptyFlow,
+    MissingStage { stage: String },
+    MissingPolicy { policy: String },
+    MissingPrompt { stage: String, prompt: String },
+    InvalidPasswordStage { stage: String, password: String },
+    InvalidExpression { policy: String, message: String },
+    UnreachableEntry { stage: String, deny: String },
+    UnauthenticatedLogin { stage: String },
+    UnauthenticatedPasswordChange { stage: String },
+    NeverLogsIn,
+    EndsOnClientStage { stage: String },
+}
+
+impl DiagnosticKind {
+    pub fn severity(&self) -> Severity {
+        match self {
+            DiagnosticKind::UnreachableEntry { .. } => Severity::Warning,
+            _ => Severity::Error,
+        }
+    }
+}
+
+impl Display for DiagnosticKind {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        match self {
+            DiagnosticKind::EmptyFlow => write!(f, "The flow has no entries"),
+            DiagnosticKind::MissingStage { stage } => write!(f, "Stage {stage} does not exist"),
+            DiagnosticKind::MissingPolicy { policy } => {
+                write!(f, "Policy {policy} does not exist")
+            }
+            DiagnosticKind::MissingPrompt { stage, prompt } => {
+                write!(
+                    f,
+                    "Stage {stage} binds prompt {prompt}, which does not exist"
+                )
+            }
+            DiagnosticKind::InvalidPasswordStage { stage, password } => write!(
+                f,
+                "Stage {stage} links to {password}, which is not a password stage"
+            ),
+            DiagnosticKind::InvalidExpression { policy, message } => {
+                write!(f, "Policy {policy} does not compile: {message}")
+            }
+            DiagnosticKind::UnreachableEntry { stage, deny } => {
+                write!(
+                    f,
+                    "Stage {stage} is never reached, {deny} denies access before"
+                )
+            }
+            DiagnosticKind::UnauthenticatedLogin { stage } => write!(
+                f,
+                "Stage {stage} logs in a user who has not been authenticated by a previous stage"
+            ),
+            DiagnosticKind::UnauthenticatedPasswordChange { stage } => write!(
+                f,
+                "Stage {stage} changes the password of a user who has not been authenticated"
+            ),
+            DiagnosticKind::NeverLogsIn => {
+                write!(f, "The authentication flow has no user_login stage")
+            }
+            DiagnosticKind::EndsOnClientStage { stage } => write!(
+                f,
+                "The flow ends on stage {stage} without logging in the user it authenticated"
+            ),
+        }
+    }
+}
+
+#[derive(Debug, Clone, PartialEq, Eq, Serialize)]
+pub struct Diagnostic {
+    pub severity: Severity,
+    pub flow: String,
+    /// Position of the entry in the flow, ordered by `ordering`
+    pub entry: Option<usize>,
+    #[serde(flatten)]
+    pub kind: DiagnosticKind,
+}
+
+impl Display for Diagnostic {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        let severity = match self.severity {
+            Severity::Error => "error",
+            Severity::Warning => "warning",
+        };
+        match self.entry {
+            Some(entry) => write!(f, "{severity}: {} entry {entry}: {}", self.flow, self.kind),
+            None => write!(f, "{severity}: {}: {}", self.flow, self.kind),
+        }
+    }
+}
+
+/// Objects referenced by flows, by their slug.
+struct Objects<'a> {
+    stages: HashMap<&'a str, &'a StageBlueprintKind>,
+    policies: HashMap<&'a str, &'a PolicyKind>,
+    prompts: HashSet<&'a str>,
+}
+
+/// Checks every flow of the blueprint for configurations which fail at runtime.
+pub fn lint(blueprint: &Blueprint) -> Vec<Diagnostic> {
+    let objects = Objects {
+        stages: blueprint
+            .stages
+            .iter()
+            .map(|stage| (stage.slug.as_str(), &stage.kind))
+            .collect(),
+        policies: blueprint
+            .policies
+            .iter()
+            .map(|policy| (policy.slug.as_str(), &policy.kind))
+            .collect(),
+        prompts: blueprint
+            .prompts
+            .iter()
+            .map(|prompt| prompt.slug.as_str())
+            .collect(),
+    };
+    let mut diagnostics = Vec::new();
+    for flow in &blueprint.flows {
+        lint_flow(&objects, flow, &mut diagnostics);
+    }
+    diagnostics
+}
+
+fn lint_flow(objects: &Objects, flow: &FlowBlueprint, diagnostics: &mut Vec<Diagnostic>) {
+    let mut report = |entry: Option<usize>, kind: DiagnosticKind| {
+        diagnostics.push(Diagnostic {
+            severity: kind.severity(),
+            flow: flow.slug.clone(),
+            entry,
+            kind,
+        })
+    };
+    if flow.entries.is_empty() {
+        report(None, DiagnosticKind::EmptyFlow);
+        return;
+    }
+    for kind in lint_bindings(objects, &flow.bindings) {
+        report(None, kind);
+    }
+    let mut entries: Vec<_> = flow.entries.iter().collect();
+    entries.sort_by_key(|entry| entry.ordering);
+
+    let mut authenticated = false;
+    // Flows requiring a session act for the logged in user until another one is identified
+    let session_user = matches!(
+        flow.authentication,
+        AuthenticationRequirement::Required | AuthenticationRequirement::Superuser
+    );
+    let mut identified = false;
+    let mut logged_in = false;
+    // Whether a stage authenticated the user after the last login
+    let mut pending_login = false;
+    let mut deny: Option<&str> = None;
+    for (idx, entry) in entries.iter().enumerate() {
+        for kind in lint_bindings(objects, &entry.bindings) {
+            report(Some(idx), kind);
+        }
+        if let Some(deny) = deny {
+            report(
+                Some(idx),
+                DiagnosticKind::UnreachableEntry {
+                    stage: entry.stage.clone(),
+                    deny: deny.to_owned(),
+                },
+            );
+        }
+        let kind = match objects.stages.get(entry.stage.as_str()) {
+            Some(kind) => kind,
+            None => {
+                let stage = entry.stage.clone();
+                report(Some(idx), DiagnosticKind::MissingStage { stage });
+                continue;
+            }
+        };
+        match kind {
+            // Bound deny stages only apply to some executions, later entries stay reachable
+            StageBlueprintKind::Deny if !entry.bindings.iter().any(|binding| binding.enabled) => {
+                deny.get_or_insert(entry.stage.as_str());
+            }
+            StageBlueprintKind::Deny => {}
+            StageBlueprintKind::Prompt { bindings } => {
+                for binding in bindings {
+                    if !objects.prompts.contains(binding.prompt.as_str()) {
+                        report(
+                            Some(idx),
+                            DiagnosticKind::MissingPrompt {
+                                stage: entry.stage.clone(),
+                                prompt: binding.prompt.clone(),
+                            },
+                        );
+                    }
+                }
+            }
+            StageBlueprintKind::Identification {
+                password: Some(password),
+                ..
+            } => match objects.stages.get(password.as_str()) {
+                Some(StageBlueprintKind::Password { .. }) => {
+                    authenticated = true;
+                    pending_login = true;
+                }
+                _ => report(
+                    Some(idx),
+                    DiagnosticKind::InvalidPasswordStage {
+                        stage: entry.stage.clone(),
+                        password: password.clone(),
+                    },
+                ),
+            },
+            StageBlueprintKind::Identification {
+                passwordless: true, ..
+            }
+            | StageBlueprintKind::Password { .. }
+            | StageBlueprintKind::Email { .. }
+            | StageBlueprintKind::UserWrite => {
+                authenticated = true;
+                pending_login = true;
+            }
+            StageBlueprintKind::Identification { .. } => {
+                identified = true;
+            }
+            StageBlueprintKind::UserLogin => {
+                if !authenticated {
+                    report(
+                        Some(idx),
+                        DiagnosticKind::UnauthenticatedLogin {
+                            stage: entry.stage.clone(),
+                        },
+                    );
+                }
+                logged_in = true;
+                pending_login = false;
+            }
+            StageBlueprintKind::UserLogout { .. } => {
+                authenticated = false;
+            }
+            StageBlueprintKind::PasswordChange => {
+                if !authenticated && (identified || !session_user) {
+                    report(
+                        Some(idx),
+                        DiagnosticKind::UnauthenticatedPasswordChange {
+                            stage: entry.stage.clone(),
+                        },
+                    );
+                }
+            }
+            _ => {}
+        }
+    }
+    if flow.designation != FlowDesignation::Authentication {
+        return;
+    }
+    if !logged_in {
+        report(None, DiagnosticKind::NeverLogsIn);
+        return;
+    }
+    // Submitting the last stage completes the flow, the user it authenticated is never logged in
+    let last = entries.len() - 1;
+    let last_stage = objects.stages.get(entries[last].stage.as_str());
+    if pending_login && matches!(last_stage, Some(kind) if requires_input(kind)) {
+        report(
+            Some(last),
+            DiagnosticKind::EndsOnClientStage {
+                stage: entries[last].stage.clone(),
+            },
+        );
+    }
+}
+
+fn lint_bindings(objects: &Objects, bindings: &[BindingBlueprint]) -> Vec<DiagnosticKind> {
+    let mut diagnostics = Vec::new();
+    for binding in bindings {
+        let BindingBlueprintKind::Policy(policy) = &binding.kind else { continue };
+        match objects.policies.get(policy.as_str()) {
+            None => diagnostics.push(DiagnosticKind::MissingPolicy {
+                policy: policy.clone(),
+            }),
+            Some(PolicyKind::Expression { expression }) => {
+                if let Err(err) = policy_engine::check(expression) {
+                    diagnostics.push(DiagnosticKind::InvalidExpression {
+                        policy: policy.clone(),
+                        message: err.to_string(),
+                    });
+                }
+            }
+            Some(_) => {}
+        }
+    }
+    diagnostics
+}
+
+/// Same as `StageKind::requires_input`.
+fn requires_input(kind: &StageBlueprintKind) -> bool {
+    !matches!(
+        kind,
+        StageBlueprintKind::UserLogin
+            | StageBlueprintKind::UserLogout { .. }
+            | StageBlueprintKind::UserWrite
+    )
+}
+
+#[cfg(test)]
+mod test {
+    use model::{
+        blueprint::{
+            BindingBlueprint, BindingBlueprintKind, Blueprint, EntryBlueprint, FlowBlueprint,
+            StageBlueprint, StageBlueprintKind,
+        },
+        AuthenticationRequirement, FlowDesignation, PasswordBackend, UserField,
+    };
+    use uuid::Uuid;
+
+    use super::{lint, DiagnosticKind, Severity};
+
+    fn stages() -> Vec<StageBlueprint> {
+        let stage = |slug: &str, kind| StageBlueprint {
+            slug: slug.to_owned(),
+            timeout: 30,
+            kind,
+        };
+        let identification = |password: Option<&str>| StageBlueprintKind::Identification {
+            password: password.map(str::to_owned),
+            user_fields: vec![UserField::Name],
+            passwordless: false,
+        };
+        vec![
+            stage("deny", StageBlueprintKind::Deny),
+            stage(
+                "password",
+                StageBlueprintKind::Password {
+                    backends: vec![PasswordBackend::Internal],
+                },
+            ),
+            stage("identification", identification(Some("password"))),
+            stage("identification-only", identification(None)),
+            stage("identification-deny", identification(Some("deny"))),
+            stage("login", StageBlueprintKind::UserLogin),
+            stage("password-change", StageBlueprintKind::PasswordChange),
+        ]
+    }
+
+    fn flow(designation: FlowDesignation, stages: &[&str]) -> FlowBlueprint {
+        FlowBlueprint {
+            slug: "flow".to_owned(),
+            title: "Flow".to_owned(),
+            designation,
+            authentication: AuthenticationRequirement::None,
+            bindings: Vec::new(),
+            entries: stages
+                .iter()
+                .zip(1..)
+                .map(|(stage, ordering)| EntryBlueprint {
+                    stage: (*stage).to_owned(),
+                    ordering: ordering * 10,
+                    bindings: Vec::new(),
+                })
+                .collect(),
+        }
+    }
+
+    fn diagnostics(flow: FlowBlueprint) -> Vec<(Option<usize>, DiagnosticKind)> {
+        let blueprint = Blueprint {
+            stages: stages(),
+            flows: vec![flow],
+            ..Default::default()
+        };
+        lint(&blueprint)
+            .into_iter()
+            .map(|diagnostic| (diagnostic.entry, diagnostic.kind))
+            .collect()
+    }
+
+    fn authentication(stages: &[&str]) -> Vec<(Option<usize>, DiagnosticKind)> {
+        diagnostics(flow(FlowDesignation::Authentication, stages))
+    }
+
+    #[test]
+    fn test_valid_flow() {
+        assert!(authentication(&["identification", "login"]).is_empty());
+        assert!(authentication(&["identification-only", "password", "login"]).is_empty());
+    }
+
+    #[test]
+    fn test_empty_flow() {
+        assert_eq!(authentication(&[]), vec![(None, DiagnosticKind::EmptyFlow)]);
+    }
+
+    #[test]
+    fn test_missing_objects() {
+        assert_eq!(
+            authentication(&["identification", "mfa", "login"]),
+            vec![(
+                Some(1),
+                DiagnosticKind::MissingStage {
+                    stage: "mfa".to_owned()
+                }
+            )]
+        );
+        let mut login = flow(
+            FlowDesignation::Authentication,
+            &["identification", "login"],
+        );
+        login.entries[1].bindings.push(BindingBlueprint {
+            kind: BindingBlueprintKind::Policy("strength".to_owned()),
+            enabled: true,
+            negate: false,
+            order: 0,
+        });
+        assert_eq!(
+            diagnostics(login),
+            vec![(
+                Some(1),
+                DiagnosticKind::MissingPolicy {
+                    policy: "strength".to_owned()
+                }
+            )]
+        );
+    }
+
+    #[test]
+    fn test_invalid_password_stage() {
+        assert_eq!(
+            diagnostics(flow(FlowDesignation::Enrollment, &["identification-deny"])),
+            vec![(
+                Some(0),
+                DiagnosticKind::InvalidPasswordStage {
+                    stage: "identification-deny".to_owned(),
+                    password: "deny".to_owned(),
+                }
+            )]
+        );
+    }
+
+    #[test]
+    fn test_unauthenticated_login() {
+        assert_eq!(
+            authentication(&["identification-only", "login"]),
+            vec![(
+                Some(1),
+                DiagnosticKind::UnauthenticatedLogin {
+                    stage: "login".to_owned()
+                }
+            )]
+        );
+    }
+
+    #[test]
+    fn test_unauthenticated_password_change() {
+        let unauthenticated = DiagnosticKind::UnauthenticatedPasswordChange {
+            stage: "password-change".to_owned(),
+        };
+        assert_eq!(
+            diagnostics(flow(
+                FlowDesignation::Recovery,
+                &["identification-only", "password-change"]
+            )),
+            vec![(Some(1), unauthenticated.clone())]
+        );
+        assert!(diagnostics(flow(
+            FlowDesignation::Recovery,
+            &["identification-only", "password", "password-change"]
+        ))
+        .is_empty());
+        // Flows requiring a session change the password of the logged in user
+        let mut configuration = flow(FlowDesignation::Configuration, &["password-change"]);
+        configuration.authentication = AuthenticationRequirement::Required;
+        assert!(diagnostics(configuration.clone()).is_empty());
+        configuration.entries.insert(
+            0,
+            EntryBlueprint {
+                stage: "identification-only".to_owned(),
+                ordering: 0,
+                bindings: Vec::new(),
+            },
+        );
+        assert_eq!(diagnostics(configuration), vec![(Some(1), unauthenticated)]);
+    }
+
+    #[test]
+    fn test_never_logs_in() {
+        assert_eq!(
+            authentication(&["identification"]),
+            vec![(None, DiagnosticKind::NeverLogsIn)]
+        );
+    }
+
+    #[test]
+    fn test_ends_on_client_stage() {
+        assert_eq!(
+            authentication(&["identification", "login", "password"]),
+            vec![(
+                Some(2),
+                DiagnosticKind::EndsOnClientStage {
+                    stage: "password".to_owned()
+                }
+            )]
+        );
+    }
+
+    #[test]
+    fn test_unreachable_entry() {
+        let blueprint = Blueprint {
+            stages: stages(),
+            flows: vec![flow(
+                FlowDesignation::Authentication,
+                &["identification", "deny", "login"],
+            )],
+            ..Default::default()
+        };
+        let diagnostics = lint(&blueprint);
+        assert_eq!(diagnostics.len(), 1);
+        assert_eq!(diagnostics[0].severity, Severity::Warning);
+        assert_eq!(diagnostics[0].entry, Some(2));
+        assert_eq!(
+            diagnostics[0].kind,
+            DiagnosticKind::UnreachableEntry {
+                stage: "login".to_owned(),
+                deny: "deny".to_owned(),
+            }
+        );
+    }
+
+    #[test]
+    fn test_conditional_deny() {
+        let mut conditional = flow(
+            FlowDesignation::Authentication,
+            &["identification", "deny", "login"],
+        );
+        conditional.entries[1].bindings.push(BindingBlueprint {
+            kind: BindingBlueprintKind::Group(Uuid::from_u128(1)),
+            enabled: true,
+            negate: true,
+            order: 0,
+        });
+        assert_eq!(diagnostics(conditional.clone()), vec![]);
+        conditional.entries[1].bindings[0].enabled = false;
+        assert_eq!(
+            diagnostics(conditional),
+            vec![(
+                Some(2),
+                DiagnosticKind::UnreachableEntry {
+                    stage: "login".to_owned(),
+                    deny: "deny".to_owned(),
+                }
+            )]
+        );
+    }
+}
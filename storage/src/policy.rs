@@ -1,7 +1,7 @@
 use async_trait::async_trait;
 use datacache::DataQueryExecutor;
 use deadpool_postgres::GenericClient;
-use model::{Policy, PolicyKind, PolicyKindSimple, PolicyQuery};
+use model::{PasswordRules, Policy, PolicyKind, PolicyKindSimple, PolicyQuery};
 use tokio_postgres::Row;
 
 use crate::{include_sql, StorageError};
@@ -83,7 +83,10 @@ impl DataQueryExecutor<Policy> for PolicyExecutor {
     }
 }
 
-async fn from_row(client: &impl GenericClient, row: Row) -> Result<Policy, StorageError> {
+pub(crate) async fn from_row(
+    client: &impl GenericClient,
+    row: Row,
+) -> Result<Policy, StorageError> {
     let simple_kind: PolicyKindSimple = row.get("kind");
     let kind = match simple_kind {
         PolicyKindSimple::PasswordExpiry => {
@@ -93,6 +96,9 @@ async fn from_row(client: &impl GenericClient, row: Row) -> Result<Policy, Stora
             password_strength_policy(client, row.get("password_strength")).await?
         }
         PolicyKindSimple::Expression => expression_policy(client, row.get("expression")).await?,
+        PolicyKindSimple::BreachedPassword => {
+            breached_password_policy(client, row.get("breached_password")).await?
+        }
     };
     Ok(Policy {
         uid: row.get("uid"),
@@ -115,12 +121,24 @@ async fn password_expiry_policy(
 }
 
 async fn password_strength_policy(
-    _client: &impl GenericClient,
-    _id: i32,
+    client: &impl GenericClient,
+    id: i32,
 ) -> Result<PolicyKind, StorageError> {
-    // let statement = client.prepare_cached(include_sql!("policy/password-strength-by-id")).await?;
-    // let row = client.query_one(&statement, &[&id]).await?;
-    Ok(PolicyKind::PasswordStrength {})
+    let statement = client
+        .prepare_cached(include_sql!("policy/password-strength-by-id"))
+        .await?;
+    let row = client.query_one(&statement, &[&id]).await?;
+    Ok(PolicyKind::PasswordStrength(PasswordRules {
+        field_key: row.get("field_key"),
+        min_length: row.get("min_length"),
+        min_uppercase: row.get("min_uppercase"),
+        min_lowercase: row.get("min_lowercase"),
+        min_digits: row.get("min_digits"),
+        min_symbols: row.get("min_symbols"),
+        max_repeated: row.get("max_repeated"),
+        check_user_attributes: row.get("check_user_attributes"),
+        min_score: row.get("min_score"),
+    }))
 }
 async fn expression_policy(
     client: &impl GenericClient,
@@ -130,5 +148,20 @@ async fn expression_policy(
         .prepare_cached(include_sql!("policy/expression-by-id"))
         .await?;
     let row = client.query_one(&statement, &[&id]).await?;
-    Ok(PolicyKind::Expression(row.get("expression")))
+    Ok(PolicyKind::Expression {
+        expression: row.get("expression"),
+    })
+}
+
+async fn breached_password_policy(
+    client: &impl GenericClient,
+    id: i32,
+) -> Result<PolicyKind, StorageError> {
+    let statement = client
+        .prepare_cached(include_sql!("policy/breached-password-by-id"))
+        .await?;
+    let row = client.query_one(&statement, &[&id]).await?;
+    Ok(PolicyKind::BreachedPassword {
+        field_key: row.get("field_key"),
+    })
 }
@@ -67,6 +67,7 @@ impl DataQueryExecutor<Prompt> for PromptExecutor {
 fn from_row(row: Row) -> Prompt {
     Prompt {
         uid: row.get("uid"),
+        slug: row.get("slug"),
         field_key: row.get("field_key"),
         label: row.get("label"),
         kind: row.get("kind"),
@@ -0,0 +1 @@
+select f.slug as flow, b.entry, p.slug as policy, b.group_binding, b.user_binding, b.ordering, b.enabled, b.negate from flow_bindings b left join flows f on f.uid = b.flow left join policies p on p.uid = b.policy order by b.ordering, b.uid
\ No newline at end of file
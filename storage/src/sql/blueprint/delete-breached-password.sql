@@ -0,0 +1 @@
+delete from breached_password_policies where uid = $1
\ No newline at end of file
@@ -0,0 +1 @@
+delete from consent_stages where uid = $1
\ No newline at end of file
@@ -0,0 +1 @@
+delete from email_stages where uid = $1
\ No newline at end of file
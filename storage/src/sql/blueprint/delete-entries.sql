@@ -0,0 +1 @@
+delete from flow_entries where flow = $1
\ No newline at end of file
@@ -0,0 +1 @@
+delete from password_expiration_policies where uid = $1
\ No newline at end of file
@@ -0,0 +1 @@
+delete from password_strength_policies where uid = $1
\ No newline at end of file
@@ -0,0 +1 @@
+delete from stage_prompt_bindings where stage = $1
\ No newline at end of file
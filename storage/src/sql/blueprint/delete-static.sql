@@ -0,0 +1 @@
+delete from static_stages where uid = $1
\ No newline at end of file
@@ -0,0 +1 @@
+delete from user_logout_stages where uid = $1
\ No newline at end of file
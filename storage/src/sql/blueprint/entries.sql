@@ -0,0 +1 @@
+select e.uid, f.slug as flow, s.slug as stage, e.ordering from flow_entries e join flows f on f.uid = e.flow join stages s on s.uid = e.stage order by e.ordering, e.uid
\ No newline at end of file
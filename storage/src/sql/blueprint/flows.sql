@@ -0,0 +1 @@
+select * from flows order by slug
\ No newline at end of file
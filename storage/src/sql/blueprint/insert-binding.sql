@@ -0,0 +1 @@
+insert into flow_bindings(flow, entry, policy, group_binding, user_binding, ordering, enabled, negate) values ($1, $2, (select uid from policies where slug = $3), $4, $5, $6, $7, $8)
\ No newline at end of file
@@ -0,0 +1 @@
+insert into breached_password_policies(field_key) values ($1) returning uid
\ No newline at end of file
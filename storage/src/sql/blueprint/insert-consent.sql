@@ -0,0 +1 @@
+insert into consent_stages(mode, until) values ($1, $2) returning uid
\ No newline at end of file
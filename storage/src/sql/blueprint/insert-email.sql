@@ -0,0 +1 @@
+insert into email_stages(subject, template, token_expiry) values ($1, $2, $3) returning uid
\ No newline at end of file
@@ -0,0 +1 @@
+insert into flow_entries(flow, stage, ordering) values ($1, (select uid from stages where slug = $2), $3) returning uid
\ No newline at end of file
@@ -0,0 +1 @@
+insert into expression_policies(expression) values ($1) returning uid
\ No newline at end of file
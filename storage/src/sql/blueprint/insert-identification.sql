@@ -0,0 +1 @@
+insert into identification_stages(fields, passwordless) values ($1, $2) returning uid
\ No newline at end of file
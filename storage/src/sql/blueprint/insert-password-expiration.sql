@@ -0,0 +1 @@
+insert into password_expiration_policies(max_age) values ($1) returning uid
\ No newline at end of file
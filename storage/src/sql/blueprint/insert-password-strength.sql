@@ -0,0 +1 @@
+insert into password_strength_policies(field_key, min_length, min_uppercase, min_lowercase, min_digits, min_symbols, max_repeated, check_user_attributes, min_score) values ($1, $2, $3, $4, $5, $6, $7, $8, $9) returning uid
\ No newline at end of file
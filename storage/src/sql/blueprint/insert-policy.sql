@@ -0,0 +1 @@
+insert into policies(slug, kind, password_expiration, password_strength, expression, breached_password) values ($1, $2, $3, $4, $5, $6) returning uid
\ No newline at end of file
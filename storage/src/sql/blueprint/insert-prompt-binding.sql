@@ -0,0 +1 @@
+insert into stage_prompt_bindings(prompt, stage, ordering) values ((select uid from prompts where slug = $1), $2, $3)
\ No newline at end of file
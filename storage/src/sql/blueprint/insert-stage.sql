@@ -0,0 +1 @@
+insert into stages(slug, kind, timeout, identification_password_stage, identification_stage, consent_stage, user_logout_stage, password_backends, totp_stage, webauthn_stage, static_stage, email_stage) values ($1, $2::text::stage_kind, $3, (select uid from stages where slug = $4), $5, $6, $7, $8, $9, $10, $11, $12) returning uid
\ No newline at end of file
@@ -0,0 +1 @@
+insert into static_stages(mode, count) values ($1, $2) returning uid
\ No newline at end of file
@@ -0,0 +1 @@
+insert into totp_stages(mode, issuer, drift) values ($1, $2, $3) returning uid
\ No newline at end of file
@@ -0,0 +1 @@
+insert into user_logout_stages(terminate_all) values ($1) returning uid
\ No newline at end of file
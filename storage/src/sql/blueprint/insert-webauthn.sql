@@ -0,0 +1 @@
+insert into webauthn_stages(mode, user_verification) values ($1, $2) returning uid
\ No newline at end of file
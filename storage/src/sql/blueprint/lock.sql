@@ -0,0 +1 @@
+select pg_advisory_xact_lock(hashtext('blueprints'))
\ No newline at end of file
@@ -0,0 +1 @@
+select * from policies order by slug
\ No newline at end of file
@@ -0,0 +1 @@
+select * from policies where slug = $1 for update
\ No newline at end of file
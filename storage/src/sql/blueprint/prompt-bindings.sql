@@ -0,0 +1 @@
+select s.slug as stage, p.slug as prompt, b.ordering from stage_prompt_bindings b join stages s on s.uid = b.stage join prompts p on p.uid = b.prompt order by b.ordering
\ No newline at end of file
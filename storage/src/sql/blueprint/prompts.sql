@@ -0,0 +1 @@
+select * from prompts order by slug
\ No newline at end of file
@@ -0,0 +1 @@
+select * from stages where slug = $1 for update
\ No newline at end of file
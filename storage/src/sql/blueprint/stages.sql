@@ -0,0 +1 @@
+select s.slug, s.kind::text as kind, s.timeout, s.password_backends, p.slug as password, i.fields, i.passwordless, c.mode, c.until, l.terminate_all, t.mode as totp_mode, t.issuer, t.drift, w.mode as webauthn_mode, w.user_verification, a.mode as static_mode, a.count, e.subject, e.template, e.token_expiry from stages s left join stages p on p.uid = s.identification_password_stage left join identification_stages i on i.uid = s.identification_stage left join consent_stages c on c.uid = s.consent_stage left join user_logout_stages l on l.uid = s.user_logout_stage left join totp_stages t on t.uid = s.totp_stage left join webauthn_stages w on w.uid = s.webauthn_stage left join static_stages a on a.uid = s.static_stage left join email_stages e on e.uid = s.email_stage order by s.slug
\ No newline at end of file
@@ -0,0 +1 @@
+select t.host, t.is_default, t.title, t.logo, t.favicon, invalidation.slug as invalidation_flow, authentication.slug as authentication_flow, authorization.slug as authorization_flow, enrollment.slug as enrollment_flow, recovery.slug as recovery_flow, unenrollment.slug as unenrollment_flow, configuration.slug as configuration_flow from tenants t left join flows invalidation on invalidation.uid = t.invalidation_flow left join flows authentication on authentication.uid = t.authentication_flow left join flows authorization on authorization.uid = t.authorization_flow left join flows enrollment on enrollment.uid = t.enrollment_flow left join flows recovery on recovery.uid = t.recovery_flow left join flows unenrollment on unenrollment.uid = t.unenrollment_flow left join flows configuration on configuration.uid = t.configuration_flow order by t.host
\ No newline at end of file
@@ -0,0 +1 @@
+update policies set kind = $2, password_expiration = $3, password_strength = $4, expression = $5, breached_password = $6 where uid = $1
\ No newline at end of file
@@ -0,0 +1 @@
+update stages set kind = $2::text::stage_kind, timeout = $3, identification_password_stage = (select uid from stages where slug = $4), identification_stage = $5, consent_stage = $6, user_logout_stage = $7, password_backends = $8, totp_stage = $9, webauthn_stage = $10, static_stage = $11, email_stage = $12 where uid = $1
\ No newline at end of file
@@ -0,0 +1 @@
+insert into flows(slug, title, designation, authentication) values ($1, $2, $3, $4) on conflict ((lower(slug))) do update set title = excluded.title, designation = excluded.designation, authentication = excluded.authentication returning uid
\ No newline at end of file
@@ -0,0 +1 @@
+insert into prompts(slug, field_key, label, kind, placeholder, required, help_text) values ($1, $2, $3, $4, $5, $6, $7) on conflict (slug) do update set field_key = excluded.field_key, label = excluded.label, kind = excluded.kind, placeholder = excluded.placeholder, required = excluded.required, help_text = excluded.help_text
\ No newline at end of file
@@ -0,0 +1 @@
+insert into tenants(host, is_default, title, logo, favicon, invalidation_flow, authentication_flow, authorization_flow, enrollment_flow, recovery_flow, unenrollment_flow, configuration_flow) values ($1, $2, $3, $4, $5, (select uid from flows where slug = $6), (select uid from flows where slug = $7), (select uid from flows where slug = $8), (select uid from flows where slug = $9), (select uid from flows where slug = $10), (select uid from flows where slug = $11), (select uid from flows where slug = $12)) on conflict (host) do update set is_default = excluded.is_default, title = excluded.title, logo = excluded.logo, favicon = excluded.favicon, invalidation_flow = excluded.invalidation_flow, authentication_flow = excluded.authentication_flow, authorization_flow = excluded.authorization_flow, enrollment_flow = excluded.enrollment_flow, recovery_flow = excluded.recovery_flow, unenrollment_flow = excluded.unenrollment_flow, configuration_flow = excluded.configuration_flow
\ No newline at end of file
@@ -1 +1 @@
-select * from flow_entries where flow = $1
\ No newline at end of file
+select * from flow_entries where flow = $1 order by ordering, uid
\ No newline at end of file
@@ -0,0 +1 @@
+select * from signing_keys where expires is null or expires > now() order by activates desc
\ No newline at end of file
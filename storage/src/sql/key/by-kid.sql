@@ -0,0 +1 @@
+select * from signing_keys where kid = $1
\ No newline at end of file
@@ -0,0 +1 @@
+insert into signing_keys(kid, algorithm, private_key, jwk, created, activates) values ($1, $2, $3, $4, $5, $6)
\ No newline at end of file
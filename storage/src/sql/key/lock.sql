@@ -0,0 +1 @@
+select pg_advisory_xact_lock(hashtext('signing_keys'))
\ No newline at end of file
@@ -0,0 +1 @@
+update signing_keys set retires = $2, expires = $3 where kid <> $1 and activates <= now() and (retires is null or retires > $2)
\ No newline at end of file
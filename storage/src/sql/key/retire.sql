@@ -0,0 +1 @@
+update signing_keys set retires = now(), expires = $2 where kid = $1 and activates <= now() and (retires is null or retires > now())
\ No newline at end of file
@@ -0,0 +1 @@
+select * from email_stages where uid = $1
\ No newline at end of file
@@ -0,0 +1 @@
+select * from webauthn_stages where uid = $1
\ No newline at end of file
@@ -102,6 +102,16 @@ enum PgStageKind {
     Password,
     #[postgres(name = "consent")]
     Consent,
+    #[postgres(name = "authenticator_totp")]
+    AuthenticatorTotp,
+    #[postgres(name = "authenticator_webauthn")]
+    AuthenticatorWebAuthn,
+    #[postgres(name = "authenticator_static")]
+    AuthenticatorStatic,
+    #[postgres(name = "email")]
+    Email,
+    #[postgres(name = "password_change")]
+    PasswordChange,
 }
 
 async fn from_row(client: &impl GenericClient, row: Row) -> Result<Stage, StorageError> {
@@ -112,10 +122,15 @@ async fn from_row(client: &impl GenericClient, row: Row) -> Result<Stage, Storag
         PgStageKind::Prompt => prompt_stage(client, uid).await?,
         PgStageKind::Identification => identification_stage(client, &row).await?,
         PgStageKind::UserLogin => StageKind::UserLogin,
-        PgStageKind::UserLogout => StageKind::UserWrite,
+        PgStageKind::UserLogout => user_logout_stage(client, &row).await?,
         PgStageKind::UserWrite => StageKind::UserWrite,
         PgStageKind::Password => password_stage(client, &row).await?,
         PgStageKind::Consent => consent_stage(client, &row).await?,
+        PgStageKind::AuthenticatorTotp => totp_stage(client, &row).await?,
+        PgStageKind::AuthenticatorWebAuthn => webauthn_stage(client, &row).await?,
+        PgStageKind::AuthenticatorStatic => static_stage(client, &row).await?,
+        PgStageKind::Email => email_stage(client, &row).await?,
+        PgStageKind::PasswordChange => StageKind::PasswordChange,
     };
     Ok(Stage {
         uid,
@@ -139,16 +154,33 @@ async fn identification_stage(
     Ok(StageKind::Identification {
         password: password_stage_id.map(|uid| DataRef::new(StageQuery::uid(uid))),
         user_fields,
+        passwordless: id_row.get("passwordless"),
     })
 }
 async fn password_stage(
     _client: &impl GenericClient,
-    _row: &Row,
+    row: &Row,
 ) -> Result<StageKind, StorageError> {
-    //TODO: Make Password backend configurable
-    Ok(StageKind::Password {
-        backends: vec![PasswordBackend::Internal],
-    })
+    let backends: Vec<PasswordBackend> = row.get("password_backends");
+    Ok(StageKind::Password { backends })
+}
+
+async fn user_logout_stage(
+    client: &impl GenericClient,
+    row: &Row,
+) -> Result<StageKind, StorageError> {
+    let logout_id: Option<i32> = row.get("user_logout_stage");
+    let terminate_all = match logout_id {
+        Some(logout_id) => {
+            let statement = client
+                .prepare_cached(include_sql!("stage/user-logout-by-id"))
+                .await?;
+            let logout_row = client.query_one(&statement, &[&logout_id]).await?;
+            logout_row.get("terminate_all")
+        }
+        None => false,
+    };
+    Ok(StageKind::UserLogout { terminate_all })
 }
 
 async fn consent_stage(client: &impl GenericClient, row: &Row) -> Result<StageKind, StorageError> {
@@ -168,6 +200,56 @@ async fn consent_stage(client: &impl GenericClient, row: &Row) -> Result<StageKi
     Ok(StageKind::Consent { mode })
 }
 
+async fn totp_stage(client: &impl GenericClient, row: &Row) -> Result<StageKind, StorageError> {
+    let statement = client
+        .prepare_cached(include_sql!("stage/totp-by-id"))
+        .await?;
+    let totp_id: i32 = row.get("totp_stage");
+    let totp_row = client.query_one(&statement, &[&totp_id]).await?;
+    Ok(StageKind::AuthenticatorTotp {
+        mode: totp_row.get("mode"),
+        issuer: totp_row.get("issuer"),
+        drift: totp_row.get("drift"),
+    })
+}
+
+async fn webauthn_stage(client: &impl GenericClient, row: &Row) -> Result<StageKind, StorageError> {
+    let statement = client
+        .prepare_cached(include_sql!("stage/webauthn-by-id"))
+        .await?;
+    let webauthn_id: i32 = row.get("webauthn_stage");
+    let webauthn_row = client.query_one(&statement, &[&webauthn_id]).await?;
+    Ok(StageKind::AuthenticatorWebAuthn {
+        mode: webauthn_row.get("mode"),
+        user_verification: webauthn_row.get("user_verification"),
+    })
+}
+
+async fn static_stage(client: &impl GenericClient, row: &Row) -> Result<StageKind, StorageError> {
+    let statement = client
+        .prepare_cached(include_sql!("stage/static-by-id"))
+        .await?;
+    let static_id: i32 = row.get("static_stage");
+    let static_row = client.query_one(&statement, &[&static_id]).await?;
+    Ok(StageKind::AuthenticatorStatic {
+        mode: static_row.get("mode"),
+        count: static_row.get("count"),
+    })
+}
+
+async fn email_stage(client: &impl GenericClient, row: &Row) -> Result<StageKind, StorageError> {
+    let statement = client
+        .prepare_cached(include_sql!("stage/email-by-id"))
+        .await?;
+    let email_id: i32 = row.get("email_stage");
+    let email_row = client.query_one(&statement, &[&email_id]).await?;
+    Ok(StageKind::Email {
+        subject: email_row.get("subject"),
+        template: email_row.get("template"),
+        token_expiry: email_row.get("token_expiry"),
+    })
+}
+
 async fn prompt_stage(
     client: &impl GenericClient,
     stage_id: i32,
@@ -199,10 +281,7 @@ impl ReverseLookup<Stage> for ProxiedStorage {
                         .expect("Failed to lookup prompt");
                 }
             }
-            model::StageKind::Identification {
-                password,
-                user_fields: _,
-            } => {
+            model::StageKind::Identification { password, .. } => {
                 if let Some(password) = password {
                     let stage = self.lookup(password).await.expect("Failed to lookup stage");
                     self.reverse_lookup(&*stage).await;
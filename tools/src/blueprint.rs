@@ -0,0 +1,72 @@
+use std::{fs, path::Path, process::exit};
+
+use model::blueprint::Blueprint;
+use storage::blueprint::BlueprintStore;
+
+use crate::{
+    cli::{BlueprintArgs, BlueprintSubcommand},
+    keys::create_pool,
+};
+
+fn is_json(path: &Path) -> bool {
+    path.extension()
+        .map_or(false, |extension| extension == "json")
+}
+
+pub fn read_blueprint(path: &Path) -> Blueprint {
+    let content = fs::read_to_string(path).expect("Failed to read blueprint");
+    if is_json(path) {
+        serde_json::from_str(&content).expect("Failed to parse blueprint")
+    } else {
+        serde_yaml::from_str(&content).expect("Failed to parse blueprint")
+    }
+}
+
+pub async fn blueprint(args: BlueprintArgs) {
+    let store = BlueprintStore::new(create_pool(&args.database_url));
+    match args.subcommand {
+        BlueprintSubcommand::Apply { file } => {
+            let blueprint = read_blueprint(&file);
+            let changes = match store.apply(&blueprint).await {
+                Ok(changes) => changes,
+                Err(err) => {
+                    eprintln!("Failed to apply blueprint: {err}");
+                    exit(1);
+                }
+            };
+            for change in &changes {
+                println!("{change}");
+            }
+            println!("Applied {} changes", changes.len());
+        }
+        BlueprintSubcommand::Export { output } => {
+            let blueprint = store.export().await.expect("Failed to export blueprint");
+            let content = match &output {
+                Some(output) if is_json(output) => {
+                    serde_json::to_string_pretty(&blueprint).expect("Failed to serialize blueprint")
+                }
+                _ => serde_yaml::to_string(&blueprint).expect("Failed to serialize blueprint"),
+            };
+            match output {
+                Some(output) => fs::write(output, content).expect("Failed to write blueprint"),
+                None => print!("{content}"),
+            }
+        }
+        BlueprintSubcommand::Diff { file } => {
+            let blueprint = read_blueprint(&file);
+            let changes = match store.diff(&blueprint).await {
+                Ok(changes) => changes,
+                Err(err) => {
+                    eprintln!("Failed to compare blueprint: {err}");
+                    exit(1);
+                }
+            };
+            if changes.is_empty() {
+                println!("The configuration matches the blueprint");
+            }
+            for change in changes {
+                println!("{change}");
+            }
+        }
+    }
+}
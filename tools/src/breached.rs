@@ -0,0 +1,66 @@
+use std::{
+    fs::{self, File},
+    io::{BufRead, BufReader},
+    path::{Path, PathBuf},
+};
+
+use storage::breached::{parse_line, BreachedFilter, PasswordHash};
+
+use crate::cli::BreachedFilterArgs;
+
+/// Prefix length of the files downloaded from the range api
+const RANGE_PREFIX_LENGTH: usize = 5;
+
+/// The files of the dump together with the hash prefix of their lines.
+fn input_files(input: &Path) -> Vec<(String, PathBuf)> {
+    if !input.is_dir() {
+        return vec![(String::new(), input.to_owned())];
+    }
+    let mut files: Vec<(String, PathBuf)> = fs::read_dir(input)
+        .expect("Failed to read input directory")
+        .map(|entry| entry.expect("Failed to read input directory").path())
+        .filter_map(|path| {
+            let prefix = path.file_stem()?.to_str()?.to_uppercase();
+            let is_prefix = prefix.len() == RANGE_PREFIX_LENGTH
+                && prefix.chars().all(|c| c.is_ascii_hexdigit());
+            is_prefix.then_some((prefix, path))
+        })
+        .collect();
+    files.sort();
+    files
+}
+
+fn for_each_hash(files: &[(String, PathBuf)], min_count: u64, mut func: impl FnMut(PasswordHash)) {
+    for (prefix, path) in files {
+        let file = File::open(path).expect("Failed to open input file");
+        for (number, line) in BufReader::new(file).lines().enumerate() {
+            let line = line.expect("Failed to read input file");
+            if line.trim().is_empty() {
+                continue;
+            }
+            match parse_line(prefix, &line) {
+                Some((hash, count)) if count >= min_count => func(hash),
+                Some(_) => {}
+                None => eprintln!("Skipping invalid line {} of {}", number + 1, path.display()),
+            }
+        }
+    }
+}
+
+pub fn breached_filter(args: BreachedFilterArgs) {
+    if !(args.false_positive_rate > 0.0 && args.false_positive_rate < 1.0) {
+        eprintln!("The false positive rate must be between 0 and 1");
+        std::process::exit(1);
+    }
+    let files = input_files(&args.input);
+    // The dump is read twice, so the filter can be sized before inserting
+    let mut expected = 0;
+    for_each_hash(&files, args.min_count, |_| expected += 1);
+    println!("Building filter for {expected} hashes...");
+    let mut filter = BreachedFilter::with_capacity(expected, args.false_positive_rate);
+    for_each_hash(&files, args.min_count, |hash| filter.insert(&hash));
+    filter
+        .save(&args.output)
+        .expect("Failed to write filter file");
+    println!("Wrote filter to {}", args.output.display());
+}
@@ -1,4 +1,9 @@
+use std::path::PathBuf;
+
 use clap::{Args, Parser, Subcommand};
+use model::SigningAlgorithm;
+
+use crate::keys::parse_algorithm;
 
 #[derive(Parser)]
 pub struct CliCommand {
@@ -9,6 +14,14 @@ pub struct CliCommand {
 #[derive(Subcommand)]
 pub enum CliSubcommand {
     LoadTest(LoadTestArgs),
+    /// Manage the keys used to sign sessions and tokens
+    Keys(KeysArgs),
+    /// Build the breached password filter from a HIBP SHA-1 dump
+    BreachedFilter(BreachedFilterArgs),
+    /// Manage the configuration with blueprints
+    Blueprint(BlueprintArgs),
+    /// Check flows for configurations which fail at runtime
+    Flow(FlowArgs),
 }
 
 #[derive(Args)]
@@ -18,3 +31,89 @@ pub struct LoadTestArgs {
     #[arg(short = 'm', long)]
     pub max_concurrent: u32,
 }
+
+#[derive(Args)]
+pub struct BreachedFilterArgs {
+    /// A dump with one `HASH:COUNT` per line or a directory of range files named by their hash prefix
+    pub input: PathBuf,
+    pub output: PathBuf,
+    #[arg(long, default_value_t = 0.001)]
+    pub false_positive_rate: f64,
+    /// Hashes seen less often than this are skipped
+    #[arg(long, default_value_t = 1)]
+    pub min_count: u64,
+}
+
+#[derive(Args)]
+pub struct KeysArgs {
+    #[arg(long, env = "AUTHUST_DATABASE_URL")]
+    pub database_url: String,
+    /// Secret of the server, the private keys are encrypted with it
+    #[arg(long, env = "AUTHUST_SECRET")]
+    pub secret: String,
+    #[command(subcommand)]
+    pub subcommand: KeysSubcommand,
+}
+
+#[derive(Subcommand)]
+pub enum KeysSubcommand {
+    /// List all keys which have not expired
+    List,
+    /// Generate a new key
+    Generate {
+        #[arg(short = 'a', long, value_parser = parse_algorithm, default_value = "RS256")]
+        algorithm: SigningAlgorithm,
+        /// Seconds until the key is used for signing, it is published right away
+        #[arg(long, default_value_t = 0)]
+        activates_in: u64,
+    },
+    /// Import a PKCS#8 PEM encoded private key
+    Import {
+        #[arg(short = 'a', long, value_parser = parse_algorithm)]
+        algorithm: SigningAlgorithm,
+        file: PathBuf,
+        /// Seconds until the key is used for signing, it is published right away
+        #[arg(long, default_value_t = 0)]
+        activates_in: u64,
+    },
+    /// Stop signing with a key
+    Retire {
+        kid: String,
+        /// Seconds the key is still accepted for verification
+        #[arg(long, default_value_t = 60 * 60 * 24 * 3)]
+        overlap: u64,
+    },
+}
+
+#[derive(Args)]
+pub struct BlueprintArgs {
+    #[arg(long, env = "AUTHUST_DATABASE_URL")]
+    pub database_url: String,
+    #[command(subcommand)]
+    pub subcommand: BlueprintSubcommand,
+}
+
+/// Blueprints are read and written as YAML, files ending with `.json` as JSON.
+#[derive(Subcommand)]
+pub enum BlueprintSubcommand {
+    /// Create and update objects to match the blueprint, objects missing from it are kept
+    Apply { file: PathBuf },
+    /// Write the current configuration to a file or stdout
+    Export { output: Option<PathBuf> },
+    /// List the changes applying the blueprint would make
+    Diff { file: PathBuf },
+}
+
+#[derive(Args)]
+pub struct FlowArgs {
+    #[arg(long, env = "AUTHUST_DATABASE_URL")]
+    pub database_url: Option<String>,
+    #[command(subcommand)]
+    pub subcommand: FlowSubcommand,
+}
+
+#[derive(Subcommand)]
+pub enum FlowSubcommand {
+    /// Lint the flows of a blueprint, or of the current configuration without one
+    Lint { file: Option<PathBuf> },
+}
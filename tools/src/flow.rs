@@ -0,0 +1,42 @@
+use std::process::exit;
+
+use storage::{
+    blueprint::BlueprintStore,
+    lint::{lint, Severity},
+};
+
+use crate::{
+    blueprint::read_blueprint,
+    cli::{FlowArgs, FlowSubcommand},
+    keys::create_pool,
+};
+
+pub async fn flow(args: FlowArgs) {
+    match args.subcommand {
+        FlowSubcommand::Lint { file } => {
+            let blueprint = match (file, args.database_url) {
+                (Some(file), _) => read_blueprint(&file),
+                (None, Some(database_url)) => BlueprintStore::new(create_pool(&database_url))
+                    .export()
+                    .await
+                    .expect("Failed to export blueprint"),
+                (None, None) => {
+                    eprintln!("Either a blueprint or the database url is required");
+                    exit(2);
+                }
+            };
+            let diagnostics = lint(&blueprint);
+            for diagnostic in &diagnostics {
+                println!("{diagnostic}");
+            }
+            let errors = diagnostics
+                .iter()
+                .filter(|diagnostic| diagnostic.severity == Severity::Error)
+                .count();
+            println!("{errors} errors, {} warnings", diagnostics.len() - errors);
+            if errors > 0 {
+                exit(1);
+            }
+        }
+    }
+}
@@ -0,0 +1,85 @@
+use std::{fs, str::FromStr};
+
+use deadpool_postgres::{Manager, ManagerConfig, Pool, RecyclingMethod};
+use model::SigningAlgorithm;
+use storage::key::{self, KeyStore};
+use time::Duration;
+
+use crate::cli::{KeysArgs, KeysSubcommand};
+
+pub fn parse_algorithm(value: &str) -> Result<SigningAlgorithm, String> {
+    match value.to_lowercase().as_str() {
+        "rs256" => Ok(SigningAlgorithm::Rs256),
+        "es256" => Ok(SigningAlgorithm::Es256),
+        "eddsa" => Ok(SigningAlgorithm::EdDsa),
+        _ => Err(format!(
+            "Unknown algorithm {value}, expected RS256, ES256 or EdDSA"
+        )),
+    }
+}
+
+pub fn create_pool(database_url: &str) -> Pool {
+    let config =
+        tokio_postgres::Config::from_str(database_url).expect("Failed to parse database url");
+    let manager = Manager::from_config(
+        config,
+        tokio_postgres::NoTls,
+        ManagerConfig {
+            recycling_method: RecyclingMethod::Fast,
+        },
+    );
+    Pool::builder(manager)
+        .max_size(1)
+        .build()
+        .expect("Failed to create pool")
+}
+
+pub async fn keys(args: KeysArgs) {
+    let store = KeyStore::new(create_pool(&args.database_url), &args.secret);
+    match args.subcommand {
+        KeysSubcommand::List => {
+            let keys = store.all().await.expect("Failed to load keys");
+            for key in keys {
+                println!(
+                    "{}\t{:?}\tactivates: {}\tretires: {}\texpires: {}",
+                    key.kid,
+                    key.algorithm,
+                    key.activates,
+                    key.retires.map_or("-".to_owned(), |v| v.to_string()),
+                    key.expires.map_or("-".to_owned(), |v| v.to_string()),
+                );
+            }
+        }
+        KeysSubcommand::Generate {
+            algorithm,
+            activates_in,
+        } => {
+            let mut key = key::generate(algorithm).expect("Failed to generate key");
+            key.activates += Duration::seconds(activates_in as i64);
+            store.insert(&key).await.expect("Failed to store key");
+            println!("Generated key {}", key.kid);
+        }
+        KeysSubcommand::Import {
+            algorithm,
+            file,
+            activates_in,
+        } => {
+            let pem = fs::read_to_string(&file).expect("Failed to read key file");
+            let mut key = key::import(algorithm, &pem).expect("Failed to import key");
+            key.activates += Duration::seconds(activates_in as i64);
+            store.insert(&key).await.expect("Failed to store key");
+            println!("Imported key {}", key.kid);
+        }
+        KeysSubcommand::Retire { kid, overlap } => {
+            let retired = store
+                .retire(&kid, Duration::seconds(overlap as i64))
+                .await
+                .expect("Failed to retire key");
+            if retired {
+                println!("Retired key {kid}");
+            } else {
+                eprintln!("No active key with kid {kid}");
+            }
+        }
+    }
+}
@@ -1,8 +1,16 @@
+use blueprint::blueprint;
+use breached::breached_filter;
 use clap::Parser;
 use cli::CliCommand;
+use flow::flow;
+use keys::keys;
 use load_test::load_test;
 
+pub mod blueprint;
+pub mod breached;
 pub mod cli;
+pub mod flow;
+pub mod keys;
 pub mod load_test;
 
 #[tokio::main]
@@ -10,5 +18,9 @@ async fn main() {
     let cli = CliCommand::parse();
     match cli.subcommand {
         cli::CliSubcommand::LoadTest(args) => load_test(args).await,
+        cli::CliSubcommand::Keys(args) => keys(args).await,
+        cli::CliSubcommand::BreachedFilter(args) => breached_filter(args),
+        cli::CliSubcommand::Blueprint(args) => blueprint(args).await,
+        cli::CliSubcommand::Flow(args) => flow(args).await,
     }
 }